@@ -1,12 +1,20 @@
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::os::unix::ffi::OsStrExt;
 use std::path::PathBuf;
 
 use clap::{App, AppSettings, Arg};
 
 mod arg {
     pub const VERBOSE: &str = "verbose";
+    pub const LOGIN: &str = "login";
+    pub const NORC: &str = "norc";
+    pub const RCFILE: &str = "rcfile";
 
+    pub const INTERACTIVE: &str = "interactive";
+    pub const STDIN: &str = "stdin";
     pub const EXEC: &str = "exec";
-    pub const FILES: &str = "files";
+    pub const ARGS: &str = "args";
 }
 
 fn app() -> App<'static, 'static> {
@@ -15,12 +23,51 @@ fn app() -> App<'static, 'static> {
         .author(pkg::authors!("\n"))
         .about(concat!("\n", pkg::description!()))
         .setting(AppSettings::ColorAuto)
+        // The arguments after the script are passed to it, even if they look
+        // like options.
+        .setting(AppSettings::TrailingVarArg)
         .arg(
             Arg::with_name(arg::VERBOSE)
                 .help("Enables verbose output")
                 .short("v")
                 .long("verbose"),
         )
+        .arg(
+            Arg::with_name(arg::LOGIN)
+                .help("Starts as a login shell, sourcing the profile files")
+                .short("l")
+                .long("login"),
+        )
+        .arg(
+            Arg::with_name(arg::NORC)
+                .help("Does not source the rc files of an interactive shell")
+                .long("norc"),
+        )
+        .arg(
+            Arg::with_name(arg::RCFILE)
+                .conflicts_with(arg::NORC)
+                .help("Sources the file instead of the rc files of an interactive shell")
+                .long("rcfile")
+                .value_name("PATH")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(arg::INTERACTIVE)
+                .conflicts_with_all(&[arg::EXEC, arg::ARGS, arg::STDIN])
+                .help("Starts an interactive shell, even if the standard input is not a terminal")
+                .short("i")
+                .long("interactive"),
+        )
+        .arg(
+            Arg::with_name(arg::STDIN)
+                .conflicts_with(arg::EXEC)
+                .help(
+                    "Reads commands from the standard input, even if it is a terminal, \
+                     taking all arguments as positional arguments",
+                )
+                .short("s")
+                .long("stdin"),
+        )
         .arg(
             Arg::with_name(arg::EXEC)
                 .help("Takes the first argument as a command to execute")
@@ -29,39 +76,167 @@ fn app() -> App<'static, 'static> {
                 .takes_value(true),
         )
         .arg(
-            Arg::with_name(arg::FILES)
-                .conflicts_with(arg::EXEC)
-                .help("Script files to execute")
-                .value_name("FILE")
-                .min_values(0)
+            Arg::with_name(arg::ARGS)
+                .help(
+                    "The script to execute and its arguments, or with `-c` the name \
+                     of the command and its arguments",
+                )
+                .value_name("SCRIPT [ARG]")
+                .multiple(true)
                 .required(false),
         )
 }
 
+#[derive(Debug, Eq, PartialEq)]
 pub enum LaunchMode {
     Exec(String),
-    Files(Vec<PathBuf>),
+    Script(PathBuf),
+    /// Reads commands from the standard input.
+    Stdin,
     Interactive,
 }
 
 pub struct Args {
     pub verbose: bool,
+    /// Is the shell a login shell, started with `-l` or by a name starting
+    /// with `-` as `login` does.
+    pub login: bool,
+    pub rc: Rc,
+    /// The name of the script or command, `$0`.
+    pub name: String,
+    /// The positional arguments of the script or command, `$args`.
+    pub positional: Vec<String>,
+}
+
+/// The rc files sourced by an interactive shell.
+pub enum Rc {
+    /// The system-wide and user rc files, those that exist.
+    Default,
+    /// A file given by `--rcfile`.
+    File(PathBuf),
+    /// None, with `--norc`.
+    None,
 }
 
 pub fn args() -> (LaunchMode, Args) {
-    let matches = app().get_matches();
+    let stdin_tty = unsafe { libc::isatty(libc::STDIN_FILENO) } == 1;
+    parse(env::args_os().collect(), stdin_tty)
+}
+
+/// Parses the arguments of the shell, starting with the name it was started
+/// by, given whether its standard input is a terminal.
+fn parse(argv: Vec<OsString>, stdin_tty: bool) -> (LaunchMode, Args) {
+    let matches = app().get_matches_from(&argv);
 
     let verbose = matches.is_present(arg::VERBOSE);
+    let login = matches.is_present(arg::LOGIN)
+        || argv
+            .first()
+            .is_some_and(|name| name.as_bytes().starts_with(b"-"));
+    let rc = match matches.value_of_os(arg::RCFILE) {
+        Some(file) => Rc::File(PathBuf::from(file)),
+        None if matches.is_present(arg::NORC) => Rc::None,
+        None => Rc::Default,
+    };
+
+    let argv0 = argv
+        .first()
+        .map_or_else(|| pkg::name!().to_owned(), |name| lossy(name));
+    let mut positional: Vec<String> = match matches.values_of_os(arg::ARGS) {
+        Some(values) => values.map(lossy).collect(),
+        None => Vec::new(),
+    };
 
-    let args = Args { verbose };
+    let (mode, name) = match matches.value_of(arg::EXEC) {
+        Some(cmd) if positional.is_empty() => (LaunchMode::Exec(cmd.to_owned()), argv0),
+        Some(cmd) => (LaunchMode::Exec(cmd.to_owned()), positional.remove(0)),
+        None if matches.is_present(arg::STDIN) => (LaunchMode::Stdin, argv0),
+        None if !positional.is_empty() => {
+            let script = positional.remove(0);
+            (LaunchMode::Script(PathBuf::from(&script)), script)
+        }
+        None if matches.is_present(arg::INTERACTIVE) => (LaunchMode::Interactive, argv0),
+        // Commands are piped or redirected to the shell.
+        None if !stdin_tty => (LaunchMode::Stdin, argv0),
+        None => (LaunchMode::Interactive, argv0),
+    };
 
-    let mode = match matches.value_of(arg::EXEC) {
-        Some(cmd) => LaunchMode::Exec(cmd.to_owned()),
-        None => match matches.values_of_os(arg::FILES) {
-            Some(files) => LaunchMode::Files(files.map(PathBuf::from).collect()),
-            None => LaunchMode::Interactive,
-        },
+    let args = Args {
+        verbose,
+        login,
+        rc,
+        name,
+        positional,
     };
 
     (mode, args)
 }
+
+fn lossy(arg: &OsStr) -> String {
+    arg.to_string_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(args: &[&str], stdin_tty: bool) -> (LaunchMode, Args) {
+        parse(args.iter().map(OsString::from).collect(), stdin_tty)
+    }
+
+    #[test]
+    fn modes() {
+        let (mode, args) = parse_args(&["jsh"], true);
+        assert_eq!(mode, LaunchMode::Interactive);
+        assert_eq!(args.name, "jsh");
+        assert_eq!(parse_args(&["jsh"], false).0, LaunchMode::Stdin);
+        assert_eq!(parse_args(&["jsh", "-i"], false).0, LaunchMode::Interactive);
+        assert_eq!(parse_args(&["jsh", "-s"], true).0, LaunchMode::Stdin);
+        assert_eq!(
+            parse_args(&["jsh", "-c", "echo"], true).0,
+            LaunchMode::Exec("echo".to_owned())
+        );
+        assert_eq!(
+            parse_args(&["jsh", "a.jsh"], false).0,
+            LaunchMode::Script(PathBuf::from("a.jsh"))
+        );
+    }
+
+    #[test]
+    fn positional() {
+        let (mode, args) = parse_args(&["jsh", "-c", "echo $1", "name", "a", "-b"], true);
+        assert_eq!(mode, LaunchMode::Exec("echo $1".to_owned()));
+        assert_eq!(args.name, "name");
+        assert_eq!(args.positional, ["a", "-b"]);
+
+        let (_, args) = parse_args(&["jsh", "-c", "echo"], true);
+        assert_eq!(args.name, "jsh");
+        assert!(args.positional.is_empty());
+
+        // Options after the script are its own.
+        let (_, args) = parse_args(&["jsh", "a.jsh", "-v", "b"], true);
+        assert_eq!(args.name, "a.jsh");
+        assert_eq!(args.positional, ["-v", "b"]);
+        assert!(!args.verbose);
+
+        let (mode, args) = parse_args(&["jsh", "-s", "a", "b"], true);
+        assert_eq!(mode, LaunchMode::Stdin);
+        assert_eq!(args.name, "jsh");
+        assert_eq!(args.positional, ["a", "b"]);
+    }
+
+    #[test]
+    fn login_and_rc() {
+        assert!(parse_args(&["-jsh"], true).1.login);
+        assert!(parse_args(&["jsh", "-l"], true).1.login);
+        assert!(!parse_args(&["jsh"], true).1.login);
+
+        assert!(matches!(parse_args(&["jsh"], true).1.rc, Rc::Default));
+        assert!(matches!(
+            parse_args(&["jsh", "--norc"], true).1.rc,
+            Rc::None
+        ));
+        let (_, args) = parse_args(&["jsh", "--rcfile", "x.jsh"], true);
+        assert!(matches!(args.rc, Rc::File(file) if file.as_os_str() == "x.jsh"));
+    }
+}
@@ -4,17 +4,21 @@ use anyhow::Result;
 use tokio::sync::mpsc::{self, Receiver, Sender};
 use tokio::sync::{Mutex, RwLock};
 
-use crate::cli::code_area::{CodeArea, CodeAreaSpec, CodeAreaState};
+use crate::cli::code_area::{CodeArea, CodeAreaSpec, CodeAreaState, CodeBuffer};
+use crate::cli::keymap::{Action, Binding, BoundFn, Line, Lookup, Mode, SharedKeymaps};
 use crate::cli::prompt::{Prompt, PromptConfig, PromptHandle};
 use crate::cli::term::buffer::Buffer;
-use crate::cli::tty::{Event, KeyCode, KeyEvent, KeyModifiers, Tty};
+use crate::cli::tty::{CursorShape, Event, KeyEvent, Tty};
 use crate::cli::widget::{Handle, Render};
 
+pub use crate::cli::code_area::HistoryFn;
+
 // TODO: Add more to AppSpec.
 pub struct AppSpec {
     pub tty: Tty,
 
     pub state: AppState,
+    pub keymaps: SharedKeymaps,
 
     pub prompt: Option<(Prompt, PromptHandle)>,
     pub rprompt: Option<(Prompt, PromptHandle)>,
@@ -29,6 +33,10 @@ pub struct App {
 
     code_area: CodeArea,
 
+    keymaps: SharedKeymaps,
+    /// The keys read so far of a sequence that is bound.
+    pending_keys: Vec<KeyEvent>,
+
     pub tty: Tty,
 
     pub state: Arc<Mutex<AppState>>,
@@ -39,6 +47,7 @@ pub struct App {
     pub rprompt_handle: PromptHandle,
 }
 
+#[derive(Default)]
 pub struct AppState {
     pub notes: Option<Vec<String>>,
 }
@@ -49,12 +58,6 @@ impl AppState {
     }
 }
 
-impl Default for AppState {
-    fn default() -> Self {
-        AppState { notes: None }
-    }
-}
-
 struct AfterLine {
     app_state: Arc<Mutex<AppState>>,
     code_area_state: Arc<RwLock<CodeAreaState>>,
@@ -74,6 +77,7 @@ impl App {
         let AppSpec {
             tty,
             state,
+            keymaps,
             prompt,
             rprompt,
         } = spec;
@@ -107,6 +111,9 @@ impl App {
 
             code_area,
 
+            keymaps,
+            pending_keys: Vec::new(),
+
             tty,
 
             state: Arc::new(Mutex::new(state)),
@@ -121,35 +128,39 @@ impl App {
     #[inline]
     pub async fn mutate_state<F>(&self, f: F)
     where
-        F: FnOnce(&mut AppState) -> (),
+        F: FnOnce(&mut AppState),
     {
         let mut state = self.state.lock().await;
         f(&mut state);
     }
 
-    async fn reset_all_states(&mut self) {
+    /// Sets the buffer of the code area, with the dot as a byte index.
+    pub async fn set_buffer(&mut self, content: String, dot: usize) {
+        self.code_area
+            .mutate_state(|state| state.buffer = CodeBuffer { content, dot })
+            .await;
+    }
+
+    /// Sets the function loading the history, walked through by
+    /// `history-up` and `history-down`.
+    pub fn set_history(&mut self, load: Option<HistoryFn>) {
+        self.code_area.set_history(load);
+    }
+
+    async fn reset_all_states(&mut self) -> Result<()> {
+        self.pending_keys.clear();
         self.mutate_state(AppState::reset_state).await;
 
         self.code_area
             .mutate_state(CodeAreaState::reset_state)
             .await;
+        self.code_area.start_line().await;
+        self.set_mode(Mode::Insert)
     }
 
     async fn handle_event(&mut self, event: Event) -> Result<()> {
         match event {
-            // EOF on Ctrl-D.
-            Event::Key(KeyEvent {
-                code: KeyCode::Char('d'),
-                modifiers: KeyModifiers::CONTROL,
-            }) => self.commit_eof().await?,
-            // Discard line on Ctrl-C.
-            Event::Key(KeyEvent {
-                code: KeyCode::Char('c'),
-                modifiers: KeyModifiers::CONTROL,
-            }) => {
-                self.reset_all_states().await;
-                self.update_prompts(true).await?;
-            }
+            Event::Key(key) => self.handle_key(key).await?,
             // Event::Key(KeyEvent {
             //     code: KeyCode::Char('?'),
             //     ..
@@ -173,6 +184,138 @@ impl App {
         Ok(())
     }
 
+    /// Looks up the keys read so far in the keymap of the mode, running the
+    /// binding once a bound sequence is complete.
+    async fn handle_key(&mut self, key: KeyEvent) -> Result<()> {
+        let mode = self.mode();
+
+        // The rest of a vi command is not looked up.
+        if mode != Mode::Insert && self.code_area.vi_pending() {
+            return self.vi_key(key, mode).await;
+        }
+
+        self.pending_keys.push(key);
+        let lookup = self.keymaps.lock().unwrap().lookup(&self.pending_keys);
+
+        match lookup {
+            Lookup::Bound(binding) => {
+                self.pending_keys.clear();
+                self.run_binding(binding, mode).await?;
+            }
+            // Wait for the rest of the sequence.
+            Lookup::Prefix => {}
+            Lookup::Unbound => {
+                // An unbound key is handled by the code area, unless it ends a
+                // sequence, which is dropped.
+                let keys = std::mem::take(&mut self.pending_keys);
+                if keys.len() > 1 {
+                    return Ok(());
+                }
+                if mode == Mode::Insert {
+                    self.code_area.handle(Event::Key(key)).await;
+                    self.update_prompts(false).await?;
+                } else {
+                    self.vi_key(key, mode).await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn run_binding(&mut self, binding: Binding, mode: Mode) -> Result<()> {
+        match binding {
+            Binding::Action(Action::Eof) => self.commit_eof().await?,
+            Binding::Action(Action::DiscardLine) => {
+                self.reset_all_states().await?;
+                self.update_prompts(true).await?;
+            }
+            Binding::Action(Action::ViNormalMode) => {
+                if mode != Mode::Normal {
+                    self.code_area.vi_normal_mode(mode).await;
+                    self.set_mode(Mode::Normal)?;
+                }
+                self.update_prompts(false).await?;
+            }
+            Binding::Action(Action::ViInsertMode) => {
+                if mode != Mode::Insert {
+                    self.code_area.vi_insert_mode().await;
+                    self.set_mode(Mode::Insert)?;
+                }
+                self.update_prompts(false).await?;
+            }
+            Binding::Action(action) => {
+                self.code_area.run_action(action).await;
+                if mode != Mode::Insert {
+                    self.code_area.vi_clamp().await;
+                }
+                self.update_prompts(false).await?;
+            }
+            Binding::Fn(f) => {
+                self.run_fn(f).await;
+                self.update_prompts(false).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn vi_key(&mut self, key: KeyEvent, mode: Mode) -> Result<()> {
+        if let Some(mode) = self.code_area.vi_key(key, mode).await {
+            self.set_mode(mode)?;
+        }
+        self.update_prompts(false).await
+    }
+
+    fn mode(&self) -> Mode {
+        self.keymaps.lock().unwrap().mode()
+    }
+
+    /// Changes the mode, and the shape of the cursor to show it in vi mode.
+    fn set_mode(&mut self, mode: Mode) -> Result<()> {
+        let vi = {
+            let mut keymaps = self.keymaps.lock().unwrap();
+            keymaps.set_mode(mode);
+            keymaps.vi()
+        };
+
+        let shape = match mode {
+            _ if !vi => None,
+            Mode::Insert => Some(CursorShape::Bar),
+            Mode::Normal | Mode::Visual => Some(CursorShape::Block),
+        };
+        self.tty.set_cursor_shape(shape)
+    }
+
+    /// Runs a bound function on the buffer, replacing it with the line the
+    /// function returns.
+    async fn run_fn(&mut self, f: BoundFn) {
+        let buffer = self.code_area.clone_state().await.buffer;
+        let line = f(Line {
+            buffer: buffer.content,
+            dot: buffer.dot,
+            notes: Vec::new(),
+        })
+        .await;
+
+        let Line {
+            buffer: content,
+            mut dot,
+            notes,
+        } = line;
+
+        dot = dot.min(content.len());
+        while !content.is_char_boundary(dot) {
+            dot -= 1;
+        }
+        self.set_buffer(content, dot).await;
+
+        if !notes.is_empty() {
+            self.mutate_state(|state| state.notes.get_or_insert_with(Vec::new).extend(notes))
+                .await;
+        }
+    }
+
     async fn update_prompts(&mut self, force: bool) -> Result<()> {
         self.prompt_handle.update(force).await?;
         self.rprompt_handle.update(force).await?;
@@ -235,7 +378,19 @@ impl App {
         buf
     }
 
+    /// Reads a line from the terminal.
+    ///
+    /// The terminal is only set up while reading, when this returns it has been
+    /// restored and no more events are read, so it can be handed over to a
+    /// child process.
     pub async fn read_line(&mut self) -> Result<Return> {
+        let ret = self.read_line_setup().await;
+        self.tty.close_events();
+        self.tty.set_cursor_shape(None)?;
+        ret
+    }
+
+    async fn read_line_setup(&mut self) -> Result<Return> {
         // TODO: Before read line.
 
         // TODO: Drop for after read line and reset states.
@@ -249,6 +404,10 @@ impl App {
             code_area_state: self.code_area.state.clone(),
         };
 
+        self.pending_keys.clear();
+        self.code_area.start_line().await;
+        self.set_mode(Mode::Insert)?;
+
         // Redraw state.
         let mut redraw = Redraw {
             size: None,
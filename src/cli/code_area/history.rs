@@ -0,0 +1,230 @@
+//! Walking through the history from the line being edited, with up and down.
+//!
+//! A walk keeps the line it started from to come back to, and if that line is
+//! not empty only shows the commands starting with it. Editing a command shown
+//! starts a new walk from it.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use super::CodeBuffer;
+
+/// Loads the commands of the history, oldest first.
+pub type HistoryFn = Arc<dyn Fn() -> Vec<String> + Send + Sync>;
+
+#[derive(Default)]
+pub struct History {
+    load: Option<HistoryFn>,
+    walk: Option<Walk>,
+}
+
+struct Walk {
+    /// The line being edited when the walk started.
+    saved: CodeBuffer,
+    /// The commands starting with the saved line, newest first and without
+    /// duplicates.
+    matches: Vec<String>,
+    /// The index in the matches of the command shown, if not the saved line.
+    index: Option<usize>,
+    /// The content of the buffer shown by the walk.
+    shown: String,
+}
+
+impl History {
+    pub fn set_load(&mut self, load: Option<HistoryFn>) {
+        self.load = load;
+        self.walk = None;
+    }
+
+    /// Ends the walk, at the start of a new line.
+    pub fn reset_line(&mut self) {
+        self.walk = None;
+    }
+
+    /// Shows the command before the one shown, with the dot at its end,
+    /// returning false if there is none.
+    pub fn prev(&mut self, buffer: &mut CodeBuffer) -> bool {
+        let walking = match &self.walk {
+            Some(walk) => walk.shown == buffer.content,
+            None => false,
+        };
+        if !walking {
+            self.walk = self.start(buffer);
+        }
+
+        let walk = match &mut self.walk {
+            Some(walk) => walk,
+            None => return false,
+        };
+        let index = walk.index.map_or(0, |index| index + 1);
+        let cmd = match walk.matches.get(index) {
+            Some(cmd) => cmd,
+            None => return false,
+        };
+
+        *buffer = CodeBuffer {
+            content: cmd.clone(),
+            dot: cmd.len(),
+        };
+        walk.index = Some(index);
+        walk.shown = cmd.clone();
+        true
+    }
+
+    /// Shows the command after the one shown, with the dot at the end of its
+    /// first line, or the line the walk started from after the last command.
+    /// Returns false if not walking.
+    pub fn next(&mut self, buffer: &mut CodeBuffer) -> bool {
+        let walk = match &mut self.walk {
+            Some(walk) if walk.shown == buffer.content => walk,
+            _ => return false,
+        };
+
+        match walk.index {
+            None => return false,
+            Some(0) => {
+                walk.index = None;
+                *buffer = walk.saved.clone();
+            }
+            Some(index) => {
+                let cmd = &walk.matches[index - 1];
+                walk.index = Some(index - 1);
+                *buffer = CodeBuffer {
+                    content: cmd.clone(),
+                    dot: cmd.find('\n').unwrap_or(cmd.len()),
+                };
+            }
+        }
+        walk.shown = buffer.content.clone();
+        true
+    }
+
+    fn start(&self, buffer: &CodeBuffer) -> Option<Walk> {
+        let load = self.load.as_ref()?;
+
+        let prefix = &*buffer.content;
+        let mut seen = HashSet::new();
+        let matches = load()
+            .into_iter()
+            .rev()
+            .filter(|cmd| cmd.starts_with(prefix) && cmd != prefix)
+            .filter(|cmd| seen.insert(cmd.clone()))
+            .collect();
+
+        Some(Walk {
+            saved: buffer.clone(),
+            matches,
+            index: None,
+            shown: buffer.content.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(cmds: &[&str]) -> History {
+        let cmds: Vec<String> = cmds.iter().map(|cmd| (*cmd).to_owned()).collect();
+        let mut history = History::default();
+        history.set_load(Some(Arc::new(move || cmds.clone())));
+        history
+    }
+
+    fn buffer(content: &str) -> CodeBuffer {
+        CodeBuffer {
+            content: content.to_owned(),
+            dot: content.len(),
+        }
+    }
+
+    #[test]
+    fn walk() {
+        let mut history = history(&["a", "b", "a", "c"]);
+        let mut buf = buffer("");
+
+        assert!(history.prev(&mut buf));
+        assert_eq!(buf, buffer("c"));
+        assert!(history.prev(&mut buf));
+        assert_eq!(buf, buffer("a"));
+        // The older `a` is a duplicate.
+        assert!(history.prev(&mut buf));
+        assert_eq!(buf, buffer("b"));
+        assert!(!history.prev(&mut buf));
+        assert_eq!(buf, buffer("b"));
+
+        assert!(history.next(&mut buf));
+        assert_eq!(buf, buffer("a"));
+        assert!(history.next(&mut buf));
+        assert_eq!(buf, buffer("c"));
+        assert!(history.next(&mut buf));
+        assert_eq!(buf, buffer(""));
+        assert!(!history.next(&mut buf));
+    }
+
+    #[test]
+    fn prefix() {
+        let mut history = history(&["git status", "ls", "git", "git log"]);
+        let mut buf = CodeBuffer {
+            content: "git".to_owned(),
+            dot: 1,
+        };
+
+        assert!(history.prev(&mut buf));
+        assert_eq!(buf, buffer("git log"));
+        assert!(history.prev(&mut buf));
+        assert_eq!(buf, buffer("git status"));
+        assert!(!history.prev(&mut buf));
+
+        // The saved line comes back with its dot.
+        history.next(&mut buf);
+        history.next(&mut buf);
+        assert_eq!(buf.content, "git");
+        assert_eq!(buf.dot, 1);
+    }
+
+    #[test]
+    fn multiline() {
+        let cmd = "if a {\n  b\n}";
+        let mut history = history(&["x", cmd]);
+        let mut buf = buffer("");
+
+        // Going up the dot is at the end of the command, and going down at
+        // the end of its first line.
+        history.prev(&mut buf);
+        assert_eq!(buf, buffer(cmd));
+        history.prev(&mut buf);
+        history.next(&mut buf);
+        assert_eq!(buf.content, cmd);
+        assert_eq!(buf.dot, "if a {".len());
+    }
+
+    #[test]
+    fn editing_restarts() {
+        let mut history = history(&["ab", "abc", "x"]);
+        let mut buf = buffer("");
+
+        history.prev(&mut buf);
+        history.prev(&mut buf);
+        assert_eq!(buf, buffer("abc"));
+
+        // Editing the command shown starts a new walk from it.
+        buf.content.pop();
+        buf.dot -= 1;
+        assert!(!history.next(&mut buf));
+        assert!(history.prev(&mut buf));
+        assert_eq!(buf, buffer("abc"));
+        assert!(!history.prev(&mut buf));
+        assert!(history.next(&mut buf));
+        assert_eq!(buf, buffer("ab"));
+    }
+
+    #[test]
+    fn without_history() {
+        let mut history = History::default();
+        let mut buf = buffer("a");
+        assert!(!history.prev(&mut buf));
+        assert!(!history.next(&mut buf));
+        assert_eq!(buf, buffer("a"));
+    }
+}
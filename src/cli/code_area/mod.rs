@@ -1,3 +1,5 @@
+mod history;
+mod vi;
 mod view;
 
 use std::sync::Arc;
@@ -7,13 +9,19 @@ use async_trait::async_trait;
 use tokio::sync::mpsc::Sender;
 use tokio::sync::RwLock;
 
+pub use self::history::HistoryFn;
+
+use self::history::History;
+use self::vi::Vi;
 use self::view::View;
 
 use crate::cli::app::Return;
+use crate::cli::keymap::{Action, Mode};
 use crate::cli::prompt::PromptHandle;
 use crate::cli::term::buffer::Buffer;
-use crate::cli::tty::{Event, KeyCode, KeyEvent};
+use crate::cli::tty::{Event, KeyCode, KeyEvent, KeyModifiers};
 use crate::cli::widget::{Handle, Render, Widget};
+use crate::parse;
 
 // TODO: Overlay handler.
 // TODO: Highlighter.
@@ -35,6 +43,8 @@ pub struct CodeArea {
     inserts: String,
     last_buffer: Option<CodeBuffer>,
     return_tx: Sender<Result<Return>>,
+    vi: Vi,
+    history: History,
     // TODO: Pasting and paste buffer?
 }
 
@@ -43,6 +53,8 @@ pub struct CodeAreaState {
     pub buffer: CodeBuffer,
     pub pending: PendingCode,
     pub hide_rprompt: bool,
+    /// The other end of the selection from the dot, in visual mode.
+    pub visual: Option<usize>,
 }
 
 /// Buffer for the CodeArea.
@@ -80,6 +92,157 @@ impl CodeBuffer {
         self.content.insert(self.dot, c);
         self.dot += c.len_utf8();
     }
+
+    /// The index of the char before the dot, or the dot at the start.
+    fn prev_char(&self) -> usize {
+        match self.content[..self.dot].chars().next_back() {
+            Some(c) => self.dot - c.len_utf8(),
+            None => self.dot,
+        }
+    }
+
+    /// The index after the char at the dot, or the dot at the end.
+    fn next_char(&self) -> usize {
+        match self.content[self.dot..].chars().next() {
+            Some(c) => self.dot + c.len_utf8(),
+            None => self.dot,
+        }
+    }
+
+    /// The index of the start of the line the dot is on.
+    fn line_start(&self) -> usize {
+        self.content[..self.dot].rfind('\n').map_or(0, |i| i + 1)
+    }
+
+    /// The index of the end of the line the dot is on, before its newline.
+    fn line_end(&self) -> usize {
+        self.content[self.dot..]
+            .find('\n')
+            .map_or(self.content.len(), |i| self.dot + i)
+    }
+
+    /// The index of the start of the word before the dot, skipping the chars
+    /// between it and the dot that are not part of a word.
+    fn prev_word(&self, is_word: fn(char) -> bool) -> usize {
+        let before = &self.content[..self.dot];
+        let end = before.trim_end_matches(|c| !is_word(c)).len();
+        before[..end].trim_end_matches(is_word).len()
+    }
+
+    /// The index of the end of the word after the dot, skipping the chars
+    /// between the dot and it that are not part of a word.
+    fn next_word(&self, is_word: fn(char) -> bool) -> usize {
+        let after = &self.content[self.dot..];
+        let start = after.len() - after.trim_start_matches(|c| !is_word(c)).len();
+        let end = after.len() - after[start..].trim_start_matches(is_word).len();
+        self.dot + end
+    }
+
+    /// Removes the text between the dot and another index, leaving the dot at
+    /// the start of the text removed.
+    fn remove_to(&mut self, index: usize) {
+        let (from, to) = if index < self.dot {
+            (index, self.dot)
+        } else {
+            (self.dot, index)
+        };
+        self.content.replace_range(from..to, "");
+        self.dot = from;
+    }
+
+    pub fn move_left(&mut self) {
+        self.dot = self.prev_char();
+    }
+
+    pub fn move_right(&mut self) {
+        self.dot = self.next_char();
+    }
+
+    pub fn move_line_start(&mut self) {
+        self.dot = self.line_start();
+    }
+
+    pub fn move_line_end(&mut self) {
+        self.dot = self.line_end();
+    }
+
+    /// Moves the dot to the same column of the line above, or of its end if
+    /// shorter, returning false on the first line.
+    pub fn move_line_up(&mut self) -> bool {
+        match self.line_start().checked_sub(1) {
+            Some(above) => {
+                self.move_to_line(above);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Moves the dot to the same column of the line below, or of its end if
+    /// shorter, returning false on the last line.
+    pub fn move_line_down(&mut self) -> bool {
+        let end = self.line_end();
+        if end < self.content.len() {
+            self.move_to_line(end + 1);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Moves the dot to the column it is in on the line with an index.
+    fn move_to_line(&mut self, index: usize) {
+        let column = self.content[self.line_start()..self.dot].chars().count();
+        self.dot = index;
+        let (start, end) = (self.line_start(), self.line_end());
+        self.dot = self.content[start..end]
+            .char_indices()
+            .nth(column)
+            .map_or(end, |(i, _)| start + i);
+    }
+
+    pub fn move_word_left(&mut self) {
+        self.dot = self.prev_word(is_alnum_word);
+    }
+
+    pub fn move_word_right(&mut self) {
+        self.dot = self.next_word(is_alnum_word);
+    }
+
+    pub fn delete_left(&mut self) {
+        self.remove_to(self.prev_char());
+    }
+
+    pub fn delete_right(&mut self) {
+        self.remove_to(self.next_char());
+    }
+
+    /// Removes the word before the dot, with words separated by whitespace.
+    pub fn kill_word_left(&mut self) {
+        self.remove_to(self.prev_word(is_space_word));
+    }
+
+    pub fn kill_word_right(&mut self) {
+        self.remove_to(self.next_word(is_alnum_word));
+    }
+
+    pub fn kill_line_left(&mut self) {
+        self.remove_to(self.line_start());
+    }
+
+    pub fn kill_line_right(&mut self) {
+        self.remove_to(self.line_end());
+    }
+}
+
+/// A char of a word for word motions, as in `foo_bar`.
+fn is_alnum_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// A char of a word separated by whitespace, as in `--foo=bar`.
+fn is_space_word(c: char) -> bool {
+    !c.is_whitespace()
 }
 
 impl Widget for CodeArea {}
@@ -138,6 +301,8 @@ impl CodeArea {
             inserts: String::new(),
             last_buffer: None,
             return_tx,
+            vi: Vi::default(),
+            history: History::default(),
         }
     }
 
@@ -153,7 +318,7 @@ impl CodeArea {
     #[inline]
     pub async fn mutate_state<F>(&mut self, f: F)
     where
-        F: FnOnce(&mut CodeAreaState) -> (),
+        F: FnOnce(&mut CodeAreaState),
     {
         let mut state = self.state.write().await;
         f(&mut state);
@@ -177,37 +342,121 @@ impl CodeArea {
         reset_inserts!(self);
     }
 
-    async fn handle_key_event(&mut self, key: KeyEvent) -> bool {
-        // TODO: Overlay handler: handle key.
+    /// Moves the dot or edits the buffer other than by inserting chars.
+    async fn edit(&mut self, f: fn(&mut CodeBuffer)) -> bool {
+        self.reset_inserts();
+        self.mutate_state(|state| f(&mut state.buffer)).await;
+        true
+    }
 
-        match key.code {
-            KeyCode::Enter => {
+    /// Runs an action of the code area, returning whether it was handled.
+    pub async fn run_action(&mut self, action: Action) -> bool {
+        match action {
+            Action::Submit => {
                 self.reset_inserts();
-                self.submit().await;
+
+                let mut state = self.state.write().await;
+                if parse::is_incomplete(&state.buffer.content) {
+                    // Continue the code on a new line, rather than submitting.
+                    state.buffer.insert_char_at_dot('\n');
+                } else {
+                    drop(state);
+                    self.submit().await;
+                }
+
                 true
             }
-            KeyCode::Backspace => {
+            Action::InsertNewline => self.edit(|buf| buf.insert_char_at_dot('\n')).await,
+            Action::MoveLeft => self.edit(CodeBuffer::move_left).await,
+            Action::MoveRight => self.edit(CodeBuffer::move_right).await,
+            Action::MoveLineStart => self.edit(CodeBuffer::move_line_start).await,
+            Action::MoveLineEnd => self.edit(CodeBuffer::move_line_end).await,
+            Action::MoveWordLeft => self.edit(CodeBuffer::move_word_left).await,
+            Action::MoveWordRight => self.edit(CodeBuffer::move_word_right).await,
+            Action::DeleteLeft => self.edit(CodeBuffer::delete_left).await,
+            Action::DeleteRight => self.edit(CodeBuffer::delete_right).await,
+            Action::KillWordLeft => self.edit(CodeBuffer::kill_word_left).await,
+            Action::KillWordRight => self.edit(CodeBuffer::kill_word_right).await,
+            Action::KillLineLeft => self.edit(CodeBuffer::kill_line_left).await,
+            Action::KillLineRight => self.edit(CodeBuffer::kill_line_right).await,
+            Action::HistoryUp => {
                 self.reset_inserts();
-                self.mutate_state(|state| {
-                    let mut buf = &mut state.buffer;
-
-                    // Check the cursor is not at the start of the buffer and the
-                    // buffer is not empty.
-                    if buf.dot > 0 && !buf.content.is_empty() {
-                        if buf.dot == buf.content.len() {
-                            buf.content.pop();
-                            buf.dot = buf.content.len();
-                        } else {
-                            let c = buf.content.remove(buf.dot);
-                            buf.dot -= c.len_utf8();
-                        }
-                    }
-                })
-                .await;
-
+                let mut state = self.state.write().await;
+                if !state.buffer.move_line_up() {
+                    self.history.prev(&mut state.buffer);
+                }
                 true
             }
-            KeyCode::Char(c) if key.modifiers.is_empty() => {
+            Action::HistoryDown => {
+                self.reset_inserts();
+                let mut state = self.state.write().await;
+                if !state.buffer.move_line_down() {
+                    self.history.next(&mut state.buffer);
+                }
+                true
+            }
+            // Actions of the app.
+            Action::DiscardLine | Action::Eof | Action::ViNormalMode | Action::ViInsertMode => {
+                false
+            }
+        }
+    }
+
+    /// Prepares for a new line, in insert mode.
+    pub async fn start_line(&mut self) {
+        self.history.reset_line();
+        self.vi.reset_line();
+        let buffer = self.state.read().await.buffer.clone();
+        self.vi.enter_insert(&buffer);
+    }
+
+    /// Sets the function loading the history walked through by up and down.
+    pub fn set_history(&mut self, load: Option<HistoryFn>) {
+        self.history.set_load(load);
+    }
+
+    /// Whether a vi command is being read, so keys are not looked up in a
+    /// keymap until it is complete.
+    pub fn vi_pending(&self) -> bool {
+        self.vi.is_pending()
+    }
+
+    /// Handles a key in a mode of vi, returning the mode to change to.
+    pub async fn vi_key(&mut self, key: KeyEvent, mode: Mode) -> Option<Mode> {
+        self.reset_inserts();
+        let mut state = self.state.write().await;
+        self.vi.handle_key(key, &mut state, mode)
+    }
+
+    /// Enters the normal mode of vi from another mode.
+    pub async fn vi_normal_mode(&mut self, from: Mode) {
+        self.reset_inserts();
+        let mut state = self.state.write().await;
+        if from == Mode::Insert {
+            self.vi.leave_insert(&mut state.buffer);
+        }
+        state.visual = None;
+    }
+
+    /// Moves the dot off the end of a line, as in the modes of vi.
+    pub async fn vi_clamp(&mut self) {
+        let mut state = self.state.write().await;
+        state.buffer.dot = vi::clamp_normal(&state.buffer.content, state.buffer.dot);
+    }
+
+    /// Enters insert mode from a mode of vi.
+    pub async fn vi_insert_mode(&mut self) {
+        let mut state = self.state.write().await;
+        state.visual = None;
+        self.vi.enter_insert(&state.buffer);
+    }
+
+    /// Handles a key with no binding, inserting it if it is a char.
+    async fn handle_key_event(&mut self, key: KeyEvent) -> bool {
+        // TODO: Overlay handler: handle key.
+
+        match key.code {
+            KeyCode::Char(c) if (key.modifiers - KeyModifiers::SHIFT).is_empty() => {
                 let mut state = self.state.write().await;
 
                 // Check if something has happened to the buffer, if so reset the state.
@@ -227,7 +476,7 @@ impl CodeArea {
 
                 true
             }
-            // Functional key with no binding.
+            // Functional key.
             _ => {
                 self.reset_inserts();
                 false
@@ -235,3 +484,110 @@ impl CodeArea {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Applies an edit to a buffer with the dot written as `|`.
+    fn edit(text: &str, f: fn(&mut CodeBuffer)) -> String {
+        let mut buffer = CodeBuffer {
+            content: text.replacen('|', "", 1),
+            dot: text.find('|').expect("no dot in text"),
+        };
+        f(&mut buffer);
+        buffer.content.insert(buffer.dot, '|');
+        buffer.content
+    }
+
+    #[test]
+    fn insert() {
+        assert_eq!(edit("a|b", |buf| buf.insert_at_dot("xy")), "axy|b");
+        assert_eq!(edit("a|b", |buf| buf.insert_char_at_dot('é')), "aé|b");
+    }
+
+    #[test]
+    fn move_chars() {
+        assert_eq!(edit("aé|b", CodeBuffer::move_left), "a|éb");
+        assert_eq!(edit("a|éb", CodeBuffer::move_right), "aé|b");
+        assert_eq!(edit("|ab", CodeBuffer::move_left), "|ab");
+        assert_eq!(edit("ab|", CodeBuffer::move_right), "ab|");
+    }
+
+    #[test]
+    fn move_lines() {
+        assert_eq!(
+            edit("ab\nc|d\nef", CodeBuffer::move_line_start),
+            "ab\n|cd\nef"
+        );
+        assert_eq!(
+            edit("ab\nc|d\nef", CodeBuffer::move_line_end),
+            "ab\ncd|\nef"
+        );
+
+        assert_eq!(
+            edit("abc\ndé|f", |buf| assert!(buf.move_line_up())),
+            "ab|c\ndéf"
+        );
+        assert_eq!(
+            edit("abc\nx\nab|c", |buf| assert!(buf.move_line_up())),
+            "abc\nx|\nabc"
+        );
+        assert_eq!(
+            edit("a|bc\nd", |buf| assert!(!buf.move_line_up())),
+            "a|bc\nd"
+        );
+
+        assert_eq!(
+            edit("éb|c\ndef", |buf| assert!(buf.move_line_down())),
+            "ébc\nde|f"
+        );
+        assert_eq!(
+            edit("ab|c\n\nd", |buf| assert!(buf.move_line_down())),
+            "abc\n|\nd"
+        );
+        assert_eq!(
+            edit("a\nb|c", |buf| assert!(!buf.move_line_down())),
+            "a\nb|c"
+        );
+    }
+
+    #[test]
+    fn move_words() {
+        assert_eq!(
+            edit("foo_bar.baz  |x", CodeBuffer::move_word_left),
+            "foo_bar.|baz  x"
+        );
+        assert_eq!(
+            edit("foo_bar.|baz", CodeBuffer::move_word_left),
+            "|foo_bar.baz"
+        );
+        assert_eq!(
+            edit("|foo_bar.baz", CodeBuffer::move_word_right),
+            "foo_bar|.baz"
+        );
+        assert_eq!(edit("foo|  bar", CodeBuffer::move_word_right), "foo  bar|");
+    }
+
+    #[test]
+    fn deletes() {
+        assert_eq!(edit("aé|b", CodeBuffer::delete_left), "a|b");
+        assert_eq!(edit("a|éb", CodeBuffer::delete_right), "a|b");
+        assert_eq!(edit("|ab", CodeBuffer::delete_left), "|ab");
+        assert_eq!(edit("ab|", CodeBuffer::delete_right), "ab|");
+    }
+
+    #[test]
+    fn kills() {
+        assert_eq!(edit("ls --foo=bar |x", CodeBuffer::kill_word_left), "ls |x");
+        assert_eq!(edit("a|.bc d", CodeBuffer::kill_word_right), "a| d");
+        assert_eq!(
+            edit("ab\nc|d\nef", CodeBuffer::kill_line_left),
+            "ab\n|d\nef"
+        );
+        assert_eq!(
+            edit("ab\nc|d\nef", CodeBuffer::kill_line_right),
+            "ab\nc|\nef"
+        );
+    }
+}
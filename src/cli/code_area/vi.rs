@@ -0,0 +1,1343 @@
+//! Modal editing in the style of vi, for the normal and visual modes.
+//!
+//! Keys with no binding in the keymap of the mode are read as vi commands,
+//! such as `d2w` or `"ayiw`, which are run once complete.
+
+use std::collections::HashMap;
+
+use super::{CodeAreaState, CodeBuffer};
+
+use crate::cli::keymap::Mode;
+use crate::cli::tty::{KeyCode, KeyEvent, KeyModifiers};
+
+const ESC: char = '\x1b';
+
+/// The largest count of a command, so a mistyped count does not hang.
+const MAX_COUNT: usize = 10_000;
+
+/// The state of vi commands, kept between lines except for the undo history.
+#[derive(Debug, Default)]
+pub struct Vi {
+    /// The keys of the command being read.
+    pending: Vec<char>,
+    registers: HashMap<char, Register>,
+    /// The last `f`, `F`, `t` or `T` motion, repeated by `;` and `,`.
+    last_find: Option<Find>,
+    /// The last command that changed the buffer, repeated by `.`.
+    last_change: Option<Change>,
+    /// The buffer before each change, restored by `u`.
+    undo: Vec<CodeBuffer>,
+    /// The insert mode session being made, if any.
+    insert: Option<Insert>,
+}
+
+#[derive(Clone, Debug)]
+struct Register {
+    text: String,
+    /// Whether the text is of whole lines, put on lines of their own.
+    linewise: bool,
+}
+
+#[derive(Clone, Debug)]
+struct Change {
+    command: Command,
+    /// The text inserted after the command, if it entered insert mode.
+    inserted: String,
+}
+
+#[derive(Clone, Debug)]
+struct Insert {
+    /// The buffer before the command that entered insert mode.
+    undo: CodeBuffer,
+    /// The buffer when insert mode was entered.
+    from: CodeBuffer,
+    /// The command that entered insert mode, to be repeated with the text
+    /// inserted.
+    command: Option<Command>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Command {
+    register: Option<char>,
+    count: Option<usize>,
+    kind: Kind,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Kind {
+    Move(Motion),
+    /// An operator on the text of a target.
+    Operate(Op, Target),
+    /// An operator on the selection of visual mode.
+    OperateSelection(Op),
+    /// Puts the text of a register after the dot, or before, or in place of
+    /// the selection in visual mode.
+    Put {
+        before: bool,
+    },
+    Replace(char),
+    ToggleCase,
+    /// Enters insert mode, after moving the dot or opening a line.
+    Insert(char),
+    Undo,
+    Repeat,
+    /// Enters or leaves visual mode.
+    Visual,
+    /// Moves the dot to the other end of the selection.
+    SwapAnchor,
+    /// Selects a text object in visual mode.
+    Select(Object),
+    Escape,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Op {
+    Delete,
+    Change,
+    Yank,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Target {
+    Motion(Motion),
+    Object(Object),
+    /// Whole lines, when an operator is doubled as in `dd`.
+    Line,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    /// `w` and `W`, with `true` for words separated by whitespace.
+    WordStart(bool),
+    WordBack(bool),
+    WordEnd(bool),
+    LineStart,
+    FirstNonBlank,
+    LineEnd,
+    Find(Find),
+    /// `;` and `,`, with `true` for the reverse direction.
+    RepeatFind(bool),
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Find {
+    c: char,
+    /// Whether the dot stops before the char, as with `t`.
+    till: bool,
+    backward: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Object {
+    /// Whether the object is without its delimiters or surrounding
+    /// whitespace, as with `iw`.
+    inner: bool,
+    kind: ObjectKind,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum ObjectKind {
+    Word(bool),
+    Quote(char),
+    Pair(char, char),
+}
+
+/// A range of the buffer to operate on.
+#[derive(Clone, Copy, Debug)]
+enum Range {
+    Chars(usize, usize),
+    /// Whole lines, from the start of the first to the end of the last,
+    /// before its newline.
+    Lines(usize, usize),
+}
+
+enum Parse<T> {
+    Done(T),
+    Incomplete,
+    Invalid,
+}
+
+impl Vi {
+    /// Resets the state kept for a line, at the start of a new one.
+    pub fn reset_line(&mut self) {
+        self.pending.clear();
+        self.undo.clear();
+        self.insert = None;
+    }
+
+    /// Whether some keys of a command have been read.
+    pub fn is_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Starts an insert mode session, other than by a command.
+    pub fn enter_insert(&mut self, buffer: &CodeBuffer) {
+        self.insert = Some(Insert {
+            undo: buffer.clone(),
+            from: buffer.clone(),
+            command: None,
+        });
+    }
+
+    /// Ends the insert mode session, moving the dot onto the char before it
+    /// as normal mode does.
+    pub fn leave_insert(&mut self, buffer: &mut CodeBuffer) {
+        if let Some(insert) = self.insert.take() {
+            if *buffer != insert.undo {
+                self.undo.push(insert.undo);
+            }
+            if let Some(command) = insert.command {
+                let inserted = inserted_text(&insert.from, buffer).unwrap_or_default();
+                self.last_change = Some(Change { command, inserted });
+            }
+        }
+
+        if buffer.dot > line_start(&buffer.content, buffer.dot) {
+            buffer.dot = prev_char(&buffer.content, buffer.dot);
+        }
+    }
+
+    /// Handles a key in normal or visual mode, returning the mode to change
+    /// to if the command changes it.
+    pub fn handle_key(
+        &mut self,
+        key: KeyEvent,
+        state: &mut CodeAreaState,
+        mode: Mode,
+    ) -> Option<Mode> {
+        let c = match key_char(key) {
+            Some(c) => c,
+            None => {
+                self.pending.clear();
+                return None;
+            }
+        };
+        self.pending.push(c);
+
+        let visual = mode == Mode::Visual;
+        match parse(&self.pending, visual) {
+            Parse::Done(command) => {
+                self.pending.clear();
+                self.execute(command, state, visual)
+            }
+            Parse::Incomplete => None,
+            Parse::Invalid => {
+                self.pending.clear();
+                None
+            }
+        }
+    }
+
+    fn execute(
+        &mut self,
+        command: Command,
+        state: &mut CodeAreaState,
+        visual: bool,
+    ) -> Option<Mode> {
+        let before = state.buffer.clone();
+
+        let mode = match command.kind {
+            Kind::Undo => {
+                self.undo(&mut state.buffer);
+                None
+            }
+            Kind::Repeat => {
+                self.repeat(command.count, state);
+                None
+            }
+            _ => {
+                let mode = self.run(command, state, visual);
+                if mode == Some(Mode::Insert) {
+                    self.insert = Some(Insert {
+                        undo: before,
+                        from: state.buffer.clone(),
+                        command: if visual { None } else { Some(command) },
+                    });
+                } else if state.buffer != before {
+                    self.undo.push(before);
+                    if !visual && command.is_change() {
+                        self.last_change = Some(Change {
+                            command,
+                            inserted: String::new(),
+                        });
+                    }
+                }
+                mode
+            }
+        };
+
+        match mode {
+            Some(Mode::Visual) => state.visual = Some(state.buffer.dot),
+            Some(_) => state.visual = None,
+            None => {}
+        }
+        if mode != Some(Mode::Insert) {
+            let buffer = &mut state.buffer;
+            buffer.dot = clamp_normal(&buffer.content, buffer.dot);
+        }
+
+        mode
+    }
+
+    /// Runs a command, returning the mode to change to.
+    fn run(&mut self, command: Command, state: &mut CodeAreaState, visual: bool) -> Option<Mode> {
+        let count = command.count.unwrap_or(1);
+        let selection = state.visual.map(|anchor| {
+            let buffer = &state.buffer;
+            let (from, to) = min_max(anchor, buffer.dot);
+            (from, next_char(&buffer.content, to))
+        });
+        let buffer = &mut state.buffer;
+
+        match command.kind {
+            Kind::Move(motion) => {
+                if let Some(target) = self.motion(buffer, motion, count) {
+                    buffer.dot = target;
+                }
+                None
+            }
+            Kind::Operate(op, target) => {
+                let range = self.range(buffer, op, target, count)?;
+                self.operate(buffer, op, range, command.register)
+            }
+            Kind::OperateSelection(op) => {
+                let (from, to) = selection?;
+                self.operate(buffer, op, Range::Chars(from, to), command.register)
+                    .or(Some(Mode::Normal))
+            }
+            Kind::Put { before } => {
+                let register = self.register(command.register)?.clone();
+                match selection {
+                    Some((from, to)) => {
+                        buffer.content.replace_range(from..to, &register.text);
+                        buffer.dot = prev_char(&buffer.content, from + register.text.len());
+                        Some(Mode::Normal)
+                    }
+                    None => {
+                        put(buffer, &register, count, before);
+                        None
+                    }
+                }
+            }
+            Kind::Replace(c) => {
+                let s = &buffer.content;
+                let end = (0..count).try_fold(buffer.dot, |i, _| match char_at(s, i) {
+                    Some(c) if c != '\n' => Some(i + c.len_utf8()),
+                    _ => None,
+                })?;
+                let with: String = std::iter::repeat(c).take(count).collect();
+                buffer.content.replace_range(buffer.dot..end, &with);
+                buffer.dot += with.len() - c.len_utf8();
+                None
+            }
+            Kind::ToggleCase => {
+                let (from, to) = match selection {
+                    Some(selection) => selection,
+                    None => {
+                        let s = &buffer.content;
+                        let end = line_end(s, buffer.dot);
+                        let to = (0..count).fold(buffer.dot, |i, _| next_char(s, i).min(end));
+                        (buffer.dot, to)
+                    }
+                };
+                let toggled: String = buffer.content[from..to].chars().map(toggle_case).collect();
+                buffer.content.replace_range(from..to, &toggled);
+                buffer.dot = if visual { from } else { from + toggled.len() };
+                if visual {
+                    Some(Mode::Normal)
+                } else {
+                    None
+                }
+            }
+            Kind::Insert(c) => {
+                let s = &buffer.content;
+                match c {
+                    'a' => buffer.dot = next_char(s, buffer.dot).min(line_end(s, buffer.dot)),
+                    'I' => buffer.dot = first_non_blank(s, buffer.dot),
+                    'A' => buffer.dot = line_end(s, buffer.dot),
+                    'o' => {
+                        buffer.dot = line_end(s, buffer.dot);
+                        buffer.insert_char_at_dot('\n');
+                    }
+                    'O' => {
+                        buffer.dot = line_start(s, buffer.dot);
+                        buffer.insert_char_at_dot('\n');
+                        buffer.dot -= 1;
+                    }
+                    _ => {}
+                }
+                Some(Mode::Insert)
+            }
+            Kind::Visual if visual => Some(Mode::Normal),
+            Kind::Visual => Some(Mode::Visual),
+            Kind::SwapAnchor => {
+                if let Some(anchor) = state.visual.as_mut() {
+                    std::mem::swap(anchor, &mut buffer.dot);
+                }
+                None
+            }
+            Kind::Select(object) => {
+                let (from, to) = object_range(&buffer.content, buffer.dot, object)?;
+                state.visual = Some(from);
+                buffer.dot = prev_char(&buffer.content, to).max(from);
+                None
+            }
+            Kind::Escape if visual => Some(Mode::Normal),
+            Kind::Escape | Kind::Undo | Kind::Repeat => None,
+        }
+    }
+
+    /// Restores the buffer before the last change.
+    fn undo(&mut self, buffer: &mut CodeBuffer) {
+        while let Some(before) = self.undo.pop() {
+            if before != *buffer {
+                *buffer = before;
+                return;
+            }
+        }
+    }
+
+    /// Repeats the last change, with a new count if given.
+    fn repeat(&mut self, count: Option<usize>, state: &mut CodeAreaState) {
+        let mut change = match self.last_change.clone() {
+            Some(change) => change,
+            None => return,
+        };
+        if count.is_some() {
+            change.command.count = count;
+        }
+
+        let before = state.buffer.clone();
+        if self.run(change.command, state, false) == Some(Mode::Insert) {
+            let buffer = &mut state.buffer;
+            buffer.insert_at_dot(&change.inserted);
+            if buffer.dot > line_start(&buffer.content, buffer.dot) {
+                buffer.dot = prev_char(&buffer.content, buffer.dot);
+            }
+        }
+        if state.buffer != before {
+            self.undo.push(before);
+        }
+        self.last_change = Some(change);
+    }
+
+    /// The index a motion moves the dot to, if it can be made.
+    fn motion(&mut self, buffer: &CodeBuffer, motion: Motion, count: usize) -> Option<usize> {
+        let s = &*buffer.content;
+        let dot = buffer.dot;
+
+        let find = match motion {
+            Motion::Find(find) => {
+                self.last_find = Some(find);
+                Some(find)
+            }
+            Motion::RepeatFind(reverse) => {
+                let mut find = self.last_find?;
+                find.backward ^= reverse;
+                Some(find)
+            }
+            _ => None,
+        };
+        if let Some(find) = find {
+            let repeat = matches!(motion, Motion::RepeatFind(_));
+            return (0..count).try_fold(dot, |i, n| find_char(s, i, find, repeat || n > 0));
+        }
+
+        let target = match motion {
+            Motion::Left => {
+                let start = line_start(s, dot);
+                (0..count).fold(dot, |i, _| prev_char(s, i).max(start))
+            }
+            Motion::Right => {
+                let end = line_end(s, dot);
+                (0..count).fold(dot, |i, _| next_char(s, i).min(end))
+            }
+            Motion::Up | Motion::Down => {
+                let column = s[line_start(s, dot)..dot].chars().count();
+                let start = (0..count).try_fold(line_start(s, dot), |start, _| {
+                    if motion == Motion::Up {
+                        start.checked_sub(1).map(|i| line_start(s, i))
+                    } else {
+                        let end = line_end(s, start);
+                        (end < s.len()).then_some(end + 1)
+                    }
+                })?;
+                let end = line_end(s, start);
+                s[start..end]
+                    .char_indices()
+                    .nth(column)
+                    .map_or(end, |(i, _)| start + i)
+            }
+            Motion::WordStart(big) => (0..count).fold(dot, |i, _| word_start(s, i, big)),
+            Motion::WordBack(big) => (0..count).fold(dot, |i, _| word_back(s, i, big)),
+            Motion::WordEnd(big) => (0..count).fold(dot, |i, _| word_end(s, i, big)),
+            Motion::LineStart => line_start(s, dot),
+            Motion::FirstNonBlank => first_non_blank(s, dot),
+            Motion::LineEnd => line_end(s, dot),
+            Motion::Find(_) | Motion::RepeatFind(_) => unreachable!(),
+        };
+        Some(target)
+    }
+
+    /// The range of an operator on a target.
+    fn range(
+        &mut self,
+        buffer: &CodeBuffer,
+        op: Op,
+        target: Target,
+        count: usize,
+    ) -> Option<Range> {
+        let s = &*buffer.content;
+        let dot = buffer.dot;
+
+        match target {
+            Target::Line => {
+                let end = (1..count).fold(line_end(s, dot), |end, _| {
+                    if end < s.len() {
+                        line_end(s, end + 1)
+                    } else {
+                        end
+                    }
+                });
+                Some(Range::Lines(line_start(s, dot), end))
+            }
+            Target::Object(object) => {
+                let (from, to) = object_range(s, dot, object)?;
+                Some(Range::Chars(from, to))
+            }
+            // As in vi, `cw` changes to the end of the word rather than the
+            // start of the next.
+            Target::Motion(Motion::WordStart(big))
+                if op == Op::Change && class(char_at(s, dot), big) != Class::Space =>
+            {
+                let mut end = dot;
+                for n in 0..count {
+                    if n > 0 || class(next_char_at(s, end), big) == class(char_at(s, end), big) {
+                        end = word_end(s, end, big);
+                    }
+                }
+                Some(Range::Chars(dot, next_char(s, end)))
+            }
+            Target::Motion(motion) => {
+                let target = self.motion(buffer, motion, count)?;
+                let (from, to) = min_max(dot, target);
+                match motion {
+                    Motion::Up | Motion::Down => {
+                        Some(Range::Lines(line_start(s, from), line_end(s, to)))
+                    }
+                    // A word motion from the last word of a line stops at
+                    // its end.
+                    Motion::WordStart(_) => {
+                        Some(Range::Chars(from, to.min(line_end(s, dot).max(from))))
+                    }
+                    _ if is_inclusive(motion, self.last_find) && target >= dot => {
+                        Some(Range::Chars(from, next_char(s, to)))
+                    }
+                    _ => Some(Range::Chars(from, to)),
+                }
+            }
+        }
+    }
+
+    /// Applies an operator to a range, returning the mode to change to.
+    fn operate(
+        &mut self,
+        buffer: &mut CodeBuffer,
+        op: Op,
+        range: Range,
+        register: Option<char>,
+    ) -> Option<Mode> {
+        let (from, to, linewise) = match range {
+            Range::Chars(from, to) => (from, to, false),
+            Range::Lines(from, to) => (from, to, true),
+        };
+        let text = buffer.content[from..to].to_owned();
+        self.store(register, text, linewise, op == Op::Yank);
+
+        match op {
+            Op::Yank => {
+                if !linewise {
+                    buffer.dot = from;
+                }
+                None
+            }
+            Op::Delete if linewise => {
+                let s = &buffer.content;
+                let (from, to) = if to < s.len() {
+                    (from, to + 1)
+                } else {
+                    (from.saturating_sub(1), to)
+                };
+                buffer.content.replace_range(from..to, "");
+                buffer.dot = first_non_blank(&buffer.content, from.min(buffer.content.len()));
+                None
+            }
+            Op::Delete | Op::Change => {
+                buffer.content.replace_range(from..to, "");
+                buffer.dot = from;
+                if op == Op::Change {
+                    Some(Mode::Insert)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    /// Stores text in a register, and in the unnamed register.
+    fn store(&mut self, register: Option<char>, text: String, linewise: bool, yank: bool) {
+        let new = Register { text, linewise };
+        match register {
+            Some('_') => return,
+            Some(r) if r.is_ascii_uppercase() => {
+                let register = self
+                    .registers
+                    .entry(r.to_ascii_lowercase())
+                    .or_insert(Register {
+                        text: String::new(),
+                        linewise,
+                    });
+                if register.linewise && !register.text.is_empty() {
+                    register.text.push('\n');
+                }
+                register.text.push_str(&new.text);
+                let register = register.clone();
+                self.registers.insert('"', register);
+                return;
+            }
+            Some(r) if r != '"' => {
+                self.registers.insert(r, new.clone());
+            }
+            _ if yank => {
+                self.registers.insert('0', new.clone());
+            }
+            _ => {}
+        }
+        self.registers.insert('"', new);
+    }
+
+    fn register(&self, register: Option<char>) -> Option<&Register> {
+        let r = register.map_or('"', |r| r.to_ascii_lowercase());
+        self.registers.get(&r)
+    }
+}
+
+impl Command {
+    /// Whether the command changes the buffer, so is repeated by `.`.
+    fn is_change(&self) -> bool {
+        match self.kind {
+            Kind::Operate(op, _) => op != Op::Yank,
+            Kind::Put { .. } | Kind::Replace(_) | Kind::ToggleCase | Kind::Insert(_) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Puts the text of a register after or before the dot, `count` times.
+fn put(buffer: &mut CodeBuffer, register: &Register, count: usize, before: bool) {
+    let s = &buffer.content;
+    if register.linewise {
+        let text = vec![&*register.text; count].join("\n");
+        let at = if before {
+            let at = line_start(s, buffer.dot);
+            buffer.content.insert_str(at, &format!("{}\n", text));
+            at
+        } else {
+            let at = line_end(s, buffer.dot);
+            buffer.content.insert_str(at, &format!("\n{}", text));
+            at + 1
+        };
+        buffer.dot = first_non_blank(&buffer.content, at);
+    } else {
+        let text = register.text.repeat(count);
+        let at = if before || s.is_empty() {
+            buffer.dot
+        } else {
+            next_char(s, buffer.dot)
+        };
+        buffer.content.insert_str(at, &text);
+        buffer.dot = prev_char(&buffer.content, at + text.len()).max(at);
+    }
+}
+
+/// The text inserted in insert mode, if only text was inserted.
+fn inserted_text(from: &CodeBuffer, to: &CodeBuffer) -> Option<String> {
+    let (before, after) = from.content.split_at(from.dot);
+    let inserted = to.content.strip_prefix(before)?.strip_suffix(after)?;
+    Some(inserted.to_owned())
+}
+
+/// Reads a key as a char of a vi command, with some keys read as the command
+/// of the same use.
+fn key_char(key: KeyEvent) -> Option<char> {
+    if !(key.modifiers - KeyModifiers::SHIFT).is_empty() {
+        return None;
+    }
+    match key.code {
+        KeyCode::Char(c) => Some(c),
+        KeyCode::Esc => Some(ESC),
+        KeyCode::Left | KeyCode::Backspace => Some('h'),
+        KeyCode::Right => Some('l'),
+        KeyCode::Up => Some('k'),
+        KeyCode::Down => Some('j'),
+        KeyCode::Home => Some('0'),
+        KeyCode::End => Some('$'),
+        KeyCode::Delete => Some('x'),
+        _ => None,
+    }
+}
+
+fn parse(keys: &[char], visual: bool) -> Parse<Command> {
+    let mut keys = keys;
+
+    let register = match keys {
+        ['"'] => return Parse::Incomplete,
+        ['"', r, rest @ ..] if r.is_ascii_alphanumeric() || *r == '"' || *r == '_' => {
+            keys = rest;
+            Some(*r)
+        }
+        ['"', ..] => return Parse::Invalid,
+        _ => None,
+    };
+    let (count, rest) = parse_count(keys);
+    keys = rest;
+
+    let (c, rest) = match keys {
+        [] => return Parse::Incomplete,
+        [c, rest @ ..] => (*c, rest),
+    };
+
+    let kind = match parse_motion(c, rest) {
+        Parse::Done(motion) => Kind::Move(motion),
+        Parse::Incomplete => return Parse::Incomplete,
+        Parse::Invalid if visual => match (c, rest) {
+            ('d', _) | ('x', _) => Kind::OperateSelection(Op::Delete),
+            ('c', _) | ('s', _) => Kind::OperateSelection(Op::Change),
+            ('y', _) => Kind::OperateSelection(Op::Yank),
+            ('p', _) | ('P', _) => Kind::Put { before: false },
+            ('~', _) => Kind::ToggleCase,
+            ('o', _) => Kind::SwapAnchor,
+            ('v', _) => Kind::Visual,
+            (ESC, _) => Kind::Escape,
+            ('i', rest) | ('a', rest) => match parse_object(c == 'i', rest) {
+                Parse::Done(object) => Kind::Select(object),
+                Parse::Incomplete => return Parse::Incomplete,
+                Parse::Invalid => return Parse::Invalid,
+            },
+            _ => return Parse::Invalid,
+        },
+        Parse::Invalid => match (c, rest) {
+            ('d', rest) | ('c', rest) | ('y', rest) => {
+                let op = match c {
+                    'd' => Op::Delete,
+                    'c' => Op::Change,
+                    _ => Op::Yank,
+                };
+                let (count2, rest) = parse_count(rest);
+                let target = match rest {
+                    [] => return Parse::Incomplete,
+                    [c2] if *c2 == c => Target::Line,
+                    [i @ 'i', rest @ ..] | [i @ 'a', rest @ ..] => {
+                        match parse_object(*i == 'i', rest) {
+                            Parse::Done(object) => Target::Object(object),
+                            Parse::Incomplete => return Parse::Incomplete,
+                            Parse::Invalid => return Parse::Invalid,
+                        }
+                    }
+                    [c2, rest @ ..] => match parse_motion(*c2, rest) {
+                        Parse::Done(motion) => Target::Motion(motion),
+                        Parse::Incomplete => return Parse::Incomplete,
+                        Parse::Invalid => return Parse::Invalid,
+                    },
+                };
+                let count = match (count, count2) {
+                    (Some(a), Some(b)) => Some(a.saturating_mul(b)),
+                    (a, b) => a.or(b),
+                };
+                return Parse::Done(Command {
+                    register,
+                    count,
+                    kind: Kind::Operate(op, target),
+                });
+            }
+            ('x', _) => Kind::Operate(Op::Delete, Target::Motion(Motion::Right)),
+            ('X', _) => Kind::Operate(Op::Delete, Target::Motion(Motion::Left)),
+            ('s', _) => Kind::Operate(Op::Change, Target::Motion(Motion::Right)),
+            ('S', _) => Kind::Operate(Op::Change, Target::Line),
+            ('D', _) => Kind::Operate(Op::Delete, Target::Motion(Motion::LineEnd)),
+            ('C', _) => Kind::Operate(Op::Change, Target::Motion(Motion::LineEnd)),
+            ('Y', _) => Kind::Operate(Op::Yank, Target::Line),
+            ('p', _) => Kind::Put { before: false },
+            ('P', _) => Kind::Put { before: true },
+            ('r', []) => return Parse::Incomplete,
+            ('r', [ESC, ..]) => return Parse::Invalid,
+            ('r', [r, ..]) => Kind::Replace(*r),
+            ('~', _) => Kind::ToggleCase,
+            ('i', _) | ('a', _) | ('I', _) | ('A', _) | ('o', _) | ('O', _) => Kind::Insert(c),
+            ('u', _) => Kind::Undo,
+            ('.', _) => Kind::Repeat,
+            ('v', _) => Kind::Visual,
+            (ESC, _) => Kind::Escape,
+            _ => return Parse::Invalid,
+        },
+    };
+
+    Parse::Done(Command {
+        register,
+        count,
+        kind,
+    })
+}
+
+/// Parses a count, which does not start with `0` as that is a motion.
+fn parse_count(keys: &[char]) -> (Option<usize>, &[char]) {
+    if !matches!(keys.first(), Some('1'..='9')) {
+        return (None, keys);
+    }
+
+    let n = keys
+        .iter()
+        .position(|c| !c.is_ascii_digit())
+        .unwrap_or(keys.len());
+    let count = keys[..n].iter().fold(0usize, |count, c| {
+        let digit = c.to_digit(10).unwrap_or(0) as usize;
+        count.saturating_mul(10).saturating_add(digit)
+    });
+    (Some(count.min(MAX_COUNT)), &keys[n..])
+}
+
+fn parse_motion(c: char, rest: &[char]) -> Parse<Motion> {
+    let motion = match c {
+        'h' => Motion::Left,
+        'l' | ' ' => Motion::Right,
+        'k' => Motion::Up,
+        'j' => Motion::Down,
+        'w' => Motion::WordStart(false),
+        'W' => Motion::WordStart(true),
+        'b' => Motion::WordBack(false),
+        'B' => Motion::WordBack(true),
+        'e' => Motion::WordEnd(false),
+        'E' => Motion::WordEnd(true),
+        '0' => Motion::LineStart,
+        '^' => Motion::FirstNonBlank,
+        '$' => Motion::LineEnd,
+        ';' => Motion::RepeatFind(false),
+        ',' => Motion::RepeatFind(true),
+        'f' | 'F' | 't' | 'T' => match rest.first() {
+            None => return Parse::Incomplete,
+            Some(&ESC) => return Parse::Invalid,
+            Some(&target) => Motion::Find(Find {
+                c: target,
+                till: c == 't' || c == 'T',
+                backward: c == 'F' || c == 'T',
+            }),
+        },
+        _ => return Parse::Invalid,
+    };
+    Parse::Done(motion)
+}
+
+fn parse_object(inner: bool, rest: &[char]) -> Parse<Object> {
+    let kind = match rest.first() {
+        None => return Parse::Incomplete,
+        Some('w') => ObjectKind::Word(false),
+        Some('W') => ObjectKind::Word(true),
+        Some(q @ '"') | Some(q @ '\'') | Some(q @ '`') => ObjectKind::Quote(*q),
+        Some('(') | Some(')') | Some('b') => ObjectKind::Pair('(', ')'),
+        Some('[') | Some(']') => ObjectKind::Pair('[', ']'),
+        Some('{') | Some('}') | Some('B') => ObjectKind::Pair('{', '}'),
+        Some('<') | Some('>') => ObjectKind::Pair('<', '>'),
+        Some(_) => return Parse::Invalid,
+    };
+    Parse::Done(Object { inner, kind })
+}
+
+/// Whether an operator on a motion includes the char the motion moves to.
+fn is_inclusive(motion: Motion, last_find: Option<Find>) -> bool {
+    match motion {
+        Motion::WordEnd(_) => true,
+        Motion::Find(find) => !find.backward,
+        Motion::RepeatFind(reverse) => last_find.is_some_and(|find| find.backward == reverse),
+        _ => false,
+    }
+}
+
+/// The range of a text object around an index.
+fn object_range(s: &str, dot: usize, object: Object) -> Option<(usize, usize)> {
+    match object.kind {
+        ObjectKind::Word(big) => {
+            let dot = if dot == s.len() {
+                prev_char(s, dot)
+            } else {
+                dot
+            };
+            let (start, end) = (line_start(s, dot), line_end(s, dot));
+            let cls = class(char_at(s, dot), big);
+            let same = |c: char| class(Some(c), big) == cls;
+
+            let from = start + s[start..dot].trim_end_matches(same).len();
+            let to = end - s[dot..end].trim_start_matches(same).len();
+            if object.inner {
+                return Some((from, to));
+            }
+
+            // Around a word is with the whitespace after it, or before it if
+            // there is none, and around whitespace is with the word after it.
+            let space = |c: char| c.is_whitespace();
+            if cls == Class::Space {
+                let next = class(char_at(s, to), big);
+                let after = end
+                    - s[to..end]
+                        .trim_start_matches(|c| class(Some(c), big) == next)
+                        .len();
+                return Some((from, after));
+            }
+            let after = end - s[to..end].trim_start_matches(space).len();
+            if after > to {
+                Some((from, after))
+            } else {
+                Some((start + s[start..from].trim_end_matches(space).len(), to))
+            }
+        }
+        ObjectKind::Quote(q) => {
+            let (start, end) = (line_start(s, dot), line_end(s, dot));
+            let quotes: Vec<usize> = s[start..end]
+                .match_indices(q)
+                .map(|(i, _)| start + i)
+                .collect();
+            let (open, close) = quotes
+                .chunks_exact(2)
+                .map(|pair| (pair[0], pair[1]))
+                .find(|(_, close)| dot <= *close)?;
+            if object.inner {
+                Some((open + q.len_utf8(), close))
+            } else {
+                Some((open, close + q.len_utf8()))
+            }
+        }
+        ObjectKind::Pair(open, close) => {
+            // Find the unmatched open before the dot, or at it.
+            let mut depth = 0;
+            let mut from = None;
+            let before = match char_at(s, dot) {
+                Some(c) if c == open => next_char(s, dot),
+                Some(c) if c == close => {
+                    depth = 1;
+                    dot
+                }
+                _ => dot,
+            };
+            for (i, c) in s[..before].char_indices().rev() {
+                if c == close {
+                    depth += 1;
+                } else if c == open {
+                    if depth == 0 {
+                        from = Some(i);
+                        break;
+                    }
+                    depth -= 1;
+                }
+            }
+            let from = from?;
+
+            let mut depth = 0;
+            let inside = from + open.len_utf8();
+            let to = s[inside..].char_indices().find_map(|(i, c)| {
+                if c == open {
+                    depth += 1;
+                } else if c == close {
+                    if depth == 0 {
+                        return Some(inside + i);
+                    }
+                    depth -= 1;
+                }
+                None
+            })?;
+
+            if object.inner {
+                Some((inside, to))
+            } else {
+                Some((from, to + close.len_utf8()))
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Class {
+    Space,
+    Word,
+    Punct,
+}
+
+/// The class of a char for word motions, with `None` for the end of the
+/// buffer.
+fn class(c: Option<char>, big: bool) -> Class {
+    match c {
+        None => Class::Space,
+        Some(c) if c.is_whitespace() => Class::Space,
+        Some(_) if big => Class::Word,
+        Some(c) if c.is_alphanumeric() || c == '_' => Class::Word,
+        Some(_) => Class::Punct,
+    }
+}
+
+/// The start of the next word, as with `w`.
+fn word_start(s: &str, i: usize, big: bool) -> usize {
+    let cls = class(char_at(s, i), big);
+    let mut i = i;
+    if cls != Class::Space {
+        while i < s.len() && class(char_at(s, i), big) == cls {
+            i = next_char(s, i);
+        }
+    }
+    while i < s.len() && class(char_at(s, i), big) == Class::Space {
+        i = next_char(s, i);
+    }
+    i
+}
+
+/// The start of the word before, as with `b`.
+fn word_back(s: &str, i: usize, big: bool) -> usize {
+    let mut i = i;
+    while i > 0 && class(char_at(s, prev_char(s, i)), big) == Class::Space {
+        i = prev_char(s, i);
+    }
+    if i > 0 {
+        let cls = class(char_at(s, prev_char(s, i)), big);
+        while i > 0 && class(char_at(s, prev_char(s, i)), big) == cls {
+            i = prev_char(s, i);
+        }
+    }
+    i
+}
+
+/// The last char of the next end of a word, as with `e`.
+fn word_end(s: &str, i: usize, big: bool) -> usize {
+    let mut i = next_char(s, i);
+    while i < s.len() && class(char_at(s, i), big) == Class::Space {
+        i = next_char(s, i);
+    }
+    let cls = class(char_at(s, i), big);
+    while next_char(s, i) < s.len() && class(next_char_at(s, i), big) == cls {
+        i = next_char(s, i);
+    }
+    i.min(prev_char(s, s.len()))
+}
+
+/// Finds a char on the line of an index, as with `f`. A repeated `t` skips
+/// the char it stopped before.
+fn find_char(s: &str, i: usize, find: Find, repeat: bool) -> Option<usize> {
+    if find.backward {
+        let start = line_start(s, i);
+        let before = if find.till && repeat {
+            prev_char(s, i).max(start)
+        } else {
+            i
+        };
+        let (at, _) = s[start..before]
+            .char_indices()
+            .rev()
+            .find(|(_, c)| *c == find.c)?;
+        let at = start + at;
+        Some(if find.till { next_char(s, at) } else { at })
+    } else {
+        let end = line_end(s, i);
+        let mut after = next_char(s, i).min(end);
+        if find.till && repeat {
+            after = next_char(s, after).min(end);
+        }
+        let (at, _) = s[after..end].char_indices().find(|(_, c)| *c == find.c)?;
+        let at = after + at;
+        Some(if find.till { prev_char(s, at) } else { at })
+    }
+}
+
+/// Moves an index off the end of a line onto its last char, as the dot is
+/// never past the end of a line in normal mode.
+pub fn clamp_normal(s: &str, i: usize) -> usize {
+    let i = i.min(s.len());
+    if i == line_end(s, i) && i > line_start(s, i) {
+        prev_char(s, i)
+    } else {
+        i
+    }
+}
+
+fn char_at(s: &str, i: usize) -> Option<char> {
+    s[i..].chars().next()
+}
+
+fn next_char_at(s: &str, i: usize) -> Option<char> {
+    char_at(s, next_char(s, i))
+}
+
+fn next_char(s: &str, i: usize) -> usize {
+    char_at(s, i).map_or(i, |c| i + c.len_utf8())
+}
+
+fn prev_char(s: &str, i: usize) -> usize {
+    s[..i].chars().next_back().map_or(i, |c| i - c.len_utf8())
+}
+
+fn line_start(s: &str, i: usize) -> usize {
+    s[..i].rfind('\n').map_or(0, |n| n + 1)
+}
+
+fn line_end(s: &str, i: usize) -> usize {
+    s[i..].find('\n').map_or(s.len(), |n| i + n)
+}
+
+fn first_non_blank(s: &str, i: usize) -> usize {
+    let (start, end) = (line_start(s, i), line_end(s, i));
+    end - s[start..end].trim_start_matches([' ', '\t']).len()
+}
+
+fn min_max(a: usize, b: usize) -> (usize, usize) {
+    if a <= b {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+fn toggle_case(c: char) -> char {
+    if c.is_lowercase() {
+        c.to_uppercase().next().unwrap_or(c)
+    } else {
+        c.to_lowercase().next().unwrap_or(c)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs keys from normal mode on a buffer with the dot written as `|`,
+    /// typing the keys in insert mode until an escape, as the editor does.
+    struct Editor {
+        vi: Vi,
+        state: CodeAreaState,
+        mode: Mode,
+    }
+
+    impl Editor {
+        fn new(text: &str) -> Editor {
+            let dot = text.find('|').expect("no dot in text");
+            let buffer = CodeBuffer {
+                content: text.replacen('|', "", 1),
+                dot,
+            };
+            Editor {
+                vi: Vi::default(),
+                state: CodeAreaState {
+                    buffer,
+                    ..CodeAreaState::default()
+                },
+                mode: Mode::Normal,
+            }
+        }
+
+        fn keys(&mut self, keys: &str) -> &mut Editor {
+            for c in keys.chars() {
+                match self.mode {
+                    Mode::Insert if c == ESC => {
+                        self.vi.leave_insert(&mut self.state.buffer);
+                        self.mode = Mode::Normal;
+                    }
+                    Mode::Insert => self.state.buffer.insert_char_at_dot(c),
+                    mode => {
+                        let key = KeyEvent::new(KeyCode::Char(c), KeyModifiers::empty());
+                        if let Some(mode) = self.vi.handle_key(key, &mut self.state, mode) {
+                            self.mode = mode;
+                        }
+                    }
+                }
+            }
+            self
+        }
+
+        /// The buffer with the dot written as `|`.
+        fn text(&self) -> String {
+            let buffer = &self.state.buffer;
+            let mut text = buffer.content.clone();
+            text.insert(buffer.dot, '|');
+            text
+        }
+    }
+
+    fn vi(text: &str, keys: &str) -> String {
+        Editor::new(text).keys(keys).text()
+    }
+
+    #[test]
+    fn counts() {
+        assert_eq!(parse_count(&['1', '2', 'w']), (Some(12), &['w'][..]));
+        assert_eq!(parse_count(&['0', 'w']), (None, &['0', 'w'][..]));
+        assert_eq!(parse_count(&['w']), (None, &['w'][..]));
+
+        let huge: Vec<char> = "99999999999999999999999x".chars().collect();
+        assert_eq!(parse_count(&huge), (Some(MAX_COUNT), &['x'][..]));
+    }
+
+    #[test]
+    fn pending() {
+        let mut editor = Editor::new("|abc");
+        editor.keys("d2");
+        assert!(editor.vi.is_pending());
+        editor.keys("l");
+        assert!(!editor.vi.is_pending());
+        assert_eq!(editor.text(), "|c");
+
+        // An invalid command is dropped.
+        editor.keys("dz");
+        assert!(!editor.vi.is_pending());
+        assert_eq!(editor.text(), "|c");
+    }
+
+    #[test]
+    fn motions() {
+        assert_eq!(vi("|foo bar.baz", "w"), "foo |bar.baz");
+        assert_eq!(vi("|foo bar.baz", "2w"), "foo bar|.baz");
+        assert_eq!(vi("|foo bar.baz", "W"), "foo |bar.baz");
+        assert_eq!(vi("|foo bar.baz", "WW"), "foo bar.ba|z");
+        assert_eq!(vi("foo bar.ba|z", "b"), "foo bar.|baz");
+        assert_eq!(vi("foo bar.ba|z", "B"), "foo |bar.baz");
+        assert_eq!(vi("|foo bar", "e"), "fo|o bar");
+        assert_eq!(vi("  fo|o", "^"), "  |foo");
+        assert_eq!(vi("  fo|o", "0"), "|  foo");
+        assert_eq!(vi("|foo", "$"), "fo|o");
+        assert_eq!(vi("|foo", "10l"), "fo|o");
+        assert_eq!(vi("fo|o", "10h"), "|foo");
+    }
+
+    #[test]
+    fn finds() {
+        assert_eq!(vi("|a,b,c,d", "f,"), "a|,b,c,d");
+        assert_eq!(vi("|a,b,c,d", "2f,"), "a,b|,c,d");
+        assert_eq!(vi("|a,b,c,d", "t,"), "|a,b,c,d");
+        assert_eq!(vi("|a,b,c,d", "f,;"), "a,b|,c,d");
+        assert_eq!(vi("|a,b,c,d", "t,;"), "a,|b,c,d");
+        assert_eq!(vi("|a,b,c,d", "f,;,"), "a|,b,c,d");
+        assert_eq!(vi("a,b,c,|d", "F,"), "a,b,c|,d");
+        assert_eq!(vi("|a,b,c,d", "fz"), "|a,b,c,d");
+    }
+
+    #[test]
+    fn lines() {
+        let text = "first\nab\nthird";
+        assert_eq!(vi("firs|t\nab\nthird", "j"), "first\na|b\nthird");
+        assert_eq!(vi("firs|t\nab\nthird", "2j"), "first\nab\nthir|d");
+        assert_eq!(vi("first\nab\nth|ird", "k"), "first\na|b\nthird");
+        assert_eq!(vi("first\nab\nth|ird", "5k"), "first\nab\nth|ird");
+        assert_eq!(vi(&text.replacen('a', "|a", 1), "dd"), "first\n|third");
+        assert_eq!(vi(&text.replacen('a', "|a", 1), "2dd"), "|first");
+        assert_eq!(vi("fi|rst\nab\nthird", "dj"), "|third");
+    }
+
+    #[test]
+    fn operators() {
+        assert_eq!(vi("|foo bar baz", "dw"), "|bar baz");
+        assert_eq!(vi("|foo bar baz", "d2w"), "|baz");
+        assert_eq!(vi("|foo bar baz", "2dw"), "|baz");
+        assert_eq!(vi("foo |bar", "dw"), "foo| ");
+        assert_eq!(vi("|foo bar", "de"), "| bar");
+        assert_eq!(vi("foo |bar baz", "d$"), "foo| ");
+        assert_eq!(vi("foo |bar baz", "D"), "foo| ");
+        assert_eq!(vi("foo |bar baz", "d0"), "|bar baz");
+        assert_eq!(vi("|a,b,c", "dt,"), "|,b,c");
+        assert_eq!(vi("|a,b,c", "df,"), "|b,c");
+        assert_eq!(vi("|abc", "2x"), "|c");
+        assert_eq!(vi("ab|c", "X"), "a|c");
+        assert_eq!(vi("|foo bar", "cwbaz\x1b"), "ba|z bar");
+        assert_eq!(vi("foo |bar", "Cx\x1b"), "foo |x");
+        assert_eq!(vi("|abc", "2rx"), "x|xc");
+        assert_eq!(vi("|abc", "5rx"), "|abc");
+        assert_eq!(vi("|abc", "2~"), "AB|c");
+    }
+
+    #[test]
+    fn objects() {
+        assert_eq!(vi("foo b|ar baz", "diw"), "foo | baz");
+        assert_eq!(vi("foo b|ar baz", "daw"), "foo |baz");
+        assert_eq!(vi("foo b|ar", "daw"), "fo|o");
+        assert_eq!(vi("say \"he|llo\" now", "di\""), "say \"|\" now");
+        assert_eq!(vi("say \"he|llo\" now", "da\""), "say | now");
+        assert_eq!(vi("f(a, (|b), c)", "di("), "f(a, (|), c)");
+        assert_eq!(vi("f(a, (b)|, c)", "di("), "f(|)");
+        assert_eq!(vi("f(a, |(b), c)", "da)"), "f(a, |, c)");
+        assert_eq!(vi("{ a [|b] }", "ciBx\x1b"), "{|x}");
+        assert_eq!(vi("n|o pair", "di("), "n|o pair");
+    }
+
+    #[test]
+    fn object_ranges() {
+        let word = |inner| Object {
+            inner,
+            kind: ObjectKind::Word(false),
+        };
+        assert_eq!(object_range("ab cd", 4, word(true)), Some((3, 5)));
+        assert_eq!(object_range("ab cd", 5, word(true)), Some((3, 5)));
+        assert_eq!(object_range("ab  cd", 2, word(false)), Some((2, 6)));
+        assert_eq!(object_range("ab.cd", 1, word(true)), Some((0, 2)));
+
+        let quote = Object {
+            inner: true,
+            kind: ObjectKind::Quote('\''),
+        };
+        assert_eq!(object_range("'a' 'b'", 5, quote), Some((5, 6)));
+        assert_eq!(object_range("'a' x", 4, quote), None);
+    }
+
+    #[test]
+    fn registers() {
+        assert_eq!(vi("|foo bar", "yawP"), "foo| foo bar");
+        assert_eq!(vi("|foo bar", "dwp"), "bfoo| ar");
+        assert_eq!(vi("|foo bar", "\"adw\"_dw\"ap"), "foo| ");
+        assert_eq!(vi("|a\nb", "yyjp"), "a\nb\n|a");
+        assert_eq!(vi("|a\nb", "yy2P"), "|a\na\na\nb");
+        assert_eq!(vi("|ab", "\"ayl\"Aylx\"ap"), "ba|a");
+        assert_eq!(vi("|abc", "yl2p"), "aa|abc");
+    }
+
+    #[test]
+    fn inserts() {
+        assert_eq!(vi("|ab", "ix\x1b"), "|xab");
+        assert_eq!(vi("|ab", "ax\x1b"), "a|xb");
+        assert_eq!(vi("a|b", "Ax\x1b"), "ab|x");
+        assert_eq!(vi("  a|b", "Ix\x1b"), "  |xab");
+        assert_eq!(vi("a|b\nc", "ox\x1b"), "ab\n|x\nc");
+        assert_eq!(vi("a\n|c", "Ox\x1b"), "a\n|x\nc");
+    }
+
+    #[test]
+    fn undo_and_repeat() {
+        assert_eq!(vi("|a b c d", "dwu"), "|a b c d");
+        assert_eq!(vi("|a b c d", "dwdwuu"), "|a b c d");
+        assert_eq!(vi("|a b c d", "dw."), "|c d");
+        assert_eq!(vi("|a b c d", "dw2."), "|d");
+        assert_eq!(vi("|a b", "cwx\x1bw."), "x |x");
+        assert_eq!(vi("|ab", "ix\x1bu"), "|ab");
+        assert_eq!(vi("|a b c", "ywx."), "|b c");
+    }
+
+    #[test]
+    fn visual() {
+        let mut editor = Editor::new("|foo bar");
+        editor.keys("v");
+        assert_eq!(editor.mode, Mode::Visual);
+        editor.keys("ed");
+        assert_eq!(editor.mode, Mode::Normal);
+        assert_eq!(editor.text(), "| bar");
+
+        assert_eq!(vi("|foo bar", "vey$p"), "foo barfo|o");
+        assert_eq!(vi("|foo bar", "vlo~"), "|FOo bar");
+        assert_eq!(vi("foo b|ar baz", "viwd"), "foo | baz");
+        assert_eq!(vi("|foo bar", "vecx\x1b"), "|x bar");
+        assert_eq!(vi("|foo bar", "vl\x1bx"), "f|o bar");
+    }
+}
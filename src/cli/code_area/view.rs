@@ -4,7 +4,7 @@ use super::{CodeArea, CodeBuffer, PendingCode};
 
 use crate::cli::term::buffer::BufferBuilder;
 use crate::cli::term::utils::wcswidth;
-use crate::cli::ui::Text;
+use crate::cli::ui::{Text, TextSegment};
 
 pub struct View {
     prompt: Arc<Text>,
@@ -22,7 +22,10 @@ impl View {
         let (_from, _to) = patch_pending(&mut code, &state.pending);
 
         // TODO: Highlighter.
-        let styled_code = Text::plain(code.content);
+        let styled_code = match state.visual {
+            Some(anchor) => select(&code, anchor),
+            None => Text::plain(code.content),
+        };
 
         // TODO: Prompts.
         let prompt = code_area.prompt.prompt().await;
@@ -57,10 +60,7 @@ impl View {
         buf.indent = 0;
 
         if let Some(rprompt) = self.rprompt {
-            let rprompt_width = rprompt
-                .iter()
-                .map(|seg| wcswidth(&seg.text))
-                .fold(0u16, std::ops::Add::add);
+            let rprompt_width = rprompt.iter().map(|seg| wcswidth(&seg.text)).sum::<u16>();
 
             if rprompt_width > 0 {
                 // Don't write rprompt if there is not room.
@@ -104,3 +104,25 @@ fn patch_pending(b: &mut CodeBuffer, p: &PendingCode) -> (usize, usize) {
 
     (p.from, p.from + p.content.len())
 }
+
+/// Shows the selection of visual mode, between the anchor and the dot and
+/// including the char at the end.
+fn select(b: &CodeBuffer, anchor: usize) -> Text {
+    let (from, to) = if anchor < b.dot {
+        (anchor, b.dot)
+    } else {
+        (b.dot, anchor)
+    };
+    let from = from.min(b.content.len());
+    let to = b.content[to.min(b.content.len())..]
+        .chars()
+        .next()
+        .map_or(b.content.len(), |c| to + c.len_utf8());
+
+    let mut text = Text::plain(&b.content[..from]);
+    text.push(TextSegment::styled(&b.content[from..to], |style| {
+        style.reverse(true)
+    }));
+    text.push(TextSegment::plain(&b.content[to..]));
+    text
+}
@@ -0,0 +1,476 @@
+//! Bindings of keys to editor actions, with a map for each editing mode.
+//!
+//! A binding is to a sequence of keys, such as `ctrl-x ctrl-e`. Keys are
+//! written as a name or char, after any of the modifiers `ctrl-`, `alt-` and
+//! `shift-`.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use futures::future::BoxFuture;
+
+use crate::cli::tty::{KeyCode, KeyEvent, KeyModifiers};
+
+/// A mode of the editor, with its own keymap.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Mode {
+    Insert,
+    /// The normal mode of vi, in which keys are commands.
+    Normal,
+    /// The visual mode of vi, in which commands act on a selection.
+    Visual,
+}
+
+impl Mode {
+    pub const ALL: [Mode; 3] = [Mode::Insert, Mode::Normal, Mode::Visual];
+
+    /// Looks up a mode by name.
+    pub fn from_name(name: &str) -> Option<Mode> {
+        Mode::ALL.iter().copied().find(|mode| mode.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Mode::Insert => "insert",
+            Mode::Normal => "normal",
+            Mode::Visual => "visual",
+        }
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A named action of the editor.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Action {
+    MoveLeft,
+    MoveRight,
+    MoveLineStart,
+    MoveLineEnd,
+    MoveWordLeft,
+    MoveWordRight,
+    DeleteLeft,
+    DeleteRight,
+    KillWordLeft,
+    KillWordRight,
+    KillLineLeft,
+    KillLineRight,
+    /// Moves the dot up a line, or to the previous command of the history on
+    /// the first line.
+    HistoryUp,
+    /// Moves the dot down a line, or to the next command of the history on
+    /// the last line.
+    HistoryDown,
+    /// Submits the line, or starts a new line if the code is incomplete.
+    Submit,
+    InsertNewline,
+    /// Discards the line, starting a new one.
+    DiscardLine,
+    /// Ends the input, exiting the shell.
+    Eof,
+    /// Enters the normal mode of vi.
+    ViNormalMode,
+    /// Enters insert mode, from the modes of vi.
+    ViInsertMode,
+}
+
+const ACTIONS: &[(&str, Action)] = &[
+    ("delete-left", Action::DeleteLeft),
+    ("delete-right", Action::DeleteRight),
+    ("discard-line", Action::DiscardLine),
+    ("eof", Action::Eof),
+    ("history-down", Action::HistoryDown),
+    ("history-up", Action::HistoryUp),
+    ("insert-newline", Action::InsertNewline),
+    ("kill-line-left", Action::KillLineLeft),
+    ("kill-line-right", Action::KillLineRight),
+    ("kill-word-left", Action::KillWordLeft),
+    ("kill-word-right", Action::KillWordRight),
+    ("move-left", Action::MoveLeft),
+    ("move-line-end", Action::MoveLineEnd),
+    ("move-line-start", Action::MoveLineStart),
+    ("move-right", Action::MoveRight),
+    ("move-word-left", Action::MoveWordLeft),
+    ("move-word-right", Action::MoveWordRight),
+    ("submit", Action::Submit),
+    ("vi-insert-mode", Action::ViInsertMode),
+    ("vi-normal-mode", Action::ViNormalMode),
+];
+
+impl Action {
+    /// Looks up an action by name.
+    pub fn from_name(name: &str) -> Option<Action> {
+        ACTIONS
+            .iter()
+            .find(|(action, _)| *action == name)
+            .map(|(_, action)| *action)
+    }
+
+    pub fn name(self) -> &'static str {
+        ACTIONS
+            .iter()
+            .find(|(_, action)| *action == self)
+            .map_or("", |(name, _)| name)
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The line given to a bound function, and returned changed by it.
+#[derive(Clone, Debug, Default)]
+pub struct Line {
+    pub buffer: String,
+    /// The position of the cursor in the buffer, as a byte index.
+    pub dot: usize,
+    /// Notes to show above the prompt.
+    pub notes: Vec<String>,
+}
+
+/// A function bound to a key, such as a closure defined by the user.
+pub type BoundFn = Arc<dyn Fn(Line) -> BoxFuture<'static, Line> + Send + Sync>;
+
+#[derive(Clone)]
+pub enum Binding {
+    Action(Action),
+    Fn(BoundFn),
+}
+
+impl fmt::Debug for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Binding::Action(action) => write!(f, "Action({})", action),
+            Binding::Fn(_) => f.write_str("Fn"),
+        }
+    }
+}
+
+/// The result of looking up a sequence of keys.
+#[derive(Clone, Debug)]
+pub enum Lookup {
+    Bound(Binding),
+    /// The keys start a longer sequence that is bound.
+    Prefix,
+    Unbound,
+}
+
+/// The keymaps of each mode, and the mode the editor is in.
+#[derive(Clone, Debug)]
+pub struct Keymaps {
+    modes: HashMap<Mode, HashMap<Vec<KeyEvent>, Binding>>,
+    mode: Mode,
+    /// Whether vi mode is on, so the cursor shows the mode.
+    vi: bool,
+}
+
+pub type SharedKeymaps = Arc<Mutex<Keymaps>>;
+
+impl Default for Keymaps {
+    fn default() -> Keymaps {
+        let mut keymaps = Keymaps {
+            modes: Mode::ALL
+                .iter()
+                .map(|mode| (*mode, HashMap::new()))
+                .collect(),
+            mode: Mode::Insert,
+            vi: false,
+        };
+
+        let defaults = [
+            ("enter", Action::Submit),
+            ("alt-enter", Action::InsertNewline),
+            ("ctrl-c", Action::DiscardLine),
+            ("ctrl-d", Action::Eof),
+            ("backspace", Action::DeleteLeft),
+            ("delete", Action::DeleteRight),
+            ("left", Action::MoveLeft),
+            ("right", Action::MoveRight),
+            ("ctrl-b", Action::MoveLeft),
+            ("ctrl-f", Action::MoveRight),
+            ("home", Action::MoveLineStart),
+            ("end", Action::MoveLineEnd),
+            ("ctrl-a", Action::MoveLineStart),
+            ("ctrl-e", Action::MoveLineEnd),
+            ("ctrl-left", Action::MoveWordLeft),
+            ("ctrl-right", Action::MoveWordRight),
+            ("alt-b", Action::MoveWordLeft),
+            ("alt-f", Action::MoveWordRight),
+            ("ctrl-w", Action::KillWordLeft),
+            ("alt-d", Action::KillWordRight),
+            ("ctrl-u", Action::KillLineLeft),
+            ("ctrl-k", Action::KillLineRight),
+            ("up", Action::HistoryUp),
+            ("down", Action::HistoryDown),
+            ("ctrl-p", Action::HistoryUp),
+            ("ctrl-n", Action::HistoryDown),
+        ];
+        for (keys, action) in &defaults {
+            keymaps.bind_default(Mode::Insert, keys, *action);
+        }
+
+        // Other keys of the vi modes are read as commands.
+        for mode in &[Mode::Normal, Mode::Visual] {
+            keymaps.bind_default(*mode, "enter", Action::Submit);
+            keymaps.bind_default(*mode, "ctrl-c", Action::DiscardLine);
+            keymaps.bind_default(*mode, "ctrl-d", Action::Eof);
+        }
+        for (keys, action) in &[
+            ("up", Action::HistoryUp),
+            ("down", Action::HistoryDown),
+            ("k", Action::HistoryUp),
+            ("j", Action::HistoryDown),
+        ] {
+            keymaps.bind_default(Mode::Normal, keys, *action);
+        }
+
+        keymaps
+    }
+}
+
+impl Keymaps {
+    fn bind_default(&mut self, mode: Mode, keys: &str, action: Action) {
+        let keys = parse_keys(keys).expect("invalid default binding");
+        self.bind(mode, keys, Binding::Action(action));
+    }
+
+    /// Binds a sequence of keys in a mode, replacing any binding of it.
+    pub fn bind(&mut self, mode: Mode, keys: Vec<KeyEvent>, binding: Binding) {
+        self.modes.entry(mode).or_default().insert(keys, binding);
+    }
+
+    /// Removes the binding of a sequence of keys in a mode, returning it.
+    pub fn unbind(&mut self, mode: Mode, keys: &[KeyEvent]) -> Option<Binding> {
+        self.modes.get_mut(&mode)?.remove(keys)
+    }
+
+    /// Looks up a sequence of keys in the keymap of the mode the editor is in.
+    pub fn lookup(&self, keys: &[KeyEvent]) -> Lookup {
+        let keymap = match self.modes.get(&self.mode) {
+            Some(keymap) => keymap,
+            None => return Lookup::Unbound,
+        };
+
+        if let Some(binding) = keymap.get(keys) {
+            return Lookup::Bound(binding.clone());
+        }
+        if keymap
+            .keys()
+            .any(|bound| bound.len() > keys.len() && bound.starts_with(keys))
+        {
+            return Lookup::Prefix;
+        }
+        Lookup::Unbound
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+    }
+
+    pub fn vi(&self) -> bool {
+        self.vi
+    }
+
+    /// Turns vi mode on or off, binding `esc` in insert mode to enter normal
+    /// mode while it is on.
+    pub fn set_vi(&mut self, vi: bool) {
+        let esc = vec![KeyEvent::from(KeyCode::Esc)];
+        if vi {
+            self.bind(Mode::Insert, esc, Binding::Action(Action::ViNormalMode));
+        } else {
+            let bound = self
+                .modes
+                .get(&Mode::Insert)
+                .and_then(|keymap| keymap.get(&esc));
+            if let Some(Binding::Action(Action::ViNormalMode)) = bound {
+                self.unbind(Mode::Insert, &esc);
+            }
+            self.mode = Mode::Insert;
+        }
+        self.vi = vi;
+    }
+}
+
+/// Parses a sequence of keys separated by whitespace, such as
+/// `ctrl-x ctrl-e`.
+pub fn parse_keys(s: &str) -> Result<Vec<KeyEvent>, String> {
+    let keys = s
+        .split_whitespace()
+        .map(parse_key)
+        .collect::<Result<Vec<_>, _>>()?;
+    if keys.is_empty() {
+        return Err("no keys".to_owned());
+    }
+    Ok(keys)
+}
+
+/// Parses a key, such as `ctrl-r`, `alt-.`, `enter` or `-`.
+fn parse_key(s: &str) -> Result<KeyEvent, String> {
+    let mut modifiers = KeyModifiers::empty();
+    let mut name = s;
+
+    loop {
+        let (modifier, rest) = match name.find('-') {
+            // A `-` at the end is the key itself.
+            Some(i) if i + 1 < name.len() => (&name[..i], &name[i + 1..]),
+            _ => break,
+        };
+        modifiers |= match &*modifier.to_ascii_lowercase() {
+            "ctrl" | "c" => KeyModifiers::CONTROL,
+            "alt" | "meta" | "a" | "m" => KeyModifiers::ALT,
+            "shift" | "s" => KeyModifiers::SHIFT,
+            _ => return Err(format!("invalid modifier `{}` in `{}`", modifier, s)),
+        };
+        name = rest;
+    }
+
+    let mut chars = name.chars();
+    let code = match (chars.next(), chars.next()) {
+        // Control chars are read as lower case letters.
+        (Some(c), None) if modifiers.contains(KeyModifiers::CONTROL) => {
+            KeyCode::Char(c.to_ascii_lowercase())
+        }
+        (Some(c), None) => KeyCode::Char(c),
+        _ => match &*name.to_ascii_lowercase() {
+            "enter" | "return" => KeyCode::Enter,
+            "tab" => KeyCode::Tab,
+            "backtab" => KeyCode::BackTab,
+            "backspace" => KeyCode::Backspace,
+            "delete" | "del" => KeyCode::Delete,
+            "insert" | "ins" => KeyCode::Insert,
+            "esc" | "escape" => KeyCode::Esc,
+            "space" => KeyCode::Char(' '),
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            lower => match lower.strip_prefix('f').and_then(|n| n.parse().ok()) {
+                Some(n) if (1..=24).contains(&n) => KeyCode::F(n),
+                _ => return Err(format!("invalid key `{}`", s)),
+            },
+        },
+    };
+
+    Ok(KeyEvent { code, modifiers })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent { code, modifiers }
+    }
+
+    fn keys(s: &str) -> Vec<KeyEvent> {
+        parse_keys(s).unwrap_or_else(|err| panic!("invalid keys {:?}: {}", s, err))
+    }
+
+    fn action(keymaps: &Keymaps, s: &str) -> Option<Action> {
+        match keymaps.lookup(&keys(s)) {
+            Lookup::Bound(Binding::Action(action)) => Some(action),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn parse() {
+        let none = KeyModifiers::empty();
+        assert_eq!(keys("a"), [key(KeyCode::Char('a'), none)]);
+        assert_eq!(
+            keys("ctrl-R"),
+            [key(KeyCode::Char('r'), KeyModifiers::CONTROL)]
+        );
+        assert_eq!(keys("alt-."), [key(KeyCode::Char('.'), KeyModifiers::ALT)]);
+        assert_eq!(
+            keys("c-a-enter"),
+            [key(
+                KeyCode::Enter,
+                KeyModifiers::CONTROL | KeyModifiers::ALT
+            )]
+        );
+        assert_eq!(keys("-"), [key(KeyCode::Char('-'), none)]);
+        assert_eq!(
+            keys("ctrl--"),
+            [key(KeyCode::Char('-'), KeyModifiers::CONTROL)]
+        );
+        assert_eq!(keys("space"), [key(KeyCode::Char(' '), none)]);
+        assert_eq!(keys("F12"), [key(KeyCode::F(12), none)]);
+        assert_eq!(
+            keys(" ctrl-x  ctrl-e "),
+            [
+                key(KeyCode::Char('x'), KeyModifiers::CONTROL),
+                key(KeyCode::Char('e'), KeyModifiers::CONTROL),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(parse_keys("").is_err());
+        assert!(parse_keys("  ").is_err());
+        assert!(parse_keys("hyper-a").is_err());
+        assert!(parse_keys("enterr").is_err());
+        assert!(parse_keys("f25").is_err());
+        assert!(parse_keys("ctrl-x nope").is_err());
+    }
+
+    #[test]
+    fn lookup() {
+        let mut keymaps = Keymaps::default();
+        assert_eq!(action(&keymaps, "ctrl-a"), Some(Action::MoveLineStart));
+        assert!(matches!(keymaps.lookup(&keys("ctrl-x")), Lookup::Unbound));
+
+        keymaps.bind(
+            Mode::Insert,
+            keys("ctrl-x ctrl-e"),
+            Binding::Action(Action::MoveLineEnd),
+        );
+        assert!(matches!(keymaps.lookup(&keys("ctrl-x")), Lookup::Prefix));
+        assert_eq!(action(&keymaps, "ctrl-x ctrl-e"), Some(Action::MoveLineEnd));
+        assert!(matches!(
+            keymaps.lookup(&keys("ctrl-x ctrl-e ctrl-e")),
+            Lookup::Unbound
+        ));
+        assert!(matches!(keymaps.lookup(&keys("ctrl-x a")), Lookup::Unbound));
+
+        // A key bound on its own is not a prefix.
+        keymaps.bind(Mode::Insert, keys("ctrl-x"), Binding::Action(Action::Eof));
+        assert_eq!(action(&keymaps, "ctrl-x"), Some(Action::Eof));
+
+        keymaps.unbind(Mode::Insert, &keys("ctrl-x"));
+        keymaps.unbind(Mode::Insert, &keys("ctrl-x ctrl-e"));
+        assert!(matches!(keymaps.lookup(&keys("ctrl-x")), Lookup::Unbound));
+    }
+
+    #[test]
+    fn modes() {
+        let mut keymaps = Keymaps::default();
+        assert!(action(&keymaps, "esc").is_none());
+
+        keymaps.set_vi(true);
+        assert_eq!(action(&keymaps, "esc"), Some(Action::ViNormalMode));
+        keymaps.set_mode(Mode::Normal);
+        assert_eq!(action(&keymaps, "k"), Some(Action::HistoryUp));
+        assert!(action(&keymaps, "ctrl-a").is_none());
+
+        keymaps.set_vi(false);
+        assert_eq!(keymaps.mode(), Mode::Insert);
+        assert!(action(&keymaps, "esc").is_none());
+    }
+}
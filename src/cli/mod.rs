@@ -1,8 +1,9 @@
 mod code_area;
 
 pub mod app;
+pub mod keymap;
+pub mod prompt;
 pub mod term;
 pub mod tty;
 pub mod ui;
 pub mod widget;
-pub mod prompt;
@@ -49,6 +49,9 @@ type ModuleEntry = (Box<dyn PromptModule>, Option<Text>, Instant);
 pub struct Prompt {
     modules: Vec<ModuleEntry>,
     config: PromptConfig,
+    /// The modules have changed since the last update, so the next update is
+    /// forced.
+    modules_changed: bool,
 
     last_wd: Option<PathBuf>,
     last_prompt: Arc<RwLock<Arc<Text>>>,
@@ -89,6 +92,7 @@ impl Prompt {
         let prompt = Prompt {
             modules,
             config,
+            modules_changed: false,
 
             last_wd,
             last_prompt,
@@ -110,7 +114,13 @@ impl Prompt {
     pub fn add_module(&mut self, module: Box<dyn PromptModule>) {
         self.modules.push((module, None, Instant::now()));
         self.modules
-            .sort_by_cached_key(|(module, _, _)| module.position())
+            .sort_by_cached_key(|(module, _, _)| module.position());
+        self.modules_changed = true;
+    }
+
+    pub fn clear_modules(&mut self) {
+        self.modules.clear();
+        self.modules_changed = true;
     }
 
     pub async fn run(&mut self) -> Result<()> {
@@ -126,17 +136,29 @@ impl Prompt {
             }
 
             // Has the working directory changed.
-            let wd_changed = env::current_dir().ok() == self.last_wd;
+            let wd = env::current_dir().ok();
+            let wd_changed = wd != self.last_wd;
 
             tokio::select! {
                 // Received update request.
                 Some(force) = self.update_req_rx.recv() => {
                     // Update prompt.
-                    self.update(force, wd_changed).await;
+                    let changed = self.update(force, wd_changed).await;
+                    self.last_wd = wd;
+
+                    // The app may have drawn the old prompt while this was
+                    // computed, if it changed send a late update to redraw.
+                    if changed {
+                        match self.late_updates_tx.try_send(()) {
+                            Ok(()) | Err(TrySendError::Full(_)) => {}
+                            Err(err) => return Err(anyhow::anyhow!(err)),
+                        }
+                    }
                 }
                 // Check for modules to update.
                 _ = delay_for(threshold) => {
                     let late_update = check_module_updates(self.modules.as_mut(), wd_changed).await;
+                    self.last_wd = wd;
                     if late_update {
                         // TODO: Check performance of using second loop here,
                         //       instead of computing prompt in `check_module_updates`.
@@ -163,7 +185,9 @@ impl Prompt {
         *last_prompt = Arc::new(prompt);
     }
 
-    async fn update(&mut self, force: bool, wd_changed: bool) {
+    /// Updates the prompt, returning whether it changed.
+    async fn update(&mut self, force: bool, wd_changed: bool) -> bool {
+        let force = force || std::mem::take(&mut self.modules_changed);
         let mut prompt = Text::EMPTY;
 
         for (module, cached, last_update) in &mut self.modules {
@@ -177,15 +201,14 @@ impl Prompt {
             }
         }
 
+        let changed = **self.last_prompt.read().await != prompt;
         self.set_prompt(prompt).await;
+        changed
     }
 }
 
 fn push_module_text(prompt: &mut Text, text: &Text) {
-    let prompt_len = prompt
-        .iter()
-        .map(|s| s.text.len())
-        .fold(0usize, std::ops::Add::add);
+    let prompt_len = prompt.iter().map(|s| s.text.len()).sum::<usize>();
 
     if prompt_len > 0 {
         prompt.push(TextSegment::plain(" "));
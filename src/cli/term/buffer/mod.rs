@@ -60,10 +60,7 @@ impl Line {
 
     #[inline]
     pub fn width_slice(slice: &[Cell]) -> u16 {
-        slice
-            .iter()
-            .map(|cell| wcswidth(&cell.text))
-            .fold(0u16, std::ops::Add::add)
+        slice.iter().map(|cell| wcswidth(&cell.text)).sum::<u16>()
     }
 
     /// Find the column of the first difference between this and another line.
@@ -111,7 +108,7 @@ impl<'a> IntoIterator for &'a Line {
     type IntoIter = <&'a Vec<Cell> as IntoIterator>::IntoIter;
 
     fn into_iter(self) -> Self::IntoIter {
-        (&self.0).into_iter()
+        self.0.iter()
     }
 }
 
@@ -147,7 +144,7 @@ impl<'a> IntoIterator for &'a Lines {
     type IntoIter = <&'a Vec<Line> as IntoIterator>::IntoIter;
 
     fn into_iter(self) -> Self::IntoIter {
-        (&self.0).into_iter()
+        self.0.iter()
     }
 }
 
@@ -307,7 +304,7 @@ impl Debug for Buffer {
             }
 
             // Write reset string if ending the line with a style.
-            if let Some(_) = &last_style {
+            if last_style.is_some() {
                 f.write_str("\x1b[m")?;
             }
 
@@ -67,7 +67,7 @@ impl Display for Style {
         add_style!(StyleFlags::ITALIC, 3);
         add_style!(StyleFlags::UNDERLINED, 4);
         add_style!(StyleFlags::BLINK, 5);
-        add_style!(StyleFlags::REVERSE, 6);
+        add_style!(StyleFlags::REVERSE, 7);
 
         if let Some(fg) = self.fg {
             write_sep!();
@@ -83,6 +83,7 @@ impl Display for Style {
     }
 }
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub enum Color {
     Black,
@@ -61,7 +61,7 @@ impl Writer {
         // Move cursor to start of buffer.
         match old_buffer.dot.line {
             0 => {}
-            line => crossterm::queue!(out, cursor::MoveUp(line as u16))?,
+            line => crossterm::queue!(out, cursor::MoveUp(line))?,
         }
         out.write_all(b"\r")?;
 
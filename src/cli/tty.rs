@@ -1,5 +1,5 @@
-use std::io::{self, Stdin, Stdout};
-use std::os::unix::io::AsRawFd;
+use std::io::{self, Stdin, Stdout, Write};
+use std::os::unix::io::{AsRawFd, RawFd};
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -21,7 +21,17 @@ pub struct Tty {
     stdout: Arc<Stdout>,
 
     writer: Writer,
-    event_stream: EventStream,
+    event_stream: Option<EventStream>,
+    /// The shape the cursor was set to, `None` if it has its default shape.
+    cursor_shape: Option<CursorShape>,
+}
+
+/// The shape of the cursor, as shown by the terminal.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CursorShape {
+    Block,
+    Underline,
+    Bar,
 }
 
 impl Tty {
@@ -36,7 +46,8 @@ impl Tty {
             stdout,
 
             writer,
-            event_stream: EventStream::new(),
+            event_stream: None,
+            cursor_shape: None,
         }
     }
 
@@ -45,14 +56,29 @@ impl Tty {
         term::setup(&self.stdin, self.stdout.clone())
     }
 
+    /// Returns the file descriptor the terminal is read from.
+    pub fn fd(&self) -> RawFd {
+        self.stdin.as_raw_fd()
+    }
+
     /// Returns the width and height of the terminal.
     pub fn size(&self) -> Result<(u16, u16)> {
         Ok(crossterm::terminal::size()?)
     }
 
+    fn event_stream(&mut self) -> &mut EventStream {
+        self.event_stream.get_or_insert_with(EventStream::new)
+    }
+
+    /// Stops reading events from the terminal, so that input is left for other
+    /// processes.
+    pub fn close_events(&mut self) {
+        self.event_stream = None;
+    }
+
     /// Reads an event from the terminal asynchronously.
     pub async fn read(&mut self) -> Result<Option<Event>> {
-        Ok(self.event_stream.try_next().await?)
+        Ok(self.event_stream().try_next().await?)
     }
 
     /// Reads an event from the terminal asynchronously, returns `Ok(None)` if
@@ -60,7 +86,7 @@ impl Tty {
     pub async fn try_read(&mut self) -> Result<Option<Event>> {
         // Event available.
         if crossterm::event::poll(Duration::from_secs(0))? {
-            return Ok(self.event_stream.try_next().await?);
+            return Ok(self.event_stream().try_next().await?);
         }
 
         Ok(None)
@@ -72,6 +98,27 @@ impl Tty {
             .map_err(TermError::FlushInput)?)
     }
 
+    /// Sets the shape of the cursor, or restores its default shape.
+    pub fn set_cursor_shape(&mut self, shape: Option<CursorShape>) -> Result<()> {
+        if shape == self.cursor_shape {
+            return Ok(());
+        }
+
+        // The `DECSCUSR` sequence, for a steady cursor of the shape.
+        let n = match shape {
+            None => 0,
+            Some(CursorShape::Block) => 2,
+            Some(CursorShape::Underline) => 4,
+            Some(CursorShape::Bar) => 6,
+        };
+        let mut stdout = self.stdout.lock();
+        write!(stdout, "\x1b[{} q", n)?;
+        stdout.flush()?;
+
+        self.cursor_shape = shape;
+        Ok(())
+    }
+
     /// Returns a reference to the current buffer.
     pub fn buffer(&self) -> &Buffer {
         self.writer.buffer()
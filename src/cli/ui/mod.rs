@@ -85,11 +85,11 @@ impl Text {
         self.segments.push(segment);
     }
 
-    pub fn iter(&self) -> std::slice::Iter<TextSegment> {
+    pub fn iter(&self) -> std::slice::Iter<'_, TextSegment> {
         self.segments.iter()
     }
 
-    pub fn iter_mut(&mut self) -> std::slice::IterMut<TextSegment> {
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, TextSegment> {
         self.segments.iter_mut()
     }
 
@@ -1,48 +1,139 @@
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::Result;
 use async_trait::async_trait;
+use futures::FutureExt;
+use tokio::task::JoinHandle;
 
-use crate::cli::app::{App, AppSpec, AppState, Return};
+use crate::cli::app::{App, AppSpec, AppState, HistoryFn, Return};
+use crate::cli::keymap::{Mode, SharedKeymaps};
 use crate::cli::prompt::{Prompt, PromptConfig, PromptModule};
 use crate::cli::term::style::Color;
 use crate::cli::tty::Tty;
 use crate::cli::ui::Text;
+use crate::eval::{self, PromptFn, SharedEditState};
 
 pub struct Editor {
     app: App,
+    /// The state shared with the `edit:` and `prompt:` builtins.
+    edit: SharedEditState,
+    /// The version of the prompts in the shared state the prompts were built
+    /// from.
+    prompts_version: usize,
+    keymaps: SharedKeymaps,
+    /// The file of the history walked through by the editor.
+    history_file: Option<PathBuf>,
 }
 
 impl Editor {
-    pub fn new(tty: Tty) -> Editor {
-        // TODO: Namespace etc.
-
-        let (mut prompt, prompt_handle) = Prompt::new(PromptConfig {
+    pub fn new(tty: Tty, edit: SharedEditState) -> Editor {
+        let (prompt, prompt_handle) = Prompt::new(PromptConfig {
             threshold: Duration::from_millis(200),
         });
 
-        prompt.add_module(Box::new(WorkingDir { wd: None }));
-        prompt.add_module(Box::new(PromptMarker));
+        let keymaps = edit.lock().unwrap().keymaps.clone();
 
         let app_spec = AppSpec {
             tty,
 
             state: AppState::default(),
+            keymaps: keymaps.clone(),
 
-            // TODO: Prompts.
             prompt: Some((prompt, prompt_handle)),
             rprompt: None,
         };
 
         let app = App::new(app_spec);
 
-        Editor { app }
+        let mut editor = Editor {
+            app,
+            edit,
+            prompts_version: 0,
+            keymaps,
+            history_file: None,
+        };
+        editor.build_prompts(Vec::new(), true);
+        editor
     }
 
     pub async fn read_line(&mut self) -> Result<Return> {
+        self.before_line().await;
         self.app.read_line().await
     }
+
+    /// Sets the file of the history, read each time a walk through it starts
+    /// so the commands of other shells are seen.
+    pub fn set_history_file(&mut self, file: Option<PathBuf>) {
+        if file == self.history_file {
+            return;
+        }
+        self.history_file = file.clone();
+
+        let load = file.map(|file| -> HistoryFn {
+            Arc::new(move || match eval::read_history(&file) {
+                Ok(entries) => entries.into_iter().map(|entry| entry.cmd).collect(),
+                Err(_) => Vec::new(),
+            })
+        });
+        self.app.set_history(load);
+    }
+
+    /// Applies the changes made by the shell since the last line.
+    async fn before_line(&mut self) {
+        let (buffer, dot, notes, prompts) = {
+            let mut edit = self.edit.lock().unwrap();
+
+            let buffer = std::mem::take(&mut edit.buffer);
+            let dot = std::mem::replace(&mut edit.dot, 0);
+            let notes = std::mem::take(&mut edit.notes);
+            let prompts = if edit.prompts_version != self.prompts_version {
+                self.prompts_version = edit.prompts_version;
+                Some((edit.prompts.clone(), edit.default_prompt))
+            } else {
+                None
+            };
+
+            (buffer, dot, notes, prompts)
+        };
+
+        if !notes.is_empty() {
+            self.app
+                .mutate_state(|state| state.notes = Some(notes))
+                .await;
+        }
+        self.app.set_buffer(buffer, dot).await;
+        if let Some((prompts, default)) = prompts {
+            self.build_prompts(prompts, default);
+        }
+    }
+
+    fn build_prompts(&mut self, prompts: Vec<PromptFn>, default: bool) {
+        self.app.prompt.clear_modules();
+        self.app.rprompt.clear_modules();
+
+        if default {
+            self.app
+                .prompt
+                .add_module(Box::new(WorkingDir { wd: None }));
+            self.app.prompt.add_module(Box::new(PromptMarker));
+        }
+        self.app.prompt.add_module(Box::new(ViMode {
+            keymaps: self.keymaps.clone(),
+            shown: None,
+        }));
+
+        for prompt in prompts {
+            let side = if prompt.right {
+                &mut self.app.rprompt
+            } else {
+                &mut self.app.prompt
+            };
+            side.add_module(Box::new(ScriptModule::new(prompt)));
+        }
+    }
 }
 
 struct WorkingDir {
@@ -95,6 +186,123 @@ impl PromptModule for PromptMarker {
     }
 
     fn position(&self) -> isize {
-        isize::max_value()
+        isize::MAX
+    }
+}
+
+/// Shows the mode of the editor while vi mode is on.
+struct ViMode {
+    keymaps: SharedKeymaps,
+    /// The mode shown, `None` while vi mode is off.
+    shown: Option<Mode>,
+}
+
+impl ViMode {
+    fn mode(&self) -> Option<Mode> {
+        let keymaps = self.keymaps.lock().unwrap();
+        if keymaps.vi() {
+            Some(keymaps.mode())
+        } else {
+            None
+        }
+    }
+}
+
+#[async_trait]
+impl PromptModule for ViMode {
+    async fn compute(&mut self) -> Option<Text> {
+        self.shown = self.mode();
+
+        let (label, color) = match self.shown? {
+            Mode::Insert => ("[I]", Color::Green),
+            Mode::Normal => ("[N]", Color::Red),
+            Mode::Visual => ("[V]", Color::Yellow),
+        };
+        Some(Text::styled(label, |style| style.fg(color).bold(true)))
+    }
+
+    async fn should_update(&self, _wd_changed: bool) -> bool {
+        self.mode() != self.shown
+    }
+
+    async fn update_threshold(&self) -> Option<Duration> {
+        None
+    }
+
+    fn position(&self) -> isize {
+        // Just before the prompt marker.
+        isize::MAX - 1
+    }
+}
+
+/// A module added by `prompt:add`, showing the output of a function.
+///
+/// The function is called in a task of its own and computing the module never
+/// waits for it, as the prompt is not updated if computing is interrupted.
+/// The previous output is shown until it finishes.
+struct ScriptModule {
+    prompt: PromptFn,
+    pending: Option<JoinHandle<Option<Text>>>,
+    /// Whether the pending call has finished.
+    done: Arc<AtomicBool>,
+    last: Option<Text>,
+}
+
+impl ScriptModule {
+    fn new(prompt: PromptFn) -> ScriptModule {
+        ScriptModule {
+            prompt,
+            pending: None,
+            done: Arc::new(AtomicBool::new(false)),
+            last: None,
+        }
+    }
+
+    fn spawn(&self) -> JoinHandle<Option<Text>> {
+        let callback = self.prompt.callback.clone();
+        let done = Arc::clone(&self.done);
+
+        tokio::spawn(async move {
+            let text = match callback.output().await {
+                Ok(output) if output.is_empty() => None,
+                Ok(output) => Some(Text::plain(output)),
+                Err(err) => Some(Text::styled(format!("[{}]", err), |style| {
+                    style.fg(Color::Red)
+                })),
+            };
+            done.store(true, Ordering::SeqCst);
+            text
+        })
+    }
+}
+
+#[async_trait]
+impl PromptModule for ScriptModule {
+    async fn compute(&mut self) -> Option<Text> {
+        if self.pending.is_none() {
+            self.pending = Some(self.spawn());
+        }
+
+        if let Some(pending) = &mut self.pending {
+            if let Some(result) = pending.now_or_never() {
+                self.pending = None;
+                self.done.store(false, Ordering::SeqCst);
+                self.last = result.ok().flatten();
+            }
+        }
+
+        self.last.clone()
+    }
+
+    async fn should_update(&self, _wd_changed: bool) -> bool {
+        self.done.load(Ordering::SeqCst)
+    }
+
+    async fn update_threshold(&self) -> Option<Duration> {
+        self.prompt.interval
+    }
+
+    fn position(&self) -> isize {
+        self.prompt.position
     }
 }
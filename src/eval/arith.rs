@@ -0,0 +1,807 @@
+//! Arithmetic expansion.
+//!
+//! `$((expr))` evaluates an arithmetic expression to a number. Numbers are
+//! 64-bit integers, which become arbitrary-precision integers rather than
+//! overflowing, or floats, written with a `.` or an exponent as in `1.5` or
+//! `2e10`. Integers may also be written in hex, octal or binary, as in
+//! `0xff`, `0o17` or `0b101`. Mixing an integer with a float gives a float.
+//!
+//! The operators, from loosest to tightest binding, are:
+//!
+//! - `c ? a : b`
+//! - `||` and `&&`, which short-circuit
+//! - `|`, `^` and `&`, on integers only
+//! - `==`, `!=`, `<`, `<=`, `>` and `>=`
+//! - `<<` and `>>`, on integers only
+//! - `+` and `-`
+//! - `*`, `/` and `%`, where `/` truncates integers as in C
+//! - the unary `-`, `+`, `!` and `~`
+//! - `**`, which is right associative, so `-2**2` is `-4`
+//!
+//! Comparisons and logical operators give `1` or `0`.
+//!
+//! Variables are written as `$name`, `${name}` or just `name`. A name
+//! containing `-` must be braced, as `a-b` is a subtraction. Variables must
+//! be set to a number, unset variables are an error rather than zero.
+//!
+//! The expression is only parsed when it is expanded, so syntax errors can be
+//! caught like any other error.
+
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+
+use num_bigint::BigInt;
+use num_traits::{Signed, ToPrimitive, Zero};
+
+use crate::eval::{Error, ErrorKind, Result, Value};
+use crate::parse::ast::ArithExpr;
+use crate::parse::Span;
+
+/// The largest number of bits an integer result may need.
+pub const MAX_BITS: usize = 1 << 20;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Number {
+    Int(i64),
+    Big(BigInt),
+    Float(f64),
+}
+
+impl Number {
+    /// Converts a big integer to a 64-bit integer if it fits.
+    fn normalize(n: BigInt) -> Number {
+        match n.to_i64() {
+            Some(n) => Number::Int(n),
+            None => Number::Big(n),
+        }
+    }
+
+    fn from_bool(b: bool) -> Number {
+        Number::Int(b as i64)
+    }
+
+    fn is_true(&self) -> bool {
+        match self {
+            Number::Int(n) => *n != 0,
+            Number::Big(n) => !n.is_zero(),
+            Number::Float(n) => *n != 0.0,
+        }
+    }
+
+    fn to_big(&self) -> Option<BigInt> {
+        match self {
+            Number::Int(n) => Some(BigInt::from(*n)),
+            Number::Big(n) => Some(n.clone()),
+            Number::Float(_) => None,
+        }
+    }
+
+    fn to_f64(&self) -> f64 {
+        match self {
+            Number::Int(n) => *n as f64,
+            Number::Big(n) => n.to_f64().unwrap_or(f64::NAN),
+            Number::Float(n) => *n,
+        }
+    }
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Number::Int(n) => n.fmt(f),
+            Number::Big(n) => n.fmt(f),
+            // Debug formatting keeps the `.0` of whole floats.
+            Number::Float(n) => write!(f, "{:?}", n),
+        }
+    }
+}
+
+/// Evaluates an expression, looking up variables with `lookup`.
+pub fn eval(expr: &ArithExpr, lookup: &dyn Fn(&str) -> Option<Value>) -> Result<Number> {
+    let end = Span::new(expr.span.end, expr.span.end);
+    let tokens = tokenize(&expr.text, expr.span.start)?;
+    let mut parser = Parser {
+        tokens: &tokens,
+        pos: 0,
+        end,
+    };
+
+    let tree = parser.expr()?;
+    if let Some(token) = parser.peek() {
+        return Err(unexpected(token));
+    }
+
+    eval_expr(&tree, lookup)
+}
+
+/// Evaluates an expression that must result in a 64-bit integer.
+pub fn eval_integer(expr: &ArithExpr, lookup: &dyn Fn(&str) -> Option<Value>) -> Result<i64> {
+    match eval(expr, lookup)? {
+        Number::Int(n) => Ok(n),
+        n => Err(Error::new(
+            ErrorKind::Arith(format!("expected an integer, found `{}`", n)),
+            expr.span,
+        )),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum TokenKind {
+    Num(Number),
+    Var(String),
+    Op(&'static str),
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    kind: TokenKind,
+    span: Span,
+}
+
+/// Operators, longer operators first so they are matched before their
+/// prefixes.
+const OPS: &[&str] = &[
+    "**", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "+", "-", "*", "/", "%", "<", ">", "&",
+    "|", "^", "!", "~", "?", ":", "(", ")",
+];
+
+fn tokenize(expr: &str, offset: usize) -> Result<Vec<Token>> {
+    let bytes = expr.as_bytes();
+    let span = |start: usize, end: usize| Span::new(offset + start, offset + end);
+
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+
+    while pos < bytes.len() {
+        let b = bytes[pos];
+        let start = pos;
+
+        let kind = if b.is_ascii_whitespace() {
+            pos += 1;
+            continue;
+        } else if b.is_ascii_digit()
+            || (b == b'.' && bytes.get(pos + 1).is_some_and(u8::is_ascii_digit))
+        {
+            let (n, len) = literal(&expr[pos..]);
+            pos += len;
+            match n {
+                Some(n) if !bytes.get(pos).copied().is_some_and(is_name_byte) => TokenKind::Num(n),
+                _ => {
+                    while bytes.get(pos).copied().is_some_and(is_name_byte) {
+                        pos += 1;
+                    }
+                    return Err(Error::new(
+                        ErrorKind::Arith(format!("invalid number `{}`", &expr[start..pos])),
+                        span(start, pos),
+                    ));
+                }
+            }
+        } else if b == b'$' && bytes.get(pos + 1) == Some(&b'{') {
+            let close = match expr[pos..].find('}') {
+                Some(i) => pos + i,
+                None => {
+                    return Err(Error::new(
+                        ErrorKind::Arith("unterminated `${`".to_owned()),
+                        span(start, expr.len()),
+                    ))
+                }
+            };
+            pos = close + 1;
+            TokenKind::Var(expr[start + 2..close].to_owned())
+        } else if b == b'$' && bytes.get(pos + 1) == Some(&b'?') {
+            pos += 2;
+            TokenKind::Var("?".to_owned())
+        } else if b == b'$' || is_name_start(b) {
+            if b == b'$' {
+                pos += 1;
+            }
+            let name_start = pos;
+            while bytes.get(pos).copied().is_some_and(is_name_byte) {
+                pos += 1;
+            }
+            if pos == name_start {
+                return Err(Error::new(
+                    ErrorKind::Arith("expected a variable name".to_owned()),
+                    span(start, pos),
+                ));
+            }
+            TokenKind::Var(expr[name_start..pos].to_owned())
+        } else if let Some(op) = OPS.iter().find(|op| expr[pos..].starts_with(*op)) {
+            pos += op.len();
+            TokenKind::Op(op)
+        } else {
+            let c = expr[pos..].chars().next().unwrap_or_default();
+            return Err(Error::new(
+                ErrorKind::Arith(format!("unexpected `{}`", c)),
+                span(start, start + c.len_utf8()),
+            ));
+        };
+
+        tokens.push(Token {
+            kind,
+            span: span(start, pos),
+        });
+    }
+
+    Ok(tokens)
+}
+
+fn is_name_start(b: u8) -> bool {
+    b.is_ascii_alphabetic() || b == b'_'
+}
+
+fn is_name_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_'
+}
+
+/// Scans a number literal at the start of `s`, returning the number, or
+/// `None` if it is invalid, and its length.
+fn literal(s: &str) -> (Option<Number>, usize) {
+    let bytes = s.as_bytes();
+    let digits = |start: usize, radix: u32| {
+        let mut end = start;
+        while bytes.get(end).is_some_and(|b| (*b as char).is_digit(radix)) {
+            end += 1;
+        }
+        end
+    };
+
+    let radix = match s.get(..2) {
+        Some("0x") | Some("0X") => 16,
+        Some("0o") | Some("0O") => 8,
+        Some("0b") | Some("0B") => 2,
+        _ => 10,
+    };
+
+    if radix != 10 {
+        let end = digits(2, radix);
+        let n = BigInt::parse_bytes(&bytes[2..end], radix).map(Number::normalize);
+        return (n, end);
+    }
+
+    let mut end = digits(0, 10);
+    let mut float = false;
+    if bytes.get(end) == Some(&b'.') {
+        float = true;
+        end = digits(end + 1, 10);
+    }
+    if matches!(bytes.get(end), Some(b'e') | Some(b'E')) {
+        let mut exp = end + 1;
+        if matches!(bytes.get(exp), Some(b'+') | Some(b'-')) {
+            exp += 1;
+        }
+        let exp_end = digits(exp, 10);
+        if exp_end > exp {
+            float = true;
+            end = exp_end;
+        }
+    }
+
+    let text = &s[..end];
+    let n = if float {
+        text.parse().ok().map(Number::Float)
+    } else {
+        match text.parse() {
+            Ok(n) => Some(Number::Int(n)),
+            Err(_) => text.parse().ok().map(Number::Big),
+        }
+    };
+    (n, end)
+}
+
+/// Parses the value of a variable as a number, with an optional sign.
+fn parse_number(s: &str) -> Option<Number> {
+    let s = s.trim();
+    let (negative, digits) = match s.as_bytes().first() {
+        Some(b'-') => (true, &s[1..]),
+        Some(b'+') => (false, &s[1..]),
+        _ => (false, s),
+    };
+
+    match literal(digits) {
+        (Some(n), len) if len == digits.len() && len > 0 => {
+            Some(if negative { negate(n) } else { n })
+        }
+        _ => None,
+    }
+}
+
+#[derive(Clone, Debug)]
+enum ExprKind {
+    Num(Number),
+    Var(String),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    Cond(Box<Expr>, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Clone, Debug)]
+struct Expr {
+    kind: ExprKind,
+    span: Span,
+}
+
+impl Expr {
+    fn binary(op: &'static str, lhs: Expr, rhs: Expr) -> Expr {
+        Expr {
+            span: Span::new(lhs.span.start, rhs.span.end),
+            kind: ExprKind::Binary(op, Box::new(lhs), Box::new(rhs)),
+        }
+    }
+}
+
+/// The binding power of a binary operator, excluding `**`.
+fn precedence(op: &str) -> Option<u8> {
+    Some(match op {
+        "||" => 1,
+        "&&" => 2,
+        "|" => 3,
+        "^" => 4,
+        "&" => 5,
+        "==" | "!=" => 6,
+        "<" | "<=" | ">" | ">=" => 7,
+        "<<" | ">>" => 8,
+        "+" | "-" => 9,
+        "*" | "/" | "%" => 10,
+        _ => return None,
+    })
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    /// The span at the end of the expression, for errors at the end.
+    end: Span,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_op(&self) -> Option<&'static str> {
+        match self.peek()?.kind {
+            TokenKind::Op(op) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, op: &'static str) -> Result<Span> {
+        match self.peek() {
+            Some(token) if token.kind == TokenKind::Op(op) => {
+                self.pos += 1;
+                Ok(token.span)
+            }
+            Some(token) => Err(Error::new(
+                ErrorKind::Arith(format!("expected `{}`, found {}", op, describe(token))),
+                token.span,
+            )),
+            None => Err(Error::new(
+                ErrorKind::Arith(format!("expected `{}`", op)),
+                self.end,
+            )),
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr> {
+        let cond = self.binary(1)?;
+        if self.peek_op() != Some("?") {
+            return Ok(cond);
+        }
+
+        self.pos += 1;
+        let then = self.expr()?;
+        self.expect(":")?;
+        let other = self.expr()?;
+
+        Ok(Expr {
+            span: Span::new(cond.span.start, other.span.end),
+            kind: ExprKind::Cond(Box::new(cond), Box::new(then), Box::new(other)),
+        })
+    }
+
+    fn binary(&mut self, min: u8) -> Result<Expr> {
+        let mut lhs = self.unary()?;
+
+        while let Some(op) = self.peek_op() {
+            let prec = match precedence(op) {
+                Some(prec) if prec >= min => prec,
+                _ => break,
+            };
+            self.pos += 1;
+            let rhs = self.binary(prec + 1)?;
+            lhs = Expr::binary(op, lhs, rhs);
+        }
+
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        match self.peek() {
+            Some(Token {
+                kind: TokenKind::Op(op),
+                span,
+            }) if matches!(*op, "-" | "+" | "!" | "~") => {
+                self.pos += 1;
+                let operand = self.unary()?;
+                Ok(Expr {
+                    span: Span::new(span.start, operand.span.end),
+                    kind: ExprKind::Unary(op, Box::new(operand)),
+                })
+            }
+            _ => self.power(),
+        }
+    }
+
+    fn power(&mut self) -> Result<Expr> {
+        let base = self.primary()?;
+        if self.peek_op() != Some("**") {
+            return Ok(base);
+        }
+
+        self.pos += 1;
+        // The exponent may itself be negated, as in `2 ** -1`.
+        let exp = self.unary()?;
+        Ok(Expr::binary("**", base, exp))
+    }
+
+    fn primary(&mut self) -> Result<Expr> {
+        let token = match self.peek() {
+            Some(token) => token,
+            None => {
+                return Err(Error::new(
+                    ErrorKind::Arith("expected an operand".to_owned()),
+                    self.end,
+                ))
+            }
+        };
+        self.pos += 1;
+
+        let kind = match &token.kind {
+            TokenKind::Num(n) => ExprKind::Num(n.clone()),
+            TokenKind::Var(name) => ExprKind::Var(name.clone()),
+            TokenKind::Op("(") => {
+                let inner = self.expr()?;
+                let close = self.expect(")")?;
+                return Ok(Expr {
+                    kind: inner.kind,
+                    span: Span::new(token.span.start, close.end),
+                });
+            }
+            TokenKind::Op(_) => return Err(unexpected(token)),
+        };
+
+        Ok(Expr {
+            kind,
+            span: token.span,
+        })
+    }
+}
+
+fn describe(token: &Token) -> String {
+    match &token.kind {
+        TokenKind::Num(n) => format!("`{}`", n),
+        TokenKind::Var(name) => format!("`{}`", name),
+        TokenKind::Op(op) => format!("`{}`", op),
+    }
+}
+
+fn unexpected(token: &Token) -> Error {
+    Error::new(
+        ErrorKind::Arith(format!("unexpected {}", describe(token))),
+        token.span,
+    )
+}
+
+fn eval_expr(expr: &Expr, lookup: &dyn Fn(&str) -> Option<Value>) -> Result<Number> {
+    let error = |msg: &str| Error::new(ErrorKind::Arith(msg.to_owned()), expr.span);
+
+    match &expr.kind {
+        ExprKind::Num(n) => Ok(n.clone()),
+        ExprKind::Var(name) => match lookup(name) {
+            Some(Value::Str(s)) => parse_number(&s).ok_or_else(|| {
+                error(&format!(
+                    "`{}` is not a number: {}",
+                    name,
+                    Value::Str(s).repr()
+                ))
+            }),
+            Some(value) => Err(Error::new(
+                ErrorKind::Type("a number", value.kind()),
+                expr.span,
+            )),
+            None => Err(Error::new(ErrorKind::UndefinedVar(name.clone()), expr.span)),
+        },
+        ExprKind::Unary(op, operand) => {
+            let n = eval_expr(operand, lookup)?;
+            match *op {
+                "-" => Ok(negate(n)),
+                "+" => Ok(n),
+                "!" => Ok(Number::from_bool(!n.is_true())),
+                _ => match n {
+                    Number::Int(n) => Ok(Number::Int(!n)),
+                    Number::Big(n) => Ok(Number::normalize(-n - 1)),
+                    Number::Float(_) => Err(error("`~` needs an integer")),
+                },
+            }
+        }
+        ExprKind::Binary("&&", lhs, rhs) => Ok(Number::from_bool(
+            eval_expr(lhs, lookup)?.is_true() && eval_expr(rhs, lookup)?.is_true(),
+        )),
+        ExprKind::Binary("||", lhs, rhs) => Ok(Number::from_bool(
+            eval_expr(lhs, lookup)?.is_true() || eval_expr(rhs, lookup)?.is_true(),
+        )),
+        ExprKind::Binary(op, lhs, rhs) => {
+            let lhs = eval_expr(lhs, lookup)?;
+            let rhs = eval_expr(rhs, lookup)?;
+            binary(op, lhs, rhs).map_err(|kind| Error::new(kind, expr.span))
+        }
+        ExprKind::Cond(cond, then, other) => {
+            if eval_expr(cond, lookup)?.is_true() {
+                eval_expr(then, lookup)
+            } else {
+                eval_expr(other, lookup)
+            }
+        }
+    }
+}
+
+fn negate(n: Number) -> Number {
+    match n {
+        Number::Int(n) => match n.checked_neg() {
+            Some(n) => Number::Int(n),
+            None => Number::Big(-BigInt::from(n)),
+        },
+        Number::Big(n) => Number::normalize(-n),
+        Number::Float(n) => Number::Float(-n),
+    }
+}
+
+fn binary(op: &str, lhs: Number, rhs: Number) -> std::result::Result<Number, ErrorKind> {
+    match op {
+        "==" | "!=" | "<" | "<=" | ">" | ">=" => {
+            let ord = compare(&lhs, &rhs);
+            let result = match op {
+                "==" => ord == Some(Ordering::Equal),
+                "!=" => ord != Some(Ordering::Equal),
+                "<" => ord == Some(Ordering::Less),
+                "<=" => matches!(ord, Some(Ordering::Less) | Some(Ordering::Equal)),
+                ">" => ord == Some(Ordering::Greater),
+                _ => matches!(ord, Some(Ordering::Greater) | Some(Ordering::Equal)),
+            };
+            Ok(Number::from_bool(result))
+        }
+        "&" | "|" | "^" | "<<" | ">>" => bitwise(op, lhs, rhs),
+        "**" => power(lhs, rhs),
+        _ => arithmetic(op, lhs, rhs),
+    }
+}
+
+fn compare(lhs: &Number, rhs: &Number) -> Option<Ordering> {
+    match (lhs, rhs) {
+        (Number::Int(a), Number::Int(b)) => Some(a.cmp(b)),
+        (Number::Float(_), _) | (_, Number::Float(_)) => lhs.to_f64().partial_cmp(&rhs.to_f64()),
+        _ => Some(lhs.to_big()?.cmp(&rhs.to_big()?)),
+    }
+}
+
+/// `+`, `-`, `*`, `/` and `%`.
+fn arithmetic(op: &str, lhs: Number, rhs: Number) -> std::result::Result<Number, ErrorKind> {
+    if matches!(op, "/" | "%") && !rhs.is_true() {
+        return Err(ErrorKind::DivideByZero);
+    }
+
+    if let (Number::Float(_), _) | (_, Number::Float(_)) = (&lhs, &rhs) {
+        let (a, b) = (lhs.to_f64(), rhs.to_f64());
+        return Ok(Number::Float(match op {
+            "+" => a + b,
+            "-" => a - b,
+            "*" => a * b,
+            "/" => a / b,
+            _ => a % b,
+        }));
+    }
+
+    if let (Number::Int(a), Number::Int(b)) = (&lhs, &rhs) {
+        let result = match op {
+            "+" => a.checked_add(*b),
+            "-" => a.checked_sub(*b),
+            "*" => a.checked_mul(*b),
+            "/" => a.checked_div(*b),
+            _ => a.checked_rem(*b),
+        };
+        if let Some(n) = result {
+            return Ok(Number::Int(n));
+        }
+    }
+
+    // The result overflowed, or one of the operands is already big.
+    let (a, b) = match (lhs.to_big(), rhs.to_big()) {
+        (Some(a), Some(b)) => (a, b),
+        _ => unreachable!("floats are handled above"),
+    };
+    Ok(Number::normalize(match op {
+        "+" => a + b,
+        "-" => a - b,
+        "*" => a * b,
+        "/" => a / b,
+        _ => a % b,
+    }))
+}
+
+fn power(base: Number, exp: Number) -> std::result::Result<Number, ErrorKind> {
+    let too_large = || ErrorKind::Arith("result is too large".to_owned());
+
+    let (base, exp) = match (base, exp) {
+        (base, Number::Int(exp)) if exp >= 0 && !matches!(base, Number::Float(_)) => (base, exp),
+        (ref base, Number::Big(ref exp))
+            if exp.is_positive() && !matches!(base, Number::Float(_)) =>
+        {
+            return Err(too_large())
+        }
+        (base, exp) => return Ok(Number::Float(base.to_f64().powf(exp.to_f64()))),
+    };
+
+    if let Number::Int(n) = base {
+        if let Some(n) = u32::try_from(exp).ok().and_then(|exp| n.checked_pow(exp)) {
+            return Ok(Number::Int(n));
+        }
+    }
+
+    let base = base.to_big().unwrap_or_default();
+    let bits = (base.bits() as u128).saturating_mul(exp as u128);
+    if bits > MAX_BITS as u128 && base.abs() > BigInt::from(1) {
+        return Err(too_large());
+    }
+
+    // A base of 0, 1 or -1 never grows, so only the parity of the exponent
+    // matters.
+    let exp = if bits > MAX_BITS as u128 {
+        2 - (exp % 2)
+    } else {
+        exp
+    };
+    Ok(Number::normalize(num_traits::pow(base, exp as usize)))
+}
+
+/// `&`, `|`, `^`, `<<` and `>>`.
+fn bitwise(op: &str, lhs: Number, rhs: Number) -> std::result::Result<Number, ErrorKind> {
+    let (a, b) = match (lhs.to_big(), rhs.to_big()) {
+        (Some(a), Some(b)) => (a, b),
+        _ => return Err(ErrorKind::Arith(format!("`{}` needs integers", op))),
+    };
+
+    let shift = || match b.to_usize() {
+        Some(n) if n <= MAX_BITS => Ok(n),
+        Some(_) => Err(ErrorKind::Arith("shift amount is too large".to_owned())),
+        None => Err(ErrorKind::Arith("shift amount is negative".to_owned())),
+    };
+
+    Ok(Number::normalize(match op {
+        "&" => &a & &b,
+        "|" => &a | &b,
+        "^" => &a ^ &b,
+        "<<" => &a << shift()?,
+        _ => &a >> shift()?,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<Value> {
+        match name {
+            "x" => Some(Value::Str("6".to_owned())),
+            "a-b" => Some(Value::Str(" -2 ".to_owned())),
+            "s" => Some(Value::Str("abc".to_owned())),
+            _ => None,
+        }
+    }
+
+    fn try_eval(text: &str) -> Result<Number> {
+        let expr = ArithExpr {
+            text: text.to_owned(),
+            span: Span::new(0, text.len()),
+        };
+        eval(&expr, &lookup)
+    }
+
+    fn eval_str(text: &str) -> String {
+        match try_eval(text) {
+            Ok(n) => n.to_string(),
+            Err(err) => panic!("failed to evaluate {:?}: {}", text, err),
+        }
+    }
+
+    fn eval_err(text: &str) -> ErrorKind {
+        match try_eval(text) {
+            Ok(n) => panic!("evaluated {:?} to {}", text, n),
+            Err(err) => err.kind,
+        }
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(eval_str("1 + 2 * 3"), "7");
+        assert_eq!(eval_str("(1 + 2) * 3"), "9");
+        assert_eq!(eval_str("10 - 4 - 3"), "3");
+        assert_eq!(eval_str("2 ** 3 ** 2"), "512");
+        assert_eq!(eval_str("-2 ** 2"), "-4");
+        assert_eq!(eval_str("1 << 2 + 1"), "8");
+        assert_eq!(eval_str("1 | 2 ^ 3 & 6"), "1");
+        assert_eq!(eval_str("1 < 2 == 1"), "1");
+    }
+
+    #[test]
+    fn integers() {
+        assert_eq!(eval_str("7 / 2"), "3");
+        assert_eq!(eval_str("-7 / 2"), "-3");
+        assert_eq!(eval_str("-7 % 2"), "-1");
+        assert_eq!(eval_str("0xff + 0o17 + 0b101"), "275");
+        assert_eq!(eval_str("~0"), "-1");
+        assert_eq!(eval_str("!5"), "0");
+    }
+
+    #[test]
+    fn big_integers() {
+        assert_eq!(eval_str("9223372036854775807 + 1"), "9223372036854775808");
+        assert_eq!(eval_str("2 ** 64"), "18446744073709551616");
+        assert_eq!(eval_str("2 ** 64 - 2 ** 64 + 1"), "1");
+        assert_eq!(eval_str("1 << 70 >> 69"), "2");
+        assert_eq!(eval_str("(-1) ** (2 ** 62 + 1)"), "-1");
+        assert!(matches!(eval_err("3 ** (2 ** 70)"), ErrorKind::Arith(_)));
+        assert!(matches!(eval_err("2 ** 10000000"), ErrorKind::Arith(_)));
+    }
+
+    #[test]
+    fn floats() {
+        assert_eq!(eval_str("1.5 * 2"), "3.0");
+        assert_eq!(eval_str("2e3"), "2000.0");
+        assert_eq!(eval_str("1 / 4."), "0.25");
+        assert_eq!(eval_str("2 ** -1"), "0.5");
+        assert_eq!(eval_str("0.5 < 1"), "1");
+        assert!(matches!(eval_err("1.5 & 1"), ErrorKind::Arith(_)));
+    }
+
+    #[test]
+    fn logic() {
+        assert_eq!(eval_str("0 && y"), "0");
+        assert_eq!(eval_str("1 || y"), "1");
+        assert_eq!(eval_str("2 && 3"), "1");
+        assert_eq!(eval_str("x > 5 ? 10 : 20"), "10");
+        assert_eq!(eval_str("0 ? 1 : 0 ? 2 : 3"), "3");
+    }
+
+    #[test]
+    fn variables() {
+        assert_eq!(eval_str("x * 2"), "12");
+        assert_eq!(eval_str("$x + ${x}"), "12");
+        assert_eq!(eval_str("${a-b} * 2"), "-4");
+        assert!(matches!(eval_err("y + 1"), ErrorKind::UndefinedVar(name) if name == "y"));
+        assert!(matches!(eval_err("s + 1"), ErrorKind::Arith(_)));
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!(eval_err("1 / 0"), ErrorKind::DivideByZero));
+        assert!(matches!(eval_err("1 % 0"), ErrorKind::DivideByZero));
+        assert!(matches!(eval_err("1 +"), ErrorKind::Arith(_)));
+        assert!(matches!(eval_err("(1"), ErrorKind::Arith(_)));
+        assert!(matches!(eval_err("1 2"), ErrorKind::Arith(_)));
+        assert!(matches!(eval_err("1 << -1"), ErrorKind::Arith(_)));
+    }
+
+    #[test]
+    fn integer_results() {
+        let expr = |text: &str| ArithExpr {
+            text: text.to_owned(),
+            span: Span::new(0, text.len()),
+        };
+
+        assert_eq!(eval_integer(&expr("x - 8"), &lookup).unwrap(), -2);
+        assert!(eval_integer(&expr("1.0"), &lookup).is_err());
+        assert!(eval_integer(&expr("2 ** 64"), &lookup).is_err());
+    }
+}
@@ -0,0 +1,85 @@
+use futures::future::{BoxFuture, FutureExt};
+
+use crate::eval::builtins::Call;
+use crate::eval::frame::{Frame, Options};
+use crate::eval::{ErrorKind, Result, Status};
+
+pub fn true_(_frame: &mut Frame, _call: Call) -> BoxFuture<'_, Result<Status>> {
+    async { Ok(Status::SUCCESS) }.boxed()
+}
+
+pub fn false_(_frame: &mut Frame, _call: Call) -> BoxFuture<'_, Result<Status>> {
+    async { Ok(Status::FAILURE) }.boxed()
+}
+
+/// `echo [-n] [ARG]...`
+///
+/// Writes the arguments separated by spaces, followed by a newline unless
+/// `-n` is given.
+pub fn echo(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        let mut args: Vec<String> = call.args.iter().map(ToString::to_string).collect();
+        let newline = args.first().map(String::as_str) != Some("-n");
+        if !newline {
+            args.remove(0);
+        }
+
+        let mut out = args.join(" ");
+        if newline {
+            out.push('\n');
+        }
+
+        frame
+            .write_out(out.into_bytes())
+            .await
+            .map_err(|err| call.error(ErrorKind::Io(err)))?;
+
+        Ok(Status::SUCCESS)
+    }
+    .boxed()
+}
+
+/// `setopt OPTION...`
+///
+/// The options are `pipefail`, and `max-iterations=N` to limit the iterations
+/// of `while` and `until` loops.
+pub fn setopt(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move { set_options(frame, &call, true) }.boxed()
+}
+
+/// `unsetopt OPTION...`
+///
+/// Unsetting `max-iterations` lets loops run any number of iterations.
+pub fn unsetopt(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move { set_options(frame, &call, false) }.boxed()
+}
+
+fn set_options(frame: &Frame, call: &Call, value: bool) -> Result<Status> {
+    let mut options = frame.options();
+
+    for arg in call.str_args()? {
+        let (name, arg) = match arg.split_once('=') {
+            Some((name, arg)) => (name, Some(arg)),
+            None => (arg, None),
+        };
+
+        match (name, arg) {
+            ("pipefail", None) => options.pipefail = value,
+            ("max-iterations", Some(max)) if value => match max.parse() {
+                Ok(max) => options.max_iterations = Some(max),
+                Err(_) => return Err(call.usage(&format!("invalid number `{}`", max))),
+            },
+            ("max-iterations", None) if !value => options.max_iterations = None,
+            ("max-iterations", _) if value => {
+                return Err(call.usage("expected `max-iterations=N`"));
+            }
+            ("pipefail", _) | ("max-iterations", _) => {
+                return Err(call.usage(&format!("option `{}` takes no value here", name)));
+            }
+            _ => return Err(call.usage(&format!("unknown option `{}`", name))),
+        }
+    }
+
+    frame.set_options(|o: &mut Options| *o = options);
+    Ok(Status::SUCCESS)
+}
@@ -0,0 +1,173 @@
+use std::fs;
+use std::sync::Arc;
+
+use futures::future::{BoxFuture, FutureExt};
+
+use crate::eval::builtins::Call;
+use crate::eval::external;
+use crate::eval::frame::Frame;
+use crate::eval::module;
+use crate::eval::value::Value;
+use crate::eval::{Error, ErrorKind, Result, Status};
+use crate::parse::{self, Source};
+
+/// `break`
+pub fn break_(_frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move { Err(call.error(ErrorKind::Break)) }.boxed()
+}
+
+/// `continue`
+pub fn continue_(_frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move { Err(call.error(ErrorKind::Continue)) }.boxed()
+}
+
+/// `return [STATUS]`
+///
+/// Without a status, returns the status of the last command.
+pub fn return_(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        let status = match &*call.str_args()? {
+            [] => frame.status,
+            [status] => match status.parse() {
+                Ok(code) => Status::new(code),
+                Err(_) => return Err(call.usage(&format!("invalid status `{}`", status))),
+            },
+            _ => return Err(call.usage("too many arguments")),
+        };
+
+        Err(call.error(ErrorKind::Return(status)))
+    }
+    .boxed()
+}
+
+/// `fail MESSAGE`, or `fail EXCEPTION`
+///
+/// Raises an exception with a message, or raises a caught exception again.
+pub fn fail(_frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        match &*call.args {
+            [Value::Exception(err)] => Err(Error {
+                kind: ErrorKind::Exception(err.clone()),
+                span: err.span,
+                src: err.src.clone(),
+            }),
+            [Value::Str(msg)] => Err(call.error(ErrorKind::Fail(msg.clone()))),
+            [value] => Err(call.error(ErrorKind::Type("a string", value.kind()))),
+            _ => Err(call.usage("expected `MESSAGE`")),
+        }
+    }
+    .boxed()
+}
+
+/// `exit [STATUS]`
+///
+/// Exits the shell, with the status of the last command by default.
+pub fn exit(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        let status = match &*call.str_args()? {
+            [] => frame.status,
+            [status] => match status.parse() {
+                Ok(code) => Status::new(code),
+                Err(_) => return Err(call.usage(&format!("invalid status `{}`", status))),
+            },
+            _ => return Err(call.usage("too many arguments")),
+        };
+
+        Err(call.error(ErrorKind::Exit(status)))
+    }
+    .boxed()
+}
+
+/// `source FILE`
+///
+/// Evaluates a file in the current scope, so the variables and functions it
+/// defines remain. `return` ends the file.
+pub fn source(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        let path = match &*call.str_args()? {
+            [path] => path.to_string(),
+            _ => return Err(call.usage("expected `FILE`")),
+        };
+
+        let code = fs::read_to_string(&path)
+            .map_err(|err| call.error(ErrorKind::Open(path.clone(), err)))?;
+        let src = Arc::new(Source::new(path, code));
+        let chunk = parse::parse(&src).map_err(|err| Error {
+            kind: ErrorKind::Syntax(err.kind),
+            span: err.span,
+            src: Some(Arc::clone(&src)),
+        })?;
+
+        let caller = frame.src.replace(Arc::clone(&src));
+        let result = frame.eval_chunk(&chunk).await;
+        frame.src = caller;
+
+        match result {
+            Err(Error {
+                kind: ErrorKind::Return(status),
+                ..
+            }) => Ok(status),
+            result => result.map_err(|err| err.or_source(Some(&src))),
+        }
+    }
+    .boxed()
+}
+
+/// `use [&as=NS] MODULE`
+///
+/// Loads a module, making its exported names available in the namespace of
+/// its name, or `NS`, for the rest of the current scope.
+pub fn use_(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        let alias = match call.opt("as", &["as"])? {
+            Some(Value::Str(alias)) => Some(alias.clone()),
+            Some(value) => return Err(call.error(ErrorKind::Type("a string", value.kind()))),
+            None => None,
+        };
+        let name = match &*call.str_args()? {
+            [name] => name.to_string(),
+            _ => return Err(call.usage("expected `MODULE`")),
+        };
+
+        if module::BUILTIN_NAMESPACES.contains(&&*name) {
+            return Ok(Status::SUCCESS);
+        }
+
+        let ns = alias.unwrap_or_else(|| module::namespace(&name).to_owned());
+        if ns.is_empty() || ns.contains(':') {
+            return Err(call.usage(&format!("invalid namespace `{}`", ns)));
+        }
+
+        let path = match module::resolve(&name, &frame.scope) {
+            Some(path) => path,
+            None => return Err(call.error(ErrorKind::NoSuchModule(name))),
+        };
+        let module = frame.load_module(&path, call.span).await?;
+        frame.scope.declare_mod(&ns, module);
+        Ok(Status::SUCCESS)
+    }
+    .boxed()
+}
+
+/// `exec COMMAND [ARG]...`
+///
+/// Replaces the shell with an external command. Without a command, the
+/// redirections of `exec` apply to the rest of the code instead.
+pub fn exec(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        let args: Vec<String> = call.str_args()?.into_iter().map(str::to_owned).collect();
+        let (name, args) = match args.split_first() {
+            Some(split) => split,
+            None => return Ok(Status::SUCCESS),
+        };
+
+        let file = match frame.search_path(name) {
+            Some(file) => file,
+            None => return Err(call.error(ErrorKind::CommandNotFound(name.clone()))),
+        };
+
+        let err = external::exec(frame, name, &file, args);
+        Err(call.error(ErrorKind::Exec(name.clone(), err)))
+    }
+    .boxed()
+}
@@ -0,0 +1,192 @@
+use std::env;
+use std::path::{Path, PathBuf};
+
+use futures::future::{BoxFuture, FutureExt};
+
+use crate::eval::builtins::Call;
+use crate::eval::frame::Frame;
+use crate::eval::value::Value;
+use crate::eval::{ErrorKind, Result, Status};
+
+/// `cd [DIR]`
+///
+/// Changes the working directory, to `$HOME` without an argument, or to
+/// `$OLDPWD` if the argument is `-`. Relative directories are searched for in
+/// `$CDPATH`.
+pub fn cd(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        let (dir, print) = match &*call.str_args()? {
+            [] => (var(frame, &call, "HOME")?, false),
+            ["-"] => (var(frame, &call, "OLDPWD")?, true),
+            [dir] => match search_cdpath(frame, dir) {
+                Some(found) => (found, true),
+                None => (dir.to_string(), false),
+            },
+            _ => return Err(call.usage("too many arguments")),
+        };
+
+        let wd = chdir(frame, &call, Path::new(&dir))?;
+        if print {
+            write_line(frame, &call, &wd.to_string_lossy()).await?;
+        }
+        Ok(Status::SUCCESS)
+    }
+    .boxed()
+}
+
+/// `pwd`
+///
+/// Writes the working directory.
+pub fn pwd(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        if !call.args.is_empty() {
+            return Err(call.usage("too many arguments"));
+        }
+
+        let wd = env::current_dir().map_err(|err| call.error(ErrorKind::Io(err)))?;
+        write_line(frame, &call, &wd.to_string_lossy()).await?;
+        Ok(Status::SUCCESS)
+    }
+    .boxed()
+}
+
+/// `pushd [DIR]`
+///
+/// Pushes the working directory onto the directory stack and changes to the
+/// directory. Without an argument, swaps the working directory with the top
+/// of the stack.
+pub fn pushd(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        let wd = env::current_dir().map_err(|err| call.error(ErrorKind::Io(err)))?;
+
+        match &*call.str_args()? {
+            [] => {
+                let top = frame.globals.dirs.lock().unwrap().pop();
+                let top = top.ok_or_else(|| call.error(ErrorKind::DirStackEmpty))?;
+                if let Err(err) = chdir(frame, &call, &top) {
+                    frame.globals.dirs.lock().unwrap().push(top);
+                    return Err(err);
+                }
+            }
+            [dir] => {
+                chdir(frame, &call, Path::new(dir))?;
+            }
+            _ => return Err(call.usage("too many arguments")),
+        }
+
+        frame.globals.dirs.lock().unwrap().push(wd);
+        write_stack(frame, &call).await?;
+        Ok(Status::SUCCESS)
+    }
+    .boxed()
+}
+
+/// `popd`
+///
+/// Pops the top of the directory stack and changes to it.
+pub fn popd(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        if !call.args.is_empty() {
+            return Err(call.usage("too many arguments"));
+        }
+
+        let top = frame.globals.dirs.lock().unwrap().pop();
+        let top = top.ok_or_else(|| call.error(ErrorKind::DirStackEmpty))?;
+        if let Err(err) = chdir(frame, &call, &top) {
+            frame.globals.dirs.lock().unwrap().push(top);
+            return Err(err);
+        }
+
+        write_stack(frame, &call).await?;
+        Ok(Status::SUCCESS)
+    }
+    .boxed()
+}
+
+/// `dirs`
+///
+/// Writes the working directory followed by the directory stack, top first.
+pub fn dirs(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        if !call.args.is_empty() {
+            return Err(call.usage("too many arguments"));
+        }
+
+        write_stack(frame, &call).await?;
+        Ok(Status::SUCCESS)
+    }
+    .boxed()
+}
+
+/// Changes the working directory, updating `$PWD` and `$OLDPWD`.
+fn chdir(frame: &Frame, call: &Call, dir: &Path) -> Result<PathBuf> {
+    let old = env::current_dir().ok();
+
+    env::set_current_dir(dir)
+        .map_err(|err| call.error(ErrorKind::Chdir(dir.to_string_lossy().into_owned(), err)))?;
+    let wd = env::current_dir().map_err(|err| call.error(ErrorKind::Io(err)))?;
+
+    if let Some(old) = old {
+        let old = old.to_string_lossy().into_owned();
+        frame.scope.set_global("OLDPWD", Value::Str(old));
+    }
+    let pwd = wd.to_string_lossy().into_owned();
+    frame.scope.set_global("PWD", Value::Str(pwd));
+
+    Ok(wd)
+}
+
+/// Searches for a relative directory in `$CDPATH`, returning the directory
+/// found if it is not in the working directory.
+fn search_cdpath(frame: &Frame, dir: &str) -> Option<String> {
+    let is_explicit = dir.starts_with('/')
+        || dir == "."
+        || dir == ".."
+        || dir.starts_with("./")
+        || dir.starts_with("../");
+    if is_explicit {
+        return None;
+    }
+
+    let cdpath = frame.scope.get("CDPATH")?.value;
+    let found = cdpath.as_str()?.split(':').find_map(|entry| {
+        let base = if entry.is_empty() { "." } else { entry };
+        let path = Path::new(base).join(dir);
+        if path.is_dir() {
+            Some((entry.is_empty(), path))
+        } else {
+            None
+        }
+    });
+
+    match found {
+        Some((false, path)) => Some(path.to_string_lossy().into_owned()),
+        _ => None,
+    }
+}
+
+fn var(frame: &Frame, call: &Call, name: &str) -> Result<String> {
+    match frame.scope.get(name).map(|var| var.value) {
+        Some(Value::Str(value)) => Ok(value),
+        Some(value) => Err(call.error(ErrorKind::Type("a string", value.kind()))),
+        None => Err(call.error(ErrorKind::UndefinedVar(name.to_owned()))),
+    }
+}
+
+async fn write_stack(frame: &Frame, call: &Call) -> Result<()> {
+    let wd = env::current_dir().map_err(|err| call.error(ErrorKind::Io(err)))?;
+
+    let mut line = wd.to_string_lossy().into_owned();
+    for dir in frame.globals.dirs.lock().unwrap().iter().rev() {
+        line.push(' ');
+        line.push_str(&dir.to_string_lossy());
+    }
+    write_line(frame, call, &line).await
+}
+
+async fn write_line(frame: &Frame, call: &Call, line: &str) -> Result<()> {
+    frame
+        .write_out(format!("{}\n", line).into_bytes())
+        .await
+        .map_err(|err| call.error(ErrorKind::Io(err)))
+}
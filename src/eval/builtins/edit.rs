@@ -0,0 +1,311 @@
+//! Builtins of the `edit:` and `prompt:` namespaces, which control the line
+//! editor of an interactive shell.
+//!
+//! The buffer is that of the next line read, so `edit:insert` in a command
+//! puts text in front of the user at the next prompt.
+
+use std::time::Duration;
+
+use futures::future::{BoxFuture, FutureExt};
+
+use crate::cli::keymap::{self, Action, Binding, Mode};
+use crate::cli::tty::KeyEvent;
+use crate::eval::builtins::Call;
+use crate::eval::edit::{Callback, PromptFn};
+use crate::eval::frame::Frame;
+use crate::eval::value::Value;
+use crate::eval::{ErrorKind, Result, Status};
+
+/// `edit:insert TEXT`
+///
+/// Inserts text in the buffer at the cursor, moving the cursor after it.
+pub fn insert(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        let text = one_arg(&call)?;
+
+        let mut edit = frame.globals.edit.lock().unwrap();
+        let dot = edit.dot;
+        edit.buffer.insert_str(dot, text);
+        edit.dot += text.len();
+        Ok(Status::SUCCESS)
+    }
+    .boxed()
+}
+
+/// `edit:replace TEXT`
+///
+/// Replaces the buffer, moving the cursor to its end.
+pub fn replace(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        let text = one_arg(&call)?;
+
+        let mut edit = frame.globals.edit.lock().unwrap();
+        edit.buffer = text.to_owned();
+        edit.dot = text.len();
+        Ok(Status::SUCCESS)
+    }
+    .boxed()
+}
+
+/// `edit:buffer`
+///
+/// Outputs the buffer.
+pub fn buffer(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        if !call.args.is_empty() {
+            return Err(call.usage("too many arguments"));
+        }
+
+        let buffer = frame.globals.edit.lock().unwrap().buffer.clone();
+        put(frame, &call, buffer).await
+    }
+    .boxed()
+}
+
+/// `edit:dot [INDEX]`
+///
+/// Outputs the position of the cursor in the buffer, in chars, or moves it to
+/// the index, clamped to the buffer.
+pub fn dot(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        let index = match &*call.str_args()? {
+            [] => None,
+            [index] => match index.parse::<usize>() {
+                Ok(index) => Some(index),
+                Err(_) => return Err(call.usage(&format!("invalid index `{}`", index))),
+            },
+            _ => return Err(call.usage("expected `[INDEX]`")),
+        };
+
+        let dot = {
+            let mut edit = frame.globals.edit.lock().unwrap();
+            match index {
+                Some(index) => {
+                    edit.dot = edit
+                        .buffer
+                        .char_indices()
+                        .nth(index)
+                        .map_or(edit.buffer.len(), |(dot, _)| dot);
+                    return Ok(Status::SUCCESS);
+                }
+                None => edit.buffer[..edit.dot].chars().count(),
+            }
+        };
+        put(frame, &call, dot.to_string()).await
+    }
+    .boxed()
+}
+
+/// `edit:notify MESSAGE`
+///
+/// Shows a message above the next prompt.
+pub fn notify(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        let msg = one_arg(&call)?;
+        frame
+            .globals
+            .edit
+            .lock()
+            .unwrap()
+            .notes
+            .push(msg.to_owned());
+        Ok(Status::SUCCESS)
+    }
+    .boxed()
+}
+
+/// `edit:bind [&mode=insert] KEYS ACTION|FN`
+///
+/// Binds a sequence of keys, such as `ctrl-x ctrl-e`, to a named action or a
+/// function in the keymap of a mode, `insert`, or `normal` or `visual` of vi
+/// mode. The function is called with the buffer
+/// of the line being edited, which it can change with the other `edit:`
+/// builtins, and its output is shown above the prompt.
+pub fn bind(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        const OPTS: &[&str] = &["mode"];
+
+        let mode = mode(&call, OPTS)?;
+        let (keys, binding) = match &*call.args {
+            [keys, Value::Str(action)] => match Action::from_name(action) {
+                Some(action) => (keys, Binding::Action(action)),
+                None => return Err(call.usage(&format!("unknown action `{}`", action))),
+            },
+            [keys, Value::Fn(closure)] => {
+                let callback = Callback::new(frame, closure.clone());
+                (keys, Binding::Fn(callback.bound_fn()))
+            }
+            [_, value] => {
+                return Err(call.error(ErrorKind::Type("a string or function", value.kind())))
+            }
+            _ => return Err(call.usage("expected `KEYS ACTION|FN`")),
+        };
+        let keys = keys_arg(&call, keys)?;
+
+        let keymaps = frame.globals.edit.lock().unwrap().keymaps.clone();
+        keymaps.lock().unwrap().bind(mode, keys, binding);
+        Ok(Status::SUCCESS)
+    }
+    .boxed()
+}
+
+/// `edit:unbind [&mode=insert] KEYS`
+///
+/// Removes the binding of a sequence of keys in the keymap of a mode, failing
+/// if it has none.
+pub fn unbind(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        const OPTS: &[&str] = &["mode"];
+
+        let mode = mode(&call, OPTS)?;
+        let keys = match &*call.args {
+            [keys] => keys_arg(&call, keys)?,
+            _ => return Err(call.usage("expected `KEYS`")),
+        };
+
+        let keymaps = frame.globals.edit.lock().unwrap().keymaps.clone();
+        let unbound = keymaps.lock().unwrap().unbind(mode, &keys);
+        Ok(match unbound {
+            Some(_) => Status::SUCCESS,
+            None => Status::FAILURE,
+        })
+    }
+    .boxed()
+}
+
+/// `edit:vi-mode [on|off]`
+///
+/// Turns vi mode on, or off. While it is on, `esc` enters the normal mode of
+/// vi, and the mode is shown in the prompt and by the shape of the cursor.
+pub fn vi_mode(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        let vi = match &*call.str_args()? {
+            [] => true,
+            ["on"] => true,
+            ["off"] => false,
+            _ => return Err(call.usage("expected `[on|off]`")),
+        };
+
+        let keymaps = frame.globals.edit.lock().unwrap().keymaps.clone();
+        keymaps.lock().unwrap().set_vi(vi);
+        Ok(Status::SUCCESS)
+    }
+    .boxed()
+}
+
+/// `prompt:add [&position=N] [&side=left|right] [&interval=SECS] FN`
+///
+/// Adds a module to a prompt, showing the output of the function. Modules are
+/// ordered by position, the default of 0 being after the working directory
+/// and before the prompt marker. The function is called each time the prompt
+/// is shown, and every interval while a line is edited if given.
+pub fn add(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        const OPTS: &[&str] = &["position", "side", "interval"];
+
+        let position = match call.opt("position", OPTS)? {
+            Some(Value::Str(position)) => match position.parse() {
+                Ok(position) => position,
+                Err(_) => return Err(call.usage(&format!("invalid position `{}`", position))),
+            },
+            Some(value) => return Err(call.error(ErrorKind::Type("a string", value.kind()))),
+            None => 0,
+        };
+        let right = side(&call, OPTS)? == Some(true);
+        let interval = match call.opt("interval", OPTS)? {
+            Some(Value::Str(secs)) => match secs.parse::<f64>() {
+                Ok(secs) if secs > 0.0 && secs.is_finite() => Some(Duration::from_secs_f64(secs)),
+                _ => return Err(call.usage(&format!("invalid interval `{}`", secs))),
+            },
+            Some(value) => return Err(call.error(ErrorKind::Type("a string", value.kind()))),
+            None => None,
+        };
+        let closure = match &*call.args {
+            [Value::Fn(closure)] => closure.clone(),
+            [value] => return Err(call.error(ErrorKind::Type("a function", value.kind()))),
+            _ => return Err(call.usage("expected `FN`")),
+        };
+
+        let prompt = PromptFn {
+            callback: Callback::new(frame, closure),
+            position,
+            right,
+            interval,
+        };
+
+        let mut edit = frame.globals.edit.lock().unwrap();
+        edit.prompts.push(prompt);
+        edit.prompts_version += 1;
+        Ok(Status::SUCCESS)
+    }
+    .boxed()
+}
+
+/// `prompt:clear [&side=left|right]`
+///
+/// Removes every module from a prompt, including the default modules, or from
+/// both prompts without a side.
+pub fn clear(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        let side = side(&call, &["side"])?;
+        if !call.args.is_empty() {
+            return Err(call.usage("too many arguments"));
+        }
+
+        let mut edit = frame.globals.edit.lock().unwrap();
+        edit.prompts
+            .retain(|prompt| side.is_some_and(|right| prompt.right != right));
+        if side != Some(true) {
+            edit.default_prompt = false;
+        }
+        edit.prompts_version += 1;
+        Ok(Status::SUCCESS)
+    }
+    .boxed()
+}
+
+/// The `&side` option, `Some(true)` for the right prompt.
+fn side(call: &Call, known: &[&str]) -> Result<Option<bool>> {
+    match call.opt("side", known)? {
+        Some(Value::Str(side)) if side == "left" => Ok(Some(false)),
+        Some(Value::Str(side)) if side == "right" => Ok(Some(true)),
+        Some(Value::Str(side)) => Err(call.usage(&format!("invalid side `{}`", side))),
+        Some(value) => Err(call.error(ErrorKind::Type("a string", value.kind()))),
+        None => Ok(None),
+    }
+}
+
+/// The `&mode` option, the mode of a keymap.
+fn mode(call: &Call, known: &[&str]) -> Result<Mode> {
+    match call.opt("mode", known)? {
+        Some(Value::Str(mode)) => match Mode::from_name(mode) {
+            Some(mode) => Ok(mode),
+            None => Err(call.usage(&format!("invalid mode `{}`", mode))),
+        },
+        Some(value) => Err(call.error(ErrorKind::Type("a string", value.kind()))),
+        None => Ok(Mode::Insert),
+    }
+}
+
+fn keys_arg(call: &Call, keys: &Value) -> Result<Vec<KeyEvent>> {
+    match keys {
+        Value::Str(keys) => keymap::parse_keys(keys).map_err(|msg| call.usage(&msg)),
+        value => Err(call.error(ErrorKind::Type("a string", value.kind()))),
+    }
+}
+
+fn one_arg(call: &Call) -> Result<&str> {
+    match &*call.args {
+        [Value::Str(s)] => Ok(s),
+        [value] => Err(call.error(ErrorKind::Type("a string", value.kind()))),
+        _ => Err(call.usage("expected `TEXT`")),
+    }
+}
+
+async fn put(frame: &Frame, call: &Call, s: String) -> Result<Status> {
+    frame
+        .write_value(Value::Str(s))
+        .await
+        .map_err(|err| call.error(ErrorKind::Io(err)))?;
+    Ok(Status::SUCCESS)
+}
@@ -0,0 +1,46 @@
+//! Builtins of the `history:` namespace, which read the commands of
+//! interactive shells.
+
+use futures::future::{BoxFuture, FutureExt};
+
+use crate::eval::builtins::Call;
+use crate::eval::frame::Frame;
+use crate::eval::history;
+use crate::eval::value::Value;
+use crate::eval::{ErrorKind, Result, Status};
+
+/// `history:list [&limit=N]`
+///
+/// Outputs the commands of the history, oldest first, or the last N of them.
+/// Each is a map of `cmd`, `time`, `cwd`, `status` and `duration`, with times
+/// in seconds and the start since the Unix epoch.
+pub fn list(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        let limit = match call.opt("limit", &["limit"])? {
+            Some(Value::Str(limit)) => match limit.parse::<usize>() {
+                Ok(limit) => Some(limit),
+                Err(_) => return Err(call.usage(&format!("invalid limit `{}`", limit))),
+            },
+            Some(value) => return Err(call.error(ErrorKind::Type("a string", value.kind()))),
+            None => None,
+        };
+        if !call.args.is_empty() {
+            return Err(call.usage("too many arguments"));
+        }
+
+        let entries = match history::path(&frame.scope) {
+            Some(file) => history::read(&file).map_err(|err| call.error(ErrorKind::Io(err)))?,
+            None => Vec::new(),
+        };
+        let skip = limit.map_or(0, |limit| entries.len().saturating_sub(limit));
+
+        for entry in &entries[skip..] {
+            frame
+                .write_value(entry.to_value())
+                .await
+                .map_err(|err| call.error(ErrorKind::Io(err)))?;
+        }
+        Ok(Status::SUCCESS)
+    }
+    .boxed()
+}
@@ -0,0 +1,545 @@
+use std::io;
+use std::time::{Duration, Instant};
+
+use futures::future::{BoxFuture, FutureExt};
+
+use crate::eval::builtins::var::valid_name;
+use crate::eval::builtins::Call;
+use crate::eval::frame::Frame;
+use crate::eval::scope::Var;
+use crate::eval::value::Value;
+use crate::eval::{ErrorKind, Result, Status};
+
+/// `printf FORMAT [ARG]...`
+///
+/// Writes the arguments formatted by `%` directives in the format, as in C.
+/// The format is reused until all the arguments are consumed.
+pub fn printf(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        let args = call.str_args()?;
+        let (format, args) = match args.split_first() {
+            Some((format, args)) => (*format, args),
+            None => return Err(call.usage("expected `FORMAT`")),
+        };
+
+        let mut out = String::new();
+        let mut args = args.iter().copied();
+        loop {
+            let more = format_once(format, &mut args, &mut out).map_err(|msg| call.usage(&msg))?;
+            if !more || args.len() == 0 {
+                break;
+            }
+        }
+
+        frame
+            .write_out(out.into_bytes())
+            .await
+            .map_err(|err| call.error(ErrorKind::Io(err)))?;
+        Ok(Status::SUCCESS)
+    }
+    .boxed()
+}
+
+/// `read [&prompt=PROMPT] [&timeout=SECONDS] [NAME]...`
+///
+/// Reads a line from the standard input. With one name, the line is assigned
+/// to the variable. With more, it is split at whitespace and the last
+/// variable is assigned the rest of the line. Without names, the line is
+/// output as a value.
+///
+/// Fails at the end of the input, or if the timeout expires.
+pub fn read(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        let prompt = call.opt("prompt", &["prompt", "timeout"])?.cloned();
+        let timeout = match call.opt("timeout", &["prompt", "timeout"])? {
+            Some(Value::Str(secs)) => match secs.parse::<f64>() {
+                Ok(secs) if secs >= 0.0 && secs.is_finite() => Some(Duration::from_secs_f64(secs)),
+                _ => return Err(call.usage(&format!("invalid timeout `{}`", secs))),
+            },
+            Some(value) => return Err(call.error(ErrorKind::Type("a string", value.kind()))),
+            None => None,
+        };
+        let names = call.str_args()?;
+        for name in &names {
+            if name.starts_with('-') {
+                return Err(call.usage(&format!(
+                    "invalid variable name `{}`, options are given as `&prompt=PROMPT` \
+                     and `&timeout=SECONDS`",
+                    name
+                )));
+            }
+            valid_name(&call, name)?;
+        }
+
+        if let Some(prompt) = prompt {
+            frame
+                .write_err(prompt.to_string().into_bytes())
+                .await
+                .map_err(|err| call.error(ErrorKind::Io(err)))?;
+        }
+
+        let input = frame.ports.get(0).cloned();
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let line = match input {
+            Some(input) => match input.read_line(deadline).await {
+                Ok(line) => line,
+                Err(err) if err.kind() == io::ErrorKind::TimedOut => {
+                    return Ok(Status::from_signal(libc::SIGALRM))
+                }
+                Err(err) => return Err(call.error(ErrorKind::Io(err))),
+            },
+            None => None,
+        };
+        let line = match line {
+            Some(line) => String::from_utf8_lossy(&line).into_owned(),
+            None => return Ok(Status::FAILURE),
+        };
+
+        if names.is_empty() {
+            frame
+                .write_value(Value::Str(line))
+                .await
+                .map_err(|err| call.error(ErrorKind::Io(err)))?;
+            return Ok(Status::SUCCESS);
+        }
+
+        let mut rest = line.as_str();
+        for (i, name) in names.iter().enumerate() {
+            let value = if i + 1 == names.len() {
+                rest.trim().to_owned()
+            } else {
+                rest = rest.trim_start();
+                let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                let (field, tail) = rest.split_at(end);
+                rest = tail;
+                field.to_owned()
+            };
+            assign(frame, name, value);
+        }
+        Ok(Status::SUCCESS)
+    }
+    .boxed()
+}
+
+/// Assigns to a variable, declaring it in the current scope if it is not
+/// defined.
+fn assign(frame: &Frame, name: &str, value: String) {
+    let mut value = Some(Value::Str(value));
+    if !frame
+        .scope
+        .modify(name, |var| var.value = value.take().unwrap_or_default())
+    {
+        let var = Var {
+            value: value.unwrap_or_default(),
+            exported: false,
+        };
+        frame.scope.declare(name, var);
+    }
+}
+
+/// Formats the arguments once through the format, returning whether the
+/// format consumed any arguments and was not stopped by `\c`.
+fn format_once<'a, I>(
+    format: &str,
+    args: &mut I,
+    out: &mut String,
+) -> std::result::Result<bool, String>
+where
+    I: Iterator<Item = &'a str>,
+{
+    let mut chars = format.chars().peekable();
+    let mut consumed = false;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                if !escape(&mut chars, out, false) {
+                    return Ok(false);
+                }
+            }
+            '%' if chars.peek() == Some(&'%') => {
+                chars.next();
+                out.push('%');
+            }
+            '%' => {
+                let mut spec = Spec::default();
+                while let Some(&flag) = chars.peek() {
+                    match flag {
+                        '-' => spec.left = true,
+                        '+' => spec.plus = true,
+                        ' ' => spec.space = true,
+                        '0' => spec.zero = true,
+                        '#' => spec.alt = true,
+                        _ => break,
+                    }
+                    chars.next();
+                }
+                spec.width = number(&mut chars);
+                if chars.peek() == Some(&'.') {
+                    chars.next();
+                    spec.precision = Some(number(&mut chars).unwrap_or(0));
+                }
+
+                let conv = chars
+                    .next()
+                    .ok_or_else(|| "missing conversion after `%`".to_owned())?;
+                let arg = args.next();
+                consumed |= arg.is_some();
+                let arg = arg.unwrap_or_default();
+
+                let text = match conv {
+                    's' => precision(arg, spec.precision),
+                    'b' => {
+                        let mut text = String::new();
+                        let mut arg_chars = arg.chars().peekable();
+                        let mut stopped = false;
+                        while let Some(c) = arg_chars.next() {
+                            if c != '\\' {
+                                text.push(c);
+                            } else if !escape(&mut arg_chars, &mut text, true) {
+                                stopped = true;
+                                break;
+                            }
+                        }
+                        out.push_str(&pad(&precision(&text, spec.precision), &spec, false));
+                        if stopped {
+                            return Ok(false);
+                        }
+                        continue;
+                    }
+                    'c' => arg.chars().next().map(String::from).unwrap_or_default(),
+                    'd' | 'i' => {
+                        let n = integer(arg)?;
+                        let digits = n.unsigned_abs().to_string();
+                        signed(n < 0, min_digits(digits, spec.precision), &spec)
+                    }
+                    'u' | 'x' | 'X' | 'o' => {
+                        let n = integer(arg)? as u64;
+                        let digits = match conv {
+                            'x' => format!("{:x}", n),
+                            'X' => format!("{:X}", n),
+                            'o' => format!("{:o}", n),
+                            _ => n.to_string(),
+                        };
+                        let mut digits = min_digits(digits, spec.precision);
+                        if spec.alt && n != 0 {
+                            digits = match conv {
+                                'x' => format!("0x{}", digits),
+                                'X' => format!("0X{}", digits),
+                                'o' => format!("0{}", digits),
+                                _ => digits,
+                            };
+                        }
+                        digits
+                    }
+                    'f' | 'F' | 'e' | 'E' | 'g' | 'G' => {
+                        let n = float(arg)?;
+                        let text = format_float(n.abs(), conv, spec.precision.unwrap_or(6));
+                        signed(n.is_sign_negative() && n != 0.0, text, &spec)
+                    }
+                    conv => return Err(format!("unknown conversion `%{}`", conv)),
+                };
+
+                // Zeros pad numbers, but not integers with a precision.
+                let zero = spec.zero
+                    && match conv {
+                        's' | 'c' => false,
+                        'd' | 'i' | 'u' | 'x' | 'X' | 'o' => spec.precision.is_none(),
+                        _ => true,
+                    };
+                out.push_str(&pad(&text, &spec, zero));
+            }
+            c => out.push(c),
+        }
+    }
+
+    Ok(consumed)
+}
+
+/// The flags, width and precision of a `%` directive.
+#[derive(Default)]
+struct Spec {
+    left: bool,
+    plus: bool,
+    space: bool,
+    zero: bool,
+    alt: bool,
+    width: Option<usize>,
+    precision: Option<usize>,
+}
+
+/// Writes the char of a backslash escape, returning `false` for `\c`, which
+/// stops all output. In arguments of `%b`, octal escapes start with `\0`.
+fn escape<I>(chars: &mut std::iter::Peekable<I>, out: &mut String, arg: bool) -> bool
+where
+    I: Iterator<Item = char>,
+{
+    let c = match chars.next() {
+        Some(c) => c,
+        None => {
+            out.push('\\');
+            return true;
+        }
+    };
+
+    match c {
+        'n' => out.push('\n'),
+        't' => out.push('\t'),
+        'r' => out.push('\r'),
+        'a' => out.push('\x07'),
+        'b' => out.push('\x08'),
+        'e' => out.push('\x1b'),
+        'f' => out.push('\x0c'),
+        'v' => out.push('\x0b'),
+        '\\' => out.push('\\'),
+        'c' => return false,
+        'x' => match digits(chars, 16, 2) {
+            Some((code, _)) => out.push(char::from(code as u8)),
+            None => out.push_str("\\x"),
+        },
+        '0'..='7' => {
+            // Octal escapes have up to three digits, after a `\0` in arguments
+            // of `%b`.
+            let (first, max) = if arg && c == '0' {
+                (0, 3)
+            } else {
+                (c.to_digit(8).unwrap_or(0), 2)
+            };
+            let code = match digits(chars, 8, max) {
+                Some((n, count)) => first * 8u32.pow(count) + n,
+                None => first,
+            };
+            out.push(char::from((code & 0xff) as u8));
+        }
+        c => {
+            out.push('\\');
+            out.push(c);
+        }
+    }
+    true
+}
+
+/// Parses up to `max` digits in a radix, returning the number and the count of
+/// digits.
+fn digits<I>(chars: &mut std::iter::Peekable<I>, radix: u32, max: u32) -> Option<(u32, u32)>
+where
+    I: Iterator<Item = char>,
+{
+    let mut n = 0;
+    let mut count = 0;
+    while count < max {
+        match chars.peek().and_then(|c| c.to_digit(radix)) {
+            Some(digit) => {
+                n = n * radix + digit;
+                count += 1;
+                chars.next();
+            }
+            None => break,
+        }
+    }
+
+    if count > 0 {
+        Some((n, count))
+    } else {
+        None
+    }
+}
+
+fn number<I>(chars: &mut std::iter::Peekable<I>) -> Option<usize>
+where
+    I: Iterator<Item = char>,
+{
+    let mut n: Option<usize> = None;
+    while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+        n = Some(
+            n.unwrap_or(0)
+                .saturating_mul(10)
+                .saturating_add(digit as usize),
+        );
+        chars.next();
+    }
+    n
+}
+
+/// Parses an integer argument, decimal, hex with `0x`, octal with `0`, or the
+/// code of the char after a quote.
+fn integer(arg: &str) -> std::result::Result<i64, String> {
+    let invalid = || format!("invalid number `{}`", arg);
+
+    let s = arg.trim();
+    if s.is_empty() {
+        return Ok(0);
+    }
+    if let Some(c) = s.strip_prefix(['\'', '"']).and_then(|s| s.chars().next()) {
+        return Ok(i64::from(u32::from(c)));
+    }
+
+    let (negative, digits) = match s.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let n = if let Some(hex) = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        i64::from_str_radix(hex, 16)
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        i64::from_str_radix(&digits[1..], 8)
+    } else {
+        digits.parse()
+    }
+    .map_err(|_| invalid())?;
+
+    Ok(if negative { -n } else { n })
+}
+
+fn float(arg: &str) -> std::result::Result<f64, String> {
+    let s = arg.trim();
+    if s.is_empty() {
+        return Ok(0.0);
+    }
+    s.parse().or_else(|_| integer(s).map(|n| n as f64))
+}
+
+fn precision(s: &str, precision: Option<usize>) -> String {
+    match precision {
+        Some(precision) => s.chars().take(precision).collect(),
+        None => s.to_owned(),
+    }
+}
+
+/// Pads digits with zeros to at least the precision.
+fn min_digits(digits: String, precision: Option<usize>) -> String {
+    match precision {
+        Some(precision) if digits.len() < precision => {
+            format!("{}{}", "0".repeat(precision - digits.len()), digits)
+        }
+        _ => digits,
+    }
+}
+
+/// Prefixes a number with its sign.
+fn signed(negative: bool, text: String, spec: &Spec) -> String {
+    if negative {
+        format!("-{}", text)
+    } else if spec.plus {
+        format!("+{}", text)
+    } else if spec.space {
+        format!(" {}", text)
+    } else {
+        text
+    }
+}
+
+/// Pads text to the width, with zeros after the sign if `zero`.
+fn pad(text: &str, spec: &Spec, zero: bool) -> String {
+    let len = text.chars().count();
+    let width = match spec.width {
+        Some(width) if width > len => width,
+        _ => return text.to_owned(),
+    };
+    let fill = width - len;
+
+    if spec.left {
+        format!("{}{}", text, " ".repeat(fill))
+    } else if zero {
+        let sign_len = if text.starts_with(['-', '+', ' ']) {
+            1
+        } else {
+            0
+        };
+        let (sign, digits) = text.split_at(sign_len);
+        format!("{}{}{}", sign, "0".repeat(fill), digits)
+    } else {
+        format!("{}{}", " ".repeat(fill), text)
+    }
+}
+
+/// Formats a non-negative float as `%f`, `%e` or `%g`.
+fn format_float(n: f64, conv: char, precision: usize) -> String {
+    if !n.is_finite() {
+        let text = if n.is_nan() { "nan" } else { "inf" };
+        return if conv.is_ascii_uppercase() {
+            text.to_uppercase()
+        } else {
+            text.to_owned()
+        };
+    }
+
+    let text = match conv.to_ascii_lowercase() {
+        'f' => format!("{:.*}", precision, n),
+        'e' => exponent(n, precision),
+        _ => {
+            // `%g` uses the shorter of `%e` and `%f`, without trailing zeros.
+            let precision = precision.max(1);
+            let exp = if n == 0.0 {
+                0
+            } else {
+                n.abs().log10().floor() as i32
+            };
+            let text = if exp < -4 || exp >= precision as i32 {
+                exponent(n, precision - 1)
+            } else {
+                format!("{:.*}", (precision as i32 - 1 - exp).max(0) as usize, n)
+            };
+            trim_zeros(&text)
+        }
+    };
+
+    if conv.is_ascii_uppercase() {
+        text.to_uppercase()
+    } else {
+        text
+    }
+}
+
+/// Formats a float as `d.ddde+dd`.
+fn exponent(n: f64, precision: usize) -> String {
+    let text = format!("{:.*e}", precision, n);
+    match text.split_once('e') {
+        Some((mantissa, exp)) => {
+            let (sign, exp) = match exp.strip_prefix('-') {
+                Some(exp) => ('-', exp),
+                None => ('+', exp),
+            };
+            format!("{}e{}{:0>2}", mantissa, sign, exp)
+        }
+        None => text,
+    }
+}
+
+/// Removes trailing zeros after the decimal point of the mantissa.
+fn trim_zeros(text: &str) -> String {
+    let (mantissa, exp) = match text.find('e') {
+        Some(i) => text.split_at(i),
+        None => (text, ""),
+    };
+    let mantissa = if mantissa.contains('.') {
+        mantissa.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        mantissa
+    };
+    format!("{}{}", mantissa, exp)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::eval::testing::output;
+
+    #[test]
+    fn read() {
+        assert_eq!(
+            output("echo 'a b  c ' | { read x y; echo $x/$y }").unwrap(),
+            "a/b  c"
+        );
+        assert_eq!(output("echo a | { read x y; echo $x/$y }").unwrap(), "a/");
+        assert_eq!(output("echo a | read").unwrap(), "a");
+    }
+
+    #[test]
+    fn read_names() {
+        let err = output("echo a | read -p 'P> ' x").unwrap_err();
+        assert!(err.to_string().contains("&prompt="), "{}", err);
+        assert!(output("echo a | read x.y").is_err());
+    }
+}
@@ -0,0 +1,172 @@
+use std::sync::Arc;
+
+use futures::future::{BoxFuture, FutureExt};
+
+use crate::eval::builtins::Call;
+use crate::eval::frame::Frame;
+use crate::eval::job::{JobState, Jobs};
+use crate::eval::{ErrorKind, Result, Status};
+
+/// `jobs`
+///
+/// Lists the jobs, forgetting those that have finished.
+pub fn jobs(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        if !call.args.is_empty() {
+            return Err(call.usage("too many arguments"));
+        }
+
+        for line in frame.globals.jobs.list() {
+            frame
+                .write_out(format!("{}\n", line).into_bytes())
+                .await
+                .map_err(|err| call.error(ErrorKind::Io(err)))?;
+        }
+        Ok(Status::SUCCESS)
+    }
+    .boxed()
+}
+
+/// `fg [JOB]`
+///
+/// Continues a job in the foreground and waits for it to finish or stop.
+pub fn fg(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        let jobs = Arc::clone(&frame.globals.jobs);
+        if !jobs.has_control() {
+            return Err(call.error(ErrorKind::NoJobControl));
+        }
+        let id = job_arg(&jobs, &call)?;
+
+        let text = jobs.text(id).unwrap_or_default();
+        frame
+            .write_out(format!("{}\n", text).into_bytes())
+            .await
+            .map_err(|err| call.error(ErrorKind::Io(err)))?;
+
+        jobs.resume(id, true)
+            .map_err(|err| call.error(ErrorKind::Io(err)))?;
+
+        match jobs.wait_job(id).await {
+            Some(JobState::Stopped) => {
+                jobs.stop(id);
+                if let Some(job) = jobs.describe(id) {
+                    eprintln!("\n{}", job);
+                }
+                Err(call.error(ErrorKind::Stopped(id)))
+            }
+            _ => {
+                jobs.restore_terminal();
+                jobs.take(id).unwrap_or(Ok(Status::SUCCESS))
+            }
+        }
+    }
+    .boxed()
+}
+
+/// `bg [JOB]`
+///
+/// Continues a stopped job in the background.
+pub fn bg(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        let jobs = Arc::clone(&frame.globals.jobs);
+        if !jobs.has_control() {
+            return Err(call.error(ErrorKind::NoJobControl));
+        }
+        let id = job_arg(&jobs, &call)?;
+
+        jobs.resume(id, false)
+            .map_err(|err| call.error(ErrorKind::Io(err)))?;
+
+        let text = jobs.text(id).unwrap_or_default();
+        frame
+            .write_out(format!("[{}]  {} &\n", id, text).into_bytes())
+            .await
+            .map_err(|err| call.error(ErrorKind::Io(err)))?;
+        Ok(Status::SUCCESS)
+    }
+    .boxed()
+}
+
+/// `disown [JOB]`
+///
+/// Forgets a job, leaving its processes running.
+pub fn disown(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        let jobs = &frame.globals.jobs;
+        let id = job_arg(jobs, &call)?;
+        jobs.remove(id);
+        Ok(Status::SUCCESS)
+    }
+    .boxed()
+}
+
+/// `wait [JOB|PID]...`
+///
+/// Waits for each job or process to finish, or all jobs without arguments,
+/// returning the status of the last.
+pub fn wait(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        let jobs = Arc::clone(&frame.globals.jobs);
+
+        let mut status = Status::SUCCESS;
+        let args = call.str_args()?;
+        if args.is_empty() {
+            for id in jobs.ids() {
+                status = wait_job(&jobs, id).await;
+            }
+            return Ok(status);
+        }
+
+        for arg in args {
+            status = match parse_spec(&jobs, arg) {
+                Some(id) => wait_job(&jobs, id).await,
+                None if arg.starts_with('%') => {
+                    return Err(call.error(ErrorKind::NoSuchJob(arg.to_owned())));
+                }
+                None => match arg.parse() {
+                    Ok(pid) => jobs.wait_pid(pid).await,
+                    Err(_) => return Err(call.usage(&format!("invalid job `{}`", arg))),
+                },
+            };
+        }
+        Ok(status)
+    }
+    .boxed()
+}
+
+/// Waits for a job to finish, forgetting it, or stop.
+async fn wait_job(jobs: &Jobs, id: usize) -> Status {
+    match jobs.wait_job(id).await {
+        Some(JobState::Done(status)) => {
+            jobs.remove(id);
+            status
+        }
+        Some(JobState::Stopped) => Status::from_signal(libc::SIGTSTP),
+        _ => Status::SUCCESS,
+    }
+}
+
+/// Resolves the optional job argument of a builtin, the current job by
+/// default.
+fn job_arg(jobs: &Jobs, call: &Call) -> Result<usize> {
+    match &*call.str_args()? {
+        [] => jobs
+            .current()
+            .ok_or_else(|| call.error(ErrorKind::NoSuchJob("%%".to_owned()))),
+        [spec] => parse_spec(jobs, spec)
+            .or_else(|| parse_spec(jobs, &format!("%{}", spec)))
+            .ok_or_else(|| call.error(ErrorKind::NoSuchJob((*spec).to_owned()))),
+        _ => Err(call.usage("too many arguments")),
+    }
+}
+
+/// Resolves a job spec: `%N` for the job `N`, or `%%` or `%+` for the current
+/// job.
+fn parse_spec(jobs: &Jobs, spec: &str) -> Option<usize> {
+    let id = match spec {
+        "%%" | "%+" => jobs.current()?,
+        _ => spec.strip_prefix('%')?.parse().ok()?,
+    };
+    jobs.ids().into_iter().find(|&job| job == id)
+}
@@ -0,0 +1,150 @@
+mod basic;
+mod control;
+mod dir;
+mod edit;
+mod history;
+mod io;
+mod job;
+mod path;
+mod string;
+mod test;
+mod value;
+mod var;
+
+use futures::future::BoxFuture;
+
+use crate::eval::frame::Frame;
+use crate::eval::value::Value;
+use crate::eval::{Error, ErrorKind, Result, Status};
+use crate::parse::Span;
+
+/// A command implemented by the shell itself.
+///
+/// Builtins run on the runtime of the shell, concurrently with the other
+/// commands of a pipeline.
+pub type BuiltinFn = for<'a> fn(&'a mut Frame, Call) -> BoxFuture<'a, Result<Status>>;
+
+/// The arguments of a call to a builtin.
+#[derive(Clone, Debug)]
+pub struct Call {
+    pub name: String,
+    pub args: Vec<Value>,
+    pub opts: Vec<(String, Value)>,
+    pub span: Span,
+}
+
+impl Call {
+    pub fn error(&self, kind: ErrorKind) -> Error {
+        Error::new(kind, self.span)
+    }
+
+    /// Returns the arguments as strings, failing if any are not strings.
+    pub fn str_args(&self) -> Result<Vec<&str>> {
+        self.args
+            .iter()
+            .map(|arg| match arg {
+                Value::Str(s) => Ok(&**s),
+                arg => Err(self.error(ErrorKind::Type("a string", arg.kind()))),
+            })
+            .collect()
+    }
+
+    /// Returns the value of an option, failing if any option is not one of
+    /// `known`.
+    pub fn opt(&self, name: &str, known: &[&str]) -> Result<Option<&Value>> {
+        if let Some((opt, _)) = self.opts.iter().find(|(opt, _)| !known.contains(&&**opt)) {
+            return Err(self.error(ErrorKind::UnknownOpt(opt.clone())));
+        }
+        Ok(self
+            .opts
+            .iter()
+            .find(|(opt, _)| opt == name)
+            .map(|(_, value)| value))
+    }
+
+    /// Returns a usage error for the builtin.
+    pub fn usage(&self, msg: &str) -> Error {
+        self.error(ErrorKind::Usage(format!("{}: {}", self.name, msg)))
+    }
+}
+
+const BUILTINS: &[(&str, BuiltinFn)] = &[
+    ("[", test::bracket),
+    ("all", value::all),
+    ("bg", job::bg),
+    ("break", control::break_),
+    ("cd", dir::cd),
+    ("continue", control::continue_),
+    ("count", value::count),
+    ("dirs", dir::dirs),
+    ("disown", job::disown),
+    ("each", value::each),
+    ("echo", basic::echo),
+    ("edit:bind", edit::bind),
+    ("edit:buffer", edit::buffer),
+    ("edit:dot", edit::dot),
+    ("edit:insert", edit::insert),
+    ("edit:notify", edit::notify),
+    ("edit:replace", edit::replace),
+    ("edit:unbind", edit::unbind),
+    ("edit:vi-mode", edit::vi_mode),
+    ("exec", control::exec),
+    ("exit", control::exit),
+    ("export", var::export),
+    ("fail", control::fail),
+    ("false", basic::false_),
+    ("fg", job::fg),
+    ("from-lines", value::from_lines),
+    ("history:list", history::list),
+    ("jobs", job::jobs),
+    ("join", string::join),
+    ("keys", value::keys),
+    ("len", string::len),
+    ("let", var::let_),
+    ("path:abs", path::abs),
+    ("path:base", path::base),
+    ("path:dir", path::dir),
+    ("path:exists", path::exists),
+    ("path:ext", path::ext),
+    ("path:is-dir", path::is_dir),
+    ("path:is-file", path::is_file),
+    ("path:join", path::join),
+    ("path:stem", path::stem),
+    ("popd", dir::popd),
+    ("printf", io::printf),
+    ("prompt:add", edit::add),
+    ("prompt:clear", edit::clear),
+    ("pushd", dir::pushd),
+    ("put", value::put),
+    ("pwd", dir::pwd),
+    ("re-find", string::re_find),
+    ("re-match", string::re_match),
+    ("re-replace", string::re_replace),
+    ("read", io::read),
+    ("replace", string::replace),
+    ("return", control::return_),
+    ("set", var::set),
+    ("setopt", basic::setopt),
+    ("source", control::source),
+    ("split", string::split),
+    ("substr", string::substr),
+    ("test", test::test),
+    ("to-lines", value::to_lines),
+    ("to-lower", string::to_lower),
+    ("to-upper", string::to_upper),
+    ("trim-prefix", string::trim_prefix),
+    ("trim-suffix", string::trim_suffix),
+    ("true", basic::true_),
+    ("unset", var::unset),
+    ("unsetopt", basic::unsetopt),
+    ("use", control::use_),
+    ("wait", job::wait),
+];
+
+/// Looks up a builtin by name.
+pub fn lookup(name: &str) -> Option<BuiltinFn> {
+    BUILTINS
+        .iter()
+        .find(|(builtin, _)| *builtin == name)
+        .map(|(_, f)| *f)
+}
@@ -0,0 +1,151 @@
+//! Builtins of the `path:` namespace.
+//!
+//! Paths are handled as strings, without accessing the file system, except by
+//! `path:abs` and the tests.
+
+use std::env;
+use std::path::{Component, Path, PathBuf};
+
+use futures::future::{BoxFuture, FutureExt};
+
+use crate::eval::builtins::Call;
+use crate::eval::frame::Frame;
+use crate::eval::value::Value;
+use crate::eval::{ErrorKind, Result, Status};
+
+/// `path:join PART...`
+///
+/// Outputs the parts joined by `/`. A part that is absolute replaces the
+/// parts before it.
+pub fn join(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        let parts = call.str_args()?;
+        if parts.is_empty() {
+            return Err(call.usage("expected `PART...`"));
+        }
+
+        let path: PathBuf = parts.iter().collect();
+        put(frame, &call, &path).await
+    }
+    .boxed()
+}
+
+/// `path:base PATH`
+///
+/// Outputs the last component of the path.
+pub fn base(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        let path = one_arg(&call)?;
+        let base = path.file_name().map(Path::new).unwrap_or(path);
+        put(frame, &call, base).await
+    }
+    .boxed()
+}
+
+/// `path:dir PATH`
+///
+/// Outputs the path without its last component, or `.` if it has only one.
+pub fn dir(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        let path = one_arg(&call)?;
+        let dir = match path.parent() {
+            Some(dir) if dir.as_os_str().is_empty() => Path::new("."),
+            Some(dir) => dir,
+            None => path,
+        };
+        put(frame, &call, dir).await
+    }
+    .boxed()
+}
+
+/// `path:ext PATH`
+///
+/// Outputs the extension of the last component of the path, with its `.`, or
+/// an empty string if it has none.
+pub fn ext(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        let path = one_arg(&call)?;
+        let ext = match path.extension() {
+            Some(ext) => format!(".{}", ext.to_string_lossy()),
+            None => String::new(),
+        };
+        put(frame, &call, Path::new(&ext)).await
+    }
+    .boxed()
+}
+
+/// `path:stem PATH`
+///
+/// Outputs the last component of the path without its extension.
+pub fn stem(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        let path = one_arg(&call)?;
+        let stem = path.file_stem().map(Path::new).unwrap_or(path);
+        put(frame, &call, stem).await
+    }
+    .boxed()
+}
+
+/// `path:abs PATH`
+///
+/// Outputs the path relative to the working directory as an absolute path,
+/// without `.` and `..` components. Symbolic links are not resolved.
+pub fn abs(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        let path = one_arg(&call)?;
+        let wd = env::current_dir().map_err(|err| call.error(ErrorKind::Io(err)))?;
+
+        let mut abs = PathBuf::new();
+        for component in wd.join(path).components() {
+            match component {
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    abs.pop();
+                }
+                component => abs.push(component),
+            }
+        }
+        put(frame, &call, &abs).await
+    }
+    .boxed()
+}
+
+/// `path:exists PATH`
+pub fn exists(_frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move { Ok(status(one_arg(&call)?.exists())) }.boxed()
+}
+
+/// `path:is-dir PATH`
+pub fn is_dir(_frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move { Ok(status(one_arg(&call)?.is_dir())) }.boxed()
+}
+
+/// `path:is-file PATH`
+pub fn is_file(_frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move { Ok(status(one_arg(&call)?.is_file())) }.boxed()
+}
+
+fn one_arg(call: &Call) -> Result<&Path> {
+    match &*call.args {
+        [Value::Str(path)] => Ok(Path::new(path)),
+        [value] => Err(call.error(ErrorKind::Type("a string", value.kind()))),
+        _ => Err(call.usage("expected `PATH`")),
+    }
+}
+
+fn status(success: bool) -> Status {
+    if success {
+        Status::SUCCESS
+    } else {
+        Status::FAILURE
+    }
+}
+
+async fn put(frame: &Frame, call: &Call, path: &Path) -> Result<Status> {
+    let path = path.to_string_lossy().into_owned();
+    frame
+        .write_value(Value::Str(path))
+        .await
+        .map_err(|err| call.error(ErrorKind::Io(err)))?;
+    Ok(Status::SUCCESS)
+}
@@ -0,0 +1,296 @@
+//! String builtins.
+//!
+//! Each outputs its result as a value, so it can be used as an expansion with
+//! an output capture, as in `(to-upper $name)`. The common operations also
+//! have an operator in parameter expansion, as in `${name^^}`.
+
+use futures::future::{BoxFuture, FutureExt};
+
+use crate::eval::builtins::Call;
+use crate::eval::frame::Frame;
+use crate::eval::regex::Regex;
+use crate::eval::strings;
+use crate::eval::value::Value;
+use crate::eval::{ErrorKind, Result, Status};
+
+/// `trim-prefix STR PREFIX`
+///
+/// Outputs the string without the prefix, or unchanged if it does not start
+/// with it.
+pub fn trim_prefix(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        let (s, prefix) = match &*call.str_args()? {
+            [s, prefix] => (*s, *prefix),
+            _ => return Err(call.usage("expected `STR PREFIX`")),
+        };
+        let trimmed = s.strip_prefix(prefix).unwrap_or(s).to_owned();
+        put(frame, &call, trimmed).await
+    }
+    .boxed()
+}
+
+/// `trim-suffix STR SUFFIX`
+///
+/// Outputs the string without the suffix, or unchanged if it does not end
+/// with it.
+pub fn trim_suffix(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        let (s, suffix) = match &*call.str_args()? {
+            [s, suffix] => (*s, *suffix),
+            _ => return Err(call.usage("expected `STR SUFFIX`")),
+        };
+        let trimmed = s.strip_suffix(suffix).unwrap_or(s).to_owned();
+        put(frame, &call, trimmed).await
+    }
+    .boxed()
+}
+
+/// `replace [&max=N] STR OLD NEW`
+///
+/// Outputs the string with occurrences of `OLD` replaced, at most `N` of them
+/// if given.
+pub fn replace(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        let max = max_opt(&call)?;
+        let (s, old, new) = match &*call.str_args()? {
+            [s, old, new] => (*s, *old, *new),
+            _ => return Err(call.usage("expected `STR OLD NEW`")),
+        };
+        let replaced = match max {
+            Some(max) => s.replacen(old, new, max),
+            None => s.replace(old, new),
+        };
+        put(frame, &call, replaced).await
+    }
+    .boxed()
+}
+
+/// `split [&max=N] SEP STR`
+///
+/// Outputs each part of the string between separators, at most `N` parts if
+/// given.
+pub fn split(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        let max = max_opt(&call)?;
+        let (sep, s) = match &*call.str_args()? {
+            [sep, s] => (*sep, *s),
+            _ => return Err(call.usage("expected `SEP STR`")),
+        };
+        if sep.is_empty() {
+            return Err(call.usage("empty separator"));
+        }
+
+        let parts: Vec<String> = match max {
+            Some(max) => s.splitn(max, sep).map(str::to_owned).collect(),
+            None => s.split(sep).map(str::to_owned).collect(),
+        };
+        for part in parts {
+            put(frame, &call, part).await?;
+        }
+        Ok(Status::SUCCESS)
+    }
+    .boxed()
+}
+
+/// `join SEP [LIST]`
+///
+/// Outputs the strings in the list, or the input values, joined by the
+/// separator.
+pub fn join(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        let (sep, values) = match &*call.args {
+            [Value::Str(sep), Value::List(list)] => (sep.clone(), list.clone()),
+            [Value::Str(sep)] => {
+                let mut values = Vec::new();
+                while let Some(value) = frame.read_value().await {
+                    values.push(value);
+                }
+                (sep.clone(), values)
+            }
+            [Value::Str(_), value] => {
+                return Err(call.error(ErrorKind::Type("a list", value.kind())))
+            }
+            [value, ..] if call.args.len() <= 2 => {
+                return Err(call.error(ErrorKind::Type("a string", value.kind())))
+            }
+            _ => return Err(call.usage("expected `SEP [LIST]`")),
+        };
+
+        let mut parts = Vec::with_capacity(values.len());
+        for value in values {
+            match value {
+                Value::Str(s) => parts.push(s),
+                value => return Err(call.error(ErrorKind::Type("a string", value.kind()))),
+            }
+        }
+        put(frame, &call, parts.join(&sep)).await
+    }
+    .boxed()
+}
+
+/// `to-upper STR`
+pub fn to_upper(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        let s = one_arg(&call)?;
+        put(frame, &call, strings::convert_case(s, true, true)).await
+    }
+    .boxed()
+}
+
+/// `to-lower STR`
+pub fn to_lower(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        let s = one_arg(&call)?;
+        put(frame, &call, strings::convert_case(s, false, true)).await
+    }
+    .boxed()
+}
+
+/// `substr STR OFFSET [LENGTH]`
+///
+/// Outputs the chars of the string from the offset, or `LENGTH` chars from
+/// it. A negative offset or length counts from the end of the string.
+pub fn substr(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        let args = call.str_args()?;
+        let integer = |arg: &str| {
+            arg.parse::<i64>()
+                .map_err(|_| call.usage(&format!("expected an integer, found `{}`", arg)))
+        };
+
+        let (s, offset, length) = match &*args {
+            [s, offset] => (*s, integer(offset)?, None),
+            [s, offset, length] => (*s, integer(offset)?, Some(integer(length)?)),
+            _ => return Err(call.usage("expected `STR OFFSET [LENGTH]`")),
+        };
+        put(
+            frame,
+            &call,
+            strings::substring(s, offset, length).to_owned(),
+        )
+        .await
+    }
+    .boxed()
+}
+
+/// `len VALUE`
+///
+/// Outputs the number of chars in a string, or elements in a list or map.
+pub fn len(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        let len = match &*call.args {
+            [Value::Str(s)] => s.chars().count(),
+            [Value::List(list)] => list.len(),
+            [Value::Map(map)] => map.len(),
+            [value] => return Err(call.error(ErrorKind::Type("a countable value", value.kind()))),
+            _ => return Err(call.usage("expected `VALUE`")),
+        };
+        put(frame, &call, len.to_string()).await
+    }
+    .boxed()
+}
+
+/// `re-match PATTERN STR`
+///
+/// Succeeds if the regular expression matches anywhere in the string.
+pub fn re_match(_frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        let (regex, s) = match &*call.str_args()? {
+            [pattern, s] => (regex(&call, pattern)?, *s),
+            _ => return Err(call.usage("expected `PATTERN STR`")),
+        };
+
+        Ok(if regex.is_match(s) {
+            Status::SUCCESS
+        } else {
+            Status::FAILURE
+        })
+    }
+    .boxed()
+}
+
+/// `re-find PATTERN STR`
+///
+/// Outputs each non-overlapping match of the regular expression in the
+/// string, or a list of it and its groups if the expression has groups.
+pub fn re_find(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        let (regex, s) = match &*call.str_args()? {
+            [pattern, s] => (regex(&call, pattern)?, *s),
+            _ => return Err(call.usage("expected `PATTERN STR`")),
+        };
+
+        let mut values = Vec::new();
+        for caps in regex.captures_iter(s) {
+            let group = |i| Value::Str(caps.get(i).map_or("", |m| m.as_str()).to_owned());
+            values.push(if caps.len() == 1 {
+                group(0)
+            } else {
+                Value::List((0..caps.len()).map(group).collect())
+            });
+        }
+
+        for value in values {
+            frame
+                .write_value(value)
+                .await
+                .map_err(|err| call.error(ErrorKind::Io(err)))?;
+        }
+        Ok(Status::SUCCESS)
+    }
+    .boxed()
+}
+
+/// `re-replace [&max=1] PATTERN REPLACEMENT STR`
+///
+/// Outputs the string with every match of the regular expression replaced,
+/// or only the first with `&max=1`. In the replacement, `$N` or `${N}` is
+/// the text of group `N`, `$NAME` or `${NAME}` that of a named group, and
+/// `$$` is a literal `$`.
+pub fn re_replace(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        let all = match max_opt(&call)? {
+            None => true,
+            Some(1) => false,
+            Some(_) => return Err(call.usage("`&max` must be 1")),
+        };
+        let (regex, rep, s) = match &*call.str_args()? {
+            [pattern, rep, s] => (regex(&call, pattern)?, *rep, *s),
+            _ => return Err(call.usage("expected `PATTERN REPLACEMENT STR`")),
+        };
+        put(frame, &call, regex.replace(s, rep, all)).await
+    }
+    .boxed()
+}
+
+fn one_arg(call: &Call) -> Result<&str> {
+    match &*call.args {
+        [Value::Str(s)] => Ok(s),
+        [value] => Err(call.error(ErrorKind::Type("a string", value.kind()))),
+        _ => Err(call.usage("expected `STR`")),
+    }
+}
+
+fn regex(call: &Call, pattern: &str) -> Result<Regex> {
+    Regex::new(pattern).map_err(|msg| call.error(ErrorKind::Regex(msg)))
+}
+
+/// The `&max` option, limiting the number of replacements or parts.
+fn max_opt(call: &Call) -> Result<Option<usize>> {
+    match call.opt("max", &["max"])? {
+        Some(Value::Str(max)) => match max.parse() {
+            Ok(max) if max > 0 => Ok(Some(max)),
+            _ => Err(call.usage(&format!("invalid `&max` `{}`", max))),
+        },
+        Some(value) => Err(call.error(ErrorKind::Type("a string", value.kind()))),
+        None => Ok(None),
+    }
+}
+
+async fn put(frame: &Frame, call: &Call, s: String) -> Result<Status> {
+    frame
+        .write_value(Value::Str(s))
+        .await
+        .map_err(|err| call.error(ErrorKind::Io(err)))?;
+    Ok(Status::SUCCESS)
+}
@@ -0,0 +1,247 @@
+use std::ffi::CString;
+use std::fs::{self, Metadata};
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+
+use futures::future::{BoxFuture, FutureExt};
+
+use crate::eval::builtins::Call;
+use crate::eval::frame::Frame;
+use crate::eval::{Result, Status};
+
+/// `test EXPR`
+///
+/// Succeeds if the expression is true, as in POSIX shells.
+pub fn test(_frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        let args = call.str_args()?;
+        eval_test(&call, &args)
+    }
+    .boxed()
+}
+
+/// `[ EXPR ]`
+pub fn bracket(_frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        let mut args = call.str_args()?;
+        if args.pop() != Some("]") {
+            return Err(call.usage("missing `]`"));
+        }
+        eval_test(&call, &args)
+    }
+    .boxed()
+}
+
+fn eval_test(call: &Call, args: &[&str]) -> Result<Status> {
+    if args.is_empty() {
+        return Ok(Status::FAILURE);
+    }
+
+    let mut parser = TestParser { args, pos: 0 };
+    let result = parser.or().map_err(|msg| call.usage(&msg))?;
+    if let Some(arg) = parser.peek() {
+        return Err(call.usage(&format!("unexpected `{}`", arg)));
+    }
+
+    Ok(if result {
+        Status::SUCCESS
+    } else {
+        Status::FAILURE
+    })
+}
+
+type TestResult = std::result::Result<bool, String>;
+
+/// Evaluates a test expression while parsing it.
+///
+/// `-o` binds looser than `-a`, which binds looser than `!`. An argument
+/// followed by a binary operator is always a comparison, so operators can be
+/// compared as strings.
+struct TestParser<'a> {
+    args: &'a [&'a str],
+    pos: usize,
+}
+
+impl<'a> TestParser<'a> {
+    fn peek(&self) -> Option<&'a str> {
+        self.args.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> std::result::Result<&'a str, String> {
+        let arg = self
+            .peek()
+            .ok_or_else(|| "expected an argument".to_owned())?;
+        self.pos += 1;
+        Ok(arg)
+    }
+
+    fn or(&mut self) -> TestResult {
+        let mut result = self.and()?;
+        while self.peek() == Some("-o") {
+            self.pos += 1;
+            result |= self.and()?;
+        }
+        Ok(result)
+    }
+
+    fn and(&mut self) -> TestResult {
+        let mut result = self.not()?;
+        while self.peek() == Some("-a") {
+            self.pos += 1;
+            result &= self.not()?;
+        }
+        Ok(result)
+    }
+
+    fn not(&mut self) -> TestResult {
+        if self.peek() == Some("!") && self.args.len() > self.pos + 1 {
+            self.pos += 1;
+            return Ok(!self.not()?);
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> TestResult {
+        let arg = self.next()?;
+
+        if let Some(op) = self.peek().filter(|op| is_binary(op)) {
+            self.pos += 1;
+            let rhs = self.next()?;
+            return binary(arg, op, rhs);
+        }
+
+        if arg == "(" && self.peek().is_some() {
+            let result = self.or()?;
+            if self.next()? != ")" {
+                return Err("expected `)`".to_owned());
+            }
+            return Ok(result);
+        }
+
+        if is_unary(arg) {
+            if let Some(operand) = self.peek() {
+                self.pos += 1;
+                return unary(arg, operand);
+            }
+        }
+
+        Ok(!arg.is_empty())
+    }
+}
+
+fn is_unary(op: &str) -> bool {
+    matches!(
+        op,
+        "-b" | "-c"
+            | "-d"
+            | "-e"
+            | "-f"
+            | "-g"
+            | "-h"
+            | "-k"
+            | "-L"
+            | "-n"
+            | "-p"
+            | "-r"
+            | "-s"
+            | "-S"
+            | "-t"
+            | "-u"
+            | "-w"
+            | "-x"
+            | "-z"
+    )
+}
+
+fn is_binary(op: &str) -> bool {
+    matches!(
+        op,
+        "=" | "=="
+            | "!="
+            | "<"
+            | ">"
+            | "-eq"
+            | "-ne"
+            | "-lt"
+            | "-le"
+            | "-gt"
+            | "-ge"
+            | "-nt"
+            | "-ot"
+            | "-ef"
+    )
+}
+
+fn unary(op: &str, arg: &str) -> TestResult {
+    let meta = || fs::metadata(arg).ok();
+    let mode = |bit: u32| meta().is_some_and(|meta| meta.mode() & bit != 0);
+
+    Ok(match op {
+        "-n" => !arg.is_empty(),
+        "-z" => arg.is_empty(),
+        "-e" => meta().is_some(),
+        "-f" => meta().is_some_and(|meta| meta.is_file()),
+        "-d" => meta().is_some_and(|meta| meta.is_dir()),
+        "-b" => meta().is_some_and(|meta| meta.file_type().is_block_device()),
+        "-c" => meta().is_some_and(|meta| meta.file_type().is_char_device()),
+        "-p" => meta().is_some_and(|meta| meta.file_type().is_fifo()),
+        "-S" => meta().is_some_and(|meta| meta.file_type().is_socket()),
+        "-h" | "-L" => fs::symlink_metadata(arg).is_ok_and(|meta| meta.file_type().is_symlink()),
+        "-s" => meta().is_some_and(|meta| meta.len() > 0),
+        "-g" => mode(libc::S_ISGID),
+        "-u" => mode(libc::S_ISUID),
+        "-k" => mode(libc::S_ISVTX),
+        "-r" => access(arg, libc::R_OK),
+        "-w" => access(arg, libc::W_OK),
+        "-x" => access(arg, libc::X_OK),
+        "-t" => {
+            let fd = integer(arg)?;
+            unsafe { libc::isatty(fd as libc::c_int) == 1 }
+        }
+        _ => return Err(format!("unknown operator `{}`", op)),
+    })
+}
+
+fn binary(lhs: &str, op: &str, rhs: &str) -> TestResult {
+    let modified = |path: &str| fs::metadata(path).and_then(|meta| meta.modified()).ok();
+    let file_id = |meta: Metadata| (meta.dev(), meta.ino());
+
+    Ok(match op {
+        "=" | "==" => lhs == rhs,
+        "!=" => lhs != rhs,
+        "<" => lhs < rhs,
+        ">" => lhs > rhs,
+        "-eq" => integer(lhs)? == integer(rhs)?,
+        "-ne" => integer(lhs)? != integer(rhs)?,
+        "-lt" => integer(lhs)? < integer(rhs)?,
+        "-le" => integer(lhs)? <= integer(rhs)?,
+        "-gt" => integer(lhs)? > integer(rhs)?,
+        "-ge" => integer(lhs)? >= integer(rhs)?,
+        "-nt" => match (modified(lhs), modified(rhs)) {
+            (Some(lhs), Some(rhs)) => lhs > rhs,
+            (lhs, rhs) => lhs.is_some() && rhs.is_none(),
+        },
+        "-ot" => match (modified(lhs), modified(rhs)) {
+            (Some(lhs), Some(rhs)) => lhs < rhs,
+            (lhs, rhs) => lhs.is_none() && rhs.is_some(),
+        },
+        "-ef" => match (fs::metadata(lhs), fs::metadata(rhs)) {
+            (Ok(lhs), Ok(rhs)) => file_id(lhs) == file_id(rhs),
+            _ => false,
+        },
+        _ => return Err(format!("unknown operator `{}`", op)),
+    })
+}
+
+fn integer(arg: &str) -> std::result::Result<i64, String> {
+    arg.trim()
+        .parse()
+        .map_err(|_| format!("expected an integer, found `{}`", arg))
+}
+
+/// Checks the access of the real user to a file.
+fn access(path: &str, mode: libc::c_int) -> bool {
+    match CString::new(path) {
+        Ok(path) => unsafe { libc::access(path.as_ptr(), mode) == 0 },
+        Err(_) => false,
+    }
+}
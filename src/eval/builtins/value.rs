@@ -0,0 +1,173 @@
+use futures::future::{BoxFuture, FutureExt};
+
+use crate::eval::builtins::Call;
+use crate::eval::frame::Frame;
+use crate::eval::value::Value;
+use crate::eval::{ErrorKind, Result, Status};
+
+/// Size of the chunks the standard input is read in.
+const READ_LEN: usize = 8192;
+
+/// `put VALUE...`
+///
+/// Outputs each argument as a value.
+pub fn put(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        for value in call.args.iter().cloned() {
+            write_value(frame, &call, value).await?;
+        }
+        Ok(Status::SUCCESS)
+    }
+    .boxed()
+}
+
+/// `all`
+///
+/// Outputs each input value.
+pub fn all(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        while let Some(value) = frame.read_value().await {
+            write_value(frame, &call, value).await?;
+        }
+        Ok(Status::SUCCESS)
+    }
+    .boxed()
+}
+
+/// `each FN`
+///
+/// Calls the function with each input value. `break` and `continue` work as
+/// they do in loops.
+pub fn each(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        let closure = match &*call.args {
+            [Value::Fn(closure)] => closure.clone(),
+            [value] => return Err(call.error(ErrorKind::Type("a function", value.kind()))),
+            _ => return Err(call.usage("expected `FN`")),
+        };
+
+        let mut status = Status::SUCCESS;
+        while let Some(value) = frame.read_value().await {
+            match frame
+                .call(&closure, vec![value], Vec::new(), call.span)
+                .await
+            {
+                Ok(s) => status = s,
+                Err(err) => match err.kind {
+                    ErrorKind::Break => break,
+                    ErrorKind::Continue => {}
+                    _ => return Err(err),
+                },
+            }
+        }
+        Ok(status)
+    }
+    .boxed()
+}
+
+/// `count [VALUE]`
+///
+/// Outputs the number of elements of a list or map, or chars of a string.
+/// Without an argument, counts the input values.
+pub fn count(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        let count = match &*call.args {
+            [] => {
+                let mut count = 0;
+                while frame.read_value().await.is_some() {
+                    count += 1;
+                }
+                count
+            }
+            [Value::Str(s)] => s.chars().count(),
+            [Value::List(list)] => list.len(),
+            [Value::Map(map)] => map.len(),
+            [value] => return Err(call.error(ErrorKind::Type("a countable value", value.kind()))),
+            _ => return Err(call.usage("too many arguments")),
+        };
+
+        write_value(frame, &call, Value::Str(count.to_string())).await?;
+        Ok(Status::SUCCESS)
+    }
+    .boxed()
+}
+
+/// `keys MAP`
+///
+/// Outputs the keys of a map.
+pub fn keys(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        let map = match &*call.args {
+            [Value::Map(map)] => map.clone(),
+            [value] => return Err(call.error(ErrorKind::Type("a map", value.kind()))),
+            _ => return Err(call.usage("expected `MAP`")),
+        };
+
+        for key in map.into_keys() {
+            write_value(frame, &call, Value::Str(key)).await?;
+        }
+        Ok(Status::SUCCESS)
+    }
+    .boxed()
+}
+
+/// `from-lines`
+///
+/// Outputs each line of the standard input as a string value.
+pub fn from_lines(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        let input = match frame.ports.get(0) {
+            Some(port) => port.clone(),
+            None => return Ok(Status::SUCCESS),
+        };
+
+        let mut buf = Vec::new();
+        loop {
+            let chunk = input
+                .read(READ_LEN)
+                .await
+                .map_err(|err| call.error(ErrorKind::Io(err)))?;
+            if chunk.is_empty() {
+                break;
+            }
+            buf.extend(chunk);
+
+            while let Some(i) = buf.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = buf.drain(..=i).collect();
+                let line = String::from_utf8_lossy(&line[..i]).into_owned();
+                write_value(frame, &call, Value::Str(line)).await?;
+            }
+        }
+
+        // The last line may not end with a newline.
+        if !buf.is_empty() {
+            let line = String::from_utf8_lossy(&buf).into_owned();
+            write_value(frame, &call, Value::Str(line)).await?;
+        }
+        Ok(Status::SUCCESS)
+    }
+    .boxed()
+}
+
+/// `to-lines`
+///
+/// Writes each input value to the standard output as a line.
+pub fn to_lines(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        while let Some(value) = frame.read_value().await {
+            frame
+                .write_out(format!("{}\n", value).into_bytes())
+                .await
+                .map_err(|err| call.error(ErrorKind::Io(err)))?;
+        }
+        Ok(Status::SUCCESS)
+    }
+    .boxed()
+}
+
+async fn write_value(frame: &Frame, call: &Call, value: Value) -> Result<()> {
+    frame
+        .write_value(value)
+        .await
+        .map_err(|err| call.error(ErrorKind::Io(err)))
+}
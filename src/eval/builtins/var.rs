@@ -0,0 +1,131 @@
+use futures::future::{BoxFuture, FutureExt};
+
+use crate::eval::builtins::Call;
+use crate::eval::frame::Frame;
+use crate::eval::scope::Var;
+use crate::eval::value::Value;
+use crate::eval::{ErrorKind, Result, Status};
+
+/// `let NAME [= VALUE]`
+///
+/// Declares a variable in the current scope.
+pub fn let_(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        let (name, value) = assignment(&call)?;
+        let var = Var {
+            value: value.unwrap_or_default(),
+            exported: false,
+        };
+
+        frame.scope.declare(name, var);
+        Ok(Status::SUCCESS)
+    }
+    .boxed()
+}
+
+/// `set NAME = VALUE`
+///
+/// Assigns to an existing variable.
+pub fn set(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        let (name, value) = match assignment(&call)? {
+            (name, Some(value)) => (name, value),
+            (_, None) => return Err(call.usage("expected `NAME = VALUE`")),
+        };
+
+        if !frame.scope.modify(name, |var| var.value = value) {
+            return Err(call.error(ErrorKind::UndefinedVar(name.to_owned())));
+        }
+        Ok(Status::SUCCESS)
+    }
+    .boxed()
+}
+
+/// `export [NAME [= VALUE]]`
+///
+/// Marks a variable to be passed to child processes, declaring it in the
+/// current scope if a value is given and it is not defined. The value may also
+/// be given as `NAME=VALUE`, as in other shells. Without arguments the exported
+/// variables are listed.
+pub fn export(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        if call.args.is_empty() {
+            let mut out = String::new();
+            for (name, value) in frame.scope.exports() {
+                out.push_str(&format!("export {}={}\n", name, value));
+            }
+
+            frame
+                .write_out(out.into_bytes())
+                .await
+                .map_err(|err| call.error(ErrorKind::Io(err)))?;
+            return Ok(Status::SUCCESS);
+        }
+
+        let (name, value) = match &*call.args {
+            [Value::Str(arg)] if arg.contains('=') => {
+                let (name, value) = arg.split_once('=').unwrap();
+                (valid_name(&call, name)?, Some(Value::Str(value.to_owned())))
+            }
+            _ => assignment(&call)?,
+        };
+        let defined = frame.scope.modify(name, |var| {
+            var.exported = true;
+            if let Some(value) = &value {
+                var.value = value.clone();
+            }
+        });
+
+        if !defined {
+            match value {
+                Some(value) => frame.scope.declare(
+                    name,
+                    Var {
+                        value,
+                        exported: true,
+                    },
+                ),
+                None => return Err(call.error(ErrorKind::UndefinedVar(name.to_owned()))),
+            }
+        }
+        Ok(Status::SUCCESS)
+    }
+    .boxed()
+}
+
+/// `unset NAME...`
+pub fn unset(frame: &mut Frame, call: Call) -> BoxFuture<'_, Result<Status>> {
+    async move {
+        for name in call.str_args()? {
+            if !frame.scope.remove(name) {
+                return Err(call.error(ErrorKind::UndefinedVar(name.to_owned())));
+            }
+        }
+        Ok(Status::SUCCESS)
+    }
+    .boxed()
+}
+
+/// Parses the arguments `NAME [= VALUE]`.
+fn assignment(call: &Call) -> Result<(&str, Option<Value>)> {
+    match &*call.args {
+        [Value::Str(name)] => Ok((valid_name(call, name)?, None)),
+        [Value::Str(name), Value::Str(eq), value] if eq == "=" => {
+            Ok((valid_name(call, name)?, Some(value.clone())))
+        }
+        _ => Err(call.usage("expected `NAME [= VALUE]`")),
+    }
+}
+
+pub fn valid_name<'a>(call: &Call, name: &'a str) -> Result<&'a str> {
+    let valid = !name.is_empty()
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-');
+
+    if valid {
+        Ok(name)
+    } else {
+        Err(call.usage(&format!("invalid variable name `{}`", name)))
+    }
+}
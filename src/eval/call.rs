@@ -0,0 +1,103 @@
+use std::sync::Arc;
+
+use crate::eval::frame::Frame;
+use crate::eval::scope::{Scope, Var};
+use crate::eval::value::{Closure, Value};
+use crate::eval::{Error, ErrorKind, Result, Status};
+use crate::parse::Span;
+
+impl Frame {
+    /// Calls a closure with positional arguments and options.
+    ///
+    /// The body is evaluated in a new scope within the scope the closure was
+    /// defined in, so it sees the variables it captured rather than those of
+    /// the caller. A closure without parameters takes any arguments, as
+    /// `$args`.
+    pub async fn call(
+        &mut self,
+        closure: &Closure,
+        args: Vec<Value>,
+        opts: Vec<(String, Value)>,
+        span: Span,
+    ) -> Result<Status> {
+        let lambda = &closure.lambda;
+
+        let params = lambda.params.len();
+        let arity = match &lambda.rest {
+            Some(_) if args.len() < params => Some(ErrorKind::ArityAtLeast(params, args.len())),
+            None if params > 0 && args.len() != params => {
+                Some(ErrorKind::Arity(params, args.len()))
+            }
+            _ => None,
+        };
+        if let Some(kind) = arity {
+            return Err(Error::new(kind, span));
+        }
+        if let Some((name, _)) = opts
+            .iter()
+            .find(|(name, _)| !lambda.opts.iter().any(|opt| opt.name == *name))
+        {
+            return Err(Error::new(ErrorKind::UnknownOpt(name.clone()), span));
+        }
+
+        // Spans in the body are in the source the closure was defined in.
+        let caller = std::mem::replace(&mut self.src, closure.src.clone());
+        let result = self.eval_closure(closure, args, opts).await;
+        self.src = caller;
+
+        match result {
+            Err(Error {
+                kind: ErrorKind::Return(status),
+                ..
+            }) => Ok(status),
+            result => result.map_err(|err| err.or_source(closure.src.as_ref())),
+        }
+    }
+
+    async fn eval_closure(
+        &mut self,
+        closure: &Closure,
+        args: Vec<Value>,
+        opts: Vec<(String, Value)>,
+    ) -> Result<Status> {
+        let lambda = &closure.lambda;
+
+        let scope = Scope::child(&closure.scope);
+        let declare = |name: &str, value| {
+            scope.declare(
+                name,
+                Var {
+                    value,
+                    exported: false,
+                },
+            )
+        };
+
+        let mut args = args.into_iter();
+        for (param, value) in lambda.params.iter().zip(&mut args) {
+            declare(param, value);
+        }
+        match &lambda.rest {
+            Some(rest) => declare(rest, Value::List(args.collect())),
+            None if lambda.params.is_empty() => declare("args", Value::List(args.collect())),
+            None => {}
+        }
+
+        for opt in &lambda.opts {
+            let value = match opts.iter().find(|(name, _)| *name == opt.name) {
+                Some((_, value)) => value.clone(),
+                // Defaults are expanded in the scope of the body.
+                None => {
+                    let caller = std::mem::replace(&mut self.scope, Arc::clone(&scope));
+                    let value = self.expand_word(&opt.value).await;
+                    self.scope = caller;
+                    value?
+                }
+            };
+
+            declare(&opt.name, value);
+        }
+
+        self.eval_in_scope(&lambda.body, scope).await
+    }
+}
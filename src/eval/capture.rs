@@ -0,0 +1,230 @@
+use std::future::Future;
+use std::os::unix::io::AsRawFd;
+use std::sync::Arc;
+
+use futures::future;
+
+use crate::eval::frame::Frame;
+use crate::eval::port::{self, Port, ValueReader};
+use crate::eval::value::{Closure, Value};
+use crate::eval::{Error, ErrorKind, Result, Status};
+use crate::parse::ast::Chunk;
+use crate::parse::Span;
+
+/// The most bytes of output that may be captured, so capturing a huge file
+/// fails rather than exhausting memory.
+pub const MAX_CAPTURE_LEN: usize = 64 * 1024 * 1024;
+
+/// The most values that may be captured.
+pub const MAX_CAPTURE_VALUES: usize = 1024 * 1024;
+
+/// Size of the chunks captured output is read in.
+const READ_LEN: usize = 8192;
+
+impl Frame {
+    /// Evaluates `$(...)`, capturing the output as a string without trailing
+    /// newlines.
+    ///
+    /// Values output by the code are captured as lines.
+    pub async fn capture_output(&mut self, chunk: &Chunk, span: Span) -> Result<String> {
+        let (bytes, _) = self
+            .capture(false, span, |mut frame| async move {
+                frame.eval_block(chunk).await
+            })
+            .await?;
+        Ok(trim_output(&bytes))
+    }
+
+    /// Calls a closure, capturing its output as a string without trailing
+    /// newlines, as with `$(...)`.
+    pub async fn capture_call(
+        &mut self,
+        closure: &Closure,
+        args: Vec<Value>,
+        span: Span,
+    ) -> Result<String> {
+        let (bytes, _) = self
+            .capture(false, span, |mut frame| async move {
+                frame.call(closure, args, Vec::new(), span).await
+            })
+            .await?;
+        Ok(trim_output(&bytes))
+    }
+
+    /// Evaluates `(...)`, capturing the values output by the code followed by
+    /// each line of its output.
+    pub async fn capture_values(&mut self, chunk: &Chunk, span: Span) -> Result<Vec<Value>> {
+        let (bytes, mut values) = self
+            .capture(true, span, |mut frame| async move {
+                frame.eval_block(chunk).await
+            })
+            .await?;
+
+        let output = String::from_utf8_lossy(&bytes);
+        let output = output.strip_suffix('\n').unwrap_or(&output);
+        if !output.is_empty() {
+            values.extend(output.split('\n').map(Value::from));
+        }
+        Ok(values)
+    }
+
+    /// Evaluates code in a copy of the frame concurrently with reading its
+    /// output, and its values if `values`.
+    async fn capture<F, T>(
+        &mut self,
+        values: bool,
+        span: Span,
+        eval: F,
+    ) -> Result<(Vec<u8>, Vec<Value>)>
+    where
+        F: FnOnce(Frame) -> T,
+        T: Future<Output = Result<Status>>,
+    {
+        let (r, w) = Port::pipe().map_err(|err| Error::new(ErrorKind::Pipe(err), span))?;
+
+        let mut frame = self.clone();
+        frame.ports.set(1, Some(w));
+        frame.pipes = Vec::new();
+
+        let value_in = if values {
+            let (r, w) = port::value_channel();
+            frame.value_out = Some(w);
+            Some(r)
+        } else {
+            frame.value_out = None;
+            None
+        };
+
+        // The frame is dropped as soon as the code finishes, closing its end
+        // of the pipe.
+        let (result, bytes, values) =
+            future::join3(eval(frame), read_bytes(r), read_values(value_in)).await;
+
+        // Code stopped by the capture being too large fails with a broken pipe,
+        // which is not the error to report.
+        let (bytes, values) = match (bytes, values) {
+            (Some(bytes), Some(values)) => (bytes, values),
+            _ => return Err(Error::new(ErrorKind::CaptureTooLarge, span)),
+        };
+
+        substitution_status(result)?;
+        Ok((bytes, values))
+    }
+
+    /// Evaluates `<(...)`, or `>(...)` if not `input`, concurrently with the
+    /// command, returning the path of the pipe to the code.
+    pub fn proc_sub(&mut self, chunk: &Arc<Chunk>, input: bool, span: Span) -> Result<String> {
+        let (r, w) = Port::pipe().map_err(|err| Error::new(ErrorKind::Pipe(err), span))?;
+        let (outer, inner) = if input { (r, w) } else { (w, r) };
+
+        let mut frame = self.clone();
+        frame.ports.set(if input { 1 } else { 0 }, Some(inner));
+        frame.value_in = None;
+        frame.value_out = None;
+        frame.pipes = Vec::new();
+
+        // The code outlives the command, so its errors can only be reported
+        // where it writes them.
+        let chunk = Arc::clone(chunk);
+        tokio::spawn(async move {
+            if let Err(err) = substitution_status(frame.eval_block(&chunk).await) {
+                if !err.is_broken_pipe() {
+                    let msg = format!("jsh: {}\n", err);
+                    let _ = frame.write_err(msg.into_bytes()).await;
+                }
+            }
+        });
+
+        let path = format!("/dev/fd/{}", outer.file().as_raw_fd());
+        self.pipes.push(outer);
+        Ok(path)
+    }
+}
+
+/// The status of the code of a substitution, which `exit` ends rather than
+/// the shell.
+fn substitution_status(result: Result<Status>) -> Result<Status> {
+    match result {
+        Err(Error {
+            kind: ErrorKind::Exit(status),
+            ..
+        }) => Ok(status),
+        result => result,
+    }
+}
+
+/// Captured output as a string without trailing newlines.
+fn trim_output(bytes: &[u8]) -> String {
+    let mut output = String::from_utf8_lossy(bytes).into_owned();
+    output.truncate(output.trim_end_matches('\n').len());
+    output
+}
+
+/// Reads all bytes from a port, or `None` if there are too many.
+///
+/// Read errors end the input.
+async fn read_bytes(port: Port) -> Option<Vec<u8>> {
+    let mut buf = Vec::new();
+    loop {
+        match port.read(READ_LEN).await {
+            Ok(chunk) if chunk.is_empty() => return Some(buf),
+            Ok(chunk) => buf.extend(chunk),
+            Err(_) => return Some(buf),
+        }
+
+        if buf.len() > MAX_CAPTURE_LEN {
+            return None;
+        }
+    }
+}
+
+/// Reads all values from a channel, or `None` if there are too many.
+async fn read_values(input: Option<ValueReader>) -> Option<Vec<Value>> {
+    let input = match input {
+        Some(input) => input,
+        None => return Some(Vec::new()),
+    };
+
+    let mut values = Vec::new();
+    while let Some(value) = input.recv().await {
+        values.push(value);
+        if values.len() > MAX_CAPTURE_VALUES {
+            return None;
+        }
+    }
+    Some(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::eval::testing::output;
+
+    #[test]
+    fn captures() {
+        assert_eq!(output("echo $(echo a; echo b)").unwrap(), "a\nb");
+        assert_eq!(output("echo $(echo a)\n\n").unwrap(), "a");
+        assert_eq!(
+            output("for x in (echo a; echo b) { echo [$x] }").unwrap(),
+            "[a]\n[b]"
+        );
+    }
+
+    #[test]
+    fn exit_ends_substitution() {
+        assert_eq!(output("echo x$(exit 3)y; echo still").unwrap(), "xy\nstill");
+        assert_eq!(
+            output("echo (echo a; exit 0; echo b); echo still").unwrap(),
+            "a\nstill"
+        );
+        assert_eq!(
+            output("cat <(echo a; exit 2; echo b); echo still").unwrap(),
+            "a\nstill"
+        );
+    }
+
+    #[test]
+    fn errors_propagate() {
+        assert!(output("echo $(false)").is_err());
+        assert!(output("echo $(nosuchcommand-jsh)").is_err());
+    }
+}
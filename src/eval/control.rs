@@ -0,0 +1,219 @@
+use std::sync::Arc;
+
+use crate::eval::frame::Frame;
+use crate::eval::scope::{Scope, Var};
+use crate::eval::value::Value;
+use crate::eval::{Error, ErrorKind, Result, Status};
+use crate::parse::ast::*;
+use crate::parse::Span;
+
+/// What to do after the body of a loop.
+enum Flow {
+    Next,
+    Break,
+}
+
+impl Frame {
+    pub async fn eval_if(
+        &mut self,
+        branches: &[(Stmt, Chunk)],
+        else_body: Option<&Chunk>,
+    ) -> Result<Status> {
+        for (cond, body) in branches {
+            if self.eval_condition(cond).await?.is_success() {
+                return self.eval_block(body).await;
+            }
+        }
+
+        match else_body {
+            Some(body) => self.eval_block(body).await,
+            None => Ok(Status::SUCCESS),
+        }
+    }
+
+    pub async fn eval_while(
+        &mut self,
+        cond: &Stmt,
+        body: &Chunk,
+        until: bool,
+        span: Span,
+    ) -> Result<Status> {
+        let mut status = Status::SUCCESS;
+        let mut iterations = 0;
+
+        while self.eval_condition(cond).await?.is_success() != until {
+            iterations += 1;
+            if let Some(max) = self.options().max_iterations {
+                if iterations > max {
+                    return Err(Error::new(ErrorKind::TooManyIterations(max), span));
+                }
+            }
+
+            let result = self.eval_block(body).await;
+            if let Flow::Break = loop_flow(result, &mut status)? {
+                break;
+            }
+        }
+
+        Ok(status)
+    }
+
+    pub async fn eval_for(&mut self, var: &str, items: &[Word], body: &Chunk) -> Result<Status> {
+        // Lists are iterated over element by element, and maps key by key.
+        let mut values = Vec::with_capacity(items.len());
+        for value in self.expand_words(items).await? {
+            match value {
+                Value::List(list) => values.extend(list),
+                Value::Map(map) => values.extend(map.into_keys().map(Value::Str)),
+                value => values.push(value),
+            }
+        }
+
+        let mut status = Status::SUCCESS;
+        for value in values {
+            // Each iteration has its own scope, containing the loop variable.
+            let scope = Scope::child(&self.scope);
+            scope.declare(
+                var,
+                Var {
+                    value,
+                    exported: false,
+                },
+            );
+
+            let result = self.eval_in_scope(body, scope).await;
+            if let Flow::Break = loop_flow(result, &mut status)? {
+                break;
+            }
+        }
+
+        Ok(status)
+    }
+
+    /// Evaluates the body of a `try`, catching any exception it raises.
+    ///
+    /// The `finally` clause is always evaluated, and an exception it raises
+    /// replaces the result of the rest.
+    pub async fn eval_try(
+        &mut self,
+        body: &Chunk,
+        catch: Option<&Catch>,
+        finally: Option<&Chunk>,
+    ) -> Result<Status> {
+        let result = match (self.eval_block(body).await, catch) {
+            (Err(err), Some(catch)) if !err.is_control_flow() => {
+                let scope = Scope::child(&self.scope);
+                if let Some(var) = &catch.var {
+                    let value = Value::Exception(Arc::new(err.or_source(self.src.as_ref())));
+                    scope.declare(
+                        var,
+                        Var {
+                            value,
+                            exported: false,
+                        },
+                    );
+                }
+                self.eval_in_scope(&catch.body, scope).await
+            }
+            (result, _) => result,
+        };
+
+        if let Some(finally) = finally {
+            self.eval_block(finally).await?;
+        }
+        result
+    }
+}
+
+/// Handles `break` and `continue` in the body of a loop, recording the status
+/// of the body.
+fn loop_flow(result: Result<Status>, status: &mut Status) -> Result<Flow> {
+    match result {
+        Ok(s) => {
+            *status = s;
+            Ok(Flow::Next)
+        }
+        Err(err) => match err.kind {
+            ErrorKind::Break => Ok(Flow::Break),
+            ErrorKind::Continue => Ok(Flow::Next),
+            _ => Err(err),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::eval::testing::output;
+    use crate::eval::ErrorKind;
+
+    #[test]
+    fn loops() {
+        let code = "let i = 0; while test $i -lt 3 { echo $i; set i = $(($i + 1)) }";
+        assert_eq!(output(code).unwrap(), "0\n1\n2");
+        let code = "let i = 0; until test $i = 2 { set i = $(($i + 1)) }; echo $i";
+        assert_eq!(output(code).unwrap(), "2");
+        assert_eq!(
+            output("for x in a b c { if test $x = b { continue }; echo $x }").unwrap(),
+            "a\nc"
+        );
+        assert_eq!(output("while true { echo a; break }").unwrap(), "a");
+    }
+
+    #[test]
+    fn iteration_limit() {
+        let err = output("setopt max-iterations=3; while true { echo a }").unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::TooManyIterations(3)));
+
+        let code =
+            "setopt max-iterations=3; let i = 0; while test $i -lt 3 { set i = $(($i + 1)) }";
+        assert!(output(code).is_ok());
+        let code = "setopt max-iterations=1; unsetopt max-iterations; \
+                    let i = 0; while test $i -lt 3 { set i = $(($i + 1)) }";
+        assert!(output(code).is_ok());
+        assert!(output("setopt max-iterations=x").is_err());
+    }
+
+    #[test]
+    fn exceptions() {
+        let code = "try { sh -c 'exit 3' } catch e { echo $e[status] $e[command] }";
+        assert_eq!(output(code).unwrap(), "3 sh");
+        assert_eq!(
+            output("try { fail boom } catch e { echo $e[message] }").unwrap(),
+            "boom"
+        );
+        assert_eq!(
+            output("try { false } catch { echo caught }").unwrap(),
+            "caught"
+        );
+        assert_eq!(output("try { echo a } catch { echo no }").unwrap(), "a");
+
+        // Tested statuses do not raise.
+        assert_eq!(
+            output("if false { echo no }; false || echo or").unwrap(),
+            "or"
+        );
+
+        let code = "try { try { false } finally { echo inner } } catch { echo outer }";
+        assert_eq!(output(code).unwrap(), "inner\nouter");
+        // An exception in `finally` replaces the one being raised.
+        let code = "try { try { fail a } finally { fail b } } catch e { echo $e[message] }";
+        assert_eq!(output(code).unwrap(), "b");
+        // Rethrown exceptions keep their status.
+        let code = "try { try { sh -c 'exit 5' } catch e { fail $e } } catch e { echo $e[status] }";
+        assert_eq!(output(code).unwrap(), "5");
+    }
+
+    #[test]
+    fn control_flow_is_not_caught() {
+        assert_eq!(
+            output("try { exit 4 } catch { echo no }; echo no").unwrap(),
+            ""
+        );
+        assert_eq!(
+            output("for x in a b { try { break } catch { echo no } }; echo done").unwrap(),
+            "done"
+        );
+        let code = "fn f { try { return } finally { echo fin }; echo no }; f";
+        assert_eq!(output(code).unwrap(), "fin");
+    }
+}
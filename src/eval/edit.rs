@@ -0,0 +1,131 @@
+//! State shared with the line editor, through the builtins of the `edit:` and
+//! `prompt:` namespaces.
+
+use std::fs::File;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::future::FutureExt;
+
+use crate::cli::keymap::{BoundFn, Keymaps, Line, SharedKeymaps};
+use crate::eval::frame::Frame;
+use crate::eval::port::Port;
+use crate::eval::value::Closure;
+use crate::eval::Result;
+
+/// The state of the line editor, as seen by the shell.
+#[derive(Debug)]
+pub struct EditState {
+    /// The buffer of the next line read.
+    pub buffer: String,
+    /// The position of the cursor in the buffer, as a byte index.
+    pub dot: usize,
+    /// Notes to show above the next prompt.
+    pub notes: Vec<String>,
+    /// Modules added to the prompts by `prompt:add`.
+    pub prompts: Vec<PromptFn>,
+    /// Whether the left prompt has its default modules.
+    pub default_prompt: bool,
+    /// Incremented whenever the prompts change, so the editor knows to
+    /// rebuild them.
+    pub prompts_version: usize,
+    /// The keymaps of the editor, changed by `edit:bind`.
+    pub keymaps: SharedKeymaps,
+}
+
+impl Default for EditState {
+    fn default() -> EditState {
+        EditState {
+            buffer: String::new(),
+            dot: 0,
+            notes: Vec::new(),
+            prompts: Vec::new(),
+            default_prompt: true,
+            prompts_version: 0,
+            keymaps: Arc::new(Mutex::new(Keymaps::default())),
+        }
+    }
+}
+
+pub type SharedEditState = Arc<Mutex<EditState>>;
+
+/// A module of a prompt, whose content is the output of a function.
+#[derive(Clone, Debug)]
+pub struct PromptFn {
+    pub callback: Callback,
+    /// The position in the prompt compared to other modules.
+    pub position: isize,
+    /// Whether the module is in the right prompt.
+    pub right: bool,
+    /// How often the module is recomputed while a line is edited, besides
+    /// each time a prompt is shown.
+    pub interval: Option<Duration>,
+}
+
+/// A closure to be called by the editor, in the frame it was given in.
+#[derive(Clone, Debug)]
+pub struct Callback {
+    frame: Frame,
+    closure: Arc<Closure>,
+}
+
+impl Callback {
+    pub fn new(frame: &Frame, closure: Arc<Closure>) -> Callback {
+        let mut frame = frame.clone();
+        // The editor owns the terminal while the closure runs.
+        frame
+            .ports
+            .set(0, File::open("/dev/null").ok().map(Port::new));
+        frame.value_in = None;
+        frame.value_out = None;
+        frame.pipes = Vec::new();
+        frame.tested = false;
+        frame.job = None;
+
+        Callback { frame, closure }
+    }
+
+    /// Calls the closure without arguments, returning its output.
+    pub async fn output(&self) -> Result<String> {
+        let mut frame = self.frame.clone();
+        let span = self.closure.lambda.span;
+        frame.capture_call(&self.closure, Vec::new(), span).await
+    }
+
+    /// Makes a function to bind to a key, which calls the closure with the
+    /// buffer of the line being edited as that of the `edit:` builtins.
+    ///
+    /// The output of the closure and any error are shown as notes.
+    pub fn bound_fn(self) -> BoundFn {
+        Arc::new(move |line| {
+            let callback = self.clone();
+            async move { callback.call_with_line(line).await }.boxed()
+        })
+    }
+
+    async fn call_with_line(&self, line: Line) -> Line {
+        let edit = &self.frame.globals.edit;
+        {
+            let mut edit = edit.lock().unwrap();
+            edit.buffer = line.buffer;
+            edit.dot = line.dot;
+        }
+
+        let result = self.output().await;
+
+        let mut edit = edit.lock().unwrap();
+        let mut notes = line.notes;
+        notes.append(&mut edit.notes);
+        match result {
+            Ok(output) if output.is_empty() => {}
+            Ok(output) => notes.push(output),
+            Err(err) => notes.push(err.to_string()),
+        }
+
+        Line {
+            buffer: std::mem::take(&mut edit.buffer),
+            dot: std::mem::replace(&mut edit.dot, 0),
+            notes,
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use std::io;
+use std::sync::Arc;
+
+use thiserror::Error;
+
+use crate::eval::Status;
+use crate::parse::{ParseErrorKind, Source, Span};
+
+#[derive(Debug, Error)]
+pub enum ErrorKind {
+    #[error("command not found: {0}")]
+    CommandNotFound(String),
+    #[error("failed to execute `{0}`: {1}")]
+    Exec(String, io::Error),
+    #[error("cannot open `{0}`: {1}")]
+    Open(String, io::Error),
+    #[error("bad file descriptor: {0}")]
+    BadFd(String),
+    #[error("failed to create pipe: {0}")]
+    Pipe(io::Error),
+    #[error("{0}")]
+    Io(io::Error),
+    #[error("cannot change directory to `{0}`: {1}")]
+    Chdir(String, io::Error),
+    #[error("directory stack is empty")]
+    DirStackEmpty,
+    #[error("{0}")]
+    Syntax(ParseErrorKind),
+    #[error("module not found: {0}")]
+    NoSuchModule(String),
+    #[error("module uses itself: {0}")]
+    CircularModule(String),
+    #[error("{0}")]
+    Usage(String),
+    #[error("expected {0}, found {1}")]
+    Type(&'static str, &'static str),
+    #[error("wrong number of arguments: expected {0}, found {1}")]
+    Arity(usize, usize),
+    #[error("wrong number of arguments: expected at least {0}, found {1}")]
+    ArityAtLeast(usize, usize),
+    #[error("unknown option `&{0}`")]
+    UnknownOpt(String),
+    #[error("invalid index: {0}")]
+    BadIndex(String),
+    #[error("index out of range: {0}")]
+    IndexOutOfRange(String),
+    #[error("no such key: {0}")]
+    NoSuchKey(String),
+    #[error("variable not defined: {0}")]
+    UndefinedVar(String),
+    #[error("{0}: {1}")]
+    Param(String, String),
+    #[error(
+        "captured output exceeds {} bytes or {} values",
+        crate::eval::capture::MAX_CAPTURE_LEN,
+        crate::eval::capture::MAX_CAPTURE_VALUES
+    )]
+    CaptureTooLarge,
+    #[error("{0}")]
+    Arith(String),
+    #[error("division by zero")]
+    DivideByZero,
+    #[error("invalid regex: {0}")]
+    Regex(String),
+    #[error("no files match `{0}`")]
+    NoMatch(String),
+    #[error("unknown qualifier `[{0}]`")]
+    BadQualifier(String),
+    #[error("`{1}` exited with status {0}")]
+    Failed(Status, String),
+    #[error("{0}")]
+    Fail(String),
+    #[error("{0}")]
+    Exception(Arc<Error>),
+    #[error("no such job: {0}")]
+    NoSuchJob(String),
+    #[error("job control is not enabled")]
+    NoJobControl,
+    #[error("job {0} stopped")]
+    Stopped(usize),
+    #[error("{0} are not supported yet")]
+    Unsupported(&'static str),
+    #[error(
+        "maximum depth of {} nested blocks exceeded",
+        crate::eval::frame::MAX_DEPTH
+    )]
+    TooDeep,
+    #[error("loop exceeded {0} iterations")]
+    TooManyIterations(usize),
+    #[error("`break` outside of a loop")]
+    Break,
+    #[error("`continue` outside of a loop")]
+    Continue,
+    #[error("`return` outside of a function or script")]
+    Return(Status),
+    #[error("`exit` outside of the shell")]
+    Exit(Status),
+}
+
+#[derive(Debug, Error)]
+#[error("{kind}")]
+pub struct Error {
+    pub kind: ErrorKind,
+    pub span: Span,
+    /// The source the span is in, if it is known.
+    pub src: Option<Arc<Source>>,
+}
+
+impl Error {
+    pub fn new(kind: ErrorKind, span: Span) -> Error {
+        Error {
+            kind,
+            span,
+            src: None,
+        }
+    }
+
+    /// Attributes the error to a source, unless it already is.
+    pub fn or_source(mut self, src: Option<&Arc<Source>>) -> Error {
+        if self.src.is_none() {
+            self.src = src.cloned();
+        }
+        self
+    }
+
+    /// Was the error caused by writing to a pipe with no reader.
+    pub fn is_broken_pipe(&self) -> bool {
+        match &self.kind {
+            ErrorKind::Io(err) => err.kind() == io::ErrorKind::BrokenPipe,
+            ErrorKind::Failed(status, _) => *status == Status::from_signal(libc::SIGPIPE),
+            ErrorKind::Exception(err) => err.is_broken_pipe(),
+            _ => false,
+        }
+    }
+
+    /// Is the error `break`, `continue`, `return` or `exit` rather than an exception.
+    pub fn is_control_flow(&self) -> bool {
+        matches!(
+            self.kind,
+            ErrorKind::Break | ErrorKind::Continue | ErrorKind::Return(_) | ErrorKind::Exit(_)
+        )
+    }
+
+    /// The exit status a failed command reports.
+    pub fn status(&self) -> Status {
+        match &self.kind {
+            ErrorKind::CommandNotFound(_) => Status::NOT_FOUND,
+            ErrorKind::Exec(_, _) => Status::NOT_EXECUTABLE,
+            ErrorKind::Usage(_) => Status::USAGE,
+            ErrorKind::Syntax(_) => Status::SYNTAX_ERROR,
+            ErrorKind::Exit(status) => *status,
+            ErrorKind::Failed(status, _) => *status,
+            ErrorKind::Exception(err) => err.status(),
+            ErrorKind::Stopped(_) => Status::from_signal(libc::SIGTSTP),
+            _ if self.is_broken_pipe() => Status::from_signal(libc::SIGPIPE),
+            _ => Status::FAILURE,
+        }
+    }
+}
@@ -0,0 +1,326 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use futures::future::{BoxFuture, FutureExt};
+
+use crate::eval::frame::Frame;
+use crate::eval::glob::{self, Pattern};
+use crate::eval::value::{Closure, Value};
+use crate::eval::{arith, module, strings};
+use crate::eval::{Error, ErrorKind, Result};
+use crate::parse::ast::*;
+use crate::parse::Span;
+
+impl Frame {
+    /// Expands words to values, spreading lists exploded with `$@name`, the
+    /// positional arguments of `$@`, the output captured by `(cmd)` and the
+    /// paths matched by patterns into separate values.
+    pub async fn expand_words(&mut self, words: &[Word]) -> Result<Vec<Value>> {
+        let mut values = Vec::with_capacity(words.len());
+
+        for word in words {
+            if is_all_args(word) {
+                values.extend(self.positional_args());
+                continue;
+            }
+
+            match &*word.parts {
+                [WordPart {
+                    kind: WordPartKind::Param(param),
+                    span,
+                }] if param.explode => match self.expand_param(param, *span).await? {
+                    Value::List(list) => values.extend(list),
+                    value => {
+                        return Err(Error::new(ErrorKind::Type("a list", value.kind()), *span))
+                    }
+                },
+                [WordPart {
+                    kind: WordPartKind::Capture(chunk),
+                    span,
+                }] => values.extend(self.capture_values(chunk, *span).await?),
+                _ if is_pattern(word) => {
+                    let pattern = self.expand_pattern(word).await?;
+                    let paths = glob::expand(&pattern)
+                        .await
+                        .map_err(|kind| Error::new(kind, word.span))?;
+                    values.extend(paths.into_iter().map(Value::Str));
+                }
+                _ => values.push(self.expand_word(word).await?),
+            }
+        }
+
+        Ok(values)
+    }
+
+    /// Expands a word to a single value.
+    ///
+    /// A word made of more than one part is concatenated into a string.
+    pub async fn expand_word(&mut self, word: &Word) -> Result<Value> {
+        match &*word.parts {
+            [part] => self.expand_part(part).await,
+            parts => Ok(Value::Str(self.concat_parts(parts).await?)),
+        }
+    }
+
+    /// Expands a word that must be a string, such as a file name.
+    pub async fn expand_string(&mut self, word: &Word) -> Result<String> {
+        match self.expand_word(word).await? {
+            Value::Str(s) => Ok(s),
+            value => Err(Error::new(
+                ErrorKind::Type("a string", value.kind()),
+                word.span,
+            )),
+        }
+    }
+
+    /// Expands a word to a pattern, in which only the unquoted literal parts
+    /// keep their special meaning.
+    async fn expand_pattern(&mut self, word: &Word) -> Result<String> {
+        let mut pattern = String::new();
+        for part in &word.parts {
+            match &part.kind {
+                WordPartKind::Bare(s) => pattern.push_str(s),
+                _ => match self.expand_part(part).await? {
+                    Value::Str(s) => glob::escape(&s, &mut pattern),
+                    value => {
+                        return Err(Error::new(
+                            ErrorKind::Type("a string", value.kind()),
+                            part.span,
+                        ))
+                    }
+                },
+            }
+        }
+        Ok(pattern)
+    }
+
+    fn expand_part<'a>(&'a mut self, part: &'a WordPart) -> BoxFuture<'a, Result<Value>> {
+        async move {
+            match &part.kind {
+                WordPartKind::Bare(s) | WordPartKind::Quoted(s) => Ok(Value::Str(s.clone())),
+                WordPartKind::DoubleQuoted(parts) => {
+                    Ok(Value::Str(self.concat_parts(parts).await?))
+                }
+                WordPartKind::Param(param) => self.expand_param(param, part.span).await,
+                WordPartKind::Lambda(lambda) => Ok(Value::Fn(Arc::new(Closure {
+                    lambda: Arc::clone(lambda),
+                    scope: Arc::clone(&self.scope),
+                    src: self.src.clone(),
+                }))),
+                WordPartKind::List(words) => Ok(Value::List(self.expand_words(words).await?)),
+                WordPartKind::Map(entries) => {
+                    let mut map = BTreeMap::new();
+                    for entry in entries {
+                        map.insert(entry.name.clone(), self.expand_word(&entry.value).await?);
+                    }
+                    Ok(Value::Map(map))
+                }
+                WordPartKind::Output(chunk) => {
+                    Ok(Value::Str(self.capture_output(chunk, part.span).await?))
+                }
+                WordPartKind::Arith(expr) => {
+                    let lookup = |name: &str| self.param_value(name);
+                    Ok(Value::Str(arith::eval(expr, &lookup)?.to_string()))
+                }
+                WordPartKind::Capture(chunk) => {
+                    Ok(Value::List(self.capture_values(chunk, part.span).await?))
+                }
+                WordPartKind::ProcSub { chunk, input } => {
+                    Ok(Value::Str(self.proc_sub(chunk, *input, part.span)?))
+                }
+            }
+        }
+        .boxed()
+    }
+
+    async fn concat_parts(&mut self, parts: &[WordPart]) -> Result<String> {
+        let mut out = String::new();
+        for part in parts {
+            match self.expand_part(part).await? {
+                Value::Str(s) => out.push_str(&s),
+                value => {
+                    return Err(Error::new(
+                        ErrorKind::Type("a string", value.kind()),
+                        part.span,
+                    ))
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    async fn expand_param(&mut self, param: &ParamExp, span: Span) -> Result<Value> {
+        let mut value = self.param_value(&param.name);
+
+        for index in &param.indices {
+            let key = self.expand_string(index).await?;
+            let indexed = value
+                .unwrap_or_default()
+                .index(&key)
+                .map_err(|kind| Error::new(kind, index.span))?;
+            value = Some(indexed);
+        }
+
+        // Unset variables and empty strings are treated the same by operators.
+        let is_set = match &value {
+            Some(Value::Str(s)) => !s.is_empty(),
+            Some(_) => true,
+            None => false,
+        };
+
+        let op = match &param.op {
+            None => return Ok(value.unwrap_or_default()),
+            Some(ParamOp::Default(_)) | Some(ParamOp::Error(_)) if is_set => {
+                return Ok(value.unwrap_or_default())
+            }
+            Some(ParamOp::Default(word)) => return self.expand_word(word).await,
+            Some(ParamOp::Error(word)) => {
+                let mut msg = self.expand_string(word).await?;
+                if msg.is_empty() {
+                    msg = "parameter not set".to_owned();
+                }
+                return Err(Error::new(ErrorKind::Param(param.name.clone(), msg), span));
+            }
+            Some(op) => op,
+        };
+
+        let s = match value.unwrap_or_default() {
+            Value::Str(s) => s,
+            Value::List(list) if *op == ParamOp::Length => {
+                return Ok(Value::Str(list.len().to_string()))
+            }
+            Value::Map(map) if *op == ParamOp::Length => {
+                return Ok(Value::Str(map.len().to_string()))
+            }
+            value => return Err(Error::new(ErrorKind::Type("a string", value.kind()), span)),
+        };
+
+        let result = match op {
+            ParamOp::Length => s.chars().count().to_string(),
+            ParamOp::TrimPrefix { pattern, longest } => {
+                let pattern = Pattern::new(&self.expand_pattern(pattern).await?);
+                strings::trim_prefix(&s, &pattern, *longest).to_owned()
+            }
+            ParamOp::TrimSuffix { pattern, longest } => {
+                let pattern = Pattern::new(&self.expand_pattern(pattern).await?);
+                strings::trim_suffix(&s, &pattern, *longest).to_owned()
+            }
+            ParamOp::Replace { pattern, with, all } => {
+                let pattern = Pattern::new(&self.expand_pattern(pattern).await?);
+                let with = match with {
+                    Some(word) => self.expand_string(word).await?,
+                    None => String::new(),
+                };
+                strings::replace(&s, &pattern, &with, *all)
+            }
+            ParamOp::Case { upper, all } => strings::convert_case(&s, *upper, *all),
+            ParamOp::Substring { offset, length } => {
+                let lookup = |name: &str| self.param_value(name);
+                let offset = arith::eval_integer(offset, &lookup)?;
+                let length = match length {
+                    Some(length) => Some(arith::eval_integer(length, &lookup)?),
+                    None => None,
+                };
+                strings::substring(&s, offset, length).to_owned()
+            }
+            ParamOp::Default(_) | ParamOp::Error(_) => unreachable!(),
+        };
+
+        Ok(Value::Str(result))
+    }
+
+    /// The value of a parameter, or `None` if it is not set.
+    fn param_value(&self, name: &str) -> Option<Value> {
+        match name {
+            "?" => Some(Value::Str(self.status.to_string())),
+            "$" => Some(Value::Str(std::process::id().to_string())),
+            "!" => {
+                let pid = self.globals.jobs.last_background_pid()?;
+                Some(Value::Str(pid.to_string()))
+            }
+            // The positional arguments, the elements of `$args`, joined by
+            // spaces unless `$@` is a word of its own.
+            "#" => Some(Value::Str(self.positional_args().len().to_string())),
+            "@" | "*" => {
+                let args: Vec<String> = self
+                    .positional_args()
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect();
+                Some(Value::Str(args.join(" ")))
+            }
+            "pipestatus" => {
+                let statuses: Vec<String> =
+                    self.pipestatus.iter().map(ToString::to_string).collect();
+                Some(Value::Str(statuses.join(" ")))
+            }
+            name if name.contains(':') => module::lookup_var(&self.scope, name),
+            // `$1`, `$2`... are the elements of `$args`.
+            name if name != "0" && name.bytes().all(|b| b.is_ascii_digit()) => {
+                let n: usize = name.parse().ok()?;
+                match self.scope.get("args")?.value {
+                    Value::List(args) => args.get(n.checked_sub(1)?).cloned(),
+                    _ => None,
+                }
+            }
+            name => self.scope.get(name).map(|var| var.value),
+        }
+    }
+
+    /// The positional arguments, the elements of `$args`.
+    fn positional_args(&self) -> Vec<Value> {
+        match self.scope.get("args").map(|var| var.value) {
+            Some(Value::List(args)) => args,
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Is a word `$@` or `"$@"`, which spread the positional arguments into
+/// separate values.
+fn is_all_args(word: &Word) -> bool {
+    let part = match &*word.parts {
+        [WordPart {
+            kind: WordPartKind::DoubleQuoted(parts),
+            ..
+        }] => match &**parts {
+            [part] => part,
+            _ => return false,
+        },
+        [part] => part,
+        _ => return false,
+    };
+    match &part.kind {
+        WordPartKind::Param(param) => {
+            param.name == "@" && param.indices.is_empty() && param.op.is_none()
+        }
+        _ => false,
+    }
+}
+
+/// Does a word contain unquoted chars with a special meaning in patterns.
+fn is_pattern(word: &Word) -> bool {
+    word.parts.iter().any(|part| match &part.kind {
+        WordPartKind::Bare(s) => glob::has_meta(s),
+        _ => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::eval::testing::output;
+
+    #[test]
+    fn positional_args() {
+        let code = "let args = [a 'b c' d]; echo $# $1 / $3 / \"[$4]\"";
+        assert_eq!(output(code).unwrap(), "3 a / d / []");
+
+        // `$@` spreads into a value for each argument, `$*` is one string.
+        let code =
+            "let args = [a 'b c']; for x in $@ { echo \"<$x>\" }; for x in $* { echo \"<$x>\" }";
+        assert_eq!(output(code).unwrap(), "<a>\n<b c>\n<a b c>");
+
+        let code = "fn f { echo $# $1 }; f x 'y z'; let args = []; echo $# \"[$1]\"";
+        assert_eq!(output(code).unwrap(), "2 x\n0 []");
+    }
+}
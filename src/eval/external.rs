@@ -0,0 +1,88 @@
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::eval::frame::Frame;
+use crate::eval::Status;
+
+/// Resolves a command name to an executable, searching `path` if the name does
+/// not contain a `/`.
+pub fn search_path(name: &str, path: Option<&OsStr>) -> Option<PathBuf> {
+    if name.contains('/') {
+        return Some(PathBuf::from(name));
+    }
+
+    std::env::split_paths(path?)
+        .map(|dir| dir.join(name))
+        .find(|file| is_executable(file))
+}
+
+fn is_executable(file: &Path) -> bool {
+    match fs::metadata(file) {
+        Ok(meta) => meta.is_file() && meta.permissions().mode() & 0o111 != 0,
+        Err(_) => false,
+    }
+}
+
+/// Runs an external command to completion, with its standard input, output
+/// and error connected to the ports of the frame.
+///
+/// The environment of the command is exactly the exported variables. The
+/// pipes of process substitutions are inherited under the same descriptors,
+/// so the paths `/dev/fd/N` given as arguments refer to them.
+///
+/// The command is part of the job of the frame, if it has one.
+pub async fn run(frame: &Frame, name: &str, file: &Path, args: &[String]) -> io::Result<Status> {
+    let mut cmd = command(frame, name, file, args)?;
+    let jobs = &frame.globals.jobs;
+    let pid = jobs.spawn(&mut cmd, frame.job)?;
+    Ok(jobs.wait_pid(pid).await)
+}
+
+/// Replaces the shell with an external command, only returning if it cannot
+/// be executed.
+pub fn exec(frame: &Frame, name: &str, file: &Path, args: &[String]) -> io::Error {
+    match command(frame, name, file, args) {
+        Ok(mut cmd) => frame.globals.jobs.exec(&mut cmd),
+        Err(err) => err,
+    }
+}
+
+fn command(frame: &Frame, name: &str, file: &Path, args: &[String]) -> io::Result<Command> {
+    let ports = &frame.ports;
+    let mut cmd = Command::new(file);
+    cmd.arg0(name)
+        .args(args)
+        .env_clear()
+        .envs(frame.scope.exports())
+        .stdin(ports.to_stdio(0)?)
+        .stdout(ports.to_stdio(1)?)
+        .stderr(ports.to_stdio(2)?);
+
+    let fds: Vec<RawFd> = frame
+        .pipes
+        .iter()
+        .map(|pipe| pipe.file().as_raw_fd())
+        .collect();
+    if !fds.is_empty() {
+        // Only the child's descriptors are changed, after the fork.
+        unsafe {
+            cmd.pre_exec(move || {
+                for &fd in &fds {
+                    let flags = libc::fcntl(fd, libc::F_GETFD);
+                    if flags < 0 || libc::fcntl(fd, libc::F_SETFD, flags & !libc::FD_CLOEXEC) < 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                Ok(())
+            });
+        }
+    }
+
+    Ok(cmd)
+}
@@ -0,0 +1,574 @@
+use std::ffi::OsStr;
+use std::fs::File;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use futures::future::{self, BoxFuture, Either, FutureExt};
+
+use crate::eval::builtins::{self, Call};
+use crate::eval::edit::SharedEditState;
+use crate::eval::external;
+use crate::eval::job::Jobs;
+use crate::eval::module;
+use crate::eval::port::{self, Port, Ports, ValueReader, ValueWriter};
+use crate::eval::scope::Scope;
+use crate::eval::value::{Closure, Value};
+use crate::eval::{Error, ErrorKind, Result, Status};
+use crate::parse::ast::*;
+use crate::parse::{Source, Span};
+
+/// The deepest blocks may be nested while evaluating, so runaway recursion
+/// fails rather than overflowing the stack.
+pub const MAX_DEPTH: usize = 1000;
+
+/// Shell options.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Options {
+    /// The status of a pipeline is the last failing status of any command,
+    /// rather than the status of the last command.
+    pub pipefail: bool,
+    /// The most iterations a `while` or `until` loop may run, so a runaway
+    /// script fails rather than hangs. Unlimited by default.
+    pub max_iterations: Option<usize>,
+}
+
+/// State shared by all frames.
+#[derive(Debug, Default)]
+pub struct Globals {
+    pub options: Mutex<Options>,
+    pub jobs: Arc<Jobs>,
+    /// The directory stack of `pushd` and `popd`, with the top last.
+    pub dirs: Mutex<Vec<PathBuf>>,
+    /// Modules loaded by `use`.
+    pub modules: module::Cache,
+    pub edit: SharedEditState,
+}
+
+/// The context code is evaluated in.
+///
+/// Each command in a pipeline is evaluated concurrently in its own frame.
+#[derive(Clone, Debug)]
+pub struct Frame {
+    pub globals: Arc<Globals>,
+    pub scope: Arc<Scope>,
+    pub ports: Ports,
+    /// Values from the previous command of a pipeline.
+    pub value_in: Option<ValueReader>,
+    /// Values to the next command of a pipeline.
+    pub value_out: Option<ValueWriter>,
+    /// Exit status of the last command.
+    pub status: Status,
+    /// Exit statuses of each command in the last pipeline.
+    pub pipestatus: Vec<Status>,
+    /// Number of blocks being evaluated.
+    pub depth: usize,
+    /// The source being evaluated.
+    pub src: Option<Arc<Source>>,
+    /// Pipes to process substitutions in the command being evaluated, open
+    /// until the command finishes.
+    pub pipes: Vec<Port>,
+    /// Whether the status of the command being evaluated is tested, by a
+    /// condition or `&&` and `||`, in which case failing commands do not raise
+    /// exceptions.
+    pub tested: bool,
+    /// The job processes started by the frame are part of.
+    pub job: Option<usize>,
+}
+
+impl Frame {
+    pub fn new(globals: Arc<Globals>, scope: Arc<Scope>, ports: Ports) -> Frame {
+        Frame {
+            globals,
+            scope,
+            ports,
+            value_in: None,
+            value_out: None,
+            status: Status::SUCCESS,
+            pipestatus: Vec::new(),
+            depth: 0,
+            src: None,
+            pipes: Vec::new(),
+            tested: false,
+            job: None,
+        }
+    }
+
+    pub fn options(&self) -> Options {
+        *self.globals.options.lock().unwrap()
+    }
+
+    pub fn set_options<F>(&self, f: F)
+    where
+        F: FnOnce(&mut Options),
+    {
+        f(&mut self.globals.options.lock().unwrap());
+    }
+
+    pub fn eval_chunk<'a>(&'a mut self, chunk: &'a Chunk) -> BoxFuture<'a, Result<Status>> {
+        async move {
+            let mut status = Status::SUCCESS;
+            for stmt in &chunk.stmts {
+                status = self.eval_stmt(stmt).await?;
+            }
+            Ok(status)
+        }
+        .boxed()
+    }
+
+    pub fn eval_stmt<'a>(&'a mut self, stmt: &'a Stmt) -> BoxFuture<'a, Result<Status>> {
+        async move {
+            if stmt.background {
+                return self.eval_background(stmt);
+            }
+
+            // Only the status of the last pipeline is not tested by `&&` or
+            // `||`.
+            let mut status = self.eval_tested(&stmt.first, !stmt.rest.is_empty()).await?;
+
+            for (i, (op, pipeline)) in stmt.rest.iter().enumerate() {
+                let run = match op {
+                    LogicOp::And => status.is_success(),
+                    LogicOp::Or => !status.is_success(),
+                };
+
+                if run {
+                    let tested = i + 1 < stmt.rest.len();
+                    status = self.eval_tested(pipeline, tested).await?;
+                }
+            }
+
+            Ok(status)
+        }
+        .boxed()
+    }
+
+    /// Starts a statement as a job in the background, evaluated concurrently
+    /// with the rest of the code.
+    fn eval_background(&mut self, stmt: &Stmt) -> Result<Status> {
+        let jobs = Arc::clone(&self.globals.jobs);
+        let id = jobs.add(&self.stmt_text(stmt), false);
+
+        let mut frame = self.clone();
+        frame.job = Some(id);
+        frame.value_in = None;
+
+        // Without job control, background jobs would compete with the shell
+        // for input from the terminal.
+        if !jobs.has_control() {
+            let null = File::open("/dev/null").map_err(|err| {
+                Error::new(ErrorKind::Open("/dev/null".to_owned(), err), stmt.span)
+            })?;
+            frame.ports.set(0, Some(Port::new(null)));
+        }
+
+        let stmt = Stmt {
+            background: false,
+            ..stmt.clone()
+        };
+        if let Err(err) = jobs.reserve(id) {
+            jobs.remove(id);
+            return Err(Error::new(ErrorKind::Io(err), stmt.span));
+        }
+        let task_jobs = Arc::clone(&jobs);
+        tokio::spawn(async move {
+            // The code stops where it is if the leader of the job is killed.
+            let killed = task_jobs.wait_killed(id).boxed();
+            let result = match future::select(frame.eval_stmt(&stmt), killed).await {
+                Either::Left((result, _)) => result,
+                Either::Right((status, _)) => Ok(status),
+            };
+            if let Some(err) = task_jobs.finish(id, result) {
+                eprintln!("jsh: {}", err);
+            }
+        });
+
+        if jobs.has_control() {
+            eprintln!("[{}]", id);
+        }
+        Ok(Status::SUCCESS)
+    }
+
+    /// The code of a statement, describing it as a job.
+    pub fn stmt_text(&self, stmt: &Stmt) -> String {
+        let code = self
+            .src
+            .as_ref()
+            .and_then(|src| src.code.get(stmt.span.start..stmt.span.end))
+            .unwrap_or_default();
+        code.trim().trim_end_matches('&').trim_end().to_owned()
+    }
+
+    /// Evaluates a statement whose status is tested, such as the condition of
+    /// an `if`.
+    pub async fn eval_condition(&mut self, stmt: &Stmt) -> Result<Status> {
+        let tested = std::mem::replace(&mut self.tested, true);
+        let result = self.eval_stmt(stmt).await;
+        self.tested = tested;
+        result
+    }
+
+    async fn eval_tested(&mut self, pipeline: &Pipeline, tested: bool) -> Result<Status> {
+        if !tested {
+            return self.eval_pipeline(pipeline).await;
+        }
+
+        let tested = std::mem::replace(&mut self.tested, true);
+        let result = self.eval_pipeline(pipeline).await;
+        self.tested = tested;
+        result
+    }
+
+    async fn eval_pipeline(&mut self, pipeline: &Pipeline) -> Result<Status> {
+        let statuses = match &*pipeline.commands {
+            [command] => vec![self.eval_command(command).await?],
+            commands => self.eval_stages(commands, pipeline.span).await?,
+        };
+
+        let last = statuses.len() - 1;
+        let stage = if self.options().pipefail {
+            statuses
+                .iter()
+                .rposition(|status| !status.is_success())
+                .unwrap_or(last)
+        } else {
+            last
+        };
+        let status = statuses[stage];
+
+        self.status = status;
+        self.pipestatus = statuses;
+
+        // A failing last command has raised an exception already, but under
+        // `pipefail` the status may be that of an earlier command.
+        if !status.is_success() && !self.tested {
+            let command = &pipeline.commands[stage];
+            let name = self.command_name(command);
+            return Err(Error::new(ErrorKind::Failed(status, name), command.span));
+        }
+        Ok(status)
+    }
+
+    /// The name of a command in errors, the code of its first word if it is a
+    /// simple command.
+    fn command_name(&self, command: &Command) -> String {
+        let span = match &command.kind {
+            CommandKind::Simple { words, .. } if !words.is_empty() => words[0].span,
+            _ => command.span,
+        };
+        self.src
+            .as_ref()
+            .and_then(|src| src.code.get(span.start..span.end))
+            .unwrap_or_default()
+            .trim()
+            .to_owned()
+    }
+
+    /// Runs the commands of a pipeline concurrently, each connected to the next
+    /// by a pipe.
+    async fn eval_stages(&mut self, commands: &[Command], span: Span) -> Result<Vec<Status>> {
+        let mut stages = Vec::with_capacity(commands.len());
+        let mut stdin = self.ports.get(0).cloned();
+        let mut value_in = self.value_in.clone();
+
+        for (i, command) in commands.iter().enumerate() {
+            let mut frame = self.clone();
+            frame.ports.set(0, stdin.take());
+            frame.value_in = value_in.take();
+            // Only the status of the pipeline can raise an exception, which is
+            // that of the last command unless `pipefail` is set.
+            if i + 1 < commands.len() {
+                frame.tested = true;
+            }
+
+            // Commands are connected by both a pipe for bytes and a channel for
+            // values.
+            if i + 1 < commands.len() {
+                let (r, w) = Port::pipe().map_err(|err| Error::new(ErrorKind::Pipe(err), span))?;
+                frame.ports.set(1, Some(w));
+                stdin = Some(r);
+
+                let (r, w) = port::value_channel();
+                frame.value_out = Some(w);
+                value_in = Some(r);
+            }
+
+            // The frame is dropped as soon as the command finishes, closing its
+            // ends of the pipes.
+            stages.push(async move { frame.eval_command(command).await });
+        }
+
+        let results = future::join_all(stages).await;
+        let last = results.len() - 1;
+
+        let mut statuses = Vec::with_capacity(results.len());
+        let mut first_err = None;
+
+        for (i, result) in results.into_iter().enumerate() {
+            match result {
+                Ok(status) => statuses.push(status),
+                // Commands that stop early because the next command stopped
+                // reading are not errors.
+                Err(err) if i < last && err.is_broken_pipe() => statuses.push(err.status()),
+                Err(err) => {
+                    statuses.push(err.status());
+                    first_err.get_or_insert(err);
+                }
+            }
+        }
+
+        match first_err {
+            Some(err) => Err(err),
+            None => Ok(statuses),
+        }
+    }
+
+    async fn eval_command(&mut self, command: &Command) -> Result<Status> {
+        let pipes = self.pipes.len();
+
+        let result = if command.redirs.is_empty() {
+            self.eval_command_kind(command).await
+        } else if is_exec_alone(command) {
+            // The redirections of `exec` without a command apply to the rest
+            // of the code.
+            self.eval_redirected(command).await
+        } else {
+            // Redirections only apply for the duration of the command.
+            let saved = self.ports.clone();
+            let result = self.eval_redirected(command).await;
+            self.ports = saved;
+            result
+        };
+
+        self.pipes.truncate(pipes);
+        result
+    }
+
+    async fn eval_redirected(&mut self, command: &Command) -> Result<Status> {
+        for redir in &command.redirs {
+            self.apply_redir(redir).await?;
+        }
+        self.eval_command_kind(command).await
+    }
+
+    async fn eval_command_kind(&mut self, command: &Command) -> Result<Status> {
+        let status = match &command.kind {
+            CommandKind::Simple { words, opts } => {
+                self.eval_simple(words, opts, command.span).await?
+            }
+            CommandKind::Block(chunk) => self.eval_block(chunk).await?,
+            CommandKind::If {
+                branches,
+                else_body,
+            } => self.eval_if(branches, else_body.as_ref()).await?,
+            CommandKind::While { cond, body, until } => {
+                self.eval_while(cond, body, *until, command.span).await?
+            }
+            CommandKind::For { var, items, body } => self.eval_for(var, items, body).await?,
+            CommandKind::Try {
+                body,
+                catch,
+                finally,
+            } => {
+                self.eval_try(body, catch.as_ref(), finally.as_ref())
+                    .await?
+            }
+            CommandKind::Fn { name, lambda } => {
+                let closure = Closure {
+                    lambda: Arc::clone(lambda),
+                    scope: Arc::clone(&self.scope),
+                    src: self.src.clone(),
+                };
+                self.scope.declare_fn(name, Arc::new(closure));
+                Status::SUCCESS
+            }
+        };
+
+        self.status = status;
+        Ok(status)
+    }
+
+    /// Evaluates a chunk in a new scope.
+    pub async fn eval_block(&mut self, chunk: &Chunk) -> Result<Status> {
+        let scope = Scope::child(&self.scope);
+        self.eval_in_scope(chunk, scope).await
+    }
+
+    /// Evaluates a chunk in the given scope.
+    pub async fn eval_in_scope(&mut self, chunk: &Chunk, scope: Arc<Scope>) -> Result<Status> {
+        if self.depth >= MAX_DEPTH {
+            return Err(Error::new(ErrorKind::TooDeep, chunk.span));
+        }
+
+        let parent = std::mem::replace(&mut self.scope, scope);
+        self.depth += 1;
+        let result = self.eval_chunk(chunk).await;
+        self.depth -= 1;
+        self.scope = parent;
+
+        result
+    }
+
+    /// Evaluates a simple command, raising an exception if it fails and its
+    /// status is not tested.
+    async fn eval_simple(&mut self, words: &[Word], opts: &[Opt], span: Span) -> Result<Status> {
+        let mut args = self.expand_words(words).await?;
+
+        let mut opt_values = Vec::with_capacity(opts.len());
+        for opt in opts {
+            opt_values.push((opt.name.clone(), self.expand_word(&opt.value).await?));
+        }
+
+        if args.is_empty() {
+            return Ok(Status::SUCCESS);
+        }
+
+        let head = args.remove(0);
+        let command = head.to_string();
+        let status = self.run_simple(head, args, opt_values, span).await?;
+
+        if !status.is_success() && !self.tested {
+            return Err(Error::new(ErrorKind::Failed(status, command), span));
+        }
+        Ok(status)
+    }
+
+    async fn run_simple(
+        &mut self,
+        head: Value,
+        args: Vec<Value>,
+        opt_values: Vec<(String, Value)>,
+        span: Span,
+    ) -> Result<Status> {
+        // Functions are looked up before builtins and external commands.
+        let name = match head {
+            Value::Str(name) => name,
+            Value::Fn(closure) => return self.call(&closure, args, opt_values, span).await,
+            value => return Err(Error::new(ErrorKind::Type("a command", value.kind()), span)),
+        };
+        if let Some(closure) = self.scope.get_fn(&name) {
+            return self.call(&closure, args, opt_values, span).await;
+        }
+        if let Some(closure) = module::lookup_fn(&self.scope, &name) {
+            return self.call(&closure, args, opt_values, span).await;
+        }
+
+        if let Some(builtin) = builtins::lookup(&name) {
+            let call = Call {
+                name,
+                args,
+                opts: opt_values,
+                span,
+            };
+            return builtin(self, call).await;
+        }
+
+        if let Some((opt, _)) = opt_values.first() {
+            return Err(Error::new(ErrorKind::UnknownOpt(opt.clone()), span));
+        }
+
+        let mut str_args = Vec::with_capacity(args.len());
+        for arg in args {
+            match arg {
+                Value::Str(arg) => str_args.push(arg),
+                arg => return Err(Error::new(ErrorKind::Type("a string", arg.kind()), span)),
+            }
+        }
+
+        let file = match self.search_path(&name) {
+            Some(file) => file,
+            None => return Err(Error::new(ErrorKind::CommandNotFound(name), span)),
+        };
+
+        external::run(self, &name, &file, &str_args)
+            .await
+            .map_err(|err| Error::new(ErrorKind::Exec(name, err), span))
+    }
+
+    /// Resolves a command name to an executable in `$PATH`.
+    pub fn search_path(&self, name: &str) -> Option<PathBuf> {
+        let path = self.scope.get("PATH").map(|var| var.value);
+        let path = path.as_ref().and_then(Value::as_str).map(OsStr::new);
+        external::search_path(name, path)
+    }
+
+    /// Outputs a value, to the next command of a pipeline if there is one,
+    /// otherwise to the standard output as a line.
+    pub async fn write_value(&self, value: Value) -> std::io::Result<()> {
+        match &self.value_out {
+            Some(out) => out.send(value).await,
+            None => self.write_out(format!("{}\n", value).into_bytes()).await,
+        }
+    }
+
+    /// Reads a value from the previous command of a pipeline, or `None` if
+    /// there are no more values.
+    pub async fn read_value(&self) -> Option<Value> {
+        match &self.value_in {
+            Some(input) => input.recv().await,
+            None => None,
+        }
+    }
+
+    /// Writes to the standard output.
+    pub async fn write_out(&self, buf: Vec<u8>) -> std::io::Result<()> {
+        self.write_fd(1, buf).await
+    }
+
+    /// Writes to the standard error.
+    pub async fn write_err(&self, buf: Vec<u8>) -> std::io::Result<()> {
+        self.write_fd(2, buf).await
+    }
+
+    async fn write_fd(&self, fd: usize, buf: Vec<u8>) -> std::io::Result<()> {
+        match self.ports.get(fd) {
+            Some(port) => port.write_all(buf).await,
+            None => Err(std::io::Error::from_raw_os_error(libc::EBADF)),
+        }
+    }
+}
+
+/// Is the command `exec` without arguments, only with redirections.
+fn is_exec_alone(command: &Command) -> bool {
+    match &command.kind {
+        CommandKind::Simple { words, opts } => match &**words {
+            [word] => opts.is_empty() && word.as_bare() == Some("exec"),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::eval::testing::output;
+    use crate::eval::ErrorKind;
+
+    #[test]
+    fn pipelines() {
+        assert_eq!(output("echo a b | tr a-z A-Z | cat").unwrap(), "A B");
+        assert_eq!(output("echo x | { read y; echo got $y }").unwrap(), "got x");
+        assert_eq!(
+            output("put a b | each {|x| echo \"[$x]\" }").unwrap(),
+            "[a]\n[b]"
+        );
+        assert_eq!(output("seq 1 100000 | tail -n 1").unwrap(), "100000");
+    }
+
+    #[test]
+    fn pipeline_status() {
+        // Only the status of the last command raises.
+        assert_eq!(
+            output("false | true; echo $? $pipestatus").unwrap(),
+            "0 1 0"
+        );
+        let err = output("true | false").unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::Failed(..)), "{:?}", err);
+
+        let code = "sh -c 'exit 3' | false | true || echo no; echo $? $pipestatus";
+        assert_eq!(output(code).unwrap(), "0 3 1 0");
+        let code = "setopt pipefail; sh -c 'exit 3' | false | true || echo $? $pipestatus";
+        assert_eq!(output(code).unwrap(), "1 3 1 0");
+        let code = "setopt pipefail; unsetopt pipefail; false | true; echo $?";
+        assert_eq!(output(code).unwrap(), "0");
+    }
+}
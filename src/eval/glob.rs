@@ -0,0 +1,611 @@
+//! Filename expansion.
+//!
+//! Unquoted words may contain the wildcards `*`, matching any chars, `?`,
+//! matching a single char, and `[...]`, matching a char in a set such as
+//! `[abc]` or `[a-z]`, or not in it if it starts with `!` or `^`. A path
+//! segment that is only `**` matches any number of directories, so `**/*.rs`
+//! matches Rust files in the current directory and every directory below it,
+//! and a trailing `**` matches everything below a directory.
+//!
+//! A word starting with `[` is a list, so a pattern starting with a set is
+//! written as `./[ab]*`.
+//!
+//! Wildcards do not match a leading `.` in a file name, or enter hidden
+//! directories, unless the `.` is written literally.
+//!
+//! Brace expansion `{a,b}` expands to each alternative, whether or not files
+//! exist, and alternatives may themselves contain wildcards.
+//!
+//! Qualifiers written at the end of a pattern filter the matches:
+//!
+//! - `[type:dir]`, `[type:regular]` and `[type:symlink]` keep only
+//!   directories, regular files or symbolic links.
+//! - `[match-hidden]` lets wildcards match hidden files.
+//! - `[nomatch-ok]` expands the pattern to nothing if it matches nothing.
+//!
+//! A pattern that matches nothing is an error, rather than being passed on
+//! literally, so a typo never reaches a command as an argument. Quote the
+//! word to pass the wildcards to the command, or use `[nomatch-ok]` to allow
+//! an empty expansion.
+//!
+//! Patterns are matched on the blocking thread pool, so walking large trees
+//! does not stall the rest of the shell.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::eval::ErrorKind;
+
+/// Does a literal word contain any chars with a special meaning in patterns.
+pub fn has_meta(s: &str) -> bool {
+    s.contains(['*', '?', '[', '{'])
+}
+
+/// Escapes a string so it matches itself in a pattern.
+pub fn escape(s: &str, out: &mut String) {
+    for c in s.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '{' | '}' | ',' | '\\') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+}
+
+/// Expands a pattern, in which literal chars are escaped with `\`, to the
+/// paths it matches.
+pub async fn expand(pattern: &str) -> Result<Vec<String>, ErrorKind> {
+    let (body, quals) = split_qualifiers(pattern)?;
+    let alts = braces(body);
+
+    // Brace expansion alone does not need to look at the file system.
+    if quals == Qualifiers::default() && !alts.iter().any(|alt| has_wildcard(alt)) {
+        return Ok(alts.iter().map(|alt| unescape(alt)).collect());
+    }
+
+    let pattern = unescape(pattern);
+    let result = tokio::task::spawn_blocking(move || {
+        let mut paths = Vec::new();
+        let mut matched = false;
+
+        for alt in alts {
+            if quals == Qualifiers::default() && !has_wildcard(&alt) {
+                paths.push(unescape(&alt));
+                continue;
+            }
+
+            let mut matches = glob(&alt, &quals);
+            matches.sort();
+            matched |= !matches.is_empty();
+            paths.extend(matches);
+        }
+
+        if matched || quals.nomatch_ok {
+            Ok(paths)
+        } else {
+            Err(ErrorKind::NoMatch(pattern))
+        }
+    })
+    .await;
+
+    match result {
+        Ok(result) => result,
+        Err(err) => Err(ErrorKind::Io(io::Error::other(err))),
+    }
+}
+
+/// Filters applied to the matches of a pattern.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+struct Qualifiers {
+    file_type: Option<FileType>,
+    match_hidden: bool,
+    nomatch_ok: bool,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum FileType {
+    Dir,
+    Regular,
+    Symlink,
+}
+
+/// Splits the qualifiers from the end of a pattern.
+fn split_qualifiers(pattern: &str) -> Result<(&str, Qualifiers), ErrorKind> {
+    let mut quals = Qualifiers::default();
+    let mut body = pattern;
+
+    while body.ends_with(']') && !is_escaped(body, body.len() - 1) {
+        let open = match body.rfind('[') {
+            Some(open) if !is_escaped(body, open) => open,
+            _ => break,
+        };
+
+        match &body[open + 1..body.len() - 1] {
+            "type:dir" => quals.file_type = Some(FileType::Dir),
+            "type:regular" => quals.file_type = Some(FileType::Regular),
+            "type:symlink" => quals.file_type = Some(FileType::Symlink),
+            "match-hidden" => quals.match_hidden = true,
+            "nomatch-ok" => quals.nomatch_ok = true,
+            qual if qual.starts_with("type:") => {
+                return Err(ErrorKind::BadQualifier(qual.to_owned()))
+            }
+            // A set of chars.
+            _ => break,
+        }
+        body = &body[..open];
+    }
+
+    Ok((body, quals))
+}
+
+/// Is the char at a byte index escaped by an odd number of backslashes.
+fn is_escaped(s: &str, i: usize) -> bool {
+    s[..i].bytes().rev().take_while(|&b| b == b'\\').count() % 2 == 1
+}
+
+/// Expands the first brace group with alternatives, and recursively the rest.
+fn braces(s: &str) -> Vec<String> {
+    let bytes = s.as_bytes();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'{' => {
+                if let Some((close, commas)) = brace_group(s, i) {
+                    let prefix = &s[..i];
+                    let suffix = &s[close + 1..];
+
+                    let mut bounds = vec![i];
+                    bounds.extend(commas);
+                    bounds.push(close);
+
+                    return bounds
+                        .windows(2)
+                        .flat_map(|w| {
+                            braces(&format!("{}{}{}", prefix, &s[w[0] + 1..w[1]], suffix))
+                        })
+                        .collect();
+                }
+                i += 1;
+            }
+            _ => i += 1,
+        }
+    }
+
+    vec![s.to_owned()]
+}
+
+/// Finds the closing brace of a group opened at `open`, and the commas
+/// separating its alternatives, if it has any.
+fn brace_group(s: &str, open: usize) -> Option<(usize, Vec<usize>)> {
+    let bytes = s.as_bytes();
+    let mut depth = 0;
+    let mut commas = Vec::new();
+    let mut i = open;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b'{' => depth += 1,
+            b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return if commas.is_empty() {
+                        None
+                    } else {
+                        Some((i, commas))
+                    };
+                }
+            }
+            b',' if depth == 1 => commas.push(i),
+            _ => {}
+        }
+        i += 1;
+    }
+
+    None
+}
+
+/// A pattern matched against strings rather than paths, as in `${name#pattern}`,
+/// in which `/` is an ordinary char.
+#[derive(Clone, Debug)]
+pub struct Pattern {
+    tokens: Vec<Token>,
+}
+
+impl Pattern {
+    pub fn new(pattern: &str) -> Pattern {
+        let tokens = tokenize(pattern)
+            .into_iter()
+            .map(|token| match token {
+                Token::Sep => Token::Char('/'),
+                token => token,
+            })
+            .collect();
+        Pattern { tokens }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    pub fn matches(&self, chars: &[char]) -> bool {
+        matches(&self.tokens, chars)
+    }
+}
+
+/// A char, or a wildcard, in a pattern.
+#[derive(Clone, Debug)]
+enum Token {
+    Char(char),
+    Sep,
+    /// `?`
+    Any,
+    /// `*`
+    Star,
+    /// `[...]`
+    Set {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+fn tokenize(pattern: &str) -> Vec<Token> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let token = match chars[i] {
+            '\\' if i + 1 < chars.len() => {
+                i += 1;
+                Token::Char(chars[i])
+            }
+            '/' => Token::Sep,
+            '?' => Token::Any,
+            '*' => Token::Star,
+            '[' => match parse_set(&chars, i) {
+                Some((token, close)) => {
+                    i = close;
+                    token
+                }
+                None => Token::Char('['),
+            },
+            c => Token::Char(c),
+        };
+        tokens.push(token);
+        i += 1;
+    }
+
+    tokens
+}
+
+/// Parses a set of chars opened at `open`, returning it and the index of the
+/// closing `]`.
+fn parse_set(chars: &[char], open: usize) -> Option<(Token, usize)> {
+    let mut i = open + 1;
+    let negated = matches!(chars.get(i), Some('!') | Some('^'));
+    if negated {
+        i += 1;
+    }
+
+    let mut ranges = Vec::new();
+    let first = i;
+    loop {
+        let mut c = *chars.get(i)?;
+        // A `]` first in the set is part of it.
+        if c == ']' && i > first {
+            return Some((Token::Set { negated, ranges }, i));
+        }
+        if c == '\\' {
+            i += 1;
+            c = *chars.get(i)?;
+        }
+
+        if chars.get(i + 1) == Some(&'-') && chars.get(i + 2).is_some_and(|&c| c != ']') {
+            let mut end = chars[i + 2];
+            i += 2;
+            if end == '\\' {
+                i += 1;
+                end = *chars.get(i)?;
+            }
+            ranges.push((c, end));
+        } else {
+            ranges.push((c, c));
+        }
+        i += 1;
+    }
+}
+
+fn has_wildcard(pattern: &str) -> bool {
+    tokenize(pattern)
+        .iter()
+        .any(|token| !matches!(token, Token::Char(_) | Token::Sep))
+}
+
+fn unescape(pattern: &str) -> String {
+    let mut out = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => out.extend(chars.next()),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// A path segment of a pattern.
+enum Segment {
+    Literal(String),
+    /// `**`
+    Recursive,
+    Wildcard(Vec<Token>),
+}
+
+/// Matches a pattern, without braces or qualifiers, against the file system.
+fn glob(pattern: &str, quals: &Qualifiers) -> Vec<String> {
+    let tokens = tokenize(pattern);
+    let absolute = matches!(tokens.first(), Some(Token::Sep));
+
+    let segments: Vec<Segment> = tokens
+        .split(|token| matches!(token, Token::Sep))
+        .skip(if absolute { 1 } else { 0 })
+        .map(|tokens| match tokens {
+            [Token::Star, Token::Star] => Segment::Recursive,
+            tokens if tokens.iter().all(|t| matches!(t, Token::Char(_))) => Segment::Literal(
+                tokens
+                    .iter()
+                    .filter_map(|t| match t {
+                        Token::Char(c) => Some(*c),
+                        _ => None,
+                    })
+                    .collect(),
+            ),
+            tokens => Segment::Wildcard(tokens.to_vec()),
+        })
+        .collect();
+
+    let mut paths = vec![if absolute { "/" } else { "" }.to_owned()];
+
+    for (i, segment) in segments.iter().enumerate() {
+        let last = i + 1 == segments.len();
+        let mut next = Vec::new();
+
+        for path in &paths {
+            match segment {
+                Segment::Literal(name) => next.push(join(path, name)),
+                Segment::Recursive => {
+                    if !last {
+                        next.push(path.clone());
+                    }
+                    walk(path, !last, quals.match_hidden, &mut next);
+                }
+                Segment::Wildcard(tokens) => {
+                    let hidden =
+                        quals.match_hidden || matches!(tokens.first(), Some(Token::Char('.')));
+                    for name in read_dir(path) {
+                        let chars: Vec<char> = name.chars().collect();
+                        if (hidden || !name.starts_with('.')) && matches(tokens, &chars) {
+                            let joined = join(path, &name);
+                            if last || is_dir(&joined) {
+                                next.push(joined);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        paths = next;
+    }
+
+    paths.retain(|path| {
+        let path = Path::new(path);
+        match quals.file_type {
+            Some(FileType::Dir) => path.is_dir(),
+            Some(FileType::Regular) => path.is_file(),
+            Some(FileType::Symlink) => {
+                fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_symlink())
+            }
+            None => fs::symlink_metadata(path).is_ok(),
+        }
+    });
+    paths
+}
+
+/// Adds every path below a directory, or only directories, to `out`.
+///
+/// Symbolic links to directories are not followed, so links cannot form a
+/// cycle.
+fn walk(dir: &str, dirs_only: bool, hidden: bool, out: &mut Vec<String>) {
+    for name in read_dir(dir) {
+        if !hidden && name.starts_with('.') {
+            continue;
+        }
+
+        let path = join(dir, &name);
+        let is_dir = fs::symlink_metadata(&path).is_ok_and(|m| m.is_dir());
+        if is_dir || !dirs_only {
+            out.push(path.clone());
+        }
+        if is_dir {
+            walk(&path, dirs_only, hidden, out);
+        }
+    }
+}
+
+/// The sorted names in a directory, or none if it cannot be read.
+fn read_dir(dir: &str) -> Vec<String> {
+    let dir = if dir.is_empty() { "." } else { dir };
+    let mut names: Vec<String> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    names.sort();
+    names
+}
+
+fn is_dir(path: &str) -> bool {
+    Path::new(path).is_dir()
+}
+
+fn join(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        name.to_owned()
+    } else if dir.ends_with('/') {
+        format!("{}{}", dir, name)
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
+/// Matches a name against the tokens of a path segment.
+fn matches(tokens: &[Token], name: &[char]) -> bool {
+    let (mut t, mut n) = (0, 0);
+    // Where to resume after the last `*` if the rest fails to match.
+    let mut backtrack = None;
+
+    while n < name.len() {
+        let matched = match tokens.get(t) {
+            Some(Token::Star) => {
+                backtrack = Some((t, n));
+                t += 1;
+                continue;
+            }
+            Some(Token::Char(c)) => *c == name[n],
+            Some(Token::Any) => true,
+            Some(Token::Set { negated, ranges }) => {
+                ranges
+                    .iter()
+                    .any(|&(lo, hi)| lo <= name[n] && name[n] <= hi)
+                    != *negated
+            }
+            Some(Token::Sep) | None => false,
+        };
+
+        if matched {
+            t += 1;
+            n += 1;
+        } else if let Some((star, pos)) = backtrack {
+            t = star + 1;
+            n = pos + 1;
+            backtrack = Some((star, pos + 1));
+        } else {
+            return false;
+        }
+    }
+
+    tokens[t..].iter().all(|token| matches!(token, Token::Star))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_match(pattern: &str, s: &str) -> bool {
+        let chars: Vec<char> = s.chars().collect();
+        Pattern::new(pattern).matches(&chars)
+    }
+
+    #[test]
+    fn patterns() {
+        assert!(is_match("*.rs", "main.rs"));
+        assert!(!is_match("*.rs", "main.rsx"));
+        assert!(is_match("a*b*c", "axxbyyc"));
+        assert!(!is_match("a*b*c", "axxbyy"));
+        assert!(is_match("?é?", "aéb"));
+        assert!(is_match("[a-c]x", "bx"));
+        assert!(!is_match("[!a-c]x", "bx"));
+        assert!(is_match("[^a-c]x", "dx"));
+        assert!(is_match("*/", "usr/lib/"));
+        assert!(is_match(r"\*", "*"));
+        assert!(!is_match(r"\*", "a"));
+        assert!(is_match("", ""));
+        assert!(Pattern::new("").is_empty());
+    }
+
+    #[test]
+    fn brace_expansion() {
+        assert_eq!(braces("a{b,c}d"), ["abd", "acd"]);
+        assert_eq!(braces("{a,b}{1,2}"), ["a1", "a2", "b1", "b2"]);
+        assert_eq!(braces("x{a,{b,c}}"), ["xa", "xb", "xc"]);
+        assert_eq!(braces("{a}"), ["{a}"]);
+        assert_eq!(braces(r"\{a,b}"), [r"\{a,b}"]);
+        assert_eq!(braces("{a,}"), ["a", ""]);
+    }
+
+    #[test]
+    fn qualifiers() {
+        let (body, quals) = split_qualifiers("*[type:dir][nomatch-ok]").unwrap();
+        assert_eq!(body, "*");
+        assert_eq!(quals.file_type, Some(FileType::Dir));
+        assert!(quals.nomatch_ok && !quals.match_hidden);
+
+        let (body, quals) = split_qualifiers("a[bc]").unwrap();
+        assert_eq!(body, "a[bc]");
+        assert_eq!(quals, Qualifiers::default());
+
+        assert!(matches!(
+            split_qualifiers("*[type:fifo]"),
+            Err(ErrorKind::BadQualifier(qual)) if qual == "type:fifo"
+        ));
+    }
+
+    #[test]
+    fn escaping() {
+        let mut escaped = String::new();
+        escape("a*b{c,d}", &mut escaped);
+        assert_eq!(escaped, r"a\*b\{c\,d\}");
+        assert!(!has_wildcard(&escaped));
+        assert_eq!(unescape(&escaped), "a*b{c,d}");
+        assert!(has_meta("a[b"));
+        assert!(!has_meta("a/b"));
+    }
+
+    #[test]
+    fn file_system() {
+        let dir = std::env::temp_dir().join(format!("jsh-glob-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for file in &["a.rs", "b.txt", ".hidden.rs", "sub/c.rs", "sub/deep/d.rs"] {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+
+        let root = dir.to_str().unwrap();
+        let glob = |pattern: &str, quals: Qualifiers| {
+            let mut paths: Vec<String> = glob(&format!("{}/{}", root, pattern), &quals)
+                .into_iter()
+                .map(|path| path[root.len() + 1..].to_owned())
+                .collect();
+            paths.sort();
+            paths
+        };
+        let hidden = Qualifiers {
+            match_hidden: true,
+            ..Qualifiers::default()
+        };
+        let dirs = Qualifiers {
+            file_type: Some(FileType::Dir),
+            ..Qualifiers::default()
+        };
+
+        assert_eq!(glob("*.rs", Qualifiers::default()), ["a.rs"]);
+        assert_eq!(glob("*.rs", hidden), [".hidden.rs", "a.rs"]);
+        assert_eq!(glob(".*.rs", Qualifiers::default()), [".hidden.rs"]);
+        assert_eq!(glob("*/*.rs", Qualifiers::default()), ["sub/c.rs"]);
+        assert_eq!(
+            glob("**/*.rs", Qualifiers::default()),
+            ["a.rs", "sub/c.rs", "sub/deep/d.rs"]
+        );
+        assert_eq!(glob("**", dirs), ["sub", "sub/deep"]);
+        assert_eq!(glob("*.none", Qualifiers::default()), Vec::<String>::new());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,371 @@
+//! The history of commands read interactively, kept in a log shared by the
+//! shells of a user.
+//!
+//! Each command is appended to the log as a line of its own once it has
+//! finished, with the log locked so the lines of shells writing at once do not
+//! interleave. A line cut short by a crash is skipped when the log is read,
+//! and the next line written starts on a line of its own.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::eval::scope::Scope;
+use crate::eval::value::Value;
+use crate::eval::Status;
+
+/// A command of the history.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Entry {
+    pub cmd: String,
+    /// When the command was started.
+    pub time: SystemTime,
+    /// The working directory the command was started in.
+    pub cwd: PathBuf,
+    pub status: Status,
+    pub duration: Duration,
+}
+
+impl Entry {
+    /// The entry as a map, as seen by scripts.
+    ///
+    /// Times are in seconds, the start since the Unix epoch.
+    pub fn to_value(&self) -> Value {
+        let time = self.time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let fields = [
+            ("cmd", self.cmd.clone()),
+            ("time", format_secs(time)),
+            ("cwd", self.cwd.to_string_lossy().into_owned()),
+            ("status", self.status.code().to_string()),
+            ("duration", format_secs(self.duration)),
+        ];
+        Value::Map(
+            fields
+                .iter()
+                .map(|(key, value)| ((*key).to_owned(), Value::Str(value.clone())))
+                .collect(),
+        )
+    }
+
+    /// The line of the entry in the log, with its fields separated by tabs.
+    fn to_line(&self) -> String {
+        let time = self.time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        format!(
+            "{}\t{}\t{}\t{}\t{}\n",
+            format_secs(time),
+            format_secs(self.duration),
+            self.status.code(),
+            escape(&self.cwd.to_string_lossy()),
+            escape(&self.cmd),
+        )
+    }
+
+    fn from_line(line: &str) -> Option<Entry> {
+        let mut fields = line.split('\t');
+        let time = parse_secs(fields.next()?)?;
+        let duration = parse_secs(fields.next()?)?;
+        let status = Status::new(fields.next()?.parse().ok()?);
+        let cwd = PathBuf::from(unescape(fields.next()?)?);
+        let cmd = unescape(fields.next()?)?;
+        if fields.next().is_some() {
+            return None;
+        }
+
+        Some(Entry {
+            cmd,
+            time: UNIX_EPOCH + time,
+            cwd,
+            status,
+            duration,
+        })
+    }
+}
+
+/// The file of the history, `$JSH_HISTORY`, `$XDG_DATA_HOME/jsh/history` or
+/// `~/.local/share/jsh/history`.
+///
+/// An empty `$JSH_HISTORY` turns the history off.
+pub fn path(scope: &Scope) -> Option<PathBuf> {
+    let var = |name: &str| {
+        let var = scope.get(name)?;
+        var.value.as_str().map(PathBuf::from)
+    };
+
+    if let Some(file) = var("JSH_HISTORY") {
+        return Some(file).filter(|file| !file.as_os_str().is_empty());
+    }
+
+    let non_empty = |dir: PathBuf| Some(dir).filter(|dir| !dir.as_os_str().is_empty());
+    var("XDG_DATA_HOME")
+        .and_then(non_empty)
+        .or_else(|| {
+            var("HOME")
+                .and_then(non_empty)
+                .map(|home| home.join(".local/share"))
+        })
+        .map(|dir| dir.join("jsh").join("history"))
+}
+
+/// Appends an entry to the history.
+pub fn append(file: &Path, entry: &Entry) -> io::Result<()> {
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let mut log = OpenOptions::new()
+        .read(true)
+        .append(true)
+        .create(true)
+        .mode(0o600)
+        .open(file)?;
+    // Unlocked when the file is closed.
+    lock(&log)?;
+
+    let mut line = entry.to_line();
+    if !ends_with_newline(&mut log)? {
+        line.insert(0, '\n');
+    }
+    // The line is written at once, so it is never read half written.
+    log.write_all(line.as_bytes())
+}
+
+/// Reads the entries of the history, oldest first.
+pub fn read(file: &Path) -> io::Result<Vec<Entry>> {
+    let mut log = match File::open(file) {
+        Ok(log) => log,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+
+    let mut bytes = Vec::new();
+    log.read_to_end(&mut bytes)?;
+    let content = String::from_utf8_lossy(&bytes);
+
+    // The last line is being written or was cut short if it has no newline.
+    let complete = content.rfind('\n').map_or("", |end| &content[..end]);
+    Ok(complete.lines().filter_map(Entry::from_line).collect())
+}
+
+fn lock(file: &File) -> io::Result<()> {
+    loop {
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } == 0 {
+            return Ok(());
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+/// Whether a file is empty or ends with a newline.
+fn ends_with_newline(file: &mut File) -> io::Result<bool> {
+    if file.seek(SeekFrom::End(0))? == 0 {
+        return Ok(true);
+    }
+    file.seek(SeekFrom::End(-1))?;
+    let mut last = [0u8];
+    file.read_exact(&mut last)?;
+    Ok(last[0] == b'\n')
+}
+
+fn format_secs(duration: Duration) -> String {
+    format!("{}.{:03}", duration.as_secs(), duration.subsec_millis())
+}
+
+fn parse_secs(s: &str) -> Option<Duration> {
+    let (secs, millis) = s.split_once('.')?;
+    if millis.len() != 3 {
+        return None;
+    }
+    Some(Duration::from_secs(secs.parse().ok()?) + Duration::from_millis(millis.parse().ok()?))
+}
+
+/// Escapes the separators of a line, and `\`.
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unescape(s: &str) -> Option<String> {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        unescaped.push(match chars.next()? {
+            '\\' => '\\',
+            't' => '\t',
+            'n' => '\n',
+            'r' => '\r',
+            _ => return None,
+        });
+    }
+    Some(unescaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(cmd: &str) -> Entry {
+        Entry {
+            cmd: cmd.to_owned(),
+            time: UNIX_EPOCH + Duration::from_millis(1_600_000_000_123),
+            cwd: PathBuf::from("/home/user/my dir"),
+            status: Status::new(3),
+            duration: Duration::from_millis(1500),
+        }
+    }
+
+    /// A file in a directory of its own, removed when dropped.
+    struct TempFile {
+        dir: PathBuf,
+        file: PathBuf,
+    }
+
+    impl TempFile {
+        fn new(name: &str) -> TempFile {
+            let dir =
+                std::env::temp_dir().join(format!("jsh-history-{}-{}", std::process::id(), name));
+            let _ = fs::remove_dir_all(&dir);
+            let file = dir.join("nested").join("history");
+            TempFile { dir, file }
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    #[test]
+    fn line_format() {
+        let line = entry("echo hi").to_line();
+        assert_eq!(
+            line,
+            "1600000000.123\t1.500\t3\t/home/user/my dir\techo hi\n"
+        );
+        assert_eq!(Entry::from_line(line.trim_end()), Some(entry("echo hi")));
+    }
+
+    #[test]
+    fn escaping() {
+        let cmd = "if a {\n\techo 'a\\tb'\r\n}";
+        let line = entry(cmd).to_line();
+        assert_eq!(line.matches('\n').count(), 1);
+        assert_eq!(line.matches('\t').count(), 4);
+        assert_eq!(Entry::from_line(line.trim_end()), Some(entry(cmd)));
+
+        assert_eq!(unescape(&escape("a\\n")), Some("a\\n".to_owned()));
+        assert_eq!(unescape("a\\x"), None);
+        assert_eq!(unescape("a\\"), None);
+    }
+
+    #[test]
+    fn malformed_lines() {
+        let lines = [
+            "",
+            "1.000\t1.000\t0\t/",
+            "1.000\t1.000\t0\t/\tcmd\textra",
+            "1.0\t1.000\t0\t/\tcmd",
+            "x.000\t1.000\t0\t/\tcmd",
+            "1.000\t1.000\tx\t/\tcmd",
+            "1.000\t1.000\t0\t/\tbad\\q",
+        ];
+        for line in &lines {
+            assert_eq!(Entry::from_line(line), None, "{:?}", line);
+        }
+        assert!(Entry::from_line("1.000\t0.000\t0\t/\tcmd").is_some());
+    }
+
+    #[test]
+    fn append_and_read() {
+        let temp = TempFile::new("append");
+        assert_eq!(read(&temp.file).unwrap(), []);
+
+        append(&temp.file, &entry("first")).unwrap();
+        append(&temp.file, &entry("second\nline")).unwrap();
+
+        let cmds: Vec<_> = read(&temp.file)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.cmd)
+            .collect();
+        assert_eq!(cmds, ["first", "second\nline"]);
+    }
+
+    #[test]
+    fn truncated_line() {
+        let temp = TempFile::new("truncated");
+        append(&temp.file, &entry("first")).unwrap();
+
+        // A line cut short, as if the shell writing it crashed.
+        let line = entry("cut").to_line();
+        let mut log = OpenOptions::new().append(true).open(&temp.file).unwrap();
+        log.write_all(&line.as_bytes()[..line.len() / 2]).unwrap();
+        drop(log);
+
+        let cmds: Vec<_> = read(&temp.file)
+            .unwrap()
+            .into_iter()
+            .map(|e| e.cmd)
+            .collect();
+        assert_eq!(cmds, ["first"]);
+
+        // The next line is not joined to the cut one.
+        append(&temp.file, &entry("next")).unwrap();
+        let cmds: Vec<_> = read(&temp.file)
+            .unwrap()
+            .into_iter()
+            .map(|e| e.cmd)
+            .collect();
+        assert_eq!(cmds, ["first", "next"]);
+    }
+
+    #[test]
+    fn paths() {
+        let scope = Scope::default();
+        assert_eq!(path(&scope), None);
+
+        let set = |name: &str, value: &str| {
+            scope.declare(
+                name,
+                crate::eval::scope::Var {
+                    value: Value::Str(value.to_owned()),
+                    exported: false,
+                },
+            )
+        };
+
+        set("HOME", "/home/user");
+        assert_eq!(
+            path(&scope),
+            Some(PathBuf::from("/home/user/.local/share/jsh/history"))
+        );
+
+        set("XDG_DATA_HOME", "/data");
+        assert_eq!(path(&scope), Some(PathBuf::from("/data/jsh/history")));
+
+        set("JSH_HISTORY", "/tmp/hist");
+        assert_eq!(path(&scope), Some(PathBuf::from("/tmp/hist")));
+
+        set("JSH_HISTORY", "");
+        assert_eq!(path(&scope), None);
+    }
+}
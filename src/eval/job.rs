@@ -0,0 +1,1101 @@
+//! Jobs and job control.
+//!
+//! While the editor reads a line the terminal does not generate signals, so
+//! `Ctrl-C` and `Ctrl-Z` are read as keys. Otherwise, with job control, the
+//! shell ignores the signals generated by the terminal and gives it to the
+//! process group of the job in the foreground, so only that job is
+//! interrupted or stopped. Once the job stops or finishes, the shell takes the
+//! terminal back and restores its modes.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::os::unix::io::{FromRawFd, RawFd};
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+
+use termios::Termios;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::watch;
+
+use crate::eval::{Error, Result, Status};
+
+pub type Pid = libc::pid_t;
+
+/// Signals the shell ignores while job control is enabled, which are restored
+/// to their defaults in child processes.
+const JOB_SIGNALS: [libc::c_int; 5] = [
+    libc::SIGINT,
+    libc::SIGQUIT,
+    libc::SIGTSTP,
+    libc::SIGTTIN,
+    libc::SIGTTOU,
+];
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ProcState {
+    Running,
+    Stopped,
+    Exited(Status),
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum JobState {
+    Running,
+    Stopped,
+    Done(Status),
+}
+
+/// Code running concurrently with the shell, and the processes it started.
+///
+/// Each statement run at the prompt is a job, so it can be stopped and
+/// continued as a whole, as is each statement ending with `&`.
+#[derive(Debug)]
+pub struct Job {
+    pub id: usize,
+    pub text: String,
+    /// The process group of the processes, led by the first, or by the leader
+    /// of a job in the background.
+    pub pgid: Option<Pid>,
+    pub pids: Vec<Pid>,
+    /// The process standing for a job started in the background, for `$!`,
+    /// which exits once the code of the job finishes. Killing it kills the
+    /// job.
+    pub leader: Option<Pid>,
+    /// The pipe the leader waits to be closed on.
+    release: Option<File>,
+    /// The signal the leader was killed with, which kills any process of the
+    /// job.
+    killed: Option<libc::c_int>,
+    /// Whether the job has the terminal.
+    pub foreground: bool,
+    /// Whether the shell is waiting for the job at the prompt, in which case
+    /// it is not listed.
+    pub awaited: bool,
+    /// The status the code of the job finished with, `None` while it runs.
+    pub status: Option<Status>,
+    /// The error the code of the job failed with, kept for whoever awaits it.
+    error: Option<Error>,
+    /// Terminal modes of the job when it was stopped.
+    tmodes: Option<Termios>,
+    /// Whether the user has been told the job stopped.
+    notified: bool,
+}
+
+/// The terminal, while job control is enabled.
+#[derive(Debug)]
+struct Control {
+    tty: RawFd,
+    pgid: Pid,
+    tmodes: Termios,
+}
+
+#[derive(Debug, Default)]
+struct Table {
+    jobs: Vec<Job>,
+    procs: HashMap<Pid, ProcState>,
+    /// The leader of the last job started in the background, for `$!`.
+    last_background: Option<Pid>,
+    /// The leaders of removed jobs, forgotten once they exit.
+    orphans: Vec<Pid>,
+}
+
+/// The jobs of the shell, and the state of every process it started.
+///
+/// A single task reaps the processes whenever one changes state, signalled by
+/// `SIGCHLD`, and records their states, so any number of commands can wait for
+/// changes.
+#[derive(Debug)]
+pub struct Jobs {
+    table: Mutex<Table>,
+    control: Mutex<Option<Control>>,
+    changed: watch::Sender<()>,
+    changes: watch::Receiver<()>,
+    /// Whether the task reaping processes has been started.
+    reaper: Mutex<bool>,
+}
+
+impl Default for Jobs {
+    fn default() -> Jobs {
+        let (changed, changes) = watch::channel(());
+        Jobs {
+            table: Mutex::default(),
+            control: Mutex::default(),
+            changed,
+            changes,
+            reaper: Mutex::new(false),
+        }
+    }
+}
+
+impl Jobs {
+    /// Enables job control on a terminal, putting the shell in its own process
+    /// group in the foreground.
+    pub fn enable_control(&self, tty: RawFd) -> io::Result<()> {
+        unsafe {
+            // Wait to be put in the foreground if started in the background.
+            loop {
+                let pgid = libc::getpgrp();
+                let fg = libc::tcgetpgrp(tty);
+                if fg < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                if fg == pgid {
+                    break;
+                }
+                libc::kill(-pgid, libc::SIGTTIN);
+            }
+
+            ignore_signals();
+
+            // Fails if the shell already leads a session, which is fine.
+            let pid = libc::getpid();
+            libc::setpgid(pid, pid);
+            let pgid = libc::getpgrp();
+            if libc::tcsetpgrp(tty, pgid) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            // Children need the terminal after their standard input has been
+            // redirected.
+            let tty = libc::fcntl(tty, libc::F_DUPFD_CLOEXEC, 10);
+            if tty < 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            let tmodes = Termios::from_fd(tty)?;
+            *self.control.lock().unwrap() = Some(Control { tty, pgid, tmodes });
+        }
+
+        Ok(())
+    }
+
+    pub fn has_control(&self) -> bool {
+        self.control.lock().unwrap().is_some()
+    }
+
+    /// Adds a job, returning its id.
+    ///
+    /// A job `awaited` at the prompt has the terminal.
+    pub fn add(&self, text: &str, awaited: bool) -> usize {
+        let mut table = self.table.lock().unwrap();
+
+        let mut id = 1;
+        while table.jobs.iter().any(|job| job.id == id) {
+            id += 1;
+        }
+
+        table.jobs.push(Job {
+            id,
+            text: text.trim().to_owned(),
+            pgid: None,
+            pids: Vec::new(),
+            leader: None,
+            release: None,
+            killed: None,
+            foreground: awaited && self.has_control(),
+            awaited,
+            status: None,
+            error: None,
+            tmodes: None,
+            notified: false,
+        });
+        id
+    }
+
+    /// Starts the leader of a job in the background, returning its pid.
+    ///
+    /// The leader only waits for the code of the job to finish, so the job has
+    /// a pid, and a process group with job control, before it starts any
+    /// process.
+    pub fn reserve(self: &Arc<Self>, id: usize) -> io::Result<Pid> {
+        let control = self.has_control();
+        self.start_reaper()?;
+
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let [read, write] = fds;
+        let release = unsafe { File::from_raw_fd(write) };
+        let max_fd = match unsafe { libc::sysconf(libc::_SC_OPEN_MAX) } {
+            n if n > 0 => n as RawFd,
+            _ => 1024,
+        };
+
+        let pid = unsafe {
+            libc::fcntl(write, libc::F_SETFD, libc::FD_CLOEXEC);
+            let pid = libc::fork();
+            if pid == 0 {
+                lead(read, max_fd, control);
+            }
+            libc::close(read);
+            pid
+        };
+        if pid < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if control {
+            // Also done in the child, so the group exists before either runs.
+            unsafe { libc::setpgid(pid, pid) };
+        }
+
+        {
+            let mut table = self.table.lock().unwrap();
+            table.procs.insert(pid, ProcState::Running);
+            table.last_background = Some(pid);
+            if let Some(job) = table.jobs.iter_mut().find(|job| job.id == id) {
+                job.leader = Some(pid);
+                job.release = Some(release);
+                if control {
+                    job.pgid = Some(pid);
+                }
+            }
+        }
+
+        // The leader may have been killed before it was recorded.
+        self.reap();
+        Ok(pid)
+    }
+
+    /// The leader of the last job started in the background.
+    pub fn last_background_pid(&self) -> Option<Pid> {
+        self.table.lock().unwrap().last_background
+    }
+
+    pub fn remove(&self, id: usize) {
+        let mut table = self.table.lock().unwrap();
+        if let Some(i) = table.jobs.iter().position(|job| job.id == id) {
+            let job = table.jobs.remove(i);
+            let Table { procs, orphans, .. } = &mut *table;
+            forget(procs, orphans, &job);
+        }
+    }
+
+    pub fn text(&self, id: usize) -> Option<String> {
+        self.with_job(id, |job| job.text.clone())
+    }
+
+    /// Records the result the code of a job finished with.
+    ///
+    /// An error is kept for whoever awaits the job, otherwise it is returned
+    /// to be reported.
+    pub fn finish(&self, id: usize, result: Result<Status>) -> Option<Error> {
+        let status = match &result {
+            Ok(status) => *status,
+            Err(err) => err.status(),
+        };
+
+        let err = {
+            let mut table = self.table.lock().unwrap();
+            match table.jobs.iter_mut().find(|job| job.id == id) {
+                Some(job) => {
+                    job.status = Some(status);
+                    job.release = None;
+                    match result {
+                        Err(err) if job.awaited => {
+                            job.error = Some(err);
+                            None
+                        }
+                        result => result.err(),
+                    }
+                }
+                None => result.err(),
+            }
+        };
+
+        self.notify();
+        err
+    }
+
+    /// Removes a job, returning the result its code finished with, if it has.
+    pub fn take(&self, id: usize) -> Option<Result<Status>> {
+        let mut table = self.table.lock().unwrap();
+        let i = table.jobs.iter().position(|job| job.id == id)?;
+        let job = table.jobs.remove(i);
+        let Table { procs, orphans, .. } = &mut *table;
+        forget(procs, orphans, &job);
+
+        match (job.error, job.status) {
+            (Some(err), _) => Some(Err(err)),
+            (None, Some(status)) => Some(Ok(status)),
+            (None, None) => None,
+        }
+    }
+
+    /// The state of a job, or `None` if there is no such job.
+    pub fn state(&self, id: usize) -> Option<JobState> {
+        let table = self.table.lock().unwrap();
+        let job = table.jobs.iter().find(|job| job.id == id)?;
+        Some(job_state(&table, job))
+    }
+
+    /// The job a builtin such as `fg` acts on by default, the most recent one
+    /// not awaited at the prompt.
+    pub fn current(&self) -> Option<usize> {
+        let table = self.table.lock().unwrap();
+        table
+            .jobs
+            .iter()
+            .filter(|job| !job.awaited)
+            .map(|job| job.id)
+            .max()
+    }
+
+    /// The ids of the jobs not awaited at the prompt.
+    pub fn ids(&self) -> Vec<usize> {
+        let table = self.table.lock().unwrap();
+        table
+            .jobs
+            .iter()
+            .filter(|job| !job.awaited)
+            .map(|job| job.id)
+            .collect()
+    }
+
+    /// Formats a job as it is listed by `jobs`.
+    pub fn describe(&self, id: usize) -> Option<String> {
+        let table = self.table.lock().unwrap();
+        let job = table.jobs.iter().find(|job| job.id == id)?;
+        Some(describe(job, job_state(&table, job)))
+    }
+
+    /// Returns a line for each job that finished or stopped since the last
+    /// call, removing the finished jobs.
+    pub fn notifications(&self) -> Vec<String> {
+        let mut table = self.table.lock().unwrap();
+        let mut lines = Vec::new();
+
+        let states: Vec<JobState> = table
+            .jobs
+            .iter()
+            .map(|job| job_state(&table, job))
+            .collect();
+
+        for (job, state) in table.jobs.iter_mut().zip(&states) {
+            match state {
+                _ if job.awaited => {}
+                JobState::Done(_) => lines.push(describe(job, *state)),
+                JobState::Stopped if !job.notified => {
+                    job.notified = true;
+                    lines.push(describe(job, *state));
+                }
+                JobState::Stopped => {}
+                JobState::Running => job.notified = false,
+            }
+        }
+
+        remove_done(&mut table, states);
+        lines
+    }
+
+    /// Returns a line for each job as listed by `jobs`, removing the finished
+    /// jobs.
+    pub fn list(&self) -> Vec<String> {
+        let mut table = self.table.lock().unwrap();
+        let states: Vec<JobState> = table
+            .jobs
+            .iter()
+            .map(|job| job_state(&table, job))
+            .collect();
+
+        let mut lines = Vec::new();
+        for (job, state) in table.jobs.iter_mut().zip(&states) {
+            if !job.awaited {
+                job.notified = true;
+                lines.push(describe(job, *state));
+            }
+        }
+
+        remove_done(&mut table, states);
+        lines
+    }
+
+    /// Spawns a process, as part of a job if `job` is given.
+    ///
+    /// With job control, the processes of a job are put in their own process
+    /// group, which is given the terminal if the job is in the foreground.
+    pub fn spawn(self: &Arc<Self>, cmd: &mut Command, job: Option<usize>) -> io::Result<Pid> {
+        let tty = self
+            .control
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|control| control.tty);
+        let group = match (tty, job) {
+            (Some(tty), Some(id)) => {
+                let table = self.table.lock().unwrap();
+                table.jobs.iter().find(|job| job.id == id).map(|job| {
+                    let pgid = job.leader.and(job.pgid).or_else(|| live_pgid(&table, job));
+                    (tty, pgid.unwrap_or(0), job.foreground)
+                })
+            }
+            _ => None,
+        };
+
+        if let Some((tty, pgid, foreground)) = group {
+            // Also done in the child, so the process is in its group before it
+            // runs. The group may have exited since, in which case the process
+            // leads a new one.
+            unsafe {
+                cmd.pre_exec(move || {
+                    if libc::setpgid(0, pgid) < 0 {
+                        libc::setpgid(0, 0);
+                    }
+                    if foreground {
+                        libc::tcsetpgrp(tty, libc::getpgrp());
+                    }
+                    reset_signals();
+                    Ok(())
+                });
+            }
+        } else if tty.is_some() {
+            unsafe {
+                cmd.pre_exec(|| {
+                    reset_signals();
+                    Ok(())
+                });
+            }
+        }
+
+        self.start_reaper()?;
+        let child = cmd.spawn()?;
+        let pid = child.id() as Pid;
+
+        {
+            let mut table = self.table.lock().unwrap();
+            table.procs.insert(pid, ProcState::Running);
+
+            if let Some(job) = job.and_then(|id| table.jobs.iter_mut().find(|job| job.id == id)) {
+                job.pids.push(pid);
+                if let Some(signal) = job.killed {
+                    unsafe { libc::kill(pid, signal) };
+                }
+
+                if let Some((tty, pgid, foreground)) = group {
+                    // Fails once the process has executed its command, having
+                    // already joined its group.
+                    let pgid = unsafe {
+                        if pgid == 0 || libc::setpgid(pid, pgid) < 0 {
+                            libc::setpgid(pid, pid);
+                        }
+                        match libc::getpgid(pid) {
+                            pgid if pgid > 0 => pgid,
+                            _ => pid,
+                        }
+                    };
+                    job.pgid = Some(pgid);
+                    if foreground {
+                        unsafe { libc::tcsetpgrp(tty, pgid) };
+                    }
+                }
+            }
+        }
+
+        // The process may have changed state before it was recorded.
+        self.reap();
+        Ok(pid)
+    }
+
+    /// Replaces the shell with a command, giving the terminal back and
+    /// restoring the signals it ignores. Only returns if the command cannot be
+    /// executed.
+    pub fn exec(&self, cmd: &mut Command) -> io::Error {
+        self.restore_terminal();
+
+        let control = self.has_control();
+        if control {
+            reset_signals();
+        }
+        let err = cmd.exec();
+        if control {
+            ignore_signals();
+        }
+        err
+    }
+
+    /// Waits for a process to exit, forgetting it.
+    pub async fn wait_pid(&self, pid: Pid) -> Status {
+        let mut changes = self.changes.clone();
+        loop {
+            {
+                let mut table = self.table.lock().unwrap();
+                match table.procs.get(&pid) {
+                    Some(ProcState::Exited(status)) => {
+                        let status = *status;
+                        table.procs.remove(&pid);
+                        return status;
+                    }
+                    None => return Status::FAILURE,
+                    _ => {}
+                }
+            }
+            changes.recv().await;
+        }
+    }
+
+    /// Waits for a job to finish or stop.
+    pub async fn wait_job(&self, id: usize) -> Option<JobState> {
+        let mut changes = self.changes.clone();
+        loop {
+            match self.state(id)? {
+                JobState::Running => {}
+                state => return Some(state),
+            }
+            changes.recv().await;
+        }
+    }
+
+    /// Waits for a job to stop, returning immediately if it is not running.
+    pub async fn wait_stopped(&self, id: usize) {
+        let mut changes = self.changes.clone();
+        loop {
+            match self.state(id) {
+                Some(JobState::Running) => {}
+                Some(JobState::Done(_)) => {
+                    // The code of the job is finished, which the caller is
+                    // also waiting for.
+                    futures::future::pending::<()>().await;
+                }
+                _ => return,
+            }
+            changes.recv().await;
+        }
+    }
+
+    /// Waits for the leader of a job to be killed before the code of the job
+    /// finishes, returning the status it was killed with.
+    pub async fn wait_killed(&self, id: usize) -> Status {
+        let mut changes = self.changes.clone();
+        loop {
+            let status = {
+                let table = self.table.lock().unwrap();
+                table
+                    .jobs
+                    .iter()
+                    .find(|job| job.id == id && job.killed.is_some())
+                    .and_then(|job| match table.procs.get(&job.leader?) {
+                        Some(ProcState::Exited(status)) => Some(*status),
+                        _ => None,
+                    })
+            };
+            if let Some(status) = status {
+                return status;
+            }
+            changes.recv().await;
+        }
+    }
+
+    /// Moves a stopped job out of the foreground, giving the terminal back to
+    /// the shell.
+    pub fn stop(&self, id: usize) {
+        let tmodes = self.restore_terminal();
+        self.with_job(id, |job| {
+            job.foreground = false;
+            job.awaited = false;
+            job.notified = true;
+            if tmodes.is_some() {
+                job.tmodes = tmodes;
+            }
+        });
+    }
+
+    /// Continues a job, in the foreground if `foreground`, in which case it is
+    /// awaited.
+    pub fn resume(&self, id: usize, foreground: bool) -> io::Result<()> {
+        let control = self.control.lock().unwrap();
+
+        let (pgid, pids) = {
+            let mut table = self.table.lock().unwrap();
+            let Table { jobs, procs, .. } = &mut *table;
+            let job = match jobs.iter_mut().find(|job| job.id == id) {
+                Some(job) => job,
+                None => return Ok(()),
+            };
+
+            job.foreground = foreground && control.is_some();
+            job.awaited = foreground;
+            job.notified = false;
+
+            if let (Some(control), Some(pgid), true) = (&*control, job.pgid, job.foreground) {
+                let tmodes = job.tmodes.take().unwrap_or(control.tmodes);
+                let _ = termios::tcsetattr(control.tty, termios::TCSADRAIN, &tmodes);
+                unsafe { libc::tcsetpgrp(control.tty, pgid) };
+            }
+
+            for pid in job.pids.iter().chain(&job.leader) {
+                if let Some(state @ ProcState::Stopped) = procs.get_mut(pid) {
+                    *state = ProcState::Running;
+                }
+            }
+            (job.pgid, job.pids.clone())
+        };
+
+        let result = match pgid {
+            Some(pgid) => unsafe { libc::kill(-pgid, libc::SIGCONT) },
+            None => pids
+                .iter()
+                .map(|&pid| unsafe { libc::kill(pid, libc::SIGCONT) })
+                .min()
+                .unwrap_or(0),
+        };
+
+        self.notify();
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Gives the terminal back to the shell, with the modes it had when job
+    /// control was enabled, returning the modes it had before.
+    pub fn restore_terminal(&self) -> Option<Termios> {
+        let control = self.control.lock().unwrap();
+        let control = control.as_ref()?;
+
+        let tmodes = Termios::from_fd(control.tty).ok();
+        unsafe { libc::tcsetpgrp(control.tty, control.pgid) };
+        let _ = termios::tcsetattr(control.tty, termios::TCSADRAIN, &control.tmodes);
+        tmodes
+    }
+
+    /// Gives the terminal back to the shell once the processes of a job in the
+    /// foreground have exited, so the rest of its code can read from it.
+    fn release_terminal(&self, pid: Pid) {
+        let control = self.control.lock().unwrap();
+        let control = match &*control {
+            Some(control) => control,
+            None => return,
+        };
+
+        let table = self.table.lock().unwrap();
+        if let Some(job) = table.jobs.iter().find(|job| job.pids.contains(&pid)) {
+            if job.foreground && live_pgid(&table, job).is_none() {
+                unsafe { libc::tcsetpgrp(control.tty, control.pgid) };
+            }
+        }
+    }
+
+    fn with_job<F, T>(&self, id: usize, f: F) -> Option<T>
+    where
+        F: FnOnce(&mut Job) -> T,
+    {
+        let mut table = self.table.lock().unwrap();
+        table.jobs.iter_mut().find(|job| job.id == id).map(f)
+    }
+
+    fn notify(&self) {
+        let _ = self.changed.broadcast(());
+    }
+
+    /// Starts the task reaping processes, before the first is spawned so none
+    /// of their `SIGCHLD` signals are missed.
+    fn start_reaper(self: &Arc<Self>) -> io::Result<()> {
+        let mut started = self.reaper.lock().unwrap();
+        if *started {
+            return Ok(());
+        }
+
+        let mut sigchld = signal(SignalKind::child())?;
+        // Tokio only asks for `SIGCHLD` when a process exits, but stopped
+        // processes must be reaped too.
+        unsafe {
+            let mut action = std::mem::zeroed::<libc::sigaction>();
+            libc::sigaction(libc::SIGCHLD, std::ptr::null(), &mut action);
+            action.sa_flags &= !libc::SA_NOCLDSTOP;
+            if libc::sigaction(libc::SIGCHLD, &action, std::ptr::null_mut()) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        let jobs = Arc::downgrade(self);
+        tokio::spawn(async move {
+            while sigchld.recv().await.is_some() {
+                match jobs.upgrade() {
+                    Some(jobs) => jobs.reap(),
+                    None => break,
+                }
+            }
+        });
+
+        *started = true;
+        Ok(())
+    }
+
+    /// Records the state of each process that has exited or stopped since it
+    /// was last reaped.
+    ///
+    /// Only the processes spawned by the shell are waited for, so a process
+    /// reaped by the standard library when it fails to execute is not.
+    fn reap(&self) {
+        let mut exited = Vec::new();
+        let mut changed = false;
+        {
+            let mut table = self.table.lock().unwrap();
+            for (&pid, state) in table.procs.iter_mut() {
+                if let ProcState::Exited(_) = state {
+                    continue;
+                }
+                if let Some(status) = try_wait(pid) {
+                    let new = proc_state(status);
+                    if let ProcState::Exited(_) = new {
+                        exited.push((pid, status));
+                    }
+                    *state = new;
+                    changed = true;
+                }
+            }
+
+            for &(pid, status) in &exited {
+                reap_leader(&mut table, pid, status);
+            }
+        }
+
+        if changed {
+            self.notify();
+        }
+        for (pid, _) in exited {
+            self.release_terminal(pid);
+        }
+    }
+}
+
+/// The state of a job, stopped if any process is stopped and done once its
+/// code and all its processes are.
+fn job_state(table: &Table, job: &Job) -> JobState {
+    let states = job.pids.iter().filter_map(|pid| table.procs.get(pid));
+
+    let mut running = job.status.is_none();
+    let mut last = None;
+    for state in states {
+        match state {
+            ProcState::Stopped => return JobState::Stopped,
+            ProcState::Running => running = true,
+            ProcState::Exited(status) => last = Some(*status),
+        }
+    }
+
+    if running {
+        JobState::Running
+    } else {
+        JobState::Done(job.status.or(last).unwrap_or_default())
+    }
+}
+
+/// Removes the finished jobs not awaited at the prompt, given the state of each
+/// job.
+fn remove_done(table: &mut Table, states: Vec<JobState>) {
+    let Table {
+        jobs,
+        procs,
+        orphans,
+        ..
+    } = table;
+    let mut states = states.into_iter();
+    jobs.retain(|job| {
+        let state = states.next();
+        let keep = job.awaited || !matches!(state, Some(JobState::Done(_)));
+        if !keep {
+            forget(procs, orphans, job);
+        }
+        keep
+    });
+}
+
+/// Forgets the processes of a removed job that have exited, leaving the others
+/// to be reaped, and its leader once it exits.
+fn forget(procs: &mut HashMap<Pid, ProcState>, orphans: &mut Vec<Pid>, job: &Job) {
+    for pid in job.pids.iter().chain(&job.leader) {
+        if let Some(ProcState::Exited(_)) = procs.get(pid) {
+            procs.remove(pid);
+        } else if job.leader == Some(*pid) {
+            orphans.push(*pid);
+        }
+    }
+}
+
+/// The process group of a job, if any process in it has not exited.
+fn live_pgid(table: &Table, job: &Job) -> Option<Pid> {
+    let pgid = job.pgid?;
+    let live = job
+        .pids
+        .iter()
+        .any(|pid| !matches!(table.procs.get(pid), Some(ProcState::Exited(_)) | None));
+    if live {
+        Some(pgid)
+    } else {
+        None
+    }
+}
+
+/// Records the exit of the leader of a job, with the status of the job if its
+/// code has finished. Otherwise the leader was killed, and so is the job, with
+/// the same signal.
+fn reap_leader(table: &mut Table, pid: Pid, status: libc::c_int) {
+    if let Some(i) = table.orphans.iter().position(|&orphan| orphan == pid) {
+        table.orphans.swap_remove(i);
+        table.procs.remove(&pid);
+        return;
+    }
+
+    let Table { jobs, procs, .. } = table;
+    let job = match jobs.iter_mut().find(|job| job.leader == Some(pid)) {
+        Some(job) => job,
+        None => return,
+    };
+
+    if let Some(status) = job.status {
+        procs.insert(pid, ProcState::Exited(status));
+        return;
+    }
+
+    let signal = unsafe {
+        if libc::WIFSIGNALED(status) {
+            libc::WTERMSIG(status)
+        } else {
+            libc::SIGTERM
+        }
+    };
+    job.killed = Some(signal);
+    for pid in &job.pids {
+        if let Some(ProcState::Running) | Some(ProcState::Stopped) = procs.get(pid) {
+            unsafe {
+                libc::kill(*pid, signal);
+                libc::kill(*pid, libc::SIGCONT);
+            }
+        }
+    }
+}
+
+/// Runs the leader of a job in the process forked for it, until the other end
+/// of the pipe `release` is closed. Only functions safe to call after `fork`
+/// in a threaded process are called.
+unsafe fn lead(release: RawFd, max_fd: RawFd, control: bool) -> ! {
+    if control {
+        libc::setpgid(0, 0);
+    }
+
+    // The handlers of the shell are of no use here, and the files it has open
+    // must not be kept open, such as the pipes between processes.
+    for signal in 1..=libc::SIGSYS {
+        let mut action = std::mem::zeroed::<libc::sigaction>();
+        if libc::sigaction(signal, std::ptr::null(), &mut action) == 0
+            && action.sa_sigaction != libc::SIG_IGN
+        {
+            libc::signal(signal, libc::SIG_DFL);
+        }
+    }
+    if control {
+        reset_signals();
+    }
+    for fd in 0..max_fd {
+        if fd != release {
+            libc::close(fd);
+        }
+    }
+
+    let mut buf = 0u8;
+    loop {
+        match libc::read(release, &mut buf as *mut u8 as *mut libc::c_void, 1) {
+            n if n < 0 && io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => {}
+            _ => libc::_exit(0),
+        }
+    }
+}
+
+fn ignore_signals() {
+    for &signal in &JOB_SIGNALS {
+        unsafe { libc::signal(signal, libc::SIG_IGN) };
+    }
+}
+
+/// Restores the signals ignored by the shell to their defaults, as for a child
+/// process.
+fn reset_signals() {
+    for &signal in &JOB_SIGNALS {
+        unsafe { libc::signal(signal, libc::SIG_DFL) };
+    }
+}
+
+fn describe(job: &Job, state: JobState) -> String {
+    let state = match state {
+        JobState::Running => "Running".to_owned(),
+        JobState::Stopped => "Stopped".to_owned(),
+        JobState::Done(status) if status.is_success() => "Done".to_owned(),
+        JobState::Done(status) => format!("Exit {}", status),
+    };
+    format!("[{}]  {:<8}  {}", job.id, state, job.text)
+}
+
+/// The status of a process, as given by `waitpid`, if it has exited or
+/// stopped since it was last waited for.
+fn try_wait(pid: Pid) -> Option<libc::c_int> {
+    let mut status = 0;
+    loop {
+        match unsafe { libc::waitpid(pid, &mut status, libc::WNOHANG | libc::WUNTRACED) } {
+            0 => return None,
+            n if n > 0 => return Some(status),
+            _ if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => {}
+            // Reaped by someone else, so it exited with an unknown status.
+            _ => return Some(1 << 8),
+        }
+    }
+}
+
+/// The state of a process given its status from `waitpid`.
+fn proc_state(status: libc::c_int) -> ProcState {
+    unsafe {
+        if libc::WIFSTOPPED(status) {
+            ProcState::Stopped
+        } else if libc::WIFSIGNALED(status) {
+            ProcState::Exited(Status::from_signal(libc::WTERMSIG(status)))
+        } else {
+            ProcState::Exited(Status::new(libc::WEXITSTATUS(status)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::testing::{block_on, frame, output, output_in};
+    use crate::eval::ErrorKind;
+    use crate::parse::Span;
+
+    /// Records a process of a job, as if it had been spawned.
+    fn add_proc(jobs: &Jobs, id: usize, pid: Pid, state: ProcState) {
+        let mut table = jobs.table.lock().unwrap();
+        table.procs.insert(pid, state);
+        let job = table.jobs.iter_mut().find(|job| job.id == id).unwrap();
+        job.pids.push(pid);
+    }
+
+    fn set_proc(jobs: &Jobs, pid: Pid, state: ProcState) {
+        jobs.table.lock().unwrap().procs.insert(pid, state);
+    }
+
+    #[test]
+    fn job_state() {
+        let jobs = Jobs::default();
+        let id = jobs.add("a | b", false);
+        assert_eq!(jobs.state(id), Some(JobState::Running));
+
+        add_proc(&jobs, id, 1, ProcState::Running);
+        add_proc(&jobs, id, 2, ProcState::Stopped);
+        assert_eq!(jobs.state(id), Some(JobState::Stopped));
+
+        // Running until the code and every process have finished.
+        set_proc(&jobs, 2, ProcState::Exited(Status::new(2)));
+        assert!(jobs.finish(id, Ok(Status::new(3))).is_none());
+        assert_eq!(jobs.state(id), Some(JobState::Running));
+        set_proc(&jobs, 1, ProcState::Exited(Status::SUCCESS));
+        assert_eq!(jobs.state(id), Some(JobState::Done(Status::new(3))));
+
+        assert_eq!(jobs.state(id + 1), None);
+    }
+
+    #[test]
+    fn ids() {
+        let jobs = Jobs::default();
+        let a = jobs.add("a", false);
+        let b = jobs.add("b", true);
+        let c = jobs.add("c", false);
+        assert_eq!((a, b, c), (1, 2, 3));
+        assert_eq!(jobs.ids(), [1, 3]);
+        assert_eq!(jobs.current(), Some(3));
+
+        // Ids are reused once free.
+        jobs.remove(a);
+        assert_eq!(jobs.add("d", false), 1);
+        assert_eq!(jobs.text(1).as_deref(), Some("d"));
+    }
+
+    #[test]
+    fn notifications() {
+        let jobs = Jobs::default();
+        let done = jobs.add("done", false);
+        let stopped = jobs.add("stopped", false);
+        let running = jobs.add("running", false);
+        let awaited = jobs.add("awaited", true);
+
+        jobs.finish(done, Ok(Status::new(1)));
+        add_proc(&jobs, stopped, 1, ProcState::Stopped);
+        jobs.finish(awaited, Ok(Status::SUCCESS));
+
+        assert_eq!(
+            jobs.notifications(),
+            ["[1]  Exit 1    done", "[2]  Stopped   stopped"]
+        );
+        // Finished jobs are removed, and stopped jobs only reported once.
+        assert!(jobs.notifications().is_empty());
+        assert_eq!(jobs.ids(), [stopped, running]);
+        assert_eq!(jobs.state(awaited), Some(JobState::Done(Status::SUCCESS)));
+
+        assert_eq!(
+            jobs.list(),
+            ["[2]  Stopped   stopped", "[3]  Running   running"]
+        );
+    }
+
+    #[test]
+    fn take() {
+        let jobs = Jobs::default();
+        let id = jobs.add("false", true);
+        assert!(jobs.take(id).is_none());
+
+        // The error of an awaited job is kept for whoever awaits it, that of
+        // another job is returned to be reported.
+        let id = jobs.add("false", true);
+        let failed = || {
+            let kind = ErrorKind::Failed(Status::FAILURE, "false".to_owned());
+            Err(Error::new(kind, Span::new(0, 5)))
+        };
+        assert!(jobs.finish(id, failed()).is_none());
+        assert!(matches!(jobs.take(id), Some(Err(_))));
+        assert_eq!(jobs.state(id), None);
+
+        let id = jobs.add("false", false);
+        assert!(jobs.finish(id, failed()).is_some());
+        add_proc(&jobs, id, 1, ProcState::Exited(Status::FAILURE));
+        assert_eq!(
+            jobs.take(id).map(|result| result.ok()),
+            Some(Some(Status::FAILURE))
+        );
+        assert!(jobs.table.lock().unwrap().procs.is_empty());
+    }
+
+    #[test]
+    fn background_pid() {
+        // The pid is known before the job starts its first process.
+        let pid = output("sleep *.x[nomatch-ok] 0 & echo $!").unwrap();
+        assert!(pid.parse::<u32>().is_ok(), "{:?}", pid);
+
+        assert_eq!(output("echo a & wait $!; echo $?").unwrap(), "a\n0");
+        assert_eq!(output("sh -c 'exit 3' & wait $! || echo $?").unwrap(), "3");
+    }
+
+    #[test]
+    fn killing_leader_kills_job() {
+        assert_eq!(
+            output("{ sleep 10; echo no } & kill $!; wait $! || echo $?").unwrap(),
+            "143"
+        );
+    }
+
+    #[test]
+    fn forgets_exited_processes() {
+        let mut frame = frame();
+        let code = "sleep 0 & sleep 0 | sleep 0; wait; sh -c 'exit 2' & wait $! || echo $?";
+        assert_eq!(block_on(output_in(&mut frame, code)).unwrap(), "2");
+
+        let table = frame.globals.jobs.table.lock().unwrap();
+        assert!(
+            table
+                .procs
+                .values()
+                .all(|state| !matches!(state, ProcState::Exited(_))),
+            "{:?}",
+            table.procs
+        );
+    }
+}
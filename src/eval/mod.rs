@@ -0,0 +1,292 @@
+mod arith;
+mod builtins;
+mod call;
+mod capture;
+mod control;
+mod edit;
+mod error;
+mod expand;
+mod external;
+mod frame;
+mod glob;
+mod history;
+mod job;
+mod module;
+mod port;
+mod redir;
+mod regex;
+mod scope;
+mod status;
+mod strings;
+mod value;
+
+use std::env;
+use std::fs;
+use std::io;
+use std::os::unix::io::RawFd;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use futures::future::{self, Either, FutureExt};
+
+use crate::parse::ast::{Chunk, Stmt};
+use crate::parse::Source;
+
+use self::frame::{Frame, Globals};
+use self::port::Ports;
+use self::scope::{Scope, Var};
+
+pub use self::edit::{PromptFn, SharedEditState};
+pub use self::error::{Error, ErrorKind};
+pub use self::history::{read as read_history, Entry as HistoryEntry};
+pub use self::status::Status;
+pub use self::value::Value;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Evaluates parsed code.
+///
+/// Words are never split after expansion, each word in the source is exactly
+/// one argument to the command.
+///
+/// A command that exits with a non-zero status raises an exception, unless its
+/// status is tested by a condition or `&&` and `||`.
+///
+/// With job control, each statement is evaluated as a job, which ends the
+/// chunk if it is stopped.
+pub struct Evaluator {
+    frame: Frame,
+}
+
+impl Evaluator {
+    pub fn new() -> io::Result<Evaluator> {
+        let globals = Arc::new(Globals::default());
+        let ports = Ports::std()?;
+
+        let scope = Scope::from_env();
+        init_env(&scope);
+
+        Ok(Evaluator {
+            frame: Frame::new(globals, scope, ports),
+        })
+    }
+
+    /// Exit status of the last command.
+    pub fn status(&self) -> Status {
+        self.frame.status
+    }
+
+    /// Evaluates a chunk parsed from a source, recording the exit status.
+    ///
+    /// A `return` at the top level ends the chunk.
+    pub async fn eval(&mut self, chunk: &Chunk, src: &Arc<Source>) -> Result<Status> {
+        self.frame.src = Some(Arc::clone(src));
+
+        let result = if self.frame.globals.jobs.has_control() {
+            self.eval_jobs(chunk).await
+        } else {
+            self.frame.eval_chunk(chunk).await
+        };
+
+        let result = match result {
+            Err(Error {
+                kind: ErrorKind::Return(status),
+                ..
+            }) => Ok(status),
+            result => result.map_err(|err| err.or_source(Some(src))),
+        };
+
+        match &result {
+            Ok(status) => self.frame.status = *status,
+            Err(err) => self.frame.status = err.status(),
+        }
+
+        result
+    }
+
+    async fn eval_jobs(&mut self, chunk: &Chunk) -> Result<Status> {
+        let mut status = Status::SUCCESS;
+        for stmt in &chunk.stmts {
+            status = self.eval_job(stmt).await?;
+        }
+        Ok(status)
+    }
+
+    /// Evaluates a statement as a job in the foreground, until it finishes or
+    /// is stopped.
+    async fn eval_job(&mut self, stmt: &Stmt) -> Result<Status> {
+        if stmt.background {
+            return self.frame.eval_stmt(stmt).await;
+        }
+
+        let jobs = Arc::clone(&self.frame.globals.jobs);
+        let id = jobs.add(&self.frame.stmt_text(stmt), true);
+
+        // The job runs in its own task, so it can continue in the background
+        // once stopped.
+        let mut frame = self.frame.clone();
+        frame.job = Some(id);
+        let task_stmt = stmt.clone();
+        let task_jobs = Arc::clone(&jobs);
+        let task = tokio::spawn(async move {
+            let result = frame.eval_stmt(&task_stmt).await;
+            if let Some(err) = task_jobs.finish(id, result) {
+                eprintln!("jsh: {}", err);
+            }
+            frame
+        });
+
+        let stopped = jobs.wait_stopped(id).boxed();
+        let frame = match future::select(task, stopped).await {
+            Either::Left((frame, _)) => frame.expect("job panicked"),
+            Either::Right(_) => {
+                jobs.stop(id);
+                if let Some(job) = jobs.describe(id) {
+                    eprintln!("\n{}", job);
+                }
+                return Err(Error::new(ErrorKind::Stopped(id), stmt.span));
+            }
+        };
+
+        jobs.restore_terminal();
+        self.frame = Frame { job: None, ..frame };
+        jobs.take(id).unwrap_or(Ok(Status::SUCCESS))
+    }
+
+    /// Enables job control on a terminal, so that jobs in the foreground can
+    /// be stopped.
+    pub fn enable_job_control(&self, tty: RawFd) -> io::Result<()> {
+        self.frame.globals.jobs.enable_control(tty)
+    }
+
+    /// Returns a line for each job that finished or stopped since the last
+    /// call, to tell the user before the prompt.
+    pub fn job_notifications(&self) -> Vec<String> {
+        self.frame.globals.jobs.notifications()
+    }
+
+    /// Sets `$0` to the name of the script or command, and `$args` to its
+    /// positional arguments.
+    pub fn set_args(&self, name: &str, args: &[String]) {
+        let var = |value| Var {
+            value,
+            exported: false,
+        };
+        let args = args.iter().cloned().map(Value::Str).collect();
+
+        self.frame
+            .scope
+            .declare("0", var(Value::Str(name.to_owned())));
+        self.frame.scope.declare("args", var(Value::List(args)));
+    }
+
+    /// The directory of the configuration of the shell.
+    pub fn config_dir(&self) -> Option<PathBuf> {
+        module::config_dir(&self.frame.scope)
+    }
+
+    /// Adds a command to the history, if it is on.
+    pub fn add_history(&self, entry: &HistoryEntry) -> io::Result<()> {
+        match history::path(&self.frame.scope) {
+            Some(file) => history::append(&file, entry),
+            None => Ok(()),
+        }
+    }
+
+    /// The file of the history, if it is on.
+    pub fn history_file(&self) -> Option<PathBuf> {
+        history::path(&self.frame.scope)
+    }
+
+    /// The commands of the history, oldest first.
+    pub fn history(&self) -> io::Result<Vec<HistoryEntry>> {
+        match history::path(&self.frame.scope) {
+            Some(file) => history::read(&file),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// The state shared with the line editor.
+    pub fn edit_state(&self) -> SharedEditState {
+        Arc::clone(&self.frame.globals.edit)
+    }
+}
+
+/// Sets up the variables the shell maintains, in the environment it was
+/// started with.
+///
+/// `SHLVL` counts the shells started from one another. `PWD` is kept if it is
+/// the working directory, through symbolic links, and a stale `OLDPWD` that
+/// is not a directory is removed.
+fn init_env(scope: &Scope) {
+    let level = scope
+        .get("SHLVL")
+        .and_then(|var| var.value.as_str()?.parse::<u32>().ok())
+        .unwrap_or(0);
+    scope.set_global("SHLVL", Value::Str(level.saturating_add(1).to_string()));
+
+    if let Ok(wd) = env::current_dir() {
+        let pwd = scope.get("PWD").map(|var| var.value);
+        let is_wd = pwd
+            .as_ref()
+            .and_then(Value::as_str)
+            .map(Path::new)
+            .is_some_and(|pwd| {
+                pwd.is_absolute() && fs::canonicalize(pwd).ok().as_ref() == Some(&wd)
+            });
+        if !is_wd {
+            scope.set_global("PWD", Value::Str(wd.to_string_lossy().into_owned()));
+        }
+    }
+
+    let oldpwd = scope.get("OLDPWD").map(|var| var.value);
+    let is_dir = oldpwd
+        .as_ref()
+        .and_then(Value::as_str)
+        .is_some_and(|oldpwd| Path::new(oldpwd).is_dir());
+    if oldpwd.is_some() && !is_dir {
+        scope.remove("OLDPWD");
+    }
+}
+
+/// Helpers for the tests of the evaluator.
+#[cfg(test)]
+pub(crate) mod testing {
+    use std::future::Future;
+    use std::sync::Arc;
+
+    use super::frame::{Frame, Globals};
+    use super::port::Ports;
+    use super::scope::Scope;
+    use super::Result;
+    use crate::parse::{self, Source};
+
+    /// Runs a future on a runtime of its own, as the shell does.
+    pub fn block_on<F: Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    /// A frame of a new shell, with the variables of the environment.
+    pub fn frame() -> Frame {
+        let globals = Arc::new(Globals::default());
+        Frame::new(globals, Scope::from_env(), Ports::std().unwrap())
+    }
+
+    /// Evaluates code in a frame, returning its output as `$(...)` does.
+    pub async fn output_in(frame: &mut Frame, code: &str) -> Result<String> {
+        let src = Arc::new(Source::new("[test]", code));
+        let chunk = parse::parse(&src).unwrap();
+        frame.src = Some(src);
+        frame.capture_output(&chunk, chunk.span).await
+    }
+
+    /// Evaluates code in a new shell, returning its output.
+    pub fn output(code: &str) -> Result<String> {
+        block_on(async { output_in(&mut frame(), code).await })
+    }
+}
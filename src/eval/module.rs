@@ -0,0 +1,218 @@
+//! Modules, loaded by `use`.
+//!
+//! `use NAME` evaluates `NAME.jsh` from the first directory in the module path
+//! that has it, and makes its functions and variables available in the
+//! namespace `NAME:`, as in `NAME:greet` or `$NAME:greeting`. The module path is
+//! `$JSH_LIB_PATH`, a list or a `:` separated string of directories, which
+//! defaults to `~/.config/jsh/lib`. A name containing `/` is found in a
+//! subdirectory, as in `use net/http`, and its namespace is its last
+//! component. A name starting with `./`, `../` or `/` is a path to the file.
+//!
+//! A module is evaluated in its own scope within the outermost scope, so the
+//! names it defines do not leak into the code using it. Names starting with
+//! `_` are private to the module, all others are exported.
+//!
+//! Each module is only evaluated the first time it is used, later uses share
+//! its namespace.
+//!
+//! The namespaces `edit:`, `path:` and `prompt:` are built into the shell and
+//! are always available, so using them does nothing.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::eval::frame::Frame;
+use crate::eval::scope::{Scope, Var};
+use crate::eval::value::{Closure, Value};
+use crate::eval::{Error, ErrorKind, Result};
+use crate::parse::{self, Source, Span};
+
+/// Namespaces of builtins rather than modules.
+pub const BUILTIN_NAMESPACES: &[&str] = &["edit", "history", "path", "prompt"];
+
+/// A module loaded by `use`.
+#[derive(Debug)]
+pub struct Module {
+    pub path: PathBuf,
+    scope: Arc<Scope>,
+}
+
+impl Module {
+    /// Looks up an exported variable.
+    pub fn get(&self, name: &str) -> Option<Var> {
+        if is_private(name) {
+            return None;
+        }
+        self.scope.get_local(name)
+    }
+
+    /// Looks up an exported function.
+    pub fn get_fn(&self, name: &str) -> Option<Arc<Closure>> {
+        if is_private(name) {
+            return None;
+        }
+        self.scope.get_local_fn(name)
+    }
+}
+
+fn is_private(name: &str) -> bool {
+    name.starts_with('_')
+}
+
+/// Loaded modules by path, or `None` while a module is being evaluated.
+pub type Cache = Mutex<HashMap<PathBuf, Option<Arc<Module>>>>;
+
+/// The directory of the configuration of the shell, `$XDG_CONFIG_HOME/jsh` or
+/// `~/.config/jsh`.
+pub fn config_dir(scope: &Scope) -> Option<PathBuf> {
+    let var = |name: &str| {
+        let var = scope.get(name)?;
+        var.value
+            .as_str()
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+    };
+
+    var("XDG_CONFIG_HOME")
+        .or_else(|| var("HOME").map(|home| home.join(".config")))
+        .map(|dir| dir.join("jsh"))
+}
+
+/// The directories modules are found in.
+fn lib_path(scope: &Scope) -> Vec<PathBuf> {
+    match scope.get("JSH_LIB_PATH").map(|var| var.value) {
+        Some(Value::List(dirs)) => dirs
+            .iter()
+            .filter_map(Value::as_str)
+            .map(PathBuf::from)
+            .collect(),
+        Some(Value::Str(dirs)) => dirs
+            .split(':')
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .collect(),
+        _ => config_dir(scope)
+            .map(|dir| dir.join("lib"))
+            .into_iter()
+            .collect(),
+    }
+}
+
+/// Finds the file of a module.
+pub fn resolve(name: &str, scope: &Scope) -> Option<PathBuf> {
+    let file = format!("{}.jsh", name);
+
+    if name.starts_with("./") || name.starts_with("../") || name.starts_with('/') {
+        return [PathBuf::from(name), PathBuf::from(file)]
+            .iter()
+            .find(|path| path.is_file())
+            .cloned();
+    }
+
+    lib_path(scope)
+        .into_iter()
+        .map(|dir| dir.join(&file))
+        .find(|path| path.is_file())
+}
+
+/// The namespace of a module, the last component of its name.
+pub fn namespace(name: &str) -> &str {
+    let base = name.rsplit('/').next().unwrap_or(name);
+    base.strip_suffix(".jsh").unwrap_or(base)
+}
+
+/// Looks up a function written as `NS:NAME` in a module.
+pub fn lookup_fn(scope: &Scope, name: &str) -> Option<Arc<Closure>> {
+    let (ns, name) = name.split_once(':')?;
+    scope.get_mod(ns)?.get_fn(name)
+}
+
+/// Looks up a variable written as `NS:NAME` in a module.
+///
+/// If `NS` is not a namespace, this is the variable `NS` followed by `:NAME`,
+/// as it was before namespaces, so `$host:port` still works.
+pub fn lookup_var(scope: &Scope, name: &str) -> Option<Value> {
+    let (ns, name) = name.split_once(':')?;
+
+    if let Some(module) = scope.get_mod(ns) {
+        return module.get(name).map(|var| var.value);
+    }
+    if BUILTIN_NAMESPACES.contains(&ns) {
+        return None;
+    }
+
+    match scope.get(ns).map(|var| var.value) {
+        Some(Value::Str(value)) => Some(Value::Str(format!("{}:{}", value, name))),
+        None => Some(Value::Str(format!(":{}", name))),
+        value => value,
+    }
+}
+
+impl Frame {
+    /// Loads the module in a file, evaluating it unless it has already been
+    /// loaded.
+    pub async fn load_module(&mut self, path: &Path, span: Span) -> Result<Arc<Module>> {
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+
+        match self.globals.modules.lock().unwrap().get(&path) {
+            Some(Some(module)) => return Ok(Arc::clone(module)),
+            Some(None) => {
+                let name = path.display().to_string();
+                return Err(Error::new(ErrorKind::CircularModule(name), span));
+            }
+            None => {}
+        }
+
+        self.globals
+            .modules
+            .lock()
+            .unwrap()
+            .insert(path.clone(), None);
+        let result = self.eval_module(&path, span).await;
+
+        let mut modules = self.globals.modules.lock().unwrap();
+        match result {
+            Ok(module) => {
+                modules.insert(path, Some(Arc::clone(&module)));
+                Ok(module)
+            }
+            Err(err) => {
+                modules.remove(&path);
+                Err(err)
+            }
+        }
+    }
+
+    async fn eval_module(&self, path: &Path, span: Span) -> Result<Arc<Module>> {
+        let name = path.to_string_lossy().into_owned();
+        let code = fs::read_to_string(path)
+            .map_err(|err| Error::new(ErrorKind::Open(name.clone(), err), span))?;
+        let src = Arc::new(Source::new(name, code));
+        let chunk = parse::parse(&src).map_err(|err| Error {
+            kind: ErrorKind::Syntax(err.kind),
+            span: err.span,
+            src: Some(Arc::clone(&src)),
+        })?;
+
+        let scope = Scope::child(&self.scope.root());
+        let mut frame = self.clone();
+        frame.scope = Arc::clone(&scope);
+        frame.src = Some(Arc::clone(&src));
+
+        match frame.eval_chunk(&chunk).await {
+            Ok(_)
+            | Err(Error {
+                kind: ErrorKind::Return(_),
+                ..
+            }) => {}
+            Err(err) => return Err(err.or_source(Some(&src))),
+        }
+
+        Ok(Arc::new(Module {
+            path: path.to_owned(),
+            scope,
+        }))
+    }
+}
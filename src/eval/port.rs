@@ -0,0 +1,229 @@
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Instant;
+
+use tokio::sync::{mpsc, Mutex};
+
+use crate::eval::value::Value;
+
+/// Number of values buffered between two commands of a pipeline, before the
+/// writer waits for the reader.
+const VALUE_BUFFER: usize = 32;
+
+/// An open file that commands read from or write to, such as a pipe or the
+/// terminal.
+#[derive(Clone, Debug)]
+pub struct Port {
+    file: Arc<File>,
+}
+
+impl Port {
+    pub fn new(file: File) -> Port {
+        Port {
+            file: Arc::new(file),
+        }
+    }
+
+    /// Creates a port from a duplicate of a file descriptor of the shell.
+    pub fn dup_fd(fd: RawFd) -> io::Result<Port> {
+        // Duplicate above the standard descriptors, closed on exec.
+        let dup = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 3) };
+        if dup < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Port::new(unsafe { File::from_raw_fd(dup) }))
+    }
+
+    /// Creates a pipe, returning the read and write ends.
+    pub fn pipe() -> io::Result<(Port, Port)> {
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let (r, w) = unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+        Ok((Port::new(r), Port::new(w)))
+    }
+
+    pub fn file(&self) -> &File {
+        &self.file
+    }
+
+    /// Returns a handle for a child process to use the port.
+    pub fn to_stdio(&self) -> io::Result<Stdio> {
+        Ok(Stdio::from(self.file.try_clone()?))
+    }
+
+    /// Writes a buffer to the port, without blocking the runtime.
+    pub async fn write_all(&self, buf: Vec<u8>) -> io::Result<()> {
+        let file = Arc::clone(&self.file);
+        blocking(move || (&*file).write_all(&buf)).await
+    }
+
+    /// Reads up to `len` bytes from the port, without blocking the runtime.
+    ///
+    /// An empty buffer signals the end of the input.
+    pub async fn read(&self, len: usize) -> io::Result<Vec<u8>> {
+        let file = Arc::clone(&self.file);
+        blocking(move || {
+            let mut buf = vec![0; len];
+            let n = (&*file).read(&mut buf)?;
+            buf.truncate(n);
+            Ok(buf)
+        })
+        .await
+    }
+
+    /// Reads a line from the port without its newline, a byte at a time so no
+    /// input after it is consumed. Returns `None` at the end of the input.
+    ///
+    /// With a deadline, fails with `TimedOut` once it passes. Each byte is
+    /// waited for by `poll` before it is read, so no read is left waiting to
+    /// take input meant for a later command.
+    pub async fn read_line(&self, deadline: Option<Instant>) -> io::Result<Option<Vec<u8>>> {
+        let file = Arc::clone(&self.file);
+        blocking(move || {
+            let mut line = Vec::new();
+            loop {
+                if let Some(deadline) = deadline {
+                    wait_readable(&file, deadline)?;
+                }
+
+                let mut byte = [0];
+                match (&*file).read(&mut byte) {
+                    Ok(0) if line.is_empty() => return Ok(None),
+                    Ok(0) => break,
+                    Ok(_) if byte[0] == b'\n' => break,
+                    Ok(_) => line.push(byte[0]),
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                    Err(err) => return Err(err),
+                }
+            }
+            Ok(Some(line))
+        })
+        .await
+    }
+}
+
+/// Waits until a file can be read without blocking, failing with `TimedOut`
+/// if the deadline passes first.
+fn wait_readable(file: &File, deadline: Instant) -> io::Result<()> {
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        // Rounded up, so the wait does not end just before the deadline.
+        let millis = left.as_nanos().div_ceil(1_000_000);
+        let timeout = millis.min(libc::c_int::MAX as u128) as libc::c_int;
+
+        let mut fd = libc::pollfd {
+            fd: file.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        match unsafe { libc::poll(&mut fd, 1, timeout) } {
+            // Closed or failed files are readable, the read reporting why.
+            n if n > 0 => return Ok(()),
+            0 => return Err(io::ErrorKind::TimedOut.into()),
+            _ => {
+                let err = io::Error::last_os_error();
+                if err.kind() != io::ErrorKind::Interrupted {
+                    return Err(err);
+                }
+            }
+        }
+    }
+}
+
+/// Runs a blocking IO operation on the blocking thread pool.
+async fn blocking<F, T>(f: F) -> io::Result<T>
+where
+    F: FnOnce() -> io::Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    match tokio::task::spawn_blocking(f).await {
+        Ok(result) => result,
+        Err(err) => Err(io::Error::other(err)),
+    }
+}
+
+/// The ports of a command, indexed by file descriptor.
+#[derive(Clone, Debug, Default)]
+pub struct Ports {
+    ports: Vec<Option<Port>>,
+}
+
+impl Ports {
+    /// Ports for the standard input, output and error of the shell.
+    pub fn std() -> io::Result<Ports> {
+        let mut ports = Ports::default();
+        for fd in 0..3 {
+            ports.set(fd, Some(Port::dup_fd(fd as RawFd)?));
+        }
+        Ok(ports)
+    }
+
+    pub fn get(&self, fd: usize) -> Option<&Port> {
+        self.ports.get(fd).and_then(Option::as_ref)
+    }
+
+    pub fn set(&mut self, fd: usize, port: Option<Port>) {
+        if fd >= self.ports.len() {
+            self.ports.resize(fd + 1, None);
+        }
+        self.ports[fd] = port;
+    }
+
+    /// Returns a handle for a child process to use a port, or `/dev/null` if
+    /// the port is closed.
+    pub fn to_stdio(&self, fd: usize) -> io::Result<Stdio> {
+        match self.get(fd) {
+            Some(port) => port.to_stdio(),
+            None => Ok(Stdio::null()),
+        }
+    }
+}
+
+/// Creates a channel for passing values between commands of a pipeline,
+/// returning the reading and writing ends.
+pub fn value_channel() -> (ValueReader, ValueWriter) {
+    let (tx, rx) = mpsc::channel(VALUE_BUFFER);
+    (
+        ValueReader {
+            rx: Arc::new(Mutex::new(rx)),
+        },
+        ValueWriter { tx },
+    )
+}
+
+/// The reading end of a channel of values.
+#[derive(Clone, Debug)]
+pub struct ValueReader {
+    rx: Arc<Mutex<mpsc::Receiver<Value>>>,
+}
+
+impl ValueReader {
+    /// Receives the next value, or `None` once all writers are gone.
+    pub async fn recv(&self) -> Option<Value> {
+        self.rx.lock().await.recv().await
+    }
+}
+
+/// The writing end of a channel of values.
+#[derive(Clone, Debug)]
+pub struct ValueWriter {
+    tx: mpsc::Sender<Value>,
+}
+
+impl ValueWriter {
+    /// Sends a value, failing with a broken pipe if the reader is gone.
+    pub async fn send(&self, value: Value) -> io::Result<()> {
+        self.tx
+            .clone()
+            .send(value)
+            .await
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))
+    }
+}
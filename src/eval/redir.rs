@@ -0,0 +1,169 @@
+use std::fs::OpenOptions;
+
+use crate::eval::frame::Frame;
+use crate::eval::port::Port;
+use crate::eval::{Error, ErrorKind, Result};
+use crate::parse::ast::*;
+
+/// Here-documents and here-strings up to this size are written to the pipe
+/// before the command starts, larger bodies are written concurrently so the
+/// pipe buffer cannot fill up.
+const INLINE_BODY_LEN: usize = 4096;
+
+impl Frame {
+    /// Applies a redirection to the ports of the frame.
+    pub async fn apply_redir(&mut self, redir: &Redir) -> Result<()> {
+        let fd = redir.fd.unwrap_or_else(|| redir.op.default_fd()) as usize;
+        let target = self.expand_string(&redir.target).await?;
+
+        let mut options = OpenOptions::new();
+        match redir.op {
+            RedirOp::Read => options.read(true),
+            RedirOp::Write | RedirOp::WriteAll => options.write(true).create(true).truncate(true),
+            RedirOp::Append | RedirOp::AppendAll => options.append(true).create(true),
+            RedirOp::ReadWrite => options.read(true).write(true).create(true),
+            RedirOp::DupRead | RedirOp::DupWrite => {
+                let port = self.dup_target(&target, redir)?;
+                self.ports.set(fd, port);
+                return Ok(());
+            }
+            RedirOp::HereDoc => {
+                let port = self.here_body(target.into_bytes(), redir).await?;
+                self.ports.set(fd, Some(port));
+                return Ok(());
+            }
+            RedirOp::HereStr => {
+                let mut body = target.into_bytes();
+                body.push(b'\n');
+                let port = self.here_body(body, redir).await?;
+                self.ports.set(fd, Some(port));
+                return Ok(());
+            }
+        };
+
+        let file = options
+            .open(&target)
+            .map_err(|err| Error::new(ErrorKind::Open(target, err), redir.span))?;
+        let port = Port::new(file);
+
+        if let RedirOp::WriteAll | RedirOp::AppendAll = redir.op {
+            self.ports.set(1, Some(port.clone()));
+            self.ports.set(2, Some(port));
+        } else {
+            self.ports.set(fd, Some(port));
+        }
+
+        Ok(())
+    }
+
+    /// Resolves the target of `<&` or `>&`, `-` closes the descriptor.
+    fn dup_target(&self, target: &str, redir: &Redir) -> Result<Option<Port>> {
+        if target == "-" {
+            return Ok(None);
+        }
+
+        let port = target
+            .parse::<usize>()
+            .ok()
+            .and_then(|fd| self.ports.get(fd));
+
+        match port {
+            Some(port) => Ok(Some(port.clone())),
+            None => Err(Error::new(ErrorKind::BadFd(target.to_owned()), redir.span)),
+        }
+    }
+
+    /// Returns the read end of a pipe that yields `body`.
+    async fn here_body(&self, body: Vec<u8>, redir: &Redir) -> Result<Port> {
+        let (r, w) = Port::pipe().map_err(|err| Error::new(ErrorKind::Pipe(err), redir.span))?;
+
+        if body.len() <= INLINE_BODY_LEN {
+            w.write_all(body)
+                .await
+                .map_err(|err| Error::new(ErrorKind::Io(err), redir.span))?;
+        } else {
+            // The write end is closed once the whole body is written, or the
+            // reader goes away.
+            tokio::spawn(async move {
+                let _ = w.write_all(body).await;
+            });
+        }
+
+        Ok(r)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use crate::eval::testing::output;
+
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> TempDir {
+            let dir =
+                std::env::temp_dir().join(format!("jsh-redir-{}-{}", std::process::id(), name));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+
+        fn path(&self, name: &str) -> String {
+            self.0.join(name).to_string_lossy().into_owned()
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn files() {
+        let dir = TempDir::new("files");
+        let f = dir.path("f");
+
+        let code = format!("echo a > '{0}'; echo b >> '{0}'; cat < '{0}'", f);
+        assert_eq!(output(&code).unwrap(), "a\nb");
+        let code = format!("echo c > '{0}'; cat '{0}'", f);
+        assert_eq!(output(&code).unwrap(), "c");
+
+        assert!(output(&format!("cat < '{}'", dir.path("missing"))).is_err());
+    }
+
+    #[test]
+    fn duplication() {
+        let dir = TempDir::new("duplication");
+        let f = dir.path("f");
+
+        assert_eq!(
+            output("sh -c 'echo err >&2' 2>&1 | tr a-z A-Z").unwrap(),
+            "ERR"
+        );
+        let code = format!("sh -c 'echo out; echo err >&2' > '{0}' 2>&1; cat '{0}'", f);
+        assert_eq!(output(&code).unwrap(), "out\nerr");
+        // Redirections apply in order.
+        assert!(output("echo a >&3 3> /dev/null").is_err());
+    }
+
+    #[test]
+    fn here_docs() {
+        assert_eq!(output("cat <<< 'a string'").unwrap(), "a string");
+        assert_eq!(
+            output("let x = 1; cat <<EOF\nx is $x\nEOF\necho after").unwrap(),
+            "x is 1\nafter"
+        );
+        assert_eq!(
+            output("let x = 1; cat <<'EOF'\nx is $x\nEOF").unwrap(),
+            "x is $x"
+        );
+        assert_eq!(
+            output("cat <<-EOF\n\t\tindented\n\tEOF").unwrap(),
+            "indented"
+        );
+    }
+}
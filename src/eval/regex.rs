@@ -0,0 +1,118 @@
+//! Regular expressions, for the `re-*` builtins.
+//!
+//! Matching is done by the `regex` crate, whose syntax covers the common
+//! forms, such as `.`, `[a-z]`, `\d`, `\b`, `*?`, `{n,m}`, `a|b`, `(...)`,
+//! `(?:...)` and `(?P<name>...)`. It runs in time linear in the text, so no
+//! pattern can hang the shell. Replacements are expanded by the crate too.
+
+/// A compiled regular expression.
+#[derive(Clone, Debug)]
+pub struct Regex {
+    regex: ::regex::Regex,
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Regex, String> {
+        match ::regex::Regex::new(pattern) {
+            Ok(regex) => Ok(Regex { regex }),
+            // The message ends with the error, after the pattern it is in.
+            Err(::regex::Error::Syntax(msg)) => {
+                let last = msg.trim_end().lines().last().unwrap_or_default();
+                Err(last.trim_start_matches("error: ").to_owned())
+            }
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.regex.is_match(text)
+    }
+
+    /// Finds every non-overlapping match, with its capture groups.
+    pub fn captures_iter<'r, 't>(&'r self, text: &'t str) -> ::regex::CaptureMatches<'r, 't> {
+        self.regex.captures_iter(text)
+    }
+
+    /// Replaces the first match, or every match if `all`, with `rep`, in
+    /// which `$N` or `${N}` is the text of group `N`, `$NAME` or `${NAME}`
+    /// that of a named group, and `$$` is a `$`.
+    pub fn replace(&self, text: &str, rep: &str, all: bool) -> String {
+        let limit = if all { 0 } else { 1 };
+        self.regex.replacen(text, limit, rep).into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn regex(pattern: &str) -> Regex {
+        Regex::new(pattern).unwrap_or_else(|err| panic!("invalid regex {:?}: {}", pattern, err))
+    }
+
+    #[test]
+    fn matching() {
+        assert!(regex("^a.c$").is_match("abc"));
+        assert!(!regex("^a.c$").is_match("abbc"));
+        assert!(regex(r"\bfoo\b").is_match("a foo b"));
+        assert!(!regex(r"\bfoo\b").is_match("afoob"));
+        assert!(regex("").is_match(""));
+    }
+
+    #[test]
+    fn captures() {
+        let text = "a1 b22 c333";
+        let matches: Vec<_> = regex(r"([a-z])(\d+)").captures_iter(text).collect();
+
+        let ranges: Vec<_> = matches
+            .iter()
+            .map(|caps| caps.get(0).unwrap().range())
+            .collect();
+        assert_eq!(ranges, [0..2, 3..6, 7..11]);
+
+        assert_eq!(matches[1].len(), 3);
+        assert_eq!(matches[1].get(1).unwrap().as_str(), "b");
+        assert_eq!(matches[1].get(2).unwrap().as_str(), "22");
+        assert!(matches[1].get(3).is_none());
+    }
+
+    #[test]
+    fn unmatched_groups() {
+        let caps = regex("(a)|(b)").captures_iter("b").next().unwrap();
+        assert!(caps.get(1).is_none());
+        assert_eq!(caps.get(2).unwrap().as_str(), "b");
+    }
+
+    #[test]
+    fn replace() {
+        let re = regex(r"(\w+)@(\w+)");
+        assert_eq!(re.replace("x@y z@w", "$2@$1", false), "y@x z@w");
+        assert_eq!(re.replace("x@y z@w", "$2@$1", true), "y@x w@z");
+        assert_eq!(re.replace("x@y", "${1}1", true), "x1");
+        assert_eq!(re.replace("x@y", "$$1", true), "$1");
+        assert_eq!(re.replace("x@y", "$5.", true), ".");
+        assert_eq!(re.replace("x@y", "$a ${", true), " ${");
+
+        let re = regex(r"(?P<user>\w+)@(?P<host>\w+)");
+        assert_eq!(re.replace("x@y", "$host/${user}s", true), "y/xs");
+        assert_eq!(regex("x*").replace("abc", "-", true), "-a-b-c-");
+    }
+
+    #[test]
+    fn linear_time() {
+        let text = "a".repeat(64);
+        assert!(!regex("^(a*)*$").is_match(&(text.clone() + "b")));
+        assert!(!regex("^(a|aa)+$").is_match(&(text + "!")));
+    }
+
+    #[test]
+    fn errors() {
+        let err = Regex::new("(a").unwrap_err();
+        assert!(!err.is_empty());
+        assert!(!err.starts_with("error: "), "{:?}", err);
+        assert!(!err.contains('\n'), "{:?}", err);
+
+        assert!(Regex::new("a{2,1}").is_err());
+        assert!(Regex::new("[z-a]").is_err());
+    }
+}
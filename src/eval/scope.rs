@@ -0,0 +1,301 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::env;
+use std::sync::{Arc, RwLock};
+
+use crate::eval::module::Module;
+use crate::eval::value::{Closure, Value};
+
+/// A shell variable.
+#[derive(Clone, Debug, Default)]
+pub struct Var {
+    pub value: Value,
+    /// Is the variable passed to the environment of child processes.
+    pub exported: bool,
+}
+
+/// A lexical scope of variables, functions and the modules imported by `use`.
+///
+/// Lookups that miss in a scope continue in the enclosing scope. The outermost
+/// scope is initialized from the environment of the shell.
+#[derive(Debug, Default)]
+pub struct Scope {
+    vars: RwLock<HashMap<String, Var>>,
+    fns: RwLock<HashMap<String, Arc<Closure>>>,
+    mods: RwLock<HashMap<String, Arc<Module>>>,
+    parent: Option<Arc<Scope>>,
+}
+
+impl Scope {
+    /// Creates the outermost scope, with the variables of the environment.
+    pub fn from_env() -> Arc<Scope> {
+        let vars = env::vars()
+            .map(|(name, value)| {
+                let var = Var {
+                    value: Value::Str(value),
+                    exported: true,
+                };
+                (name, var)
+            })
+            .collect();
+
+        Arc::new(Scope {
+            vars: RwLock::new(vars),
+            fns: RwLock::default(),
+            mods: RwLock::default(),
+            parent: None,
+        })
+    }
+
+    /// Creates a scope nested within `parent`.
+    pub fn child(parent: &Arc<Scope>) -> Arc<Scope> {
+        Arc::new(Scope {
+            vars: RwLock::default(),
+            fns: RwLock::default(),
+            mods: RwLock::default(),
+            parent: Some(Arc::clone(parent)),
+        })
+    }
+
+    /// The outermost scope enclosing this one, or this scope if it is the
+    /// outermost.
+    pub fn root(self: &Arc<Scope>) -> Arc<Scope> {
+        let mut scope = self;
+        while let Some(parent) = &scope.parent {
+            scope = parent;
+        }
+        Arc::clone(scope)
+    }
+
+    /// Iterates over this scope and the enclosing scopes, innermost first.
+    fn chain(&self) -> impl Iterator<Item = &Scope> {
+        std::iter::successors(Some(self), |scope| scope.parent.as_deref())
+    }
+
+    /// Looks up a variable.
+    pub fn get(&self, name: &str) -> Option<Var> {
+        self.chain()
+            .find_map(|scope| scope.vars.read().unwrap().get(name).cloned())
+    }
+
+    /// Looks up a variable declared in this scope, ignoring the enclosing
+    /// scopes.
+    pub fn get_local(&self, name: &str) -> Option<Var> {
+        self.vars.read().unwrap().get(name).cloned()
+    }
+
+    /// Declares a variable in this scope, shadowing any variable of the same
+    /// name in the enclosing scopes.
+    pub fn declare(&self, name: &str, var: Var) {
+        self.vars.write().unwrap().insert(name.to_owned(), var);
+    }
+
+    /// Modifies the innermost variable with the name, returning `false` if no
+    /// such variable exists.
+    pub fn modify<F>(&self, name: &str, f: F) -> bool
+    where
+        F: FnOnce(&mut Var),
+    {
+        for scope in self.chain() {
+            if let Some(var) = scope.vars.write().unwrap().get_mut(name) {
+                f(var);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Assigns to the innermost variable with the name, declaring it exported
+    /// in the outermost scope if it is not defined, as for variables the shell
+    /// maintains such as `PWD`.
+    pub fn set_global(&self, name: &str, value: Value) {
+        let mut value = Some(value);
+        if !self.modify(name, |var| var.value = value.take().unwrap_or_default()) {
+            let root = self.chain().last().unwrap_or(self);
+            root.declare(
+                name,
+                Var {
+                    value: value.unwrap_or_default(),
+                    exported: true,
+                },
+            );
+        }
+    }
+
+    /// Removes the innermost variable with the name, returning `false` if no
+    /// such variable exists.
+    pub fn remove(&self, name: &str) -> bool {
+        self.chain()
+            .any(|scope| scope.vars.write().unwrap().remove(name).is_some())
+    }
+
+    /// Looks up a function.
+    pub fn get_fn(&self, name: &str) -> Option<Arc<Closure>> {
+        self.chain()
+            .find_map(|scope| scope.fns.read().unwrap().get(name).cloned())
+    }
+
+    /// Looks up a function declared in this scope, ignoring the enclosing
+    /// scopes.
+    pub fn get_local_fn(&self, name: &str) -> Option<Arc<Closure>> {
+        self.fns.read().unwrap().get(name).cloned()
+    }
+
+    /// Declares a function in this scope.
+    pub fn declare_fn(&self, name: &str, closure: Arc<Closure>) {
+        self.fns.write().unwrap().insert(name.to_owned(), closure);
+    }
+
+    /// Looks up a module by the name of its namespace.
+    pub fn get_mod(&self, name: &str) -> Option<Arc<Module>> {
+        self.chain()
+            .find_map(|scope| scope.mods.read().unwrap().get(name).cloned())
+    }
+
+    /// Declares a module in this scope, under the name of its namespace.
+    pub fn declare_mod(&self, name: &str, module: Arc<Module>) {
+        self.mods.write().unwrap().insert(name.to_owned(), module);
+    }
+
+    /// The exported variables visible in this scope, for the environment of a
+    /// child process.
+    ///
+    /// Only string values can be exported.
+    pub fn exports(&self) -> BTreeMap<String, String> {
+        let mut exports = BTreeMap::new();
+        // Inner variables hide outer variables, even if they are not exported.
+        let mut seen = HashSet::new();
+
+        for scope in self.chain() {
+            for (name, var) in scope.vars.read().unwrap().iter() {
+                if !seen.insert(name.clone()) {
+                    continue;
+                }
+
+                match &var.value {
+                    Value::Str(value) if var.exported => {
+                        exports.insert(name.clone(), value.clone());
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        exports
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::testing::output;
+
+    fn var(value: &str, exported: bool) -> Var {
+        Var {
+            value: Value::Str(value.to_owned()),
+            exported,
+        }
+    }
+
+    fn get(scope: &Scope, name: &str) -> Option<String> {
+        match scope.get(name)?.value {
+            Value::Str(s) => Some(s),
+            value => panic!("not a string: {:?}", value),
+        }
+    }
+
+    #[test]
+    fn lookup() {
+        let root = Arc::new(Scope::default());
+        root.declare("a", var("root", false));
+        root.declare("b", var("root", false));
+        let child = Scope::child(&root);
+        child.declare("a", var("child", false));
+
+        assert_eq!(get(&child, "a").as_deref(), Some("child"));
+        assert_eq!(get(&child, "b").as_deref(), Some("root"));
+        assert_eq!(get(&root, "a").as_deref(), Some("root"));
+        assert!(child.get_local("b").is_none());
+        assert!(Arc::ptr_eq(&child.root(), &root));
+
+        assert!(child.modify("b", |var| var.value = Value::Str("set".to_owned())));
+        assert_eq!(get(&root, "b").as_deref(), Some("set"));
+        assert!(!child.modify("c", |_| {}));
+
+        assert!(child.remove("a"));
+        assert_eq!(get(&child, "a").as_deref(), Some("root"));
+        assert!(!child.remove("c"));
+    }
+
+    #[test]
+    fn set_global() {
+        let root = Arc::new(Scope::default());
+        let child = Scope::child(&root);
+        let inner = Scope::child(&child);
+
+        // Declared exported in the outermost scope if not defined.
+        inner.set_global("PWD", Value::Str("/a".to_owned()));
+        assert!(inner.get_local("PWD").is_none());
+        assert!(child.get_local("PWD").is_none());
+        assert!(root.get_local("PWD").unwrap().exported);
+
+        // Otherwise the innermost variable is assigned.
+        child.declare("PWD", var("/b", false));
+        inner.set_global("PWD", Value::Str("/c".to_owned()));
+        assert_eq!(get(&child, "PWD").as_deref(), Some("/c"));
+        assert_eq!(get(&root, "PWD").as_deref(), Some("/a"));
+        assert!(!child.get_local("PWD").unwrap().exported);
+    }
+
+    #[test]
+    fn exports() {
+        let root = Arc::new(Scope::default());
+        root.declare("A", var("a", true));
+        root.declare("B", var("b", true));
+        root.declare("C", var("c", false));
+        root.declare(
+            "L",
+            Var {
+                value: Value::List(vec![Value::Str("l".to_owned())]),
+                exported: true,
+            },
+        );
+        let child = Scope::child(&root);
+        child.declare("A", var("inner", true));
+        // Hides the exported variable of the enclosing scope.
+        child.declare("B", var("hidden", false));
+
+        let exports: Vec<_> = child.exports().into_iter().collect();
+        assert_eq!(
+            exports,
+            [("A".to_owned(), "inner".to_owned())],
+            "{:?}",
+            exports
+        );
+        let exports: Vec<_> = root.exports().into_iter().collect();
+        assert_eq!(
+            exports,
+            [
+                ("A".to_owned(), "a".to_owned()),
+                ("B".to_owned(), "b".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn variables() {
+        assert_eq!(
+            output("let x = 1; { let x = 2; echo $x }; echo $x").unwrap(),
+            "2\n1"
+        );
+        assert_eq!(output("let x = 1; { set x = 2 }; echo $x").unwrap(), "2");
+        assert_eq!(
+            output("export JSH_TEST_X=a=b; sh -c 'echo $JSH_TEST_X'").unwrap(),
+            "a=b"
+        );
+        assert_eq!(
+            output("let JSH_TEST_Y = a; sh -c 'echo \"[$JSH_TEST_Y]\"'").unwrap(),
+            "[]"
+        );
+        assert_eq!(output("let x = 1; unset x; echo \"[$x]\"").unwrap(), "[]");
+    }
+}
@@ -0,0 +1,65 @@
+use std::fmt::{self, Display};
+use std::process::ExitStatus;
+
+/// The exit status of a command.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Status(i32);
+
+impl Status {
+    pub const SUCCESS: Status = Status(0);
+    pub const FAILURE: Status = Status(1);
+
+    /// Source code could not be parsed.
+    pub const SYNTAX_ERROR: Status = Status(2);
+    /// A builtin was called with invalid arguments.
+    pub const USAGE: Status = Status(2);
+    /// Command was found but could not be executed.
+    pub const NOT_EXECUTABLE: Status = Status(126);
+    /// Command could not be found.
+    pub const NOT_FOUND: Status = Status(127);
+
+    #[inline]
+    pub fn new(code: i32) -> Status {
+        Status(code)
+    }
+
+    /// Status of a command terminated by a signal.
+    #[inline]
+    pub fn from_signal(signal: i32) -> Status {
+        Status(128 + signal)
+    }
+
+    #[inline]
+    pub fn code(self) -> i32 {
+        self.0
+    }
+
+    #[inline]
+    pub fn is_success(self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl Default for Status {
+    fn default() -> Self {
+        Status::SUCCESS
+    }
+}
+
+impl From<ExitStatus> for Status {
+    fn from(status: ExitStatus) -> Self {
+        use std::os::unix::process::ExitStatusExt;
+
+        match (status.code(), status.signal()) {
+            (Some(code), _) => Status(code),
+            (None, Some(signal)) => Status::from_signal(signal),
+            (None, None) => Status::FAILURE,
+        }
+    }
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
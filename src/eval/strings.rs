@@ -0,0 +1,182 @@
+//! String operations shared by the string builtins and the operators of
+//! parameter expansion.
+//!
+//! Offsets and lengths count chars rather than bytes, so a multi-byte char is
+//! never split.
+
+use crate::eval::glob::Pattern;
+
+/// The byte offset of each char in `s`, followed by the length of `s`.
+fn char_offsets(s: &str) -> Vec<usize> {
+    s.char_indices()
+        .map(|(offset, _)| offset)
+        .chain(Some(s.len()))
+        .collect()
+}
+
+/// The chars from `offset`, or `length` chars from `offset`. A negative offset
+/// counts from the end, as does a negative length. Offsets past either end are
+/// clamped to it.
+pub fn substring(s: &str, offset: i64, length: Option<i64>) -> &str {
+    let offsets = char_offsets(s);
+    let len = (offsets.len() - 1) as i64;
+
+    let start = if offset < 0 {
+        (len + offset).max(0)
+    } else {
+        offset.min(len)
+    };
+    let end = match length {
+        None => len,
+        Some(length) if length < 0 => (len + length).max(start),
+        Some(length) => start.saturating_add(length).min(len),
+    };
+
+    &s[offsets[start as usize]..offsets[end as usize]]
+}
+
+/// Removes the shortest, or longest, prefix matching a pattern.
+pub fn trim_prefix<'a>(s: &'a str, pattern: &Pattern, longest: bool) -> &'a str {
+    let chars: Vec<char> = s.chars().collect();
+    let offsets = char_offsets(s);
+
+    let mut ends = 0..=chars.len();
+    let end = if longest {
+        ends.rev().find(|&end| pattern.matches(&chars[..end]))
+    } else {
+        ends.find(|&end| pattern.matches(&chars[..end]))
+    };
+
+    match end {
+        Some(end) => &s[offsets[end]..],
+        None => s,
+    }
+}
+
+/// Removes the shortest, or longest, suffix matching a pattern.
+pub fn trim_suffix<'a>(s: &'a str, pattern: &Pattern, longest: bool) -> &'a str {
+    let chars: Vec<char> = s.chars().collect();
+    let offsets = char_offsets(s);
+
+    let mut starts = 0..=chars.len();
+    let start = if longest {
+        starts.find(|&start| pattern.matches(&chars[start..]))
+    } else {
+        starts.rev().find(|&start| pattern.matches(&chars[start..]))
+    };
+
+    match start {
+        Some(start) => &s[..offsets[start]],
+        None => s,
+    }
+}
+
+/// Replaces the first, or every, longest match of a pattern.
+pub fn replace(s: &str, pattern: &Pattern, with: &str, all: bool) -> String {
+    if pattern.is_empty() {
+        return s.to_owned();
+    }
+
+    let chars: Vec<char> = s.chars().collect();
+    let mut out = String::with_capacity(s.len());
+    let mut start = 0;
+    let mut replaced = false;
+
+    while start < chars.len() {
+        let end = if replaced && !all {
+            None
+        } else {
+            (start + 1..=chars.len())
+                .rev()
+                .find(|&end| pattern.matches(&chars[start..end]))
+        };
+
+        match end {
+            Some(end) => {
+                out.push_str(with);
+                start = end;
+                replaced = true;
+            }
+            None => {
+                out.push(chars[start]);
+                start += 1;
+            }
+        }
+    }
+
+    out
+}
+
+/// Converts the first char, or all chars, to upper or lower case.
+pub fn convert_case(s: &str, upper: bool, all: bool) -> String {
+    let convert = |s: &str| {
+        if upper {
+            s.to_uppercase()
+        } else {
+            s.to_lowercase()
+        }
+    };
+
+    match s.chars().next() {
+        Some(_) if all => convert(s),
+        Some(first) => {
+            let rest = &s[first.len_utf8()..];
+            convert(&s[..first.len_utf8()]) + rest
+        }
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn substrings() {
+        assert_eq!(substring("hello", 1, None), "ello");
+        assert_eq!(substring("hello", 1, Some(3)), "ell");
+        assert_eq!(substring("hello", -3, None), "llo");
+        assert_eq!(substring("hello", 1, Some(-1)), "ell");
+        assert_eq!(substring("hello", 10, None), "");
+        assert_eq!(substring("hello", -10, Some(2)), "he");
+        assert_eq!(substring("hello", 3, Some(-4)), "");
+        assert_eq!(substring("héllo", 1, Some(2)), "él");
+        assert_eq!(substring("hello", 0, Some(i64::MAX)), "hello");
+    }
+
+    #[test]
+    fn trimming() {
+        let path = "/usr/lib/x.tar.gz";
+        let slash = Pattern::new("*/");
+        let ext = Pattern::new(".*");
+
+        assert_eq!(trim_prefix(path, &slash, false), "usr/lib/x.tar.gz");
+        assert_eq!(trim_prefix(path, &slash, true), "x.tar.gz");
+        assert_eq!(trim_suffix(path, &ext, false), "/usr/lib/x.tar");
+        assert_eq!(trim_suffix(path, &ext, true), "/usr/lib/x");
+        assert_eq!(trim_prefix(path, &Pattern::new("x"), true), path);
+        assert_eq!(trim_suffix("aé", &Pattern::new("?"), false), "a");
+    }
+
+    #[test]
+    fn replacing() {
+        let pattern = Pattern::new("a*");
+        assert_eq!(replace("banana", &Pattern::new("a"), "o", false), "bonana");
+        assert_eq!(replace("banana", &Pattern::new("a"), "o", true), "bonono");
+        assert_eq!(replace("banana", &pattern, "", false), "b");
+        assert_eq!(
+            replace("banana", &Pattern::new("[bn]"), "_", true),
+            "_a_a_a"
+        );
+        assert_eq!(replace("banana", &Pattern::new(""), "x", true), "banana");
+    }
+
+    #[test]
+    fn case() {
+        assert_eq!(convert_case("hello world", true, false), "Hello world");
+        assert_eq!(convert_case("hello world", true, true), "HELLO WORLD");
+        assert_eq!(convert_case("HELLO", false, false), "hELLO");
+        assert_eq!(convert_case("éa", true, false), "Éa");
+        assert_eq!(convert_case("", true, true), "");
+    }
+}
@@ -0,0 +1,194 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::eval::scope::Scope;
+use crate::eval::{Error, ErrorKind};
+use crate::parse::ast::Lambda;
+use crate::parse::Source;
+
+/// A value, such as the value of a variable or an argument to a command.
+#[derive(Clone, Debug)]
+pub enum Value {
+    Str(String),
+    List(Vec<Value>),
+    Map(BTreeMap<String, Value>),
+    Fn(Arc<Closure>),
+    /// An error caught by `try`.
+    Exception(Arc<Error>),
+}
+
+impl Value {
+    /// The name of the type of the value, for error messages.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Value::Str(_) => "string",
+            Value::List(_) => "list",
+            Value::Map(_) => "map",
+            Value::Fn(_) => "function",
+            Value::Exception(_) => "exception",
+        }
+    }
+
+    /// Indexes the value, as in `$x[0]`.
+    ///
+    /// Lists and strings take an index, negative from the end, or a slice
+    /// `FROM:TO` with either bound optional. Strings are indexed by chars.
+    /// Maps take a key, and exceptions take `message`, `status` or `command`.
+    pub fn index(&self, index: &str) -> Result<Value, ErrorKind> {
+        match self {
+            Value::Str(s) => {
+                let chars: Vec<char> = s.chars().collect();
+                match parse_index(index, chars.len())? {
+                    Index::Single(i) => Ok(Value::Str(chars[i].to_string())),
+                    Index::Slice(from, to) => Ok(Value::Str(chars[from..to].iter().collect())),
+                }
+            }
+            Value::List(list) => match parse_index(index, list.len())? {
+                Index::Single(i) => Ok(list[i].clone()),
+                Index::Slice(from, to) => Ok(Value::List(list[from..to].to_vec())),
+            },
+            Value::Map(map) => match map.get(index) {
+                Some(value) => Ok(value.clone()),
+                None => Err(ErrorKind::NoSuchKey(index.to_owned())),
+            },
+            Value::Exception(err) => match (index, &err.kind) {
+                ("message", _) => Ok(Value::Str(err.to_string())),
+                ("status", _) => Ok(Value::Str(err.status().to_string())),
+                ("command", ErrorKind::Failed(_, command)) => Ok(Value::Str(command.clone())),
+                _ => Err(ErrorKind::NoSuchKey(index.to_owned())),
+            },
+            Value::Fn(_) => Err(ErrorKind::Type("an indexable value", self.kind())),
+        }
+    }
+
+    /// Formats the value as it would be written in code.
+    pub fn repr(&self) -> String {
+        match self {
+            Value::Str(s) => quote(s),
+            value => value.to_string(),
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Value {
+    fn default() -> Value {
+        Value::Str(String::new())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Value {
+        Value::Str(s)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Value {
+        Value::Str(s.to_owned())
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Str(s) => f.write_str(s),
+            Value::List(list) => {
+                let elems: Vec<String> = list.iter().map(Value::repr).collect();
+                write!(f, "[{}]", elems.join(" "))
+            }
+            Value::Map(map) if map.is_empty() => f.write_str("[&]"),
+            Value::Map(map) => {
+                let entries: Vec<String> = map
+                    .iter()
+                    .map(|(key, value)| format!("&{}={}", key, value.repr()))
+                    .collect();
+                write!(f, "[{}]", entries.join(" "))
+            }
+            Value::Fn(_) => f.write_str("<closure>"),
+            Value::Exception(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+/// A parsed index into a sequence.
+enum Index {
+    Single(usize),
+    Slice(usize, usize),
+}
+
+fn parse_index(index: &str, len: usize) -> Result<Index, ErrorKind> {
+    let bad_index = || ErrorKind::BadIndex(index.to_owned());
+    let out_of_range = || ErrorKind::IndexOutOfRange(index.to_owned());
+
+    // Resolves a possibly negative position, allowing the end of the sequence.
+    let position = |s: &str, default: usize| -> Result<usize, ErrorKind> {
+        if s.is_empty() {
+            return Ok(default);
+        }
+
+        let i: isize = s.parse().map_err(|_| bad_index())?;
+        let i = if i < 0 { len as isize + i } else { i };
+        if i < 0 || i as usize > len {
+            return Err(out_of_range());
+        }
+        Ok(i as usize)
+    };
+
+    match index.find(':') {
+        Some(colon) => {
+            let from = position(&index[..colon], 0)?;
+            let to = position(&index[colon + 1..], len)?;
+            if from > to {
+                return Err(out_of_range());
+            }
+            Ok(Index::Slice(from, to))
+        }
+        None if index.is_empty() => Err(bad_index()),
+        None => match position(index, 0)? {
+            i if i < len => Ok(Index::Single(i)),
+            _ => Err(out_of_range()),
+        },
+    }
+}
+
+/// Quotes a string if it would not be read back as a single word.
+fn quote(s: &str) -> String {
+    let is_plain = !s.is_empty()
+        && s.chars().all(|c| {
+            c.is_alphanumeric()
+                || matches!(c, '-' | '_' | '.' | '/' | ',' | ':' | '+' | '=' | '@' | '%')
+        });
+
+    if is_plain {
+        s.to_owned()
+    } else if !s.contains('\'') {
+        format!("'{}'", s)
+    } else {
+        let mut quoted = String::from("\"");
+        for c in s.chars() {
+            if matches!(c, '"' | '\\' | '$') {
+                quoted.push('\\');
+            }
+            quoted.push(c);
+        }
+        quoted.push('"');
+        quoted
+    }
+}
+
+/// A lambda together with the scope it was defined in.
+#[derive(Debug)]
+pub struct Closure {
+    pub lambda: Arc<Lambda>,
+    pub scope: Arc<Scope>,
+    /// The source the lambda was parsed from.
+    pub src: Option<Arc<Source>>,
+}
@@ -3,23 +3,48 @@
 mod args;
 mod cli;
 mod editor;
+mod eval;
+mod parse;
 mod shell;
 
+use std::thread;
+
 use anyhow::Result;
 
 use crate::args::LaunchMode;
 use crate::shell::Shell;
 
-#[tokio::main]
-async fn main() -> Result<()> {
+/// Stack size of the thread the shell runs on, nested blocks are evaluated
+/// recursively.
+const STACK_SIZE: usize = 256 * 1024 * 1024;
+
+fn main() -> Result<()> {
+    let shell = thread::Builder::new()
+        .name("jsh".to_owned())
+        .stack_size(STACK_SIZE)
+        .spawn(|| {
+            let mut runtime = tokio::runtime::Builder::new()
+                .basic_scheduler()
+                .enable_all()
+                .build()?;
+            runtime.block_on(run())
+        })?;
+
+    shell.join().unwrap()
+}
+
+async fn run() -> Result<()> {
     let (mode, args) = args::args();
     let shell = Shell::new(args)?;
 
     // TODO: Add logging to file.
 
-    match mode {
-        LaunchMode::Exec(cmd) => shell.exec_command(&cmd).await,
-        LaunchMode::Files(files) => shell.exec_files(&files).await,
-        LaunchMode::Interactive => shell.interactive().await,
-    }
+    let status = match mode {
+        LaunchMode::Exec(cmd) => shell.exec_command(&cmd).await?,
+        LaunchMode::Script(file) => shell.exec_script(&file).await?,
+        LaunchMode::Stdin => shell.exec_stdin().await?,
+        LaunchMode::Interactive => shell.interactive().await?,
+    };
+
+    std::process::exit(status.code());
 }
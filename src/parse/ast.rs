@@ -0,0 +1,268 @@
+use std::sync::Arc;
+
+use super::span::Span;
+
+/// A sequence of statements, such as a whole script or the body of a block.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Chunk {
+    pub stmts: Vec<Stmt>,
+    pub span: Span,
+}
+
+/// A list of pipelines joined by `&&` and `||`, optionally run in the
+/// background with `&`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Stmt {
+    pub first: Pipeline,
+    pub rest: Vec<(LogicOp, Pipeline)>,
+    pub background: bool,
+    pub span: Span,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum LogicOp {
+    /// `&&`
+    And,
+    /// `||`
+    Or,
+}
+
+/// Commands joined by `|`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Pipeline {
+    pub commands: Vec<Command>,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Command {
+    pub kind: CommandKind,
+    pub redirs: Vec<Redir>,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum CommandKind {
+    /// A command name followed by arguments and options, may be empty if the
+    /// command only has redirections.
+    Simple { words: Vec<Word>, opts: Vec<Opt> },
+    /// `{ ... }`
+    Block(Chunk),
+    /// `if COND { ... } elif COND { ... } else { ... }`
+    If {
+        branches: Vec<(Stmt, Chunk)>,
+        else_body: Option<Chunk>,
+    },
+    /// `while COND { ... }`, or `until COND { ... }` if `until`.
+    While {
+        cond: Stmt,
+        body: Chunk,
+        until: bool,
+    },
+    /// `for NAME in WORD... { ... }`
+    For {
+        var: String,
+        items: Vec<Word>,
+        body: Chunk,
+    },
+    /// `fn NAME {|...| ... }`, or `fn NAME { ... }` without parameters.
+    Fn { name: String, lambda: Arc<Lambda> },
+    /// `try { ... } catch NAME { ... } finally { ... }`, with either the
+    /// `catch` or `finally` clause optional.
+    Try {
+        body: Chunk,
+        catch: Option<Catch>,
+        finally: Option<Chunk>,
+    },
+}
+
+/// The `catch` clause of a `try`, binding the exception to `var` if named.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Catch {
+    pub var: Option<String>,
+    pub body: Chunk,
+}
+
+/// `&name=value`, a named argument or option.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Opt {
+    pub name: String,
+    pub value: Word,
+    pub span: Span,
+}
+
+/// `{|a b &name=default| ...}`, an anonymous function.
+///
+/// Lambdas are shared with the closures created from them, which may outlive
+/// the code they were defined in.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Lambda {
+    pub params: Vec<String>,
+    /// The parameter given the arguments after the others as a list, as in
+    /// `{|a @rest| ...}`.
+    pub rest: Option<String>,
+    /// Options the lambda accepts, with their default values.
+    pub opts: Vec<Opt>,
+    pub body: Chunk,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Redir {
+    /// Explicit file descriptor, as in `2>file`.
+    pub fd: Option<u32>,
+    pub op: RedirOp,
+    pub target: Word,
+    pub span: Span,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum RedirOp {
+    /// `<`
+    Read,
+    /// `>`
+    Write,
+    /// `>>`
+    Append,
+    /// `<>`
+    ReadWrite,
+    /// `<&`
+    DupRead,
+    /// `>&`
+    DupWrite,
+    /// `&>`
+    WriteAll,
+    /// `&>>`
+    AppendAll,
+    /// `<<` or `<<-`, the target is the body of the here-document.
+    HereDoc,
+    /// `<<<`
+    HereStr,
+}
+
+impl RedirOp {
+    /// The file descriptor redirected when none is given explicitly.
+    pub fn default_fd(self) -> u32 {
+        match self {
+            RedirOp::Read
+            | RedirOp::ReadWrite
+            | RedirOp::DupRead
+            | RedirOp::HereDoc
+            | RedirOp::HereStr => 0,
+            RedirOp::Write
+            | RedirOp::Append
+            | RedirOp::DupWrite
+            | RedirOp::WriteAll
+            | RedirOp::AppendAll => 1,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Word {
+    pub parts: Vec<WordPart>,
+    pub span: Span,
+}
+
+impl Word {
+    /// Returns the literal text of the word if it consists only of unquoted
+    /// text, such as a keyword.
+    pub fn as_bare(&self) -> Option<&str> {
+        match &*self.parts {
+            [WordPart {
+                kind: WordPartKind::Bare(s),
+                ..
+            }] => Some(s),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct WordPart {
+    pub kind: WordPartKind,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum WordPartKind {
+    /// Unquoted text.
+    Bare(String),
+    /// Quoted or escaped text, taken literally.
+    Quoted(String),
+    /// `"..."`
+    DoubleQuoted(Vec<WordPart>),
+    /// `$name` or `${name}`.
+    Param(ParamExp),
+    /// `{|...| ...}`
+    Lambda(Arc<Lambda>),
+    /// `[a b c]`
+    List(Vec<Word>),
+    /// `[&key=value ...]`, or `[&]` if empty.
+    Map(Vec<Opt>),
+    /// `$(...)`, the output of code as a string.
+    Output(Arc<Chunk>),
+    /// `$((...))`
+    Arith(ArithExpr),
+    /// `(...)`, the output of code as values.
+    Capture(Arc<Chunk>),
+    /// `<(...)`, or `>(...)` if not `input`, the path of a pipe from or to
+    /// code running concurrently with the command.
+    ProcSub { chunk: Arc<Chunk>, input: bool },
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ParamExp {
+    pub name: String,
+    /// Was the expansion written as `${name}`.
+    pub braced: bool,
+    /// Was the expansion written as `$@name`, spreading a list into separate
+    /// arguments.
+    pub explode: bool,
+    /// Indices applied to the value, as in `$name[0][1:]`.
+    pub indices: Vec<Word>,
+    pub op: Option<ParamOp>,
+}
+
+/// An operator applied to the value of a braced expansion.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ParamOp {
+    /// `${name:-word}`, expands to the word if the variable is unset or empty.
+    Default(Word),
+    /// `${name:?word}`, fails with the word as the message if the variable is
+    /// unset or empty.
+    Error(Word),
+    /// `${#name}`, the number of chars in a string or elements in a list or
+    /// map.
+    Length,
+    /// `${name#pattern}`, or `${name##pattern}` if `longest`, removes the
+    /// shortest or longest prefix matching the pattern.
+    TrimPrefix { pattern: Word, longest: bool },
+    /// `${name%pattern}`, or `${name%%pattern}` if `longest`, removes the
+    /// shortest or longest suffix matching the pattern.
+    TrimSuffix { pattern: Word, longest: bool },
+    /// `${name/pattern/word}`, or `${name//pattern/word}` if `all`, replaces
+    /// the first or every longest match of the pattern with the word.
+    Replace {
+        pattern: Word,
+        with: Option<Word>,
+        all: bool,
+    },
+    /// `${name^}` or `${name^^}` if `upper`, or `${name,}` or `${name,,}`,
+    /// converts the first char, or all chars if `all`, to upper or lower case.
+    Case { upper: bool, all: bool },
+    /// `${name:offset}` or `${name:offset:length}`, the chars from an offset,
+    /// counting from the end if it is negative.
+    Substring {
+        offset: ArithExpr,
+        length: Option<ArithExpr>,
+    },
+}
+
+/// An arithmetic expression, kept as text until it is evaluated so syntax
+/// errors are raised as exceptions.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ArithExpr {
+    pub text: String,
+    pub span: Span,
+}
@@ -0,0 +1,46 @@
+use thiserror::Error;
+
+use super::span::Span;
+
+#[derive(Clone, Debug, Error, Eq, PartialEq)]
+pub enum ParseErrorKind {
+    #[error("unterminated single quote")]
+    UnterminatedSingleQuote,
+    #[error("unterminated double quote")]
+    UnterminatedDoubleQuote,
+    #[error("unterminated `{0}`")]
+    Unterminated(&'static str),
+    #[error("unexpected end of input")]
+    UnexpectedEof,
+    #[error("unexpected `{0}`")]
+    Unexpected(String),
+    #[error("expected {0}")]
+    Expected(&'static str),
+    #[error("invalid variable name")]
+    InvalidVarName,
+}
+
+#[derive(Clone, Debug, Error, Eq, PartialEq)]
+#[error("{kind}")]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub span: Span,
+}
+
+impl ParseError {
+    pub fn new(kind: ParseErrorKind, span: Span) -> ParseError {
+        ParseError { kind, span }
+    }
+
+    /// Returns `true` if the error was caused by the input ending early, and
+    /// could be resolved by reading more input.
+    pub fn is_incomplete(&self) -> bool {
+        matches!(
+            self.kind,
+            ParseErrorKind::UnterminatedSingleQuote
+                | ParseErrorKind::UnterminatedDoubleQuote
+                | ParseErrorKind::Unterminated(_)
+                | ParseErrorKind::UnexpectedEof
+        )
+    }
+}
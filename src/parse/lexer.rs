@@ -0,0 +1,592 @@
+use super::ast::RedirOp;
+use super::error::{ParseError, ParseErrorKind};
+use super::span::Span;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TokenKind {
+    /// A word, its internal structure is left to the parser.
+    Word,
+    /// A redirection operator, with an optional file descriptor prefix.
+    Redir(Option<u32>, RedirOp),
+    /// `<<` or `<<-`, with an optional file descriptor prefix and whether
+    /// leading tabs are stripped.
+    HereDoc(Option<u32>, bool),
+    /// `\n`
+    Newline,
+    /// `;`
+    Semi,
+    /// `|`
+    Pipe,
+    /// `&`
+    Amp,
+    /// `&&`
+    AndAnd,
+    /// `||`
+    OrOr,
+    /// `{`, when followed by whitespace.
+    LBrace,
+    /// `}`
+    RBrace,
+    /// `)`
+    RParen,
+    Eof,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+/// Splits source code into tokens.
+///
+/// The lexer only finds the boundaries of words, respecting quotes and
+/// escapes, the parser is responsible for the contents of words.
+#[derive(Clone, Debug)]
+pub struct Lexer<'a> {
+    src: &'a str,
+    pos: usize,
+    end: usize,
+    /// Here-document bodies already read from the lines following the current
+    /// line, as the position of the newline ending the current line and the
+    /// position after the bodies.
+    heredoc_skip: Option<(usize, usize)>,
+}
+
+type Result<T> = std::result::Result<T, ParseError>;
+
+impl<'a> Lexer<'a> {
+    /// Creates a lexer over the region `span` of `src`.
+    pub fn new(src: &'a str, span: Span) -> Lexer<'a> {
+        Lexer {
+            src,
+            pos: span.start,
+            end: span.end,
+            heredoc_skip: None,
+        }
+    }
+
+    /// The current byte position.
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+
+    pub fn src(&self) -> &'a str {
+        self.src
+    }
+
+    fn byte(&self, pos: usize) -> Option<u8> {
+        if pos < self.end {
+            Some(self.src.as_bytes()[pos])
+        } else {
+            None
+        }
+    }
+
+    fn token(&mut self, kind: TokenKind, len: usize) -> Token {
+        let start = self.pos;
+        self.pos += len;
+
+        Token {
+            kind,
+            span: Span::new(start, self.pos),
+        }
+    }
+
+    /// Skips spaces, line continuations and comments.
+    fn skip_blanks(&mut self) {
+        while let Some(b) = self.byte(self.pos) {
+            match b {
+                b' ' | b'\t' | b'\r' => self.pos += 1,
+                b'\\' if self.byte(self.pos + 1) == Some(b'\n') => self.pos += 2,
+                b'#' => {
+                    while let Some(b) = self.byte(self.pos) {
+                        if b == b'\n' {
+                            break;
+                        }
+                        self.pos += 1;
+                    }
+                }
+                _ => break,
+            }
+        }
+    }
+
+    pub fn next_token(&mut self) -> Result<Token> {
+        self.skip_blanks();
+
+        let b = match self.byte(self.pos) {
+            Some(b) => b,
+            None => return Ok(self.token(TokenKind::Eof, 0)),
+        };
+        let next = self.byte(self.pos + 1);
+
+        let token = match (b, next) {
+            (b'\n', _) => {
+                let token = self.token(TokenKind::Newline, 1);
+
+                // Skip the bodies of here-documents on the line.
+                if let Some((newline, resume)) = self.heredoc_skip {
+                    if newline == token.span.start {
+                        self.pos = resume;
+                        self.heredoc_skip = None;
+                    }
+                }
+
+                token
+            }
+            (b';', _) => self.token(TokenKind::Semi, 1),
+            (b'|', Some(b'|')) => self.token(TokenKind::OrOr, 2),
+            (b'|', _) => self.token(TokenKind::Pipe, 1),
+            (b'&', Some(b'&')) => self.token(TokenKind::AndAnd, 2),
+            (b'&', Some(b'>')) => match self.byte(self.pos + 2) {
+                Some(b'>') => self.token(TokenKind::Redir(None, RedirOp::AppendAll), 3),
+                _ => self.token(TokenKind::Redir(None, RedirOp::WriteAll), 2),
+            },
+            // An option, such as `&sep=,`.
+            (b'&', Some(b)) if b.is_ascii_alphabetic() || b == b'_' => self.word()?,
+            (b'&', _) => self.token(TokenKind::Amp, 1),
+            // Process substitution, as in `<(cmd)`.
+            (b'<', Some(b'(')) | (b'>', Some(b'(')) => self.word()?,
+            (b'<', _) | (b'>', _) => {
+                let (kind, len) = self.redir_op(self.pos, None);
+                self.token(kind, len)
+            }
+            (b'{', next) if is_block_delim(next) => self.token(TokenKind::LBrace, 1),
+            (b'}', _) => self.token(TokenKind::RBrace, 1),
+            (b')', _) => self.token(TokenKind::RParen, 1),
+            (b, _) if b.is_ascii_digit() => match self.fd_prefix() {
+                Some((fd, digits)) => {
+                    let (kind, len) = self.redir_op(self.pos + digits, Some(fd));
+                    self.token(kind, digits + len)
+                }
+                None => self.word()?,
+            },
+            _ => self.word()?,
+        };
+
+        Ok(token)
+    }
+
+    /// Reads the redirection operator at `pos`, returning the token kind and
+    /// the length of the operator.
+    fn redir_op(&self, pos: usize, fd: Option<u32>) -> (TokenKind, usize) {
+        let (op, len) = match (self.byte(pos), self.byte(pos + 1), self.byte(pos + 2)) {
+            (Some(b'<'), Some(b'<'), Some(b'<')) => (RedirOp::HereStr, 3),
+            (Some(b'<'), Some(b'<'), Some(b'-')) => return (TokenKind::HereDoc(fd, true), 3),
+            (Some(b'<'), Some(b'<'), _) => return (TokenKind::HereDoc(fd, false), 2),
+            (Some(b'<'), Some(b'>'), _) => (RedirOp::ReadWrite, 2),
+            (Some(b'<'), Some(b'&'), _) => (RedirOp::DupRead, 2),
+            (Some(b'<'), _, _) => (RedirOp::Read, 1),
+            (Some(b'>'), Some(b'>'), _) => (RedirOp::Append, 2),
+            (Some(b'>'), Some(b'&'), _) => (RedirOp::DupWrite, 2),
+            (Some(b'>'), _, _) => (RedirOp::Write, 1),
+            _ => unreachable!("not a redirection operator"),
+        };
+        (TokenKind::Redir(fd, op), len)
+    }
+
+    /// Reads the body of a here-document from the lines following the current
+    /// line, returning the span of the body.
+    ///
+    /// The body is skipped when the lexer reaches the end of the current line.
+    pub fn read_heredoc(&mut self, delim: &str, strip_tabs: bool) -> Result<Span> {
+        // Find the end of the current line.
+        let mut lexer = self.clone();
+        let newline = loop {
+            let token = lexer.next_token()?;
+            match token.kind {
+                TokenKind::Newline => break token.span.start,
+                TokenKind::Eof => {
+                    return Err(ParseError::new(
+                        ParseErrorKind::Unterminated("here-document"),
+                        token.span,
+                    ))
+                }
+                _ => {}
+            }
+        };
+
+        // Bodies of multiple here-documents on a line follow each other.
+        let start = match self.heredoc_skip {
+            Some((skip_newline, resume)) if skip_newline == newline => resume,
+            _ => newline + 1,
+        };
+
+        let mut line_start = start;
+        while line_start < self.end {
+            let line_end = self.src[line_start..self.end]
+                .find('\n')
+                .map(|i| line_start + i)
+                .unwrap_or(self.end);
+
+            let mut line = &self.src[line_start..line_end];
+            if strip_tabs {
+                line = line.trim_start_matches('\t');
+            }
+
+            if line == delim {
+                let resume = (line_end + 1).min(self.end);
+                self.heredoc_skip = Some((newline, resume));
+
+                return Ok(Span::new(start, line_start));
+            }
+
+            line_start = line_end + 1;
+        }
+
+        Err(ParseError::new(
+            ParseErrorKind::Unterminated("here-document"),
+            Span::new(start, self.end),
+        ))
+    }
+
+    /// Reads a file descriptor directly followed by a redirection operator,
+    /// returning the descriptor and the number of digits.
+    fn fd_prefix(&self) -> Option<(u32, usize)> {
+        let mut pos = self.pos;
+        while let Some(b) = self.byte(pos) {
+            if !b.is_ascii_digit() {
+                break;
+            }
+            pos += 1;
+        }
+
+        match self.byte(pos) {
+            Some(b'<') | Some(b'>') => {
+                let fd = self.src[self.pos..pos].parse().ok()?;
+                Some((fd, pos - self.pos))
+            }
+            _ => None,
+        }
+    }
+
+    /// Reads a word.
+    fn word(&mut self) -> Result<Token> {
+        let start = self.pos;
+        let end = self.scan_word(start)?;
+
+        self.pos = end;
+        Ok(Token {
+            kind: TokenKind::Word,
+            span: Span::new(start, end),
+        })
+    }
+
+    /// Finds the end of the word starting at `pos`.
+    fn scan_word(&self, start: usize) -> Result<usize> {
+        self.scan_word_in(start, false)
+    }
+
+    /// Finds the end of the word starting at `pos`, `in_list` if the word is an
+    /// element of a list, which ends at an unmatched `]`.
+    fn scan_word_in(&self, start: usize, in_list: bool) -> Result<usize> {
+        let mut pos = start;
+        // Depth of unquoted braces, as in `{a,b}`.
+        let mut brace_depth = 0usize;
+        // Depth of unquoted brackets, as in `[ch]`.
+        let mut bracket_depth = 0usize;
+        // Start of the value of an option, which may be a list as in `&k=[v]`.
+        let mut value_start = None;
+
+        while let Some(b) = self.byte(pos) {
+            match b {
+                // Output capture, as in `(cmd)`, or process substitution.
+                b'(' if pos == start || Some(pos) == value_start => pos = self.skip_capture(pos)?,
+                b'<' | b'>' if pos == start && self.byte(pos + 1) == Some(b'(') => {
+                    pos = self.skip_capture(pos + 1)?
+                }
+                b' ' | b'\t' | b'\r' | b'\n' | b';' | b'|' | b'<' | b'>' | b')' => break,
+                // Options start with `&`.
+                b'&' if pos > start => break,
+                b'}' if brace_depth == 0 => break,
+                b'=' if value_start.is_none() && self.byte(start) == Some(b'&') => {
+                    pos += 1;
+                    value_start = Some(pos);
+                }
+                b'[' if (pos == start || Some(pos) == value_start)
+                    && !is_list_delim(self.byte(pos + 1)) =>
+                {
+                    pos = self.scan_list(pos)?.1;
+                }
+                b']' if in_list && bracket_depth == 0 => break,
+                b'[' => {
+                    bracket_depth += 1;
+                    pos += 1;
+                }
+                b']' => {
+                    bracket_depth = bracket_depth.saturating_sub(1);
+                    pos += 1;
+                }
+                b'{' if self.byte(pos + 1) == Some(b'|') => pos = self.skip_lambda(pos)?.1,
+                b'}' => {
+                    brace_depth -= 1;
+                    pos += 1;
+                }
+                b'{' => {
+                    brace_depth += 1;
+                    pos += 1;
+                }
+                b'\\' => match self.byte(pos + 1) {
+                    Some(_) => pos = self.next_char(pos + 1),
+                    None => {
+                        return Err(ParseError::new(
+                            ParseErrorKind::UnexpectedEof,
+                            Span::new(pos, pos + 1),
+                        ))
+                    }
+                },
+                b'\'' => pos = self.skip_single_quote(pos)?,
+                b'"' => pos = self.skip_double_quote(pos)?,
+                b'$' => pos = self.skip_dollar(pos)?,
+                _ => pos += 1,
+            }
+        }
+
+        Ok(pos)
+    }
+
+    /// Scans a list or map starting with `[` at `pos`, returning the spans of
+    /// the elements and the position after the closing `]`.
+    pub fn scan_list(&self, start: usize) -> Result<(Vec<Span>, usize)> {
+        let mut elems = Vec::new();
+        let mut pos = start + 1;
+
+        loop {
+            // Elements may be separated by newlines and comments.
+            while let Some(b) = self.byte(pos) {
+                match b {
+                    b' ' | b'\t' | b'\r' | b'\n' => pos += 1,
+                    b'#' => {
+                        while self.byte(pos).is_some_and(|b| b != b'\n') {
+                            pos += 1;
+                        }
+                    }
+                    _ => break,
+                }
+            }
+
+            match self.byte(pos) {
+                None => {
+                    return Err(ParseError::new(
+                        ParseErrorKind::Unterminated("["),
+                        Span::new(start, self.end),
+                    ))
+                }
+                Some(b']') => return Ok((elems, pos + 1)),
+                Some(b) => {
+                    let end = self.scan_word_in(pos, true)?;
+                    if end == pos {
+                        return Err(ParseError::new(
+                            ParseErrorKind::Unexpected((b as char).to_string()),
+                            Span::new(pos, pos + 1),
+                        ));
+                    }
+
+                    elems.push(Span::new(pos, end));
+                    pos = end;
+                }
+            }
+        }
+    }
+
+    /// Skips a lambda starting at `pos`, returning the position of the `|`
+    /// closing the parameters and the position after the closing `}`.
+    pub fn skip_lambda(&self, pos: usize) -> Result<(usize, usize)> {
+        let unterminated =
+            || ParseError::new(ParseErrorKind::Unterminated("{|"), Span::new(pos, self.end));
+
+        let bar = match self.src[pos + 2..self.end].find('|') {
+            Some(i) => pos + 2 + i,
+            None => return Err(unterminated()),
+        };
+
+        // The body is lexed as code, so braces in comments and quotes are not
+        // counted.
+        let mut lexer = Lexer::new(self.src, Span::new(bar + 1, self.end));
+        let mut depth = 0usize;
+
+        loop {
+            let token = lexer.next_token()?;
+            match token.kind {
+                TokenKind::LBrace => depth += 1,
+                TokenKind::RBrace if depth == 0 => return Ok((bar, token.span.end)),
+                TokenKind::RBrace => depth -= 1,
+                TokenKind::Eof => return Err(unterminated()),
+                _ => {}
+            }
+        }
+    }
+
+    /// Skips code in parentheses starting at `pos`, returning the position
+    /// after the closing `)`.
+    pub fn skip_capture(&self, pos: usize) -> Result<usize> {
+        // Nested parentheses are skipped as part of words.
+        let mut lexer = Lexer::new(self.src, Span::new(pos + 1, self.end));
+
+        loop {
+            let token = lexer.next_token()?;
+            match token.kind {
+                TokenKind::RParen => return Ok(token.span.end),
+                TokenKind::Eof => {
+                    return Err(ParseError::new(
+                        ParseErrorKind::Unterminated("("),
+                        Span::new(pos, self.end),
+                    ))
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Skips an arithmetic expansion `$((...))` starting at `pos`, returning
+    /// the position after the closing `))`.
+    pub fn skip_arith(&self, pos: usize) -> Result<usize> {
+        let unterminated = || {
+            ParseError::new(
+                ParseErrorKind::Unterminated("$(("),
+                Span::new(pos, self.end),
+            )
+        };
+
+        let mut depth = 0usize;
+        let mut i = pos + 3;
+        while let Some(b) = self.byte(i) {
+            match b {
+                b'(' => depth += 1,
+                b')' if depth > 0 => depth -= 1,
+                b')' if self.byte(i + 1) == Some(b')') => return Ok(i + 2),
+                b')' => return Err(unterminated()),
+                _ => {}
+            }
+            i += 1;
+        }
+
+        Err(unterminated())
+    }
+
+    /// Returns the position after the char at `pos`.
+    fn next_char(&self, pos: usize) -> usize {
+        match self.src[pos..self.end].chars().next() {
+            Some(c) => pos + c.len_utf8(),
+            None => pos,
+        }
+    }
+
+    /// Skips a single quoted string starting at `pos`.
+    fn skip_single_quote(&self, pos: usize) -> Result<usize> {
+        match self.src[pos + 1..self.end].find('\'') {
+            Some(i) => Ok(pos + 1 + i + 1),
+            None => Err(ParseError::new(
+                ParseErrorKind::UnterminatedSingleQuote,
+                Span::new(pos, self.end),
+            )),
+        }
+    }
+
+    /// Skips a double quoted string starting at `pos`.
+    fn skip_double_quote(&self, start: usize) -> Result<usize> {
+        let mut pos = start + 1;
+
+        while let Some(b) = self.byte(pos) {
+            match b {
+                b'"' => return Ok(pos + 1),
+                b'\\' => pos += 2,
+                b'$' => pos = self.skip_dollar(pos)?,
+                _ => pos += 1,
+            }
+        }
+
+        Err(ParseError::new(
+            ParseErrorKind::UnterminatedDoubleQuote,
+            Span::new(start, self.end),
+        ))
+    }
+
+    /// Skips an expansion starting with `$` at `pos`.
+    fn skip_dollar(&self, pos: usize) -> Result<usize> {
+        let name_start = match self.byte(pos + 1) {
+            Some(b'{') => return self.skip_balanced(pos + 1, b'{', b'}', "${"),
+            Some(b'(') if self.byte(pos + 2) == Some(b'(') => return self.skip_arith(pos),
+            Some(b'(') => return self.skip_capture(pos + 1),
+            // An exploded list, as in `$@name`.
+            Some(b'@') => pos + 2,
+            _ => pos + 1,
+        };
+
+        let mut pos = name_start;
+        while self.byte(pos).is_some_and(is_name_byte) {
+            pos += 1;
+        }
+
+        // Indices, as in `$name[0]`.
+        if pos > name_start {
+            while self.byte(pos) == Some(b'[') {
+                pos = self.skip_balanced(pos, b'[', b']', "[")?;
+            }
+        }
+
+        Ok(pos)
+    }
+
+    /// Skips a bracketed region starting at `pos`, respecting quotes.
+    fn skip_balanced(
+        &self,
+        start: usize,
+        open: u8,
+        close: u8,
+        what: &'static str,
+    ) -> Result<usize> {
+        let mut depth = 0usize;
+        let mut pos = start;
+
+        while let Some(b) = self.byte(pos) {
+            match b {
+                b'\\' => pos += 2,
+                b'\'' => pos = self.skip_single_quote(pos)?,
+                b'"' => pos = self.skip_double_quote(pos)?,
+                b if b == open => {
+                    depth += 1;
+                    pos += 1;
+                }
+                b if b == close => {
+                    depth -= 1;
+                    pos += 1;
+
+                    if depth == 0 {
+                        return Ok(pos);
+                    }
+                }
+                _ => pos += 1,
+            }
+        }
+
+        Err(ParseError::new(
+            ParseErrorKind::Unterminated(what),
+            Span::new(start, self.end),
+        ))
+    }
+}
+
+/// Can the byte appear in a variable name.
+pub fn is_name_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_' || b == b'-'
+}
+
+/// Is a `[` followed by this byte a word of its own, such as the `[` command,
+/// rather than the start of a list.
+pub fn is_list_delim(next: Option<u8>) -> bool {
+    match next {
+        None => true,
+        Some(b) => b.is_ascii_whitespace(),
+    }
+}
+
+/// Is a `{` followed by this byte the start of a block rather than part of a
+/// word.
+fn is_block_delim(next: Option<u8>) -> bool {
+    match next {
+        None => true,
+        Some(b) => b.is_ascii_whitespace(),
+    }
+}
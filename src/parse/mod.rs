@@ -0,0 +1,26 @@
+mod error;
+mod lexer;
+mod parser;
+mod span;
+
+pub mod ast;
+
+pub use self::error::{ParseError, ParseErrorKind};
+pub use self::parser::Parser;
+pub use self::span::{LineCol, Source, Span};
+
+use self::ast::Chunk;
+
+/// Parses a whole source into a chunk.
+pub fn parse(src: &Source) -> Result<Chunk, ParseError> {
+    Parser::new(&src.code, Span::new(0, src.code.len())).parse()
+}
+
+/// Is the code incomplete, such as an open quote or here-document, such that
+/// more lines of input could complete it.
+pub fn is_incomplete(code: &str) -> bool {
+    match Parser::new(code, Span::new(0, code.len())).parse() {
+        Ok(_) => false,
+        Err(err) => err.is_incomplete(),
+    }
+}
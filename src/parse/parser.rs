@@ -0,0 +1,1507 @@
+use std::sync::Arc;
+
+use super::ast::*;
+use super::error::{ParseError, ParseErrorKind};
+use super::lexer::{is_list_delim, is_name_byte, Lexer, Token, TokenKind};
+use super::span::Span;
+
+type Result<T> = std::result::Result<T, ParseError>;
+
+/// Context in which the parts of a word are parsed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Mode {
+    /// An unquoted word.
+    Word,
+    /// Inside `"..."`.
+    DoubleQuoted,
+    /// The body of a here-document, expansions are only performed if the
+    /// delimiter was unquoted.
+    HereDoc { expand: bool, strip_tabs: bool },
+}
+
+impl Mode {
+    /// Can the character be escaped with `\`.
+    fn is_escapable(self, s: &str) -> bool {
+        match self {
+            Mode::Word => true,
+            Mode::DoubleQuoted => matches!(s, "$" | "\"" | "\\"),
+            Mode::HereDoc { .. } => matches!(s, "$" | "\\"),
+        }
+    }
+}
+
+/// A parsed `{ ... }`, with the span including the braces.
+struct Block {
+    chunk: Chunk,
+    span: Span,
+}
+
+/// Token that ends a chunk.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Terminator {
+    Eof,
+    RBrace,
+}
+
+pub struct Parser<'a> {
+    lexer: Lexer<'a>,
+    peeked: Option<Token>,
+}
+
+impl<'a> Parser<'a> {
+    /// Creates a parser over the region `span` of `src`.
+    pub fn new(src: &'a str, span: Span) -> Parser<'a> {
+        Parser {
+            lexer: Lexer::new(src, span),
+            peeked: None,
+        }
+    }
+
+    fn src(&self) -> &'a str {
+        self.lexer.src()
+    }
+
+    fn peek(&mut self) -> Result<Token> {
+        match self.peeked {
+            Some(token) => Ok(token),
+            None => {
+                let token = self.lexer.next_token()?;
+                self.peeked = Some(token);
+                Ok(token)
+            }
+        }
+    }
+
+    fn next(&mut self) -> Result<Token> {
+        match self.peeked.take() {
+            Some(token) => Ok(token),
+            None => self.lexer.next_token(),
+        }
+    }
+
+    /// Consumes the next token if it is of the given kind.
+    fn eat(&mut self, kind: TokenKind) -> Result<Option<Token>> {
+        let token = self.peek()?;
+        if token.kind == kind {
+            self.peeked = None;
+            Ok(Some(token))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn skip_newlines(&mut self) -> Result<()> {
+        while self.eat(TokenKind::Newline)?.is_some() {}
+        Ok(())
+    }
+
+    fn unexpected(&self, token: Token) -> ParseError {
+        let kind = match token.kind {
+            TokenKind::Eof => ParseErrorKind::UnexpectedEof,
+            TokenKind::Newline => ParseErrorKind::Unexpected("newline".to_owned()),
+            _ => ParseErrorKind::Unexpected(self.src()[token.span.range()].to_owned()),
+        };
+        ParseError::new(kind, token.span)
+    }
+
+    /// Parses the whole input.
+    pub fn parse(mut self) -> Result<Chunk> {
+        self.parse_chunk(Terminator::Eof)
+    }
+
+    fn parse_chunk(&mut self, terminator: Terminator) -> Result<Chunk> {
+        let start = self.peek()?.span.start;
+        let mut stmts = Vec::new();
+
+        loop {
+            let token = self.peek()?;
+            match (token.kind, terminator) {
+                (TokenKind::Newline, _) | (TokenKind::Semi, _) => {
+                    self.next()?;
+                }
+                (TokenKind::Eof, Terminator::Eof) | (TokenKind::RBrace, Terminator::RBrace) => {
+                    let end = stmts
+                        .last()
+                        .map(|stmt: &Stmt| stmt.span.end)
+                        .unwrap_or(start);
+                    return Ok(Chunk {
+                        stmts,
+                        span: Span::new(start, end),
+                    });
+                }
+                (TokenKind::Eof, Terminator::RBrace) => {
+                    return Err(ParseError::new(
+                        ParseErrorKind::Unterminated("{"),
+                        token.span,
+                    ))
+                }
+                _ => stmts.push(self.parse_stmt()?),
+            }
+        }
+    }
+
+    fn parse_stmt(&mut self) -> Result<Stmt> {
+        let mut stmt = self.parse_and_or()?;
+
+        // `&` ends a statement as `;` does, so another can follow it.
+        if let Some(amp) = self.eat(TokenKind::Amp)? {
+            stmt.span = stmt.span.to(amp.span);
+            stmt.background = true;
+            return Ok(stmt);
+        }
+
+        // A statement must be followed by a separator or the end of a chunk.
+        let token = self.peek()?;
+        match token.kind {
+            TokenKind::Newline | TokenKind::Semi | TokenKind::Eof | TokenKind::RBrace => {}
+            _ => return Err(self.unexpected(token)),
+        }
+
+        Ok(stmt)
+    }
+
+    /// Parses pipelines joined by `&&` and `||`, such as the condition of an
+    /// `if`.
+    fn parse_and_or(&mut self) -> Result<Stmt> {
+        let first = self.parse_pipeline()?;
+        let mut rest = Vec::new();
+
+        loop {
+            let op = match self.peek()?.kind {
+                TokenKind::AndAnd => LogicOp::And,
+                TokenKind::OrOr => LogicOp::Or,
+                _ => break,
+            };
+            self.next()?;
+            self.skip_newlines()?;
+
+            rest.push((op, self.parse_pipeline()?));
+        }
+
+        let span = rest
+            .last()
+            .map(|(_, p): &(LogicOp, Pipeline)| first.span.to(p.span))
+            .unwrap_or(first.span);
+
+        Ok(Stmt {
+            first,
+            rest,
+            background: false,
+            span,
+        })
+    }
+
+    fn parse_pipeline(&mut self) -> Result<Pipeline> {
+        let mut commands = vec![self.parse_command()?];
+
+        while self.eat(TokenKind::Pipe)?.is_some() {
+            self.skip_newlines()?;
+            commands.push(self.parse_command()?);
+        }
+
+        let span = commands[0].span.to(commands[commands.len() - 1].span);
+        Ok(Pipeline { commands, span })
+    }
+
+    fn parse_command(&mut self) -> Result<Command> {
+        let token = self.peek()?;
+
+        let (kind, mut span) = match token.kind {
+            TokenKind::LBrace => {
+                let body = self.parse_block()?;
+                let span = token.span.to(body.span);
+                (CommandKind::Block(body.chunk), span)
+            }
+            TokenKind::Word if self.is_keyword(token, "if") => self.parse_if()?,
+            TokenKind::Word if self.is_keyword(token, "while") => self.parse_while(false)?,
+            TokenKind::Word if self.is_keyword(token, "until") => self.parse_while(true)?,
+            TokenKind::Word if self.is_keyword(token, "for") => self.parse_for()?,
+            TokenKind::Word if self.is_keyword(token, "fn") => self.parse_fn()?,
+            TokenKind::Word if self.is_keyword(token, "try") => self.parse_try()?,
+            TokenKind::Word | TokenKind::Redir(..) | TokenKind::HereDoc(..) => {
+                let kind = CommandKind::Simple {
+                    words: Vec::new(),
+                    opts: Vec::new(),
+                };
+                (kind, token.span)
+            }
+            _ => return Err(self.unexpected(token)),
+        };
+
+        let is_simple = matches!(kind, CommandKind::Simple { .. });
+        let mut redirs = Vec::new();
+        let mut words = Vec::new();
+        let mut opts = Vec::new();
+
+        loop {
+            let token = self.peek()?;
+            match token.kind {
+                TokenKind::Word if is_simple => {
+                    self.next()?;
+                    span = span.to(token.span);
+
+                    if self.src().as_bytes()[token.span.start] == b'&' {
+                        opts.push(parse_opt(self.src(), token.span)?);
+                    } else {
+                        words.push(self.parse_word(token.span)?);
+                    }
+                }
+                TokenKind::Redir(fd, op) => {
+                    self.next()?;
+                    let redir = self.parse_redir(token, fd, op)?;
+                    span = span.to(redir.span);
+                    redirs.push(redir);
+                }
+                TokenKind::HereDoc(fd, strip_tabs) => {
+                    self.next()?;
+                    let redir = self.parse_heredoc(token, fd, strip_tabs)?;
+                    span = span.to(redir.span);
+                    redirs.push(redir);
+                }
+                _ => break,
+            }
+        }
+
+        let kind = match kind {
+            CommandKind::Simple { .. } => CommandKind::Simple { words, opts },
+            kind => kind,
+        };
+
+        Ok(Command { kind, redirs, span })
+    }
+
+    /// Is the token the unquoted word `keyword`.
+    fn is_keyword(&self, token: Token, keyword: &str) -> bool {
+        token.kind == TokenKind::Word && &self.src()[token.span.range()] == keyword
+    }
+
+    /// Parses a `{ ... }` block.
+    fn parse_block(&mut self) -> Result<Block> {
+        let lbrace = self.next()?;
+        match lbrace.kind {
+            TokenKind::LBrace => {}
+            TokenKind::Eof => return Err(self.unexpected(lbrace)),
+            _ => {
+                return Err(ParseError::new(
+                    ParseErrorKind::Expected("`{`"),
+                    lbrace.span,
+                ))
+            }
+        }
+
+        let chunk = self.parse_chunk(Terminator::RBrace)?;
+        let rbrace = self.next()?;
+
+        Ok(Block {
+            chunk,
+            span: lbrace.span.to(rbrace.span),
+        })
+    }
+
+    /// Parses `if COND { ... } [elif COND { ... }]... [else { ... }]`.
+    fn parse_if(&mut self) -> Result<(CommandKind, Span)> {
+        let start = self.next()?.span;
+        let mut branches = Vec::new();
+        let mut else_body = None;
+
+        let end = loop {
+            let cond = self.parse_and_or()?;
+            let body = self.parse_block()?;
+            let mut end = body.span;
+            branches.push((cond, body.chunk));
+
+            let token = self.peek()?;
+            if self.is_keyword(token, "elif") {
+                self.next()?;
+                continue;
+            }
+            if self.is_keyword(token, "else") {
+                self.next()?;
+                let body = self.parse_block()?;
+                end = body.span;
+                else_body = Some(body.chunk);
+            }
+            break end;
+        };
+
+        let kind = CommandKind::If {
+            branches,
+            else_body,
+        };
+        Ok((kind, start.to(end)))
+    }
+
+    /// Parses `while COND { ... }`, or `until COND { ... }`.
+    fn parse_while(&mut self, until: bool) -> Result<(CommandKind, Span)> {
+        let start = self.next()?.span;
+        let cond = self.parse_and_or()?;
+        let body = self.parse_block()?;
+
+        let kind = CommandKind::While {
+            cond,
+            body: body.chunk,
+            until,
+        };
+        Ok((kind, start.to(body.span)))
+    }
+
+    /// Parses `for NAME in WORD... { ... }`.
+    fn parse_for(&mut self) -> Result<(CommandKind, Span)> {
+        let start = self.next()?.span;
+
+        let token = self.next()?;
+        let var = match token.kind {
+            TokenKind::Word => self.src()[token.span.range()].to_owned(),
+            _ => String::new(),
+        };
+        if var.is_empty() || !var.bytes().all(is_name_byte) {
+            return Err(ParseError::new(ParseErrorKind::InvalidVarName, token.span));
+        }
+
+        let token = self.next()?;
+        if !self.is_keyword(token, "in") {
+            return Err(ParseError::new(
+                ParseErrorKind::Expected("`in`"),
+                token.span,
+            ));
+        }
+
+        let mut items = Vec::new();
+        while self.peek()?.kind == TokenKind::Word {
+            let token = self.next()?;
+            items.push(self.parse_word(token.span)?);
+        }
+        let body = self.parse_block()?;
+
+        let kind = CommandKind::For {
+            var,
+            items,
+            body: body.chunk,
+        };
+        Ok((kind, start.to(body.span)))
+    }
+
+    /// Parses `fn NAME {|...| ... }`, or `fn NAME { ... }`.
+    fn parse_fn(&mut self) -> Result<(CommandKind, Span)> {
+        let start = self.next()?.span;
+
+        let token = self.next()?;
+        let name = match token.kind {
+            TokenKind::Word => &self.src()[token.span.range()],
+            _ => "",
+        };
+        if name.is_empty() || !name.bytes().all(is_name_byte) {
+            return Err(ParseError::new(ParseErrorKind::InvalidVarName, token.span));
+        }
+        let name = name.to_owned();
+
+        let token = self.peek()?;
+        let lambda = match token.kind {
+            TokenKind::LBrace => {
+                let body = self.parse_block()?;
+                Arc::new(Lambda {
+                    params: Vec::new(),
+                    rest: None,
+                    opts: Vec::new(),
+                    body: body.chunk,
+                    span: body.span,
+                })
+            }
+            TokenKind::Word => {
+                self.next()?;
+                let word = self.parse_word(token.span)?;
+                match &*word.parts {
+                    [WordPart {
+                        kind: WordPartKind::Lambda(lambda),
+                        ..
+                    }] => Arc::clone(lambda),
+                    _ => {
+                        return Err(ParseError::new(
+                            ParseErrorKind::Expected("a lambda or block"),
+                            token.span,
+                        ))
+                    }
+                }
+            }
+            _ => return Err(self.unexpected(token)),
+        };
+
+        let span = start.to(lambda.span);
+        Ok((CommandKind::Fn { name, lambda }, span))
+    }
+
+    /// Parses `try { ... } [catch [NAME] { ... }] [finally { ... }]`.
+    fn parse_try(&mut self) -> Result<(CommandKind, Span)> {
+        let start = self.next()?.span;
+        let body = self.parse_block()?;
+        let mut end = body.span;
+
+        let mut catch = None;
+        let token = self.peek()?;
+        if self.is_keyword(token, "catch") {
+            self.next()?;
+
+            let token = self.peek()?;
+            let var = match token.kind {
+                TokenKind::Word => {
+                    self.next()?;
+                    let var = &self.src()[token.span.range()];
+                    if !var.bytes().all(is_name_byte) {
+                        return Err(ParseError::new(ParseErrorKind::InvalidVarName, token.span));
+                    }
+                    Some(var.to_owned())
+                }
+                _ => None,
+            };
+
+            let body = self.parse_block()?;
+            end = body.span;
+            catch = Some(Catch {
+                var,
+                body: body.chunk,
+            });
+        }
+
+        let mut finally = None;
+        let token = self.peek()?;
+        if self.is_keyword(token, "finally") {
+            self.next()?;
+            let body = self.parse_block()?;
+            end = body.span;
+            finally = Some(body.chunk);
+        }
+
+        if catch.is_none() && finally.is_none() {
+            let token = self.peek()?;
+            return Err(ParseError::new(
+                ParseErrorKind::Expected("`catch` or `finally`"),
+                token.span,
+            ));
+        }
+
+        let kind = CommandKind::Try {
+            body: body.chunk,
+            catch,
+            finally,
+        };
+        Ok((kind, start.to(end)))
+    }
+
+    fn parse_redir(&mut self, op_token: Token, fd: Option<u32>, op: RedirOp) -> Result<Redir> {
+        let token = self.next()?;
+        if token.kind != TokenKind::Word {
+            return Err(ParseError::new(
+                ParseErrorKind::Expected("a word after redirection"),
+                token.span,
+            ));
+        }
+
+        Ok(Redir {
+            fd,
+            op,
+            target: self.parse_word(token.span)?,
+            span: op_token.span.to(token.span),
+        })
+    }
+
+    /// Parses a here-document, the body is read from the lines following the
+    /// current line.
+    fn parse_heredoc(
+        &mut self,
+        op_token: Token,
+        fd: Option<u32>,
+        strip_tabs: bool,
+    ) -> Result<Redir> {
+        let token = self.next()?;
+        if token.kind != TokenKind::Word {
+            return Err(ParseError::new(
+                ParseErrorKind::Expected("a delimiter after here-document"),
+                token.span,
+            ));
+        }
+
+        let delim = self.parse_word(token.span)?;
+        // Quoting any part of the delimiter disables expansions in the body.
+        let expand = delim.as_bare().is_some();
+        let delim = literal_text(&delim.parts);
+
+        let body = self.lexer.read_heredoc(&delim, strip_tabs)?;
+        let mut parser = WordParser::new(self.src(), body);
+        let parts = parser.parse_parts(Mode::HereDoc { expand, strip_tabs })?;
+
+        Ok(Redir {
+            fd,
+            op: RedirOp::HereDoc,
+            target: Word { parts, span: body },
+            span: op_token.span.to(token.span),
+        })
+    }
+
+    /// Parses the contents of a word token.
+    fn parse_word(&mut self, span: Span) -> Result<Word> {
+        WordParser::new(self.src(), span).parse()
+    }
+}
+
+/// Parses the internal structure of a word.
+struct WordParser<'a> {
+    src: &'a str,
+    start: usize,
+    pos: usize,
+    end: usize,
+}
+
+impl<'a> WordParser<'a> {
+    fn new(src: &'a str, span: Span) -> WordParser<'a> {
+        WordParser {
+            src,
+            start: span.start,
+            pos: span.start,
+            end: span.end,
+        }
+    }
+
+    fn byte(&self, pos: usize) -> Option<u8> {
+        if pos < self.end {
+            Some(self.src.as_bytes()[pos])
+        } else {
+            None
+        }
+    }
+
+    fn next_char(&self, pos: usize) -> usize {
+        match self.src[pos..self.end].chars().next() {
+            Some(c) => pos + c.len_utf8(),
+            None => pos,
+        }
+    }
+
+    fn parse(mut self) -> Result<Word> {
+        let span = Span::new(self.pos, self.end);
+        let parts = self.parse_parts(Mode::Word)?;
+        Ok(Word { parts, span })
+    }
+
+    /// Parses word parts until the end of the region, or the closing `"` in
+    /// double quotes.
+    fn parse_parts(&mut self, mode: Mode) -> Result<Vec<WordPart>> {
+        let quoted = mode != Mode::Word;
+        let (expand, strip_tabs) = match mode {
+            Mode::HereDoc { expand, strip_tabs } => (expand, strip_tabs),
+            _ => (true, false),
+        };
+
+        let mut parts = Vec::new();
+        // Start of the pending literal text.
+        let mut lit_start = self.pos;
+        let mut lit = String::new();
+
+        macro_rules! flush_lit {
+            ($end:expr) => {
+                if !lit.is_empty() {
+                    let text = std::mem::take(&mut lit);
+                    let kind = if quoted {
+                        WordPartKind::Quoted(text)
+                    } else {
+                        WordPartKind::Bare(text)
+                    };
+                    parts.push(WordPart {
+                        kind,
+                        span: Span::new(lit_start, $end),
+                    });
+                }
+            };
+        }
+
+        while let Some(b) = self.byte(self.pos) {
+            if strip_tabs && b == b'\t' && self.at_line_start() {
+                flush_lit!(self.pos);
+                while self.byte(self.pos) == Some(b'\t') {
+                    self.pos += 1;
+                }
+                lit_start = self.pos;
+                continue;
+            }
+
+            match b {
+                b'"' if mode == Mode::DoubleQuoted => break,
+                b'\\' if expand => {
+                    let start = self.pos;
+                    let escaped = self.pos + 1;
+                    let end = self.next_char(escaped);
+                    self.pos = end;
+
+                    match &self.src[escaped..end] {
+                        // Line continuation.
+                        "\n" => {}
+                        // Inside quotes only some characters can be escaped.
+                        s if !mode.is_escapable(s) => {
+                            lit.push('\\');
+                            lit.push_str(s);
+                        }
+                        s => {
+                            flush_lit!(start);
+                            parts.push(WordPart {
+                                kind: WordPartKind::Quoted(s.to_owned()),
+                                span: Span::new(start, end),
+                            });
+                            lit_start = end;
+                        }
+                    }
+                }
+                b'\'' if mode == Mode::Word => {
+                    flush_lit!(self.pos);
+                    let start = self.pos;
+                    let close = start + 1 + self.src[start + 1..self.end].find('\'').unwrap();
+                    self.pos = close + 1;
+
+                    parts.push(WordPart {
+                        kind: WordPartKind::Quoted(self.src[start + 1..close].to_owned()),
+                        span: Span::new(start, self.pos),
+                    });
+                    lit_start = self.pos;
+                }
+                b'"' if mode == Mode::Word => {
+                    flush_lit!(self.pos);
+                    let start = self.pos;
+                    self.pos += 1;
+                    let inner = self.parse_parts(Mode::DoubleQuoted)?;
+                    // Skip closing quote.
+                    self.pos += 1;
+
+                    parts.push(WordPart {
+                        kind: WordPartKind::DoubleQuoted(inner),
+                        span: Span::new(start, self.pos),
+                    });
+                    lit_start = self.pos;
+                }
+                b'{' if mode == Mode::Word && self.byte(self.pos + 1) == Some(b'|') => {
+                    flush_lit!(self.pos);
+                    let lambda = self.parse_lambda()?;
+                    let span = lambda.span;
+                    parts.push(WordPart {
+                        kind: WordPartKind::Lambda(Arc::new(lambda)),
+                        span,
+                    });
+                    lit_start = self.pos;
+                }
+                b'[' if mode == Mode::Word
+                    && self.pos == self.start
+                    && !is_list_delim(self.byte(self.pos + 1)) =>
+                {
+                    parts.push(self.parse_list()?);
+                    lit_start = self.pos;
+                }
+                b'(' if mode == Mode::Word && self.pos == self.start => {
+                    let start = self.pos;
+                    let chunk = self.parse_capture(start)?;
+                    parts.push(WordPart {
+                        kind: WordPartKind::Capture(chunk),
+                        span: Span::new(start, self.pos),
+                    });
+                    lit_start = self.pos;
+                }
+                b'<' | b'>'
+                    if mode == Mode::Word
+                        && self.pos == self.start
+                        && self.byte(self.pos + 1) == Some(b'(') =>
+                {
+                    let start = self.pos;
+                    let chunk = self.parse_capture(start + 1)?;
+                    parts.push(WordPart {
+                        kind: WordPartKind::ProcSub {
+                            chunk,
+                            input: b == b'<',
+                        },
+                        span: Span::new(start, self.pos),
+                    });
+                    lit_start = self.pos;
+                }
+                b'$' if expand && self.is_expansion(self.pos + 1) => {
+                    flush_lit!(self.pos);
+                    parts.push(self.parse_dollar()?);
+                    lit_start = self.pos;
+                }
+                _ => {
+                    let end = self.next_char(self.pos);
+                    lit.push_str(&self.src[self.pos..end]);
+                    self.pos = end;
+                }
+            }
+        }
+
+        flush_lit!(self.pos);
+        Ok(parts)
+    }
+
+    /// Parses a lambda starting with `{|`.
+    fn parse_lambda(&mut self) -> Result<Lambda> {
+        let start = self.pos;
+        let (bar, end) = Lexer::new(self.src, Span::new(start, self.end)).skip_lambda(start)?;
+
+        let mut params = Vec::new();
+        let mut rest = None;
+        let mut opts = Vec::new();
+
+        let mut lexer = Lexer::new(self.src, Span::new(start + 2, bar));
+        loop {
+            let token = lexer.next_token()?;
+            let text = &self.src[token.span.range()];
+            match token.kind {
+                TokenKind::Eof => break,
+                TokenKind::Word if text.starts_with('&') => {
+                    opts.push(parse_opt(self.src, token.span)?);
+                }
+                // Only options may follow the rest parameter.
+                TokenKind::Word if rest.is_some() => {
+                    return Err(ParseError::new(
+                        ParseErrorKind::Unexpected(text.to_owned()),
+                        token.span,
+                    ))
+                }
+                TokenKind::Word if text.bytes().all(is_name_byte) => {
+                    params.push(text.to_owned());
+                }
+                TokenKind::Word
+                    if text.len() > 1
+                        && text.starts_with('@')
+                        && text[1..].bytes().all(is_name_byte) =>
+                {
+                    rest = Some(text[1..].to_owned());
+                }
+                _ => {
+                    return Err(ParseError::new(
+                        ParseErrorKind::Expected("a parameter name"),
+                        token.span,
+                    ))
+                }
+            }
+        }
+
+        // The body is between the `|` and the closing `}`.
+        let body = Parser::new(self.src, Span::new(bar + 1, end - 1)).parse()?;
+        self.pos = end;
+
+        Ok(Lambda {
+            params,
+            rest,
+            opts,
+            body,
+            span: Span::new(start, end),
+        })
+    }
+
+    /// Is the current position at the start of a line.
+    fn at_line_start(&self) -> bool {
+        self.pos == 0 || self.src.as_bytes()[self.pos - 1] == b'\n'
+    }
+
+    /// Is a `$` followed by the byte at `pos` an expansion.
+    fn is_expansion(&self, pos: usize) -> bool {
+        match self.byte(pos) {
+            Some(b'{') | Some(b'(') => true,
+            Some(b) => is_special_param(b) || is_name_byte(b),
+            None => false,
+        }
+    }
+
+    /// Parses an expansion starting with `$`.
+    fn parse_dollar(&mut self) -> Result<WordPart> {
+        let start = self.pos;
+        self.pos += 1;
+
+        match self.byte(self.pos) {
+            Some(b'(') if self.byte(self.pos + 1) == Some(b'(') => {
+                let close = Lexer::new(self.src, Span::new(start, self.end)).skip_arith(start)?;
+                self.pos = close;
+                Ok(WordPart {
+                    kind: WordPartKind::Arith(self.arith_expr(start + 3, close - 2)),
+                    span: Span::new(start, self.pos),
+                })
+            }
+            Some(b'(') => {
+                let chunk = self.parse_capture(self.pos)?;
+                Ok(WordPart {
+                    kind: WordPartKind::Output(chunk),
+                    span: Span::new(start, self.pos),
+                })
+            }
+            Some(b'{') => {
+                let mut name_start = self.pos + 1;
+                let close = self.find_close(self.pos, b'{', b'}');
+
+                // `${#name}` is the length of the variable, but `${#}` is the
+                // variable `#`.
+                let length = self.byte(name_start) == Some(b'#') && name_start + 1 < close;
+                if length {
+                    name_start += 1;
+                }
+
+                let mut name_end = name_start;
+                match self.byte(name_start) {
+                    Some(b) if is_special_param(b) => name_end += 1,
+                    _ => {
+                        while self.byte(name_end).is_some_and(is_name_byte) {
+                            name_end += 1;
+                        }
+                    }
+                }
+
+                let name = &self.src[name_start..name_end];
+                if name.is_empty() {
+                    return Err(ParseError::new(
+                        ParseErrorKind::InvalidVarName,
+                        Span::new(name_start, close),
+                    ));
+                }
+
+                let op = if length {
+                    if name_end != close {
+                        return Err(ParseError::new(
+                            ParseErrorKind::Unexpected(self.src[name_end..close].to_owned()),
+                            Span::new(name_end, close),
+                        ));
+                    }
+                    Some(ParamOp::Length)
+                } else {
+                    self.parse_param_op(name_end, close)?
+                };
+                self.pos = close + 1;
+
+                Ok(WordPart {
+                    kind: WordPartKind::Param(ParamExp {
+                        name: name.to_owned(),
+                        braced: true,
+                        explode: false,
+                        indices: Vec::new(),
+                        op,
+                    }),
+                    span: Span::new(start, self.pos),
+                })
+            }
+            Some(b'@') if self.byte(self.pos + 1).is_some_and(is_name_byte) => {
+                self.pos += 1;
+                self.parse_name_param(start, true)
+            }
+            Some(b) if is_special_param(b) => {
+                self.pos += 1;
+                Ok(WordPart {
+                    kind: WordPartKind::Param(ParamExp {
+                        name: (b as char).to_string(),
+                        braced: false,
+                        explode: false,
+                        indices: Vec::new(),
+                        op: None,
+                    }),
+                    span: Span::new(start, self.pos),
+                })
+            }
+            _ => self.parse_name_param(start, false),
+        }
+    }
+
+    /// Parses code in parentheses starting at `open`, as in `(cmd)`.
+    fn parse_capture(&mut self, open: usize) -> Result<Arc<Chunk>> {
+        let close = Lexer::new(self.src, Span::new(open, self.end)).skip_capture(open)?;
+        let chunk = Parser::new(self.src, Span::new(open + 1, close - 1)).parse()?;
+        self.pos = close;
+        Ok(Arc::new(chunk))
+    }
+
+    /// Parses `$name`, or `$@name` if `explode`, followed by any indices.
+    fn parse_name_param(&mut self, start: usize, explode: bool) -> Result<WordPart> {
+        let name_start = self.pos;
+        while self.byte(self.pos).is_some_and(is_name_byte) {
+            self.pos += 1;
+        }
+        // A namespace, as in `$ns:name`.
+        while self.pos > name_start
+            && self.byte(self.pos) == Some(b':')
+            && self
+                .byte(self.pos + 1)
+                .is_some_and(|b| b.is_ascii_alphabetic() || b == b'_')
+        {
+            self.pos += 1;
+            while self.byte(self.pos).is_some_and(is_name_byte) {
+                self.pos += 1;
+            }
+        }
+        let name = self.src[name_start..self.pos].to_owned();
+
+        let mut indices = Vec::new();
+        while self.byte(self.pos) == Some(b'[') {
+            let close = self.find_close(self.pos, b'[', b']');
+            let index = WordParser::new(self.src, Span::new(self.pos + 1, close)).parse()?;
+            indices.push(index);
+            self.pos = close + 1;
+        }
+
+        Ok(WordPart {
+            kind: WordPartKind::Param(ParamExp {
+                name,
+                braced: false,
+                explode,
+                indices,
+                op: None,
+            }),
+            span: Span::new(start, self.pos),
+        })
+    }
+
+    /// Parses a list or map literal.
+    fn parse_list(&mut self) -> Result<WordPart> {
+        let start = self.pos;
+        let (elems, end) = Lexer::new(self.src, Span::new(start, self.end)).scan_list(start)?;
+        self.pos = end;
+
+        let is_map = |span: &Span| self.src.as_bytes()[span.start] == b'&';
+        let kind = match &*elems {
+            // `[&]` is an empty map.
+            [elem] if &self.src[elem.range()] == "&" => WordPartKind::Map(Vec::new()),
+            [first, ..] if is_map(first) => {
+                let mut entries = Vec::with_capacity(elems.len());
+                for elem in elems {
+                    if !is_map(&elem) {
+                        return Err(ParseError::new(
+                            ParseErrorKind::Expected("`&key=value` in a map"),
+                            elem,
+                        ));
+                    }
+                    entries.push(parse_opt(self.src, elem)?);
+                }
+                WordPartKind::Map(entries)
+            }
+            _ => {
+                let mut words = Vec::with_capacity(elems.len());
+                for elem in elems {
+                    if is_map(&elem) {
+                        return Err(ParseError::new(
+                            ParseErrorKind::Expected("a value in a list"),
+                            elem,
+                        ));
+                    }
+                    words.push(WordParser::new(self.src, elem).parse()?);
+                }
+                WordPartKind::List(words)
+            }
+        };
+
+        Ok(WordPart {
+            kind,
+            span: Span::new(start, end),
+        })
+    }
+
+    /// Parses the operator of a braced expansion, between the end of the name
+    /// and the closing `}`.
+    fn parse_param_op(&self, start: usize, close: usize) -> Result<Option<ParamOp>> {
+        if start == close {
+            return Ok(None);
+        }
+
+        let word =
+            |start: usize, end: usize| WordParser::new(self.src, Span::new(start, end)).parse();
+        // Is the operator char repeated, as in `##`.
+        let doubled = |b: u8| self.byte(start + 1) == Some(b);
+
+        match self.src.as_bytes()[start] {
+            b':' if self.byte(start + 1) == Some(b'-') => {
+                Ok(Some(ParamOp::Default(word(start + 2, close)?)))
+            }
+            b':' if self.byte(start + 1) == Some(b'?') => {
+                Ok(Some(ParamOp::Error(word(start + 2, close)?)))
+            }
+            b':' => {
+                let body = start + 1;
+                let (offset, length) = match self.find_unquoted(body, close, b':') {
+                    Some(colon) => (
+                        self.arith_expr(body, colon),
+                        Some(self.arith_expr(colon + 1, close)),
+                    ),
+                    None => (self.arith_expr(body, close), None),
+                };
+                Ok(Some(ParamOp::Substring { offset, length }))
+            }
+            b @ b'#' | b @ b'%' => {
+                let longest = doubled(b);
+                let pattern = word(start + 1 + longest as usize, close)?;
+                Ok(Some(if b == b'#' {
+                    ParamOp::TrimPrefix { pattern, longest }
+                } else {
+                    ParamOp::TrimSuffix { pattern, longest }
+                }))
+            }
+            b'/' => {
+                let all = doubled(b'/');
+                let body = start + 1 + all as usize;
+                let (pattern, with) = match self.find_unquoted(body, close, b'/') {
+                    Some(slash) => (word(body, slash)?, Some(word(slash + 1, close)?)),
+                    None => (word(body, close)?, None),
+                };
+                Ok(Some(ParamOp::Replace { pattern, with, all }))
+            }
+            b @ b'^' | b @ b',' => {
+                let all = doubled(b);
+                let end = start + 1 + all as usize;
+                if end != close {
+                    return Err(ParseError::new(
+                        ParseErrorKind::Unexpected(self.src[end..close].to_owned()),
+                        Span::new(end, close),
+                    ));
+                }
+                Ok(Some(ParamOp::Case {
+                    upper: b == b'^',
+                    all,
+                }))
+            }
+            _ => {
+                let op_end = (start + 2).min(close);
+                Err(ParseError::new(
+                    ParseErrorKind::Unexpected(self.src[start..op_end].to_owned()),
+                    Span::new(start, op_end),
+                ))
+            }
+        }
+    }
+
+    fn arith_expr(&self, start: usize, end: usize) -> ArithExpr {
+        let span = Span::new(start, end);
+        ArithExpr {
+            text: self.src[span.range()].to_owned(),
+            span,
+        }
+    }
+
+    /// Finds the first `target` byte in `start..end` that is not quoted,
+    /// escaped or nested in brackets.
+    fn find_unquoted(&self, start: usize, end: usize, target: u8) -> Option<usize> {
+        let mut pos = start;
+
+        while pos < end {
+            match self.src.as_bytes()[pos] {
+                b if b == target => return Some(pos),
+                b'\\' => pos += 1,
+                b'\'' => pos += self.src[pos + 1..end].find('\'').map_or(0, |i| i + 1),
+                b'"' => pos += self.src[pos + 1..end].find('"').map_or(0, |i| i + 1),
+                b'{' => pos = self.find_close(pos, b'{', b'}'),
+                b'(' => pos = self.find_close(pos, b'(', b')'),
+                b'[' => pos = self.find_close(pos, b'[', b']'),
+                _ => {}
+            }
+            pos += 1;
+        }
+
+        None
+    }
+
+    /// Finds the bracket matching the one at `pos`, respecting quotes and
+    /// nesting.
+    ///
+    /// The lexer has already checked the brackets are balanced.
+    fn find_close(&self, pos: usize, open: u8, close: u8) -> usize {
+        let mut depth = 0usize;
+        let mut pos = pos;
+
+        while let Some(b) = self.byte(pos) {
+            match b {
+                b'\\' => pos += 1,
+                b'\'' => pos += self.src[pos + 1..self.end].find('\'').map_or(0, |i| i + 1),
+                b'"' => {
+                    pos += 1;
+                    while let Some(b) = self.byte(pos) {
+                        match b {
+                            b'"' => break,
+                            b'\\' => pos += 2,
+                            _ => pos += 1,
+                        }
+                    }
+                }
+                b if b == open => depth += 1,
+                b if b == close => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return pos;
+                    }
+                }
+                _ => {}
+            }
+            pos += 1;
+        }
+
+        self.end
+    }
+}
+
+/// Parses an option word, `&name=value`.
+fn parse_opt(src: &str, span: Span) -> Result<Opt> {
+    let text = &src[span.range()];
+    let eq = match text.find('=') {
+        Some(eq) => eq,
+        None => {
+            return Err(ParseError::new(
+                ParseErrorKind::Expected("`=` after option name"),
+                span,
+            ))
+        }
+    };
+
+    let name = &text[1..eq];
+    if name.is_empty() || !name.bytes().all(is_name_byte) {
+        return Err(ParseError::new(
+            ParseErrorKind::InvalidVarName,
+            Span::new(span.start + 1, span.start + eq),
+        ));
+    }
+
+    let value = WordParser::new(src, Span::new(span.start + eq + 1, span.end)).parse()?;
+    Ok(Opt {
+        name: name.to_owned(),
+        value,
+        span,
+    })
+}
+
+/// Concatenates the literal text of word parts, expansions are kept as they
+/// appear in the source.
+fn literal_text(parts: &[WordPart]) -> String {
+    let mut text = String::new();
+    for part in parts {
+        match &part.kind {
+            WordPartKind::Bare(s) | WordPartKind::Quoted(s) => text.push_str(s),
+            WordPartKind::DoubleQuoted(parts) => text.push_str(&literal_text(parts)),
+            WordPartKind::Param(param) if param.braced => {
+                text.push_str("${");
+                text.push_str(&param.name);
+                text.push('}');
+            }
+            WordPartKind::Param(param) => {
+                text.push('$');
+                text.push_str(&param.name);
+            }
+            WordPartKind::List(_)
+            | WordPartKind::Map(_)
+            | WordPartKind::Lambda(_)
+            | WordPartKind::Output(_)
+            | WordPartKind::Arith(_)
+            | WordPartKind::Capture(_)
+            | WordPartKind::ProcSub { .. } => {}
+        }
+    }
+    text
+}
+
+/// Is the byte a single character special parameter, such as `$?`.
+fn is_special_param(b: u8) -> bool {
+    matches!(b, b'?' | b'$' | b'#' | b'@' | b'*' | b'!')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(code: &str) -> Chunk {
+        Parser::new(code, Span::new(0, code.len()))
+            .parse()
+            .unwrap_or_else(|err| panic!("failed to parse {:?}: {}", code, err))
+    }
+
+    fn parse_err(code: &str) -> ParseError {
+        match Parser::new(code, Span::new(0, code.len())).parse() {
+            Ok(chunk) => panic!("parsed {:?} as {:?}", code, chunk),
+            Err(err) => err,
+        }
+    }
+
+    /// The only command of the code.
+    fn command(code: &str) -> Command {
+        let mut chunk = parse(code);
+        assert_eq!(chunk.stmts.len(), 1, "{:?}", code);
+        let stmt = chunk.stmts.remove(0);
+        assert!(stmt.rest.is_empty(), "{:?}", code);
+        let mut commands = stmt.first.commands;
+        assert_eq!(commands.len(), 1, "{:?}", code);
+        commands.remove(0)
+    }
+
+    /// The words and options of the only command of the code, which is simple.
+    fn simple(code: &str) -> (Vec<Word>, Vec<Opt>) {
+        match command(code).kind {
+            CommandKind::Simple { words, opts } => (words, opts),
+            kind => panic!("{:?} is not a simple command: {:?}", code, kind),
+        }
+    }
+
+    /// The parts of the only word of the code.
+    fn parts(code: &str) -> Vec<WordPartKind> {
+        let (mut words, _) = simple(code);
+        assert_eq!(words.len(), 1, "{:?}", code);
+        words
+            .remove(0)
+            .parts
+            .into_iter()
+            .map(|part| part.kind)
+            .collect()
+    }
+
+    fn bare(s: &str) -> WordPartKind {
+        WordPartKind::Bare(s.to_owned())
+    }
+
+    fn quoted(s: &str) -> WordPartKind {
+        WordPartKind::Quoted(s.to_owned())
+    }
+
+    fn param(code: &str) -> ParamExp {
+        match &*parts(code) {
+            [WordPartKind::Param(param)] => param.clone(),
+            parts => panic!("{:?} is not a parameter: {:?}", code, parts),
+        }
+    }
+
+    #[test]
+    fn simple_command() {
+        let (words, opts) = simple("echo a  b &sep=,");
+        let words: Vec<_> = words.iter().map(|word| word.as_bare().unwrap()).collect();
+        assert_eq!(words, ["echo", "a", "b"]);
+
+        assert_eq!(opts.len(), 1);
+        assert_eq!(opts[0].name, "sep");
+        assert_eq!(opts[0].value.as_bare(), Some(","));
+    }
+
+    #[test]
+    fn spans() {
+        let code = "echo  hello | cat";
+        let chunk = parse(code);
+        let pipeline = &chunk.stmts[0].first;
+
+        assert_eq!(chunk.span, Span::new(0, code.len()));
+        assert_eq!(pipeline.span, Span::new(0, code.len()));
+        assert_eq!(&code[pipeline.commands[0].span.range()], "echo  hello");
+        assert_eq!(&code[pipeline.commands[1].span.range()], "cat");
+    }
+
+    #[test]
+    fn separators() {
+        let chunk = parse("a; b\n\nc;");
+        assert_eq!(chunk.stmts.len(), 3);
+    }
+
+    #[test]
+    fn pipelines_and_logic() {
+        let chunk = parse("a | b && c ||\n d");
+        let stmt = &chunk.stmts[0];
+
+        assert_eq!(chunk.stmts.len(), 1);
+        assert_eq!(stmt.first.commands.len(), 2);
+        let ops: Vec<_> = stmt.rest.iter().map(|(op, _)| *op).collect();
+        assert_eq!(ops, [LogicOp::And, LogicOp::Or]);
+    }
+
+    #[test]
+    fn background() {
+        let code = "sleep 1 & echo x";
+        let chunk = parse(code);
+
+        assert_eq!(chunk.stmts.len(), 2);
+        assert!(chunk.stmts[0].background);
+        assert_eq!(&code[chunk.stmts[0].span.range()], "sleep 1 &");
+        assert!(!chunk.stmts[1].background);
+
+        assert!(parse("a &").stmts[0].background);
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(parts("a'b c'd"), [bare("a"), quoted("b c"), bare("d")]);
+        assert_eq!(parts(r"a\ b"), [bare("a"), quoted(" "), bare("b")]);
+
+        match &*parts(r#""x \n $y""#) {
+            [WordPartKind::DoubleQuoted(inner)] => {
+                let inner: Vec<_> = inner.iter().map(|part| &part.kind).collect();
+                match &*inner {
+                    [WordPartKind::Quoted(text), WordPartKind::Param(param)] => {
+                        assert_eq!(text, r"x \n ");
+                        assert_eq!(param.name, "y");
+                    }
+                    inner => panic!("unexpected parts {:?}", inner),
+                }
+            }
+            parts => panic!("unexpected parts {:?}", parts),
+        }
+    }
+
+    #[test]
+    fn params() {
+        let p = param("$foo");
+        assert_eq!(p.name, "foo");
+        assert!(!p.braced && !p.explode && p.op.is_none());
+
+        assert!(param("$@xs").explode);
+        assert_eq!(param("$?").name, "?");
+        assert_eq!(param("$#").name, "#");
+        assert_eq!(param("$xs[0]").indices.len(), 1);
+
+        let p = param("${foo}");
+        assert!(p.braced);
+        assert_eq!(p.name, "foo");
+
+        assert_eq!(param("${#foo}").op, Some(ParamOp::Length));
+        assert!(matches!(param("${foo:-x}").op, Some(ParamOp::Default(_))));
+        assert!(matches!(
+            param("${foo##*/}").op,
+            Some(ParamOp::TrimPrefix { longest: true, .. })
+        ));
+        assert!(matches!(
+            param("${foo//a/b}").op,
+            Some(ParamOp::Replace { all: true, .. })
+        ));
+        assert_eq!(
+            param("${foo^^}").op,
+            Some(ParamOp::Case {
+                upper: true,
+                all: true
+            })
+        );
+    }
+
+    #[test]
+    fn redirections() {
+        let redirs = command("cmd <in 2>err >>out").redirs;
+        let redirs: Vec<_> = redirs
+            .iter()
+            .map(|redir| (redir.fd, redir.op, redir.target.as_bare().unwrap()))
+            .collect();
+
+        assert_eq!(
+            redirs,
+            [
+                (None, RedirOp::Read, "in"),
+                (Some(2), RedirOp::Write, "err"),
+                (None, RedirOp::Append, "out"),
+            ]
+        );
+    }
+
+    #[test]
+    fn heredocs() {
+        let code = "cat <<EOF; echo after\nhello $name\nEOF\n";
+        let chunk = parse(code);
+        assert_eq!(chunk.stmts.len(), 2);
+
+        let redir = &chunk.stmts[0].first.commands[0].redirs[0];
+        assert_eq!(redir.op, RedirOp::HereDoc);
+        assert_eq!(&code[redir.target.span.range()], "hello $name\n");
+        assert!(redir
+            .target
+            .parts
+            .iter()
+            .any(|part| matches!(part.kind, WordPartKind::Param(_))));
+
+        // A quoted delimiter disables expansions.
+        let redir = &command("cat <<'EOF'\n$name\nEOF\n").redirs[0];
+        let kinds: Vec<_> = redir.target.parts.iter().map(|part| &part.kind).collect();
+        assert_eq!(kinds, [&quoted("$name\n")]);
+    }
+
+    #[test]
+    fn lambdas() {
+        let lambda = match &*parts("{|a b @rest &opt=x| echo $a}") {
+            [WordPartKind::Lambda(lambda)] => Arc::clone(lambda),
+            parts => panic!("unexpected parts {:?}", parts),
+        };
+
+        assert_eq!(lambda.params, ["a", "b"]);
+        assert_eq!(lambda.rest.as_deref(), Some("rest"));
+        assert_eq!(lambda.opts.len(), 1);
+        assert_eq!(lambda.opts[0].name, "opt");
+        assert_eq!(lambda.body.stmts.len(), 1);
+
+        parse_err("echo {|@rest a| }");
+    }
+
+    #[test]
+    fn control_flow() {
+        match command("if a { b } elif c { d } else { e }").kind {
+            CommandKind::If {
+                branches,
+                else_body,
+            } => {
+                assert_eq!(branches.len(), 2);
+                assert!(else_body.is_some());
+            }
+            kind => panic!("unexpected command {:?}", kind),
+        }
+
+        match command("until a && b { c }").kind {
+            CommandKind::While { cond, until, .. } => {
+                assert!(until);
+                assert_eq!(cond.rest.len(), 1);
+            }
+            kind => panic!("unexpected command {:?}", kind),
+        }
+
+        match command("for x in a b c { echo $x }").kind {
+            CommandKind::For { var, items, .. } => {
+                assert_eq!(var, "x");
+                assert_eq!(items.len(), 3);
+            }
+            kind => panic!("unexpected command {:?}", kind),
+        }
+
+        match command("try { a } catch e { b } finally { c }").kind {
+            CommandKind::Try { catch, finally, .. } => {
+                assert_eq!(catch.unwrap().var.as_deref(), Some("e"));
+                assert!(finally.is_some());
+            }
+            kind => panic!("unexpected command {:?}", kind),
+        }
+
+        match command("fn greet {|name| echo $name }").kind {
+            CommandKind::Fn { name, lambda } => {
+                assert_eq!(name, "greet");
+                assert_eq!(lambda.params, ["name"]);
+            }
+            kind => panic!("unexpected command {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            parse_err("a | | b").kind,
+            ParseErrorKind::Unexpected("|".to_owned())
+        );
+        assert_eq!(
+            parse_err("for a.b in c { }").kind,
+            ParseErrorKind::InvalidVarName
+        );
+        assert_eq!(
+            parse_err("for x a { }").kind,
+            ParseErrorKind::Expected("`in`")
+        );
+        assert_eq!(
+            parse_err("try { a }").kind,
+            ParseErrorKind::Expected("`catch` or `finally`")
+        );
+
+        let err = parse_err("a | | b");
+        assert_eq!(err.span, Span::new(4, 5));
+        assert!(!err.is_incomplete());
+    }
+
+    #[test]
+    fn incomplete() {
+        for code in &[
+            "echo 'a",
+            "echo \"a",
+            "if a {",
+            "a |",
+            "a &&",
+            "cat <<EOF\nbody\n",
+        ] {
+            let err = parse_err(code);
+            assert!(err.is_incomplete(), "{:?}: {}", code, err);
+        }
+    }
+}
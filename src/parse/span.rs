@@ -0,0 +1,110 @@
+use std::fmt::{self, Display};
+use std::ops::Range;
+
+/// A byte range within a [`Source`].
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Span {
+    /// Byte index of the start of the span.
+    pub start: usize,
+    /// Byte index one past the end of the span.
+    pub end: usize,
+}
+
+impl Span {
+    #[inline]
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+
+    /// Returns an empty span at the given position.
+    #[inline]
+    pub fn empty(pos: usize) -> Span {
+        Span::new(pos, pos)
+    }
+
+    #[inline]
+    pub fn len(self) -> usize {
+        self.end - self.start
+    }
+
+    #[inline]
+    pub fn is_empty(self) -> bool {
+        self.start == self.end
+    }
+
+    /// Returns a span covering both this span and another.
+    pub fn to(self, other: Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+
+    #[inline]
+    pub fn range(self) -> Range<usize> {
+        self.start..self.end
+    }
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
+/// Named source code, the target of all spans in an AST.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Source {
+    /// Name of the source, such as a file path.
+    pub name: String,
+    /// The source code.
+    pub code: String,
+}
+
+/// A 1-based line and column position, the column is counted in chars.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct LineCol {
+    pub line: usize,
+    pub col: usize,
+}
+
+impl Source {
+    pub fn new<N, C>(name: N, code: C) -> Source
+    where
+        N: Into<String>,
+        C: Into<String>,
+    {
+        Source {
+            name: name.into(),
+            code: code.into(),
+        }
+    }
+
+    /// Returns the source code covered by a span.
+    pub fn slice(&self, span: Span) -> &str {
+        &self.code[span.range()]
+    }
+
+    /// Returns the line and column of a byte index.
+    pub fn line_col(&self, pos: usize) -> LineCol {
+        let pos = pos.min(self.code.len());
+        let before = &self.code[..pos];
+
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let col = before[line_start..].chars().count() + 1;
+
+        LineCol { line, col }
+    }
+
+    /// Returns the span of the line containing a byte index, excluding the
+    /// trailing newline.
+    pub fn line_span(&self, pos: usize) -> Span {
+        let pos = pos.min(self.code.len());
+
+        let start = self.code[..pos].rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let end = self.code[pos..]
+            .find('\n')
+            .map(|i| pos + i)
+            .unwrap_or_else(|| self.code.len());
+
+        Span::new(start, end)
+    }
+}
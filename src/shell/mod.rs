@@ -1,54 +1,363 @@
-use std::fs::File;
-use std::io::BufReader;
-use std::path::PathBuf;
+mod report;
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Instant, SystemTime};
 
 use anyhow::Result;
 
-use crate::args::Args;
+use crate::args::{Args, Rc};
 use crate::cli::app::Return;
 use crate::cli::tty::Tty;
 use crate::editor::Editor;
+use crate::eval::{ErrorKind, Evaluator, HistoryEntry, Status};
+use crate::parse::ast::Chunk;
+use crate::parse::{self, Source};
+
+use self::report::report;
+
+/// The directory of the system-wide configuration, whose files are sourced
+/// before those of the user.
+const SYSTEM_CONFIG_DIR: &str = "/etc/jsh";
 
-pub struct Shell {}
+pub struct Shell {
+    evaluator: Evaluator,
+    args: Args,
+    /// The status to exit with, once `exit` has been called.
+    exit: Option<Status>,
+    /// Whether writing to the history has failed, which is only reported
+    /// once.
+    history_failed: bool,
+}
 
 impl Shell {
-    pub fn new(_args: Args) -> Result<Shell> {
-        Ok(Shell {})
+    pub fn new(args: Args) -> Result<Shell> {
+        let evaluator = Evaluator::new()?;
+        evaluator.set_args(&args.name, &args.positional);
+
+        Ok(Shell {
+            evaluator,
+            args,
+            exit: None,
+            history_failed: false,
+        })
     }
 
-    pub async fn exec_command(self, _cmd: &str) -> Result<()> {
-        todo!("read statements from cmd string")
+    pub async fn exec_command(mut self, cmd: &str) -> Result<Status> {
+        if let Some(status) = self.startup(false).await {
+            return Ok(status);
+        }
+
+        let src = Source::new("[command]", cmd);
+        Ok(self.run_source(src).await)
     }
 
-    pub async fn exec_files(self, files: &[PathBuf]) -> Result<()> {
-        for file in files {
-            let file = File::open(file)?;
-            let _buffer = BufReader::new(file);
+    pub async fn exec_script(mut self, file: &Path) -> Result<Status> {
+        if let Some(status) = self.startup(false).await {
+            return Ok(status);
+        }
+
+        match read_source(file) {
+            Some(src) => Ok(self.run_source(src).await),
+            None => Ok(Status::NOT_FOUND),
+        }
+    }
 
-            todo!("read statements from buffer");
+    /// Reads code from the standard input, evaluating each statement as soon
+    /// as it is complete, so a script can be piped in as it is written.
+    ///
+    /// As with a script file, an error ends the input.
+    pub async fn exec_stdin(mut self) -> Result<Status> {
+        if let Some(status) = self.startup(false).await {
+            return Ok(status);
         }
 
-        Ok(())
+        let mut status = Status::SUCCESS;
+        let mut code = String::new();
+
+        loop {
+            let line = read_stdin_line().await?;
+            let eof = line.is_none();
+            if let Some(line) = line {
+                code.push_str(&String::from_utf8_lossy(&line));
+            }
+            if !eof && parse::is_incomplete(&code) {
+                continue;
+            }
+
+            let src = Source::new("[stdin]", std::mem::take(&mut code));
+            let chunk = match parse::parse(&src) {
+                Ok(chunk) => chunk,
+                Err(err) => {
+                    report(&src, err.span, &err);
+                    return Ok(Status::SYNTAX_ERROR);
+                }
+            };
+
+            // Blank lines and comments leave the status of the last statement.
+            if !chunk.stmts.is_empty() {
+                status = match self.run_chunk(&chunk, src).await {
+                    Ok(status) => status,
+                    Err(status) => return Ok(status),
+                };
+                if let Some(status) = self.exit {
+                    return Ok(status);
+                }
+            }
+            if eof {
+                return Ok(status);
+            }
+        }
     }
 
-    pub async fn interactive(self) -> Result<()> {
-        // TODO: Check isatty.
-        let mut editor = Editor::new(Tty::std());
+    pub async fn interactive(mut self) -> Result<Status> {
+        if unsafe { libc::isatty(libc::STDIN_FILENO) } != 1 {
+            return self.interactive_stdin().await;
+        }
 
-        // TODO: Source config files.
+        let tty = Tty::std();
+        if let Err(err) = self.evaluator.enable_job_control(tty.fd()) {
+            eprintln!("jsh: cannot enable job control: {}", err);
+        }
+        let mut editor = Editor::new(tty, self.evaluator.edit_state());
 
-        // TODO: Initialize editor.
+        if let Some(status) = self.startup(true).await {
+            return Ok(status);
+        }
 
         loop {
+            for line in self.evaluator.job_notifications() {
+                eprintln!("{}", line);
+            }
+
+            editor.set_history_file(self.evaluator.history_file());
+            // The terminal is restored once a line has been read, so commands
+            // are free to use it.
             let line = editor.read_line().await?;
 
             match line {
-                Return::Input(line) => println!("read line: {}", line),
+                Return::Input(line) => {
+                    if let Some(status) = self.run_line(line).await {
+                        println!("exit");
+                        return Ok(status);
+                    }
+                }
                 Return::Exit => {
                     println!("exit");
-                    return Ok(());
+                    return Ok(self.evaluator.status());
                 }
             }
         }
     }
+
+    /// Reads commands from the standard input when it is not a terminal, as an
+    /// interactive shell without an editor or job control, writing a plain
+    /// prompt to the standard error before each statement.
+    async fn interactive_stdin(mut self) -> Result<Status> {
+        if let Some(status) = self.startup(true).await {
+            return Ok(status);
+        }
+
+        let mut code = String::new();
+        loop {
+            if code.is_empty() {
+                for line in self.evaluator.job_notifications() {
+                    eprintln!("{}", line);
+                }
+                eprint!("{}", plain_prompt());
+            }
+
+            let line = read_stdin_line().await?;
+            let eof = line.is_none();
+            if let Some(line) = line {
+                code.push_str(&String::from_utf8_lossy(&line));
+            }
+            if !eof && parse::is_incomplete(&code) {
+                continue;
+            }
+
+            let line = std::mem::take(&mut code);
+            let line = line.trim_end_matches('\n');
+            if !line.trim().is_empty() {
+                if let Some(status) = self.run_line(line.to_owned()).await {
+                    println!("exit");
+                    return Ok(status);
+                }
+            }
+            if eof {
+                eprintln!();
+                println!("exit");
+                return Ok(self.evaluator.status());
+            }
+        }
+    }
+
+    /// Evaluates a line read interactively and adds it to the history,
+    /// returning the status to exit with if it called `exit`.
+    async fn run_line(&mut self, line: String) -> Option<Status> {
+        let time = SystemTime::now();
+        let start = Instant::now();
+        let cwd = env::current_dir().unwrap_or_default();
+
+        let src = Source::new("[interactive]", line.clone());
+        let status = self.run_source(src).await;
+
+        if !line.trim().is_empty() {
+            self.add_history(HistoryEntry {
+                cmd: line,
+                time,
+                cwd,
+                status,
+                duration: start.elapsed(),
+            });
+        }
+        self.exit
+    }
+
+    fn add_history(&mut self, entry: HistoryEntry) {
+        if let Err(err) = self.evaluator.add_history(&entry) {
+            if !self.history_failed {
+                eprintln!("jsh: cannot write history: {}", err);
+                self.history_failed = true;
+            }
+        }
+    }
+
+    /// Sources the profile files of a login shell, and then the rc files of
+    /// an interactive shell, returning the status to exit with if one called
+    /// `exit`.
+    ///
+    /// Errors are reported and end the file they are in, but the shell still
+    /// starts.
+    async fn startup(&mut self, interactive: bool) -> Option<Status> {
+        let mut files = Vec::new();
+        if self.args.login {
+            files.extend(self.config_files("profile.jsh"));
+        }
+        if interactive {
+            match &self.args.rc {
+                Rc::Default => files.extend(self.config_files("rc.jsh")),
+                Rc::File(file) => files.push(file.clone()),
+                Rc::None => {}
+            }
+        }
+
+        for file in files {
+            if let Some(src) = read_source(&file) {
+                self.run_source(src).await;
+            }
+            if self.exit.is_some() {
+                return self.exit;
+            }
+        }
+        None
+    }
+
+    /// The system-wide and user configuration files with a name, those that
+    /// exist.
+    fn config_files(&self, name: &str) -> Vec<PathBuf> {
+        let user = self.evaluator.config_dir();
+        Some(PathBuf::from(SYSTEM_CONFIG_DIR))
+            .into_iter()
+            .chain(user)
+            .map(|dir| dir.join(name))
+            .filter(|file| file.exists())
+            .collect()
+    }
+
+    /// Parses and evaluates a source, reporting any errors.
+    async fn run_source(&mut self, src: Source) -> Status {
+        let chunk = match parse::parse(&src) {
+            Ok(chunk) => chunk,
+            Err(err) => {
+                report(&src, err.span, &err);
+                return Status::SYNTAX_ERROR;
+            }
+        };
+
+        self.run_chunk(&chunk, src)
+            .await
+            .unwrap_or_else(|status| status)
+    }
+
+    /// Evaluates a chunk parsed from a source, reporting any errors, in which
+    /// case the status of the error is returned as an error.
+    async fn run_chunk(&mut self, chunk: &Chunk, src: Source) -> Result<Status, Status> {
+        let src = Arc::new(src);
+        match self.evaluator.eval(chunk, &src).await {
+            Ok(status) => Ok(status),
+            Err(err) if matches!(err.kind, ErrorKind::Exit(_)) => {
+                let status = err.status();
+                self.exit = Some(status);
+                Ok(status)
+            }
+            // The job has already been reported as stopped.
+            Err(err) if matches!(err.kind, ErrorKind::Stopped(_)) => Ok(err.status()),
+            Err(err) => {
+                // The error may be in a function defined in another source.
+                report(err.src.as_deref().unwrap_or(&src), err.span, &err);
+                Err(err.status())
+            }
+        }
+    }
+}
+
+/// The prompt written without an editor, as the default prompt of the editor
+/// without its styles.
+fn plain_prompt() -> String {
+    let dir = env::current_dir().ok();
+    match dir.as_deref().and_then(Path::file_name) {
+        Some(name) => format!("{} \u{276f} ", name.to_string_lossy()),
+        None => "\u{276f} ".to_owned(),
+    }
+}
+
+/// Reads a source from a file, reporting an error if it cannot be read.
+fn read_source(file: &Path) -> Option<Source> {
+    match fs::read_to_string(file) {
+        Ok(code) => Some(Source::new(file.to_string_lossy(), code)),
+        Err(err) => {
+            eprintln!("jsh: cannot read `{}`: {}", file.display(), err);
+            None
+        }
+    }
+}
+
+/// Reads a line from the standard input, or what remains of it at the end of
+/// the input.
+///
+/// The input is read a byte at a time, so the input after the line is left
+/// for the commands it runs to read.
+async fn read_stdin_line() -> io::Result<Option<Vec<u8>>> {
+    let read = tokio::task::spawn_blocking(|| {
+        let mut line = Vec::new();
+        loop {
+            let mut byte = 0u8;
+            let n = unsafe { libc::read(libc::STDIN_FILENO, (&mut byte as *mut u8).cast(), 1) };
+            match n {
+                0 if line.is_empty() => return Ok(None),
+                0 => return Ok(Some(line)),
+                1 => {
+                    line.push(byte);
+                    if byte == b'\n' {
+                        return Ok(Some(line));
+                    }
+                }
+                _ => {
+                    let err = io::Error::last_os_error();
+                    if err.kind() != io::ErrorKind::Interrupted {
+                        return Err(err);
+                    }
+                }
+            }
+        }
+    });
+
+    match read.await {
+        Ok(result) => result,
+        Err(err) => Err(io::Error::other(err)),
+    }
 }
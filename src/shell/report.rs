@@ -0,0 +1,54 @@
+use std::fmt::{self, Write};
+
+use crate::parse::{LineCol, Source, Span};
+
+const RED: &str = "\x1b[1;31m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// Reports an error to the standard error in the style of rustc, underlining
+/// the span in the line of source it starts on.
+///
+/// The report is colored if the standard error is a terminal.
+pub fn report(src: &Source, span: Span, msg: &dyn fmt::Display) {
+    let color = unsafe { libc::isatty(libc::STDERR_FILENO) } == 1;
+    eprint!("{}", render(src, span, msg, color));
+}
+
+fn render(src: &Source, span: Span, msg: &dyn fmt::Display, color: bool) -> String {
+    let paint = |style: &'static str| if color { style } else { "" };
+    let (red, blue, bold, reset) = (paint(RED), paint(BLUE), paint(BOLD), paint(RESET));
+
+    let LineCol { line, col } = src.line_col(span.start);
+    let line_span = src.line_span(span.start);
+    let start = span.start.min(line_span.end);
+    let end = span.end.min(line_span.end).max(start);
+
+    // Tabs are kept in the padding so the carets line up with the code.
+    let padding: String = src
+        .slice(Span::new(line_span.start, start))
+        .chars()
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let carets = "^".repeat(src.slice(Span::new(start, end)).chars().count().max(1));
+
+    let width = line.to_string().len();
+    let gutter = " ".repeat(width);
+
+    let mut out = String::new();
+    let _ = writeln!(out, "{}error{}{}: {}{}", red, reset, bold, msg, reset);
+    let _ = writeln!(
+        out,
+        "{}{}-->{} {}:{}:{}",
+        gutter, blue, reset, src.name, line, col
+    );
+    let _ = writeln!(out, "{} {}|{}", gutter, blue, reset);
+    let _ = writeln!(out, "{}{} |{} {}", blue, line, reset, src.slice(line_span));
+    let _ = writeln!(
+        out,
+        "{} {}|{} {}{}{}{}",
+        gutter, blue, reset, padding, red, carets, reset
+    );
+    out
+}